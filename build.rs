@@ -0,0 +1,17 @@
+//! Emits the `valida` cfg when compiling for the Valida VM.
+//!
+//! The Valida target has shipped under more than one architecture name, so the crate matches on
+//! a single `cfg(valida)` instead of repeating every `target_arch` spelling.
+
+/// Architecture names the Valida VM target has been published under.
+const VALIDA_ARCHES: &[&str] = &["valida", "delendum"];
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(valida)");
+    println!("cargo::rerun-if-changed=build.rs");
+
+    let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    if VALIDA_ARCHES.contains(&arch.as_str()) {
+        println!("cargo::rustc-cfg=valida");
+    }
+}
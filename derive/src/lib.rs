@@ -0,0 +1,125 @@
+//! Derive macros of `valida-rs`; use them through `valida_rs::schema`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitInt};
+
+/// Derives `valida_rs::schema::ValidaIo`; see there.
+#[proc_macro_derive(ValidaIo, attributes(valida_io))]
+pub fn derive_valida_io(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let version = version(&input)?;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            name,
+            "ValidaIo can only be derived for structs",
+        ));
+    };
+
+    // The fields in declaration order, which is the order they are encoded in.
+    let (members, types): (Vec<_>, Vec<_>) = match &data.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                (quote!(#ident), &field.ty)
+            })
+            .unzip(),
+        Fields::Unnamed(fields) => fields
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let index = syn::Index::from(i);
+                (quote!(#index), &field.ty)
+            })
+            .unzip(),
+        Fields::Unit => (Vec::new(), Vec::new()),
+    };
+
+    let schema: String = std::iter::once(format!("{name}@{version}"))
+        .chain(
+            members
+                .iter()
+                .zip(&types)
+                .map(|(member, ty)| format!("{member}:{}", quote!(#ty))),
+        )
+        .collect::<Vec<_>>()
+        .join(";");
+    let schema_hash = fnv1a(schema.as_bytes());
+
+    // Each value takes its type from the field it initializes.
+    let values = members.iter().map(|_| quote!(decoder.field()?));
+    let construct = match &data.fields {
+        Fields::Named(_) => quote!(Self { #(#members: #values,)* }),
+        Fields::Unnamed(_) => quote!(Self(#(#values,)*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let mut where_clause = where_clause
+        .cloned()
+        .unwrap_or_else(|| syn::parse_quote!(where));
+    for ty in &types {
+        where_clause.predicates.push(syn::parse_quote!(
+            #ty: ::valida_rs::schema::serde::Serialize
+                + ::valida_rs::schema::serde::de::DeserializeOwned
+        ));
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::valida_rs::schema::ValidaIo for #name #type_generics #where_clause {
+            const VERSION: u8 = #version;
+            const SCHEMA_HASH: u64 = #schema_hash;
+
+            fn encode_fields(
+                &self,
+                encoder: &mut ::valida_rs::schema::FieldEncoder,
+            ) -> ::std::result::Result<(), ::std::boxed::Box<dyn ::std::error::Error>> {
+                #(encoder.field(&self.#members)?;)*
+                ::std::result::Result::Ok(())
+            }
+
+            fn decode_fields(
+                decoder: &mut ::valida_rs::schema::FieldDecoder<'_>,
+            ) -> ::std::result::Result<Self, ::std::boxed::Box<dyn ::std::error::Error>> {
+                ::std::result::Result::Ok(#construct)
+            }
+        }
+    })
+}
+
+/// The version from `#[valida_io(version = N)]`, or 0.
+fn version(input: &DeriveInput) -> syn::Result<u8> {
+    let mut version = 0;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("valida_io"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("version") {
+                version = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("expected `version = N`"))
+            }
+        })?;
+    }
+    Ok(version)
+}
+
+/// The 64-bit FNV-1a hash, which is stable across compilers and platforms.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,7 +1,19 @@
 //! These are unit tests in a library.
-#![feature(custom_test_frameworks, test)]
+#![feature(custom_test_frameworks)]
 #![test_runner(valida_rs::test_utils::test_runner)]
 
+// Doctests are run by rustdoc, outside the custom test runner; this registers them as a test.
+valida_rs::doctests!();
+
+/// Doubles a number.
+///
+/// ```
+/// assert_eq!(testing::double(21), 42);
+/// ```
+pub fn double(x: u32) -> u32 {
+    x * 2
+}
+
 #[test]
 fn test_add() {
     assert_eq!(2 + 2, 4);
@@ -5,7 +5,7 @@
 //!
 //! For integration tests or a library you will not need the entrypoint macro or no_main attributes.
 //! For a full example of integration tests see the `tests/test.rs` file.
-#![feature(custom_test_frameworks, test)]
+#![feature(custom_test_frameworks)]
 #![test_runner(valida_rs::test_utils::test_runner)]
 #![cfg_attr(not(test), no_main)]
 valida_rs::entrypoint!(main);
@@ -1,8 +1,30 @@
 //! These are unit tests in a library.
-#![feature(custom_test_frameworks, test)]
+#![feature(custom_test_frameworks)]
 #![test_runner(valida_rs::test_utils::test_runner)]
 
 #[test]
 fn test_integration() {
     assert_eq!(3 + 3, 6);
 }
+
+// Failing inputs are shrunk on the host and, with `VALIDA_TEST=1`, replayed in the VM. A
+// property that passes natively runs in the VM with its first generated input.
+valida_rs::valida_proptest! {
+    fn prop_add_commutes(a in any::<u32>(), b in any::<u32>()) {
+        println!("checked {a} + {b}");
+        assert_eq!(a.wrapping_add(b), b.wrapping_add(a));
+    }
+
+    #[should_panic]
+    fn prop_small_numbers(x in 0u32..1000) {
+        println!("checking {x}");
+        assert!(x < 500);
+    }
+}
+
+// With `VALIDA_TEST=1`, the VM must run the properties: the passing one with a sampled input and
+// the `should_panic` one with the minimized input, 500.
+valida_rs::valida_expect_output!(
+    prop_add_commutes, "checked ";
+    prop_small_numbers, "checking 500";
+);
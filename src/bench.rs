@@ -0,0 +1,218 @@
+//! Lightweight benchmarks that run on both the host and the Valida VM.
+//!
+//! On the host each iteration is timed in nanoseconds. In the VM each iteration is measured in
+//! cycles, provided the cycle counter is available (see [`crate::intrinsics`]).
+//!
+//! Benchmarks are registered with [`valida_bench!`](crate::valida_bench), which does not need
+//! `#![feature(test)]`, or with a regular `#[bench]` function.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The number of iterations a benchmark runs when none is specified.
+pub const DEFAULT_ITERATIONS: u32 = 10;
+
+/// Prefix of the line a benchmark prints its summary on.
+pub const REPORT_PREFIX: &str = "valida-bench:";
+
+/// Summaries of the benchmarks run on the host that the test runner has not reported yet.
+static HOST_REPORTS: Mutex<Vec<BenchSummary>> = Mutex::new(Vec::new());
+
+/// What a benchmark sample counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchUnit {
+    Cycles,
+    Nanoseconds,
+}
+
+impl BenchUnit {
+    fn as_str(self) -> &'static str {
+        match self {
+            BenchUnit::Cycles => "cycles",
+            BenchUnit::Nanoseconds => "ns",
+        }
+    }
+}
+
+/// Measures the cost of a closure over a fixed number of iterations.
+pub struct Bencher {
+    iterations: u32,
+    samples: Vec<u64>,
+    unit: Option<BenchUnit>,
+}
+
+impl Bencher {
+    pub fn new(iterations: u32) -> Self {
+        Self {
+            iterations: iterations.max(1),
+            samples: Vec::new(),
+            unit: None,
+        }
+    }
+
+    /// Run `f` once per iteration, recording the cost of each call.
+    pub fn iter<T>(&mut self, mut f: impl FnMut() -> T) {
+        for _ in 0..self.iterations {
+            self.measure(|| {
+                std::hint::black_box(f());
+            });
+        }
+    }
+
+    /// Record the cost of a single call to `f`.
+    fn measure(&mut self, f: impl FnOnce()) {
+        if crate::target::is_valida() {
+            let start = crate::intrinsics::cycle_count();
+            f();
+            if let (Some(start), Some(end)) = (start, crate::intrinsics::cycle_count()) {
+                self.samples.push(end - start);
+                self.unit = Some(BenchUnit::Cycles);
+            }
+        } else {
+            let start = Instant::now();
+            f();
+            self.samples.push(duration_as_nanos(start.elapsed()));
+            self.unit = Some(BenchUnit::Nanoseconds);
+        }
+    }
+
+    /// Summarize the samples recorded so far.
+    pub fn summary(&self, name: &str) -> BenchSummary {
+        let mut samples = self.samples.clone();
+        samples.sort_unstable();
+
+        BenchSummary {
+            name: name.to_string(),
+            iterations: self.iterations,
+            min: samples.first().copied(),
+            median: samples.get(samples.len() / 2).copied(),
+            unit: self.unit,
+        }
+    }
+}
+
+fn duration_as_nanos(d: Duration) -> u64 {
+    u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// The result of a benchmark.
+///
+/// `min` and `median` are per iteration, and are `None` if nothing could be measured (e.g. the VM
+/// has no cycle counter).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchSummary {
+    pub name: String,
+    pub iterations: u32,
+    pub min: Option<u64>,
+    pub median: Option<u64>,
+    pub unit: Option<BenchUnit>,
+}
+
+impl fmt::Display for BenchSummary {
+    /// Formats the summary as the report line parsed by [`BenchSummary::parse_line`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opt = |v: Option<u64>| v.map_or_else(|| "-".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "{REPORT_PREFIX} {} iterations={} min={} median={} unit={}",
+            self.name,
+            self.iterations,
+            opt(self.min),
+            opt(self.median),
+            self.unit.map_or("-", BenchUnit::as_str),
+        )
+    }
+}
+
+impl BenchSummary {
+    /// Parse a report line printed by a benchmark.
+    pub fn parse_line(line: &str) -> Option<Self> {
+        let mut fields = line.trim().strip_prefix(REPORT_PREFIX)?.split_whitespace();
+        let name = fields.next()?.to_string();
+
+        let mut summary = BenchSummary {
+            name,
+            iterations: 0,
+            min: None,
+            median: None,
+            unit: None,
+        };
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            match key {
+                "iterations" => summary.iterations = value.parse().ok()?,
+                "min" => summary.min = u64::from_str(value).ok(),
+                "median" => summary.median = u64::from_str(value).ok(),
+                "unit" => {
+                    summary.unit = match value {
+                        "cycles" => Some(BenchUnit::Cycles),
+                        "ns" => Some(BenchUnit::Nanoseconds),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(summary)
+    }
+
+    /// A short human readable description of the result, e.g. `median 120 cycles/iter (min 118)`.
+    pub fn describe(&self) -> String {
+        match (self.min, self.median, self.unit) {
+            (Some(min), Some(median), Some(unit)) => format!(
+                "median {median} {unit}/iter (min {min}) over {} iterations",
+                self.iterations,
+                unit = unit.as_str()
+            ),
+            _ => format!("{} iterations, no measurements available", self.iterations),
+        }
+    }
+}
+
+/// Run a benchmark and report its summary.
+///
+/// In the VM the summary is printed for the host test runner to pick up; on the host it is
+/// handed to the test runner directly, since test output is captured.
+pub fn run(name: &str, iterations: u32, f: impl FnOnce(&mut Bencher)) -> BenchSummary {
+    let mut bencher = Bencher::new(iterations);
+    f(&mut bencher);
+    let summary = bencher.summary(name);
+    report(&summary);
+    summary
+}
+
+/// Run a `#[bench]` function `iterations` times, measuring each whole call.
+#[cfg(any(valida, feature = "host"))]
+pub(crate) fn run_bench_fn(
+    name: &str,
+    iterations: u32,
+    f: &dyn Fn(&mut test::Bencher) -> Result<(), String>,
+) -> Result<BenchSummary, String> {
+    let mut bencher = Bencher::new(iterations);
+    let mut result = Ok(());
+    for _ in 0..bencher.iterations {
+        bencher.measure(|| result = test::bench::run_once(f));
+        result.clone()?;
+    }
+    let summary = bencher.summary(name);
+    report(&summary);
+    Ok(summary)
+}
+
+fn report(summary: &BenchSummary) {
+    if crate::target::is_valida() {
+        println!("{summary}");
+    } else {
+        HOST_REPORTS.lock().unwrap().push(summary.clone());
+    }
+}
+
+/// Take the summaries of the benchmarks that ran on the host since the last call.
+#[cfg(any(valida, feature = "host"))]
+pub(crate) fn take_host_reports() -> Vec<BenchSummary> {
+    std::mem::take(&mut *HOST_REPORTS.lock().unwrap())
+}
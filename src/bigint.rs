@@ -0,0 +1,307 @@
+//! 256-bit unsigned integers, for RSA- and EVM-style guests.
+//!
+//! Requires the `bigint` feature. With the `precompiles` feature, multiplication and modular
+//! arithmetic use the VM's wide-arithmetic precompiles; otherwise, and always on the host, they use
+//! `crypto-bigint`. Modular operations follow the EVM: reducing modulo zero gives zero.
+
+use core::cmp::Ordering;
+
+/// A 256-bit unsigned integer.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct U256([u32; 8]);
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_u256_mul(a: *const u32, b: *const u32, lo: *mut u32, hi: *mut u32);
+    fn valida_u256_addmod(a: *const u32, b: *const u32, modulus: *const u32, out: *mut u32);
+    fn valida_u256_mulmod(a: *const u32, b: *const u32, modulus: *const u32, out: *mut u32);
+    fn valida_u256_modexp(base: *const u32, exp: *const u32, modulus: *const u32, out: *mut u32);
+}
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 8]);
+    pub const ONE: U256 = U256([1, 0, 0, 0, 0, 0, 0, 0]);
+    pub const MAX: U256 = U256([u32::MAX; 8]);
+
+    /// The integer with the given 32-bit limbs, least significant first.
+    pub const fn from_limbs(limbs: [u32; 8]) -> Self {
+        Self(limbs)
+    }
+
+    /// The 32-bit limbs, least significant first.
+    pub const fn limbs(&self) -> [u32; 8] {
+        self.0
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0; 8];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.as_chunks::<4>().0.iter().rev()) {
+            *limb = u32::from_be_bytes(*chunk);
+        }
+        Self(limbs)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (chunk, limb) in bytes.as_chunks_mut::<4>().0.iter_mut().rev().zip(self.0) {
+            *chunk = limb.to_be_bytes();
+        }
+        bytes
+    }
+
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0; 8];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.as_chunks::<4>().0) {
+            *limb = u32::from_le_bytes(*chunk);
+        }
+        Self(limbs)
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (chunk, limb) in bytes.as_chunks_mut::<4>().0.iter_mut().zip(self.0) {
+            *chunk = limb.to_le_bytes();
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    /// `self + rhs` modulo `2^256`, and whether it overflowed.
+    pub fn overflowing_add(&self, rhs: &Self) -> (Self, bool) {
+        let mut sum = [0; 8];
+        let mut carry = false;
+        for (i, limb) in sum.iter_mut().enumerate() {
+            let (s, c1) = self.0[i].overflowing_add(rhs.0[i]);
+            let (s, c2) = s.overflowing_add(carry as u32);
+            *limb = s;
+            carry = c1 || c2;
+        }
+        (Self(sum), carry)
+    }
+
+    /// `self - rhs` modulo `2^256`, and whether it underflowed.
+    pub fn overflowing_sub(&self, rhs: &Self) -> (Self, bool) {
+        let mut difference = [0; 8];
+        let mut borrow = false;
+        for (i, limb) in difference.iter_mut().enumerate() {
+            let (d, b1) = self.0[i].overflowing_sub(rhs.0[i]);
+            let (d, b2) = d.overflowing_sub(borrow as u32);
+            *limb = d;
+            borrow = b1 || b2;
+        }
+        (Self(difference), borrow)
+    }
+
+    pub fn wrapping_add(&self, rhs: &Self) -> Self {
+        self.overflowing_add(rhs).0
+    }
+
+    pub fn wrapping_sub(&self, rhs: &Self) -> Self {
+        self.overflowing_sub(rhs).0
+    }
+
+    /// The full 512-bit product, as its low and high halves.
+    pub fn widening_mul(&self, rhs: &Self) -> (Self, Self) {
+        #[cfg(all(valida, feature = "precompiles"))]
+        {
+            let (mut lo, mut hi) = (Self::ZERO, Self::ZERO);
+            unsafe {
+                valida_u256_mul(
+                    self.0.as_ptr(),
+                    rhs.0.as_ptr(),
+                    lo.0.as_mut_ptr(),
+                    hi.0.as_mut_ptr(),
+                )
+            };
+            (lo, hi)
+        }
+
+        #[cfg(not(all(valida, feature = "precompiles")))]
+        {
+            let (lo, hi) = self.to_uint().mul_wide(&rhs.to_uint());
+            (Self::from_uint(lo), Self::from_uint(hi))
+        }
+    }
+
+    /// `self * rhs` modulo `2^256`.
+    pub fn wrapping_mul(&self, rhs: &Self) -> Self {
+        self.widening_mul(rhs).0
+    }
+
+    /// `(self + rhs) % modulus`, computed without overflow.
+    pub fn add_mod(&self, rhs: &Self, modulus: &Self) -> Self {
+        #[cfg(all(valida, feature = "precompiles"))]
+        {
+            let mut out = Self::ZERO;
+            unsafe {
+                valida_u256_addmod(
+                    self.0.as_ptr(),
+                    rhs.0.as_ptr(),
+                    modulus.0.as_ptr(),
+                    out.0.as_mut_ptr(),
+                )
+            };
+            out
+        }
+
+        #[cfg(not(all(valida, feature = "precompiles")))]
+        {
+            if modulus.is_zero() {
+                return Self::ZERO;
+            }
+            let modulus = modulus.to_uint();
+            let a = self.to_uint().wrapping_rem(&modulus);
+            let b = rhs.to_uint().wrapping_rem(&modulus);
+            Self::from_uint(a.add_mod(&b, &modulus))
+        }
+    }
+
+    /// `(self * rhs) % modulus`, computed without overflow.
+    pub fn mul_mod(&self, rhs: &Self, modulus: &Self) -> Self {
+        #[cfg(all(valida, feature = "precompiles"))]
+        {
+            let mut out = Self::ZERO;
+            unsafe {
+                valida_u256_mulmod(
+                    self.0.as_ptr(),
+                    rhs.0.as_ptr(),
+                    modulus.0.as_ptr(),
+                    out.0.as_mut_ptr(),
+                )
+            };
+            out
+        }
+
+        #[cfg(not(all(valida, feature = "precompiles")))]
+        {
+            if modulus.is_zero() {
+                return Self::ZERO;
+            }
+            let product = self.to_uint().mul_wide(&rhs.to_uint());
+            let (remainder, _) = crypto_bigint::U256::const_rem_wide(product, &modulus.to_uint());
+            Self::from_uint(remainder)
+        }
+    }
+
+    /// `self.pow(exp) % modulus`, as computed by the EVM's `modexp` precompile.
+    pub fn pow_mod(&self, exp: &Self, modulus: &Self) -> Self {
+        #[cfg(all(valida, feature = "precompiles"))]
+        {
+            let mut out = Self::ZERO;
+            unsafe {
+                valida_u256_modexp(
+                    self.0.as_ptr(),
+                    exp.0.as_ptr(),
+                    modulus.0.as_ptr(),
+                    out.0.as_mut_ptr(),
+                )
+            };
+            out
+        }
+
+        #[cfg(not(all(valida, feature = "precompiles")))]
+        {
+            if modulus.is_zero() {
+                return Self::ZERO;
+            }
+            // Square and multiply, from the most significant bit of the exponent.
+            let mut result = Self::ONE.add_mod(&Self::ZERO, modulus);
+            for limb in exp.0.iter().rev() {
+                for bit in (0..32).rev() {
+                    result = result.mul_mod(&result, modulus);
+                    if limb >> bit & 1 == 1 {
+                        result = result.mul_mod(self, modulus);
+                    }
+                }
+            }
+            result
+        }
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    fn to_uint(self) -> crypto_bigint::U256 {
+        crypto_bigint::U256::from_le_slice(&self.to_le_bytes())
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    fn from_uint(value: crypto_bigint::U256) -> Self {
+        use crypto_bigint::Encoding;
+
+        Self::from_le_bytes(value.to_le_bytes())
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        Self([value as u32, (value >> 32) as u32, 0, 0, 0, 0, 0, 0])
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl core::fmt::Debug for U256 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "U256({self:#x})")
+    }
+}
+
+impl core::fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        for limb in self.0.iter().rev() {
+            write!(f, "{limb:08x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_u256_arithmetic() {
+    let p = U256::from(1_000_000_007);
+    let a = U256::MAX;
+    let b = U256::from(u64::MAX);
+
+    assert_eq!(a.overflowing_add(&U256::ONE), (U256::ZERO, true));
+    assert_eq!(U256::ZERO.wrapping_sub(&U256::ONE), U256::MAX);
+    assert_eq!(
+        a.widening_mul(&a),
+        (U256::ONE, U256::MAX.wrapping_sub(&U256::ONE))
+    );
+    assert_eq!(
+        b.wrapping_mul(&b),
+        U256::from_limbs([1, 0, u32::MAX - 1, u32::MAX, 0, 0, 0, 0])
+    );
+
+    // 2^256 - 1 = 792845265 (mod 1000000007)
+    assert_eq!(a.add_mod(&U256::ZERO, &p), U256::from(792_845_265));
+    assert_eq!(
+        a.mul_mod(&a, &p),
+        U256::from(792_845_265u64 * 792_845_265 % 1_000_000_007)
+    );
+    assert_eq!(
+        U256::from(3).pow_mod(&(p.wrapping_sub(&U256::ONE)), &p),
+        U256::ONE
+    );
+    assert_eq!(a.mul_mod(&a, &U256::ZERO), U256::ZERO);
+
+    let bytes = U256::from(0x0102).to_be_bytes();
+    assert_eq!(bytes[30..], [1, 2]);
+    assert_eq!(U256::from_be_bytes(bytes), U256::from(0x0102));
+    assert!(U256::from_limbs([0, 0, 0, 0, 0, 0, 0, 1]) > U256::from(u64::MAX));
+}
@@ -0,0 +1,5 @@
+//! `cargo valida`: see [`valida_rs::cli`].
+
+fn main() {
+    std::process::exit(valida_rs::cli::main(std::env::args().skip(1)));
+}
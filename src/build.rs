@@ -0,0 +1,422 @@
+//! Build guest crates for Valida from a host crate's build script.
+//!
+//! [`build_guest`] compiles a guest crate for the Valida target and tells cargo where the binary
+//! is, so [`embed_guest!`](crate::embed_guest) can include it in the host binary:
+//! ```rust,ignore
+//! // build.rs
+//! fn main() {
+//!     valida_rs::build::build_guest("../guest");
+//! }
+//!
+//! // src/main.rs
+//! const GUEST: &[u8] = valida_rs::embed_guest!("guest");
+//! ```
+//!
+//! The Valida toolchain is expected in `/valida-toolchain`; set `VALIDA_TOOLCHAIN_DIR` to use
+//! another installation, e.g. on macOS or Windows hosts.
+
+use std::{
+    env::{self, consts::EXE_SUFFIX},
+    io::BufRead,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+mod audit;
+mod size;
+
+pub use audit::{audit, audit_report, AuditFinding};
+pub use size::{SizeEntry, SizeReport};
+
+/// Prefix of the environment variable [`build_guest`] sets to the path of a guest binary.
+pub const GUEST_ENV_PREFIX: &str = "VALIDA_GUEST_";
+
+/// Environment variable with the directory the Valida toolchain is installed in.
+pub const TOOLCHAIN_DIR_ENV: &str = "VALIDA_TOOLCHAIN_DIR";
+
+/// Where the Valida toolchain is installed, from `VALIDA_TOOLCHAIN_DIR` or `/valida-toolchain`.
+pub fn toolchain_dir() -> PathBuf {
+    env::var_os(TOOLCHAIN_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/valida-toolchain"))
+}
+
+/// The files of the toolchain that [`valida_cargo_command`] builds with, with what each is.
+pub(crate) fn toolchain_files(triple: &str) -> Vec<(&'static str, PathBuf)> {
+    let toolchain = toolchain_dir();
+    vec![
+        (
+            "the linker",
+            toolchain.join(format!("bin/ld.lld{EXE_SUFFIX}")),
+        ),
+        (
+            "the C compiler",
+            toolchain.join(format!("bin/clang{EXE_SUFFIX}")),
+        ),
+        ("the linker script", toolchain.join("valida.ld")),
+        (
+            "the entry point",
+            toolchain.join(crate::target::entry_point_object(&toolchain)),
+        ),
+        ("libc", toolchain.join(format!("lib/{triple}/libc.a"))),
+        ("libm", toolchain.join(format!("lib/{triple}/libm.a"))),
+    ]
+}
+
+/// Quote a path as a TOML literal string, so Windows path separators need no escaping.
+fn toml_literal(path: &Path) -> String {
+    format!("'{}'", path.display())
+}
+
+/// Create a `cargo +valida <subcommand>` command configured to build for the Valida target.
+///
+/// This only sets up the target and linker; [`GuestBuildOptions::cargo_command`] also selects the
+/// profile.
+pub fn valida_cargo_command(subcommand: &str) -> Command {
+    let triple = crate::target::target_triple();
+    let env_suffix = crate::target::env_var_suffix(&triple);
+
+    let mut command = Command::new("cargo");
+    // Variables set for an outer cargo invocation, e.g. when called from a build script, would
+    // override the Valida toolchain.
+    for var in [
+        "RUSTC",
+        "RUSTC_WRAPPER",
+        "RUSTC_WORKSPACE_WRAPPER",
+        "RUSTDOC",
+        "RUSTUP_TOOLCHAIN",
+        "CARGO_ENCODED_RUSTFLAGS",
+    ] {
+        command.env_remove(var);
+    }
+
+    let toolchain = toolchain_dir();
+    let entry_point = crate::target::entry_point_object(&toolchain);
+    let tool = |path: &str| toml_literal(&toolchain.join(path));
+    let link_arg = |arg: String| format!("'-C','link-arg={arg}'");
+
+    let rustflags = [
+        link_arg(toolchain.join(entry_point).display().to_string()),
+        link_arg(format!(
+            "--script={}",
+            toolchain.join("valida.ld").display()
+        )),
+        link_arg(
+            toolchain
+                .join(format!("lib/{triple}/libc.a"))
+                .display()
+                .to_string(),
+        ),
+        link_arg(
+            toolchain
+                .join(format!("lib/{triple}/libm.a"))
+                .display()
+                .to_string(),
+        ),
+        link_arg("--noinhibit-exec".to_string()),
+    ];
+    let cflags = format!(
+        "--sysroot={} -isystem {}",
+        toolchain.display(),
+        toolchain.join("include").display()
+    );
+
+    command
+        .arg("+valida")
+        .arg(subcommand)
+        .arg(format!("--target={triple}"))
+        .arg("--config")
+        .arg(format!("build.target=\"{triple}\""))
+        .arg("--config")
+        .arg(format!("target.{triple}.runner=\"echo\""))
+        .arg("--config")
+        .arg(format!(
+            "target.{triple}.linker={}",
+            tool(&format!("bin/ld.lld{EXE_SUFFIX}"))
+        ))
+        .arg("--config")
+        .arg(format!(
+            "target.{triple}.rustflags=[{}]",
+            rustflags.join(",")
+        ))
+        .arg("--config")
+        .arg(format!(
+            "env.CC_{env_suffix}={}",
+            tool(&format!("bin/clang{EXE_SUFFIX}"))
+        ))
+        .arg("--config")
+        .arg(format!("env.CFLAGS_{env_suffix}='{cflags}'"));
+
+    command
+}
+
+/// How a panic in a guest is handled.
+///
+/// Panics cannot be caught in the VM, so the presets of [`GuestBuildOptions`] abort, which saves
+/// the unwinding tables and landing pads in the binary; `entrypoint!` makes a panic halt the VM
+/// with a failure status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicStrategy {
+    Unwind,
+    Abort,
+}
+
+/// The profile settings guests are compiled with.
+///
+/// Start from a preset and override individual settings; unset settings come from the guest's
+/// own cargo profile. Every preset sets `panic = "abort"`.
+/// ```rust
+/// use valida_rs::build::{GuestBuildOptions, PanicStrategy};
+///
+/// let options = GuestBuildOptions::min_size().panic(PanicStrategy::Abort);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GuestBuildOptions {
+    /// Build with the `release` profile rather than `dev`.
+    pub release: bool,
+    /// `opt-level`: `"0"` to `"3"`, `"s"` or `"z"`.
+    pub opt_level: Option<String>,
+    /// Link-time optimization across crates.
+    pub lto: Option<bool>,
+    pub codegen_units: Option<u32>,
+    pub panic: Option<PanicStrategy>,
+    /// Print a [`SizeReport`] of each binary built, with this many of the biggest symbols.
+    pub size_report: Option<usize>,
+}
+
+impl GuestBuildOptions {
+    /// The `dev` profile, as used by `cargo test`.
+    pub fn debug() -> Self {
+        Self {
+            panic: Some(PanicStrategy::Abort),
+            ..Self::default()
+        }
+    }
+
+    /// The `release` profile, as used by `cargo test --release`.
+    pub fn release() -> Self {
+        Self {
+            release: true,
+            ..Self::debug()
+        }
+    }
+
+    /// Release, optimized for the smallest binary.
+    pub fn min_size() -> Self {
+        Self {
+            opt_level: Some("z".to_string()),
+            lto: Some(true),
+            codegen_units: Some(1),
+            ..Self::release()
+        }
+    }
+
+    /// Release, optimized for the fewest cycles.
+    pub fn max_speed() -> Self {
+        Self {
+            opt_level: Some("3".to_string()),
+            lto: Some(true),
+            codegen_units: Some(1),
+            ..Self::release()
+        }
+    }
+
+    pub fn opt_level(mut self, opt_level: impl Into<String>) -> Self {
+        self.opt_level = Some(opt_level.into());
+        self
+    }
+
+    pub fn lto(mut self, lto: bool) -> Self {
+        self.lto = Some(lto);
+        self
+    }
+
+    pub fn codegen_units(mut self, codegen_units: u32) -> Self {
+        self.codegen_units = Some(codegen_units);
+        self
+    }
+
+    pub fn panic(mut self, panic: PanicStrategy) -> Self {
+        self.panic = Some(panic);
+        self
+    }
+
+    pub fn size_report(mut self, top: usize) -> Self {
+        self.size_report = Some(top);
+        self
+    }
+
+    /// The cargo profile the options build with.
+    pub fn profile(&self) -> &'static str {
+        if self.release {
+            "release"
+        } else {
+            "dev"
+        }
+    }
+
+    /// The `--config` values overriding the profile.
+    fn profile_overrides(&self) -> Vec<String> {
+        let profile = self.profile();
+        let mut overrides = Vec::new();
+        if let Some(opt_level) = &self.opt_level {
+            // Numeric levels are integers in cargo's config, `s` and `z` are strings.
+            let value = match opt_level.parse::<u8>() {
+                Ok(level) => level.to_string(),
+                Err(_) => format!("\"{opt_level}\""),
+            };
+            overrides.push(format!("profile.{profile}.opt-level={value}"));
+        }
+        if let Some(lto) = self.lto {
+            overrides.push(format!("profile.{profile}.lto={lto}"));
+        }
+        if let Some(codegen_units) = self.codegen_units {
+            overrides.push(format!("profile.{profile}.codegen-units={codegen_units}"));
+        }
+        if let Some(panic) = self.panic {
+            let panic = match panic {
+                PanicStrategy::Unwind => "unwind",
+                PanicStrategy::Abort => "abort",
+            };
+            overrides.push(format!("profile.{profile}.panic=\"{panic}\""));
+        }
+        overrides
+    }
+
+    /// Create a `cargo +valida <subcommand>` command that builds with these options.
+    pub fn cargo_command(&self, subcommand: &str) -> Command {
+        let mut command = valida_cargo_command(subcommand);
+        if self.release {
+            command.arg("--release");
+        }
+        for value in self.profile_overrides() {
+            command.arg("--config").arg(value);
+        }
+        // Cargo ignores the profile's panic strategy for test harnesses, so pass it to rustc
+        // directly. The flags are appended to those `valida_cargo_command` sets.
+        if self.panic == Some(PanicStrategy::Abort) && matches!(subcommand, "test" | "bench") {
+            command.arg("--config").arg(format!(
+                "target.{}.rustflags=['-C','panic=abort']",
+                crate::target::target_triple()
+            ));
+        }
+        command
+    }
+}
+
+/// Compile the guest crate in `crate_dir` for Valida in release mode and return the path of its
+/// binary. See [`build_guest_with`] to choose the profile settings.
+pub fn build_guest(crate_dir: impl AsRef<Path>) -> PathBuf {
+    build_guest_with(crate_dir, &GuestBuildOptions::release())
+}
+
+/// Compile the guest crate in `crate_dir` for Valida and return the path of its binary.
+///
+/// Must be called from a build script. Sets `VALIDA_GUEST_<name>` for the crate being built to
+/// the binary's path, where `<name>` is the guest's binary target name, and reruns the build
+/// script when the guest's sources change. With [`GuestBuildOptions::size_report`], the report is
+/// shown as build script warnings.
+///
+/// # Panics
+/// If the guest fails to build or does not produce a binary.
+pub fn build_guest_with(crate_dir: impl AsRef<Path>, options: &GuestBuildOptions) -> PathBuf {
+    let crate_dir = crate_dir.as_ref();
+    let out_dir =
+        PathBuf::from(env::var("OUT_DIR").expect("build_guest must be called from a build script"));
+    let manifest = crate_dir.join("Cargo.toml");
+
+    println!("cargo::rerun-if-changed={}", manifest.display());
+    println!(
+        "cargo::rerun-if-changed={}",
+        crate_dir.join("src").display()
+    );
+
+    let output = options
+        .cargo_command("build")
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(out_dir.join("valida-guests"))
+        .arg("--message-format=json-render-diagnostics")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap_or_else(|e| panic!("Failed to run cargo to build {}: {e}", crate_dir.display()));
+
+    if !output.status.success() {
+        panic!("Failed to build guest {} for valida", crate_dir.display());
+    }
+
+    let (name, path) = output
+        .stdout
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| guest_executable(&line))
+        .last()
+        .unwrap_or_else(|| panic!("Guest {} did not produce a binary", crate_dir.display()));
+
+    println!(
+        "cargo::rustc-env={GUEST_ENV_PREFIX}{name}={}",
+        path.display()
+    );
+    if let Some(top) = options.size_report {
+        // Cargo only shows a build script's output as warnings, one per line.
+        match SizeReport::of(&path) {
+            Ok(report) => {
+                println!("cargo::warning=size of guest {name}:");
+                for line in report.table(top).lines() {
+                    println!("cargo::warning={line}");
+                }
+            }
+            Err(e) => println!("cargo::warning=Failed to analyze guest {name}: {e}"),
+        }
+    }
+    path
+}
+
+/// The target name and path of the executable in a cargo JSON message, if it reports one.
+fn guest_executable(message: &str) -> Option<(String, PathBuf)> {
+    let message: serde_json::Value = serde_json::from_str(message).ok()?;
+    if message["reason"] != "compiler-artifact" {
+        return None;
+    }
+    let name = message["target"]["name"].as_str()?.to_string();
+    let path = PathBuf::from(message["executable"].as_str()?);
+    Some((name, path))
+}
+
+#[test]
+fn test_guest_executable_from_cargo_message() {
+    let artifact =
+        r#"{"reason":"compiler-artifact","target":{"name":"guest"},"executable":"/t/guest"}"#;
+    assert_eq!(
+        guest_executable(artifact),
+        Some(("guest".to_string(), PathBuf::from("/t/guest")))
+    );
+    let library = r#"{"reason":"compiler-artifact","target":{"name":"dep"},"executable":null}"#;
+    assert_eq!(guest_executable(library), None);
+}
+
+#[test]
+fn test_presets_override_the_profile() {
+    assert_eq!(
+        GuestBuildOptions::debug().profile_overrides(),
+        ["profile.dev.panic=\"abort\""]
+    );
+    assert_eq!(
+        GuestBuildOptions::min_size().profile_overrides(),
+        [
+            "profile.release.opt-level=\"z\"",
+            "profile.release.lto=true",
+            "profile.release.codegen-units=1",
+            "profile.release.panic=\"abort\"",
+        ]
+    );
+    assert_eq!(
+        GuestBuildOptions::debug()
+            .opt_level("1")
+            .panic(PanicStrategy::Unwind)
+            .profile_overrides(),
+        ["profile.dev.opt-level=1", "profile.dev.panic=\"unwind\""]
+    );
+}
@@ -0,0 +1,178 @@
+//! Finding code in a guest binary that is known to cost size and cycles for little use.
+
+use std::{fmt, path::Path};
+
+use super::{SizeEntry, SizeReport};
+
+/// Code of one kind found in a guest binary, with how to get rid of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditFinding {
+    /// What the code is.
+    pub what: &'static str,
+    pub suggestion: &'static str,
+    /// The symbols of the code, biggest first.
+    pub symbols: Vec<SizeEntry>,
+}
+
+impl AuditFinding {
+    /// The total size of the symbols.
+    pub fn size(&self) -> u64 {
+        self.symbols.iter().map(|symbol| symbol.size).sum()
+    }
+}
+
+impl fmt::Display for AuditFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({} bytes in {} symbols)",
+            self.what,
+            self.size(),
+            self.symbols.len()
+        )?;
+        for symbol in self.symbols.iter().take(3) {
+            writeln!(f, "    {} ({} bytes)", symbol.name, symbol.size)?;
+        }
+        if self.symbols.len() > 3 {
+            writeln!(f, "    ...")?;
+        }
+        write!(f, "  help: {}", self.suggestion)
+    }
+}
+
+struct Rule {
+    what: &'static str,
+    /// Substrings of the demangled names of the symbols.
+    patterns: &'static [&'static str],
+    /// How big the symbols must be together to be reported.
+    min_size: u64,
+    suggestion: &'static str,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        what: "unwinding machinery",
+        patterns: &[
+            "_Unwind_",
+            "panic_unwind::",
+            "__rust_start_panic",
+            "gimli::",
+        ],
+        min_size: 0,
+        suggestion: "panics cannot be caught in the VM; build with `panic = \"abort\"`, as the \
+                     presets of `GuestBuildOptions` do",
+    },
+    Rule {
+        what: "backtrace support",
+        patterns: &["std::backtrace", "std::sys::backtrace", "addr2line::"],
+        min_size: 0,
+        suggestion: "backtraces are not available in the VM; record phases with \
+                     `trace::breadcrumb!` instead of capturing a `Backtrace`",
+    },
+    Rule {
+        what: "float formatting and parsing",
+        patterns: &[
+            "core::fmt::float::",
+            "core::num::flt2dec::",
+            "core::num::dec2flt::",
+        ],
+        min_size: 0,
+        suggestion: "formatting or parsing `f32`/`f64` pulls in large tables; print and parse \
+                     `float::Decimal` or integers instead",
+    },
+    Rule {
+        what: "software floating-point arithmetic",
+        patterns: &[
+            "compiler_builtins::float::",
+            "__adddf3",
+            "__subdf3",
+            "__muldf3",
+            "__divdf3",
+            "__addsf3",
+            "__mulsf3",
+            "__divsf3",
+        ],
+        min_size: 0,
+        suggestion: "the VM emulates floats in software at many cycles per operation; use \
+                     `float::I64F64` or `float::Decimal`",
+    },
+    Rule {
+        what: "SipHash",
+        patterns: &["core::hash::sip::", "std::hash::random::RandomState"],
+        min_size: 0,
+        suggestion: "`std::collections::HashMap` hashes with SipHash; use the maps in \
+                     `valida_rs::collections`, which hash with FxHash",
+    },
+    Rule {
+        what: "inputs embedded with `include_input!`",
+        patterns: &["::VALIDA_INCLUDED_INPUT"],
+        min_size: 0,
+        suggestion: "embedded data is loaded with the program and grows every proof; read \
+                     large or varying data from the input tape instead",
+    },
+    Rule {
+        what: "`core::fmt` machinery",
+        patterns: &["core::fmt::"],
+        min_size: 16 * 1024,
+        suggestion: "formatting is large and slow in the VM; write results with `io::write_vec` \
+                     or `io::write_serde` rather than `println!`, and avoid `{:?}` of big types",
+    },
+];
+
+/// Scan the ELF binary at `binary` for code known to bloat guests, such as unwinding tables and
+/// float formatting, returning what was found with suggestions, biggest first.
+pub fn audit(binary: &Path) -> Result<Vec<AuditFinding>, String> {
+    Ok(audit_report(&SizeReport::of(binary)?))
+}
+
+/// The findings of [`audit`] for an analyzed binary.
+pub fn audit_report(report: &SizeReport) -> Vec<AuditFinding> {
+    let mut findings: Vec<_> = RULES
+        .iter()
+        .filter_map(|rule| {
+            let symbols: Vec<_> = report
+                .symbols
+                .iter()
+                .filter(|symbol| rule.patterns.iter().any(|p| symbol.name.contains(p)))
+                .cloned()
+                .collect();
+            let finding = AuditFinding {
+                what: rule.what,
+                suggestion: rule.suggestion,
+                symbols,
+            };
+            (!finding.symbols.is_empty() && finding.size() >= rule.min_size).then_some(finding)
+        })
+        .collect();
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.size()));
+    findings
+}
+
+#[test]
+fn test_audit_finds_known_bloat() {
+    let symbol = |name: &str, size| SizeEntry {
+        name: name.to_string(),
+        size,
+    };
+    let report = SizeReport {
+        sections: vec![symbol(".text", 10_000)],
+        symbols: vec![
+            symbol("core::fmt::float::float_to_decimal_common_exact", 3000),
+            symbol("core::fmt::Formatter::pad", 500),
+            symbol("_Unwind_RaiseException", 200),
+            symbol("guest::main::VALIDA_INCLUDED_INPUT", 1000),
+            symbol("guest::main", 100),
+        ],
+    };
+    let findings = audit_report(&report);
+    let found: Vec<_> = findings.iter().map(|finding| finding.what).collect();
+    assert_eq!(
+        found,
+        [
+            "float formatting and parsing",
+            "inputs embedded with `include_input!`",
+            "unwinding machinery"
+        ]
+    );
+    assert!(findings[0].to_string().contains("help: "));
+}
@@ -0,0 +1,133 @@
+//! What takes up the space in a guest binary.
+
+use std::{fmt, path::Path};
+
+use object::{Object, ObjectSection, ObjectSymbol, SectionFlags, SymbolKind};
+
+/// A section or symbol of a guest binary and how many bytes it takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeEntry {
+    pub name: String,
+    pub size: u64,
+}
+
+/// The sizes of the sections loaded from a guest binary and of its functions and data.
+///
+/// The time to set up a proof grows with the size of the program, so this shows where to start
+/// shrinking it. Its `Display` prints every section and the 10 biggest symbols;
+/// [`table`](Self::table) chooses how many symbols.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    /// The loaded sections, biggest first.
+    pub sections: Vec<SizeEntry>,
+    /// The functions and data objects, with demangled names, biggest first.
+    pub symbols: Vec<SizeEntry>,
+}
+
+impl SizeReport {
+    /// Analyze the ELF binary at `binary`.
+    pub fn of(binary: &Path) -> Result<Self, String> {
+        let data = std::fs::read(binary).map_err(|e| e.to_string())?;
+        Self::parse(&data)
+    }
+
+    /// Analyze an ELF binary.
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let file = object::File::parse(data).map_err(|e| e.to_string())?;
+
+        let mut sections: Vec<_> = file
+            .sections()
+            .filter(|section| {
+                matches!(section.flags(), SectionFlags::Elf { sh_flags }
+                    if sh_flags & u64::from(object::elf::SHF_ALLOC) != 0)
+            })
+            .map(|section| SizeEntry {
+                name: section.name().unwrap_or("<unnamed>").to_string(),
+                size: section.size(),
+            })
+            .collect();
+        sections.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        let mut symbols: Vec<_> = file
+            .symbols()
+            .filter(|symbol| {
+                symbol.size() > 0 && matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data)
+            })
+            .filter_map(|symbol| {
+                Some(SizeEntry {
+                    name: demangle(symbol.name().ok()?),
+                    size: symbol.size(),
+                })
+            })
+            .collect();
+        symbols.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        Ok(Self { sections, symbols })
+    }
+
+    /// The total size of the loaded sections.
+    pub fn total(&self) -> u64 {
+        self.sections.iter().map(|section| section.size).sum()
+    }
+
+    /// A table of every section and the `top` biggest symbols, with their share of the total.
+    pub fn table(&self, top: usize) -> String {
+        let total = self.total();
+        let share = |size: u64| 100.0 * size as f64 / total.max(1) as f64;
+        let mut table = format!("{:>10}  {:>6}  section\n", "size", "share");
+        for section in &self.sections {
+            table += &format!(
+                "{:>10}  {:>5.1}%  {}\n",
+                format_size(section.size),
+                share(section.size),
+                section.name
+            );
+        }
+        table += &format!("{:>10}  {:>6}  total\n", format_size(total), "");
+        if top > 0 && !self.symbols.is_empty() {
+            table += &format!("\n{:>10}  {:>6}  symbol\n", "size", "share");
+            for symbol in self.symbols.iter().take(top) {
+                table += &format!(
+                    "{:>10}  {:>5.1}%  {}\n",
+                    format_size(symbol.size),
+                    share(symbol.size),
+                    symbol.name
+                );
+            }
+        }
+        table
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.table(10))
+    }
+}
+
+/// A symbol name without its mangling and hash.
+pub(crate) fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[test]
+fn test_size_report_of_own_binary() {
+    let exe = std::env::current_exe().unwrap();
+    let Ok(report) = SizeReport::of(&exe) else {
+        // The test binary is not ELF on every host.
+        return;
+    };
+    assert!(report.sections.iter().any(|s| s.name == ".text"));
+    assert!(report.symbols.windows(2).all(|w| w[0].size >= w[1].size));
+    let table = report.table(3);
+    assert!(table.contains(".text"));
+    assert_eq!(format_size(1536), "1.5 KiB");
+}
@@ -0,0 +1,314 @@
+//! The `cargo valida` command, enabled by the `cli` feature.
+//!
+//! `cargo valida test` runs the tests of every crate in a cargo workspace on the host and in the
+//! Valida VM, and aggregates their results into a single report:
+//! ```text
+//! cargo valida test [--package <name>]... [--release] [--native-only] [-- <test args>...]
+//! ```
+//! The tests are built for Valida once, up front, so build errors are reported before any test
+//! runs. Arguments after `--` are passed to each test binary, as with `cargo test`.
+
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use crate::build::GuestBuildOptions;
+
+/// The options of `cargo valida test`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestOptions {
+    /// The packages to test; every workspace member when empty.
+    pub packages: Vec<String>,
+    /// Build and test in release mode.
+    pub release: bool,
+    /// Only run the tests natively.
+    pub native_only: bool,
+    /// Arguments passed to each test binary.
+    pub test_args: Vec<String>,
+}
+
+impl TestOptions {
+    /// Parse the arguments following `cargo valida test`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-p" | "--package" => options
+                    .packages
+                    .push(args.next().ok_or("--package needs a value")?),
+                "--release" => options.release = true,
+                "--native-only" => options.native_only = true,
+                "--" => options.test_args.extend(args.by_ref()),
+                other => match other.strip_prefix("--package=") {
+                    Some(package) => options.packages.push(package.to_string()),
+                    None => return Err(format!("unexpected argument '{other}'")),
+                },
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Test counts for one environment, read from the summary the test runner prints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl Counts {
+    /// Parse the `N passed; M failed; ...` part of a summary line.
+    fn parse(summary: &str) -> Self {
+        let mut counts = Self::default();
+        for part in summary.split(';') {
+            let mut words = part.split_whitespace();
+            let (Some(Ok(n)), Some(label)) = (words.next().map(str::parse), words.next()) else {
+                continue;
+            };
+            match label {
+                "passed" => counts.passed = n,
+                "failed" => counts.failed = n,
+                _ => {}
+            }
+        }
+        counts
+    }
+
+    fn add(&mut self, other: Counts) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+    }
+}
+
+/// The results of testing one package.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageReport {
+    pub package: String,
+    pub native: Counts,
+    pub valida: Counts,
+    /// `cargo test` exited successfully.
+    pub success: bool,
+}
+
+impl PackageReport {
+    /// Add the counts from one line of test runner output, if it is a summary line.
+    fn record_line(&mut self, line: &str) {
+        if let Some(summary) = line.trim().strip_prefix("on native:") {
+            self.native.add(Counts::parse(summary));
+        } else if let Some(summary) = line.trim().strip_prefix("on valida:") {
+            self.valida.add(Counts::parse(summary));
+        }
+    }
+}
+
+/// Run `cargo valida` with the arguments following the binary name, returning the exit code.
+pub fn main(args: impl IntoIterator<Item = String>) -> i32 {
+    let mut args = args.into_iter().peekable();
+    // Cargo passes the subcommand name when invoked as `cargo valida`.
+    if args.peek().map(String::as_str) == Some("valida") {
+        args.next();
+    }
+
+    let result = match args.next().as_deref() {
+        Some("test") => TestOptions::parse(args).and_then(|options| test(&options)),
+        _ => Err(
+            "usage: cargo valida test [--package <name>]... [--release] \
+                  [--native-only] [-- <test args>...]"
+                .to_string(),
+        ),
+    };
+
+    match result {
+        Ok(reports) if reports.iter().all(|r| r.success) => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            eprintln!("error: {e}");
+            2
+        }
+    }
+}
+
+/// Test the workspace, printing a report of every package.
+pub fn test(options: &TestOptions) -> Result<Vec<PackageReport>, String> {
+    let workspace = Workspace::load()?;
+    let packages = if options.packages.is_empty() {
+        workspace.members.clone()
+    } else {
+        options.packages.clone()
+    };
+
+    if !options.native_only {
+        println!("Building tests for valida");
+        build_for_valida(&workspace, &packages, options.release)?;
+    }
+
+    let mut reports = Vec::new();
+    for package in &packages {
+        println!("\nTesting {package}");
+        reports.push(test_package(package, options)?);
+    }
+
+    print_report(&reports);
+    Ok(reports)
+}
+
+/// The parts of `cargo metadata` the command needs.
+struct Workspace {
+    /// The names of the packages in the workspace.
+    members: Vec<String>,
+    target_directory: PathBuf,
+}
+
+impl Workspace {
+    /// Read the metadata of the cargo workspace in the current directory.
+    fn load() -> Result<Self, String> {
+        let output = Command::new("cargo")
+            .args(["metadata", "--format-version", "1", "--no-deps"])
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| format!("failed to run cargo metadata: {e}"))?;
+        if !output.status.success() {
+            return Err("cargo metadata failed".to_string());
+        }
+
+        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("invalid cargo metadata: {e}"))?;
+        let members = metadata["workspace_members"]
+            .as_array()
+            .ok_or("cargo metadata has no workspace members")?;
+        let packages = metadata["packages"]
+            .as_array()
+            .ok_or("cargo metadata has no packages")?;
+        let target_directory = metadata["target_directory"]
+            .as_str()
+            .ok_or("cargo metadata has no target directory")?;
+
+        Ok(Self {
+            members: packages
+                .iter()
+                .filter(|p| members.contains(&p["id"]))
+                .filter_map(|p| p["name"].as_str().map(str::to_string))
+                .collect(),
+            target_directory: PathBuf::from(target_directory),
+        })
+    }
+}
+
+/// Build the tests of the packages for Valida, so the test runners find them already built.
+fn build_for_valida(
+    workspace: &Workspace,
+    packages: &[String],
+    release: bool,
+) -> Result<(), String> {
+    let options = if release {
+        GuestBuildOptions::release()
+    } else {
+        GuestBuildOptions::debug()
+    };
+    let mut command = options.cargo_command("test");
+    command.arg("--no-run").arg("--target-dir").arg(
+        workspace
+            .target_directory
+            .join(crate::test_utils::VALIDA_TESTS_DIR),
+    );
+    for package in packages {
+        command.arg("--package").arg(package);
+    }
+
+    let status = command
+        .status()
+        .map_err(|e| format!("failed to run cargo: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err("failed to build tests for valida".to_string())
+    }
+}
+
+/// Run `cargo test` for one package, echoing its output.
+fn test_package(package: &str, options: &TestOptions) -> Result<PackageReport, String> {
+    let mut command = Command::new("cargo");
+    command.arg("test").arg("--package").arg(package);
+    if options.release {
+        command.arg("--release");
+    }
+    command
+        .arg("--")
+        .args(&options.test_args)
+        .env(
+            crate::test_utils::VALIDA_TEST_ENV,
+            if options.native_only { "0" } else { "1" },
+        )
+        .stdout(Stdio::piped());
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("failed to run cargo test: {e}"))?;
+
+    let mut report = PackageReport {
+        package: package.to_string(),
+        ..Default::default()
+    };
+    // unwrap is safe because we know the stdout is piped
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        let Ok(line) = line else { break };
+        println!("{line}");
+        report.record_line(&line);
+    }
+
+    report.success = child
+        .wait()
+        .map_err(|e| format!("failed to wait for cargo test: {e}"))?
+        .success();
+    Ok(report)
+}
+
+fn print_report(reports: &[PackageReport]) {
+    let mut native = Counts::default();
+    let mut valida = Counts::default();
+
+    println!("\ncargo valida test report:");
+    for report in reports {
+        native.add(report.native);
+        valida.add(report.valida);
+        println!(
+            "{:<8} {}: native {} passed, {} failed; valida {} passed, {} failed",
+            if report.success { "ok" } else { "FAILED" },
+            report.package,
+            report.native.passed,
+            report.native.failed,
+            report.valida.passed,
+            report.valida.failed,
+        );
+    }
+    println!(
+        "total: native {} passed, {} failed; valida {} passed, {} failed",
+        native.passed, native.failed, valida.passed, valida.failed
+    );
+}
+
+#[test]
+fn test_report_reads_runner_summary() {
+    let mut report = PackageReport::default();
+    report.record_line("on native:      3 passed; 1 failed; 0 skipped");
+    report.record_line("on valida:      2 passed; 1 failed (1 panicked); 0 flaky; 1 skipped");
+    report.record_line("on valida:      1 passed; 0 failed; 0 flaky; 0 skipped");
+    assert_eq!(
+        report.native,
+        Counts {
+            passed: 3,
+            failed: 1
+        }
+    );
+    assert_eq!(
+        report.valida,
+        Counts {
+            passed: 3,
+            failed: 1
+        }
+    );
+}
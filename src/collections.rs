@@ -0,0 +1,29 @@
+//! Hash maps and sets with a fast, deterministic hasher.
+//!
+//! `std`'s `RandomState` seeds SipHash from `getrandom`, which in the VM only ever yields the same
+//! fixed seed, so its maps are deterministic anyway but pay for a hash designed to resist
+//! flooding. These aliases use FxHash instead, which costs far fewer cycles and makes the
+//! determinism explicit. They are constructed with `default()` or `with_capacity_and_hasher`
+//! rather than `new()`:
+//! ```rust
+//! use valida_rs::collections::HashMap;
+//!
+//! let mut balances: HashMap<&str, u64> = HashMap::default();
+//! balances.insert("alice", 100);
+//! ```
+
+pub use rustc_hash::{FxBuildHasher as BuildHasher, FxHasher as Hasher};
+
+/// A [`std::collections::HashMap`] using [`Hasher`].
+pub type HashMap<K, V> = std::collections::HashMap<K, V, BuildHasher>;
+
+/// A [`std::collections::HashSet`] using [`Hasher`].
+pub type HashSet<T> = std::collections::HashSet<T, BuildHasher>;
+
+#[test]
+fn test_iteration_order_is_deterministic() {
+    let keys = || (0..100u32).map(|i| i.wrapping_mul(2_654_435_761));
+    let a: HashSet<u32> = keys().collect();
+    let b: HashSet<u32> = keys().collect();
+    assert!(a.iter().eq(b.iter()));
+}
@@ -0,0 +1,35 @@
+//! Cryptographic primitives that use the VM's precompiles.
+//!
+//! Requires the `crypto` feature. With the `precompiles` feature, guests call the VM's
+//! accelerated implementations, which cost far fewer cycles than hashing in Rust. Without it,
+//! and always on the host, the same functions use pure-Rust implementations, so guests can be
+//! tested natively and give the same results.
+
+#[cfg(feature = "aead")]
+pub mod aead;
+mod blake3;
+#[cfg(feature = "bls12_381")]
+pub mod bls12_381;
+#[cfg(feature = "bn254")]
+pub mod bn254;
+#[cfg(feature = "ed25519")]
+mod ed25519;
+mod keccak;
+pub mod merkle;
+mod poseidon2;
+#[cfg(feature = "secp256k1")]
+mod secp256k1;
+mod sha256;
+
+pub use self::blake3::{blake3_hash, Blake3};
+pub use digest;
+#[cfg(feature = "ed25519")]
+pub use ed25519::{ed25519_verify, ed25519_verify_batch};
+pub use keccak::keccak256;
+pub use poseidon2::{
+    poseidon2, poseidon2_compress, poseidon2_permute, Poseidon2Sponge, RATE as POSEIDON2_RATE,
+    WIDTH as POSEIDON2_WIDTH,
+};
+#[cfg(feature = "secp256k1")]
+pub use secp256k1::{ecrecover, eth_address, secp256k1_recover, secp256k1_verify};
+pub use sha256::{sha256, Sha256};
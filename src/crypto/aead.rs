@@ -0,0 +1,237 @@
+//! Authenticated encryption with AES-256-GCM and ChaCha20-Poly1305.
+//!
+//! Requires the `aead` feature. Ciphertexts are the encrypted message followed by the 16-byte
+//! authentication tag, as produced by the RustCrypto `aead` crates; decryption returns `None` if
+//! the tag does not authenticate the ciphertext and associated data.
+
+/// The length of the authentication tag appended to ciphertexts.
+pub const TAG_LEN: usize = 16;
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_aes256_gcm_encrypt(
+        key: *const u8,
+        nonce: *const u8,
+        aad: *const u8,
+        aad_len: usize,
+        input: *const u8,
+        input_len: usize,
+        out: *mut u8,
+    );
+    fn valida_aes256_gcm_decrypt(
+        key: *const u8,
+        nonce: *const u8,
+        aad: *const u8,
+        aad_len: usize,
+        input: *const u8,
+        input_len: usize,
+        out: *mut u8,
+    ) -> u32;
+    fn valida_chacha20_poly1305_encrypt(
+        key: *const u8,
+        nonce: *const u8,
+        aad: *const u8,
+        aad_len: usize,
+        input: *const u8,
+        input_len: usize,
+        out: *mut u8,
+    );
+    fn valida_chacha20_poly1305_decrypt(
+        key: *const u8,
+        nonce: *const u8,
+        aad: *const u8,
+        aad_len: usize,
+        input: *const u8,
+        input_len: usize,
+        out: *mut u8,
+    ) -> u32;
+}
+
+/// Encrypt `plaintext` with AES-256-GCM, authenticating it together with `aad`.
+pub fn aes256_gcm_encrypt(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Vec<u8> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = vec![0; plaintext.len() + TAG_LEN];
+        unsafe {
+            valida_aes256_gcm_encrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                plaintext.as_ptr(),
+                plaintext.len(),
+                out.as_mut_ptr(),
+            )
+        };
+        out
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    encrypt::<aes_gcm::Aes256Gcm>(key, nonce, aad, plaintext)
+}
+
+/// Decrypt an AES-256-GCM `ciphertext`, or return `None` if it or `aad` was tampered with.
+pub fn aes256_gcm_decrypt(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Option<Vec<u8>> {
+    let len = ciphertext.len().checked_sub(TAG_LEN)?;
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = vec![0; len];
+        let authentic = unsafe {
+            valida_aes256_gcm_decrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                ciphertext.as_ptr(),
+                ciphertext.len(),
+                out.as_mut_ptr(),
+            )
+        };
+        (authentic != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        let _ = len;
+        decrypt::<aes_gcm::Aes256Gcm>(key, nonce, aad, ciphertext)
+    }
+}
+
+/// Encrypt `plaintext` with ChaCha20-Poly1305, authenticating it together with `aad`.
+pub fn chacha20_poly1305_encrypt(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Vec<u8> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = vec![0; plaintext.len() + TAG_LEN];
+        unsafe {
+            valida_chacha20_poly1305_encrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                plaintext.as_ptr(),
+                plaintext.len(),
+                out.as_mut_ptr(),
+            )
+        };
+        out
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    encrypt::<chacha20poly1305::ChaCha20Poly1305>(key, nonce, aad, plaintext)
+}
+
+/// Decrypt a ChaCha20-Poly1305 `ciphertext`, or return `None` if it or `aad` was tampered with.
+pub fn chacha20_poly1305_decrypt(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Option<Vec<u8>> {
+    let len = ciphertext.len().checked_sub(TAG_LEN)?;
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = vec![0; len];
+        let authentic = unsafe {
+            valida_chacha20_poly1305_decrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                ciphertext.as_ptr(),
+                ciphertext.len(),
+                out.as_mut_ptr(),
+            )
+        };
+        (authentic != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        let _ = len;
+        decrypt::<chacha20poly1305::ChaCha20Poly1305>(key, nonce, aad, ciphertext)
+    }
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn encrypt<C>(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8>
+where
+    C: aes_gcm::aead::Aead
+        + aes_gcm::aead::KeyInit
+        + aes_gcm::AeadCore<NonceSize = aes_gcm::aead::consts::U12>,
+{
+    use aes_gcm::aead::Payload;
+
+    // unwraps are safe because both ciphers take 32-byte keys and 12-byte nonces, and
+    // encryption only fails for messages too long to be held in memory
+    C::new_from_slice(key)
+        .unwrap()
+        .encrypt(
+            nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .unwrap()
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decrypt<C>(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>>
+where
+    C: aes_gcm::aead::Aead
+        + aes_gcm::aead::KeyInit
+        + aes_gcm::AeadCore<NonceSize = aes_gcm::aead::consts::U12>,
+{
+    use aes_gcm::aead::Payload;
+
+    // unwrap is safe because both ciphers take 32-byte keys
+    C::new_from_slice(key)
+        .unwrap()
+        .decrypt(
+            nonce.into(),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .ok()
+}
+
+#[test]
+fn test_aead_round_trip() {
+    let key = [3; 32];
+    let nonce = [5; 12];
+    for (encrypt, decrypt) in [
+        (
+            aes256_gcm_encrypt as fn(&_, &_, &_, &_) -> _,
+            aes256_gcm_decrypt as fn(&_, &_, &_, &_) -> _,
+        ),
+        (chacha20_poly1305_encrypt, chacha20_poly1305_decrypt),
+    ] {
+        let ciphertext = encrypt(&key, &nonce, b"header", b"secret input");
+        assert_eq!(ciphertext.len(), 12 + TAG_LEN);
+        assert_eq!(
+            decrypt(&key, &nonce, b"header", &ciphertext).as_deref(),
+            Some(&b"secret input"[..])
+        );
+        assert_eq!(decrypt(&key, &nonce, b"other", &ciphertext), None);
+        assert_eq!(decrypt(&key, &nonce, b"header", &ciphertext[..8]), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! BLAKE3, following the structure of the reference implementation: the input is split into
+//! 1 KiB chunks whose chaining values are merged in a binary tree. Only the compression function
+//! is accelerated by the VM.
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_blake3_compress(
+        chaining_value: *const u32,
+        block_words: *const u32,
+        counter: u64,
+        block_len: u32,
+        flags: u32,
+        output: *mut u32,
+    );
+}
+
+const OUT_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+const CHUNK_START: u32 = 1 << 0;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+
+const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+    // Mix the columns.
+    g(state, 0, 4, 8, 12, m[0], m[1]);
+    g(state, 1, 5, 9, 13, m[2], m[3]);
+    g(state, 2, 6, 10, 14, m[4], m[5]);
+    g(state, 3, 7, 11, 15, m[6], m[7]);
+    // Mix the diagonals.
+    g(state, 0, 5, 10, 15, m[8], m[9]);
+    g(state, 1, 6, 11, 12, m[10], m[11]);
+    g(state, 2, 7, 8, 13, m[12], m[13]);
+    g(state, 3, 4, 9, 14, m[14], m[15]);
+}
+
+/// The BLAKE3 compression function, returning the full 16 word state.
+fn compress(
+    chaining_value: &[u32; 8],
+    block_words: &[u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+) -> [u32; 16] {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut output = [0; 16];
+        unsafe {
+            valida_blake3_compress(
+                chaining_value.as_ptr(),
+                block_words.as_ptr(),
+                counter,
+                block_len,
+                flags,
+                output.as_mut_ptr(),
+            );
+        }
+        output
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        #[rustfmt::skip]
+        let mut state = [
+            chaining_value[0], chaining_value[1], chaining_value[2], chaining_value[3],
+            chaining_value[4], chaining_value[5], chaining_value[6], chaining_value[7],
+            IV[0], IV[1], IV[2], IV[3],
+            counter as u32, (counter >> 32) as u32, block_len, flags,
+        ];
+        let mut block = *block_words;
+
+        for i in 0..7 {
+            round(&mut state, &block);
+            if i < 6 {
+                block = MSG_PERMUTATION.map(|j| block[j]);
+            }
+        }
+        for i in 0..8 {
+            state[i] ^= state[i + 8];
+            state[i + 8] ^= chaining_value[i];
+        }
+        state
+    }
+}
+
+fn first_8_words(compression_output: [u32; 16]) -> [u32; 8] {
+    compression_output[..8].try_into().unwrap()
+}
+
+fn words_from_le_bytes(bytes: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, chunk) in words.iter_mut().zip(bytes.as_chunks::<4>().0) {
+        *word = u32::from_le_bytes(*chunk);
+    }
+    words
+}
+
+/// The inputs of a compression that may produce the root hash.
+struct Output {
+    input_chaining_value: [u32; 8],
+    block_words: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        first_8_words(compress(
+            &self.input_chaining_value,
+            &self.block_words,
+            self.counter,
+            self.block_len,
+            self.flags,
+        ))
+    }
+
+    fn root_hash(&self) -> [u8; OUT_LEN] {
+        let words = compress(
+            &self.input_chaining_value,
+            &self.block_words,
+            0,
+            self.block_len,
+            self.flags | ROOT,
+        );
+        let mut hash = [0; OUT_LEN];
+        for (chunk, word) in hash.as_chunks_mut::<4>().0.iter_mut().zip(words) {
+            *chunk = word.to_le_bytes();
+        }
+        hash
+    }
+}
+
+#[derive(Clone)]
+struct ChunkState {
+    chaining_value: [u32; 8],
+    chunk_counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: u8,
+    blocks_compressed: u8,
+}
+
+impl ChunkState {
+    fn new(chunk_counter: u64) -> Self {
+        Self {
+            chaining_value: IV,
+            chunk_counter,
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            blocks_compressed: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        BLOCK_LEN * self.blocks_compressed as usize + self.block_len as usize
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks_compressed == 0 {
+            CHUNK_START
+        } else {
+            0
+        }
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // Only compress a full block once more input arrives, as the last block is special.
+            if self.block_len as usize == BLOCK_LEN {
+                self.chaining_value = first_8_words(compress(
+                    &self.chaining_value,
+                    &words_from_le_bytes(&self.block),
+                    self.chunk_counter,
+                    BLOCK_LEN as u32,
+                    self.start_flag(),
+                ));
+                self.blocks_compressed += 1;
+                self.block = [0; BLOCK_LEN];
+                self.block_len = 0;
+            }
+
+            let take = (BLOCK_LEN - self.block_len as usize).min(input.len());
+            self.block[self.block_len as usize..][..take].copy_from_slice(&input[..take]);
+            self.block_len += take as u8;
+            input = &input[take..];
+        }
+    }
+
+    fn output(&self) -> Output {
+        Output {
+            input_chaining_value: self.chaining_value,
+            block_words: words_from_le_bytes(&self.block),
+            counter: self.chunk_counter,
+            block_len: self.block_len as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+}
+
+fn parent_output(left_child_cv: [u32; 8], right_child_cv: [u32; 8]) -> Output {
+    let mut block_words = [0; 16];
+    block_words[..8].copy_from_slice(&left_child_cv);
+    block_words[8..].copy_from_slice(&right_child_cv);
+    Output {
+        input_chaining_value: IV,
+        block_words,
+        counter: 0,
+        block_len: BLOCK_LEN as u32,
+        flags: PARENT,
+    }
+}
+
+/// An incremental BLAKE3 hasher.
+///
+/// ```rust
+/// let mut hasher = valida_rs::crypto::Blake3::new();
+/// hasher.update(b"hello ");
+/// hasher.update(b"world");
+/// let hash: [u8; 32] = hasher.finalize();
+/// assert_eq!(hash, valida_rs::crypto::blake3_hash(b"hello world"));
+/// ```
+#[derive(Clone)]
+pub struct Blake3 {
+    chunk_state: ChunkState,
+    /// The chaining values of complete subtrees, at most one per level of the tree.
+    cv_stack: [[u32; 8]; 54],
+    cv_stack_len: u8,
+}
+
+impl Default for Blake3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blake3 {
+    pub fn new() -> Self {
+        Self {
+            chunk_state: ChunkState::new(0),
+            cv_stack: [[0; 8]; 54],
+            cv_stack_len: 0,
+        }
+    }
+
+    fn push_stack(&mut self, cv: [u32; 8]) {
+        self.cv_stack[self.cv_stack_len as usize] = cv;
+        self.cv_stack_len += 1;
+    }
+
+    fn pop_stack(&mut self) -> [u32; 8] {
+        self.cv_stack_len -= 1;
+        self.cv_stack[self.cv_stack_len as usize]
+    }
+
+    fn add_chunk_chaining_value(&mut self, mut new_cv: [u32; 8], mut total_chunks: u64) {
+        // Each trailing zero bit of the chunk count is a subtree completed by this chunk.
+        while total_chunks & 1 == 0 {
+            new_cv = parent_output(self.pop_stack(), new_cv).chaining_value();
+            total_chunks >>= 1;
+        }
+        self.push_stack(new_cv);
+    }
+
+    /// Add input to the hash.
+    pub fn update(&mut self, mut input: &[u8]) -> &mut Self {
+        while !input.is_empty() {
+            // Only finish a full chunk once more input arrives, as the last chunk is special.
+            if self.chunk_state.len() == CHUNK_LEN {
+                let chunk_cv = self.chunk_state.output().chaining_value();
+                let total_chunks = self.chunk_state.chunk_counter + 1;
+                self.add_chunk_chaining_value(chunk_cv, total_chunks);
+                self.chunk_state = ChunkState::new(total_chunks);
+            }
+
+            let take = (CHUNK_LEN - self.chunk_state.len()).min(input.len());
+            self.chunk_state.update(&input[..take]);
+            input = &input[take..];
+        }
+        self
+    }
+
+    /// The hash of the input so far.
+    pub fn finalize(&self) -> [u8; OUT_LEN] {
+        let mut output = self.chunk_state.output();
+        for i in (0..self.cv_stack_len as usize).rev() {
+            output = parent_output(self.cv_stack[i], output.chaining_value());
+        }
+        output.root_hash()
+    }
+}
+
+/// The BLAKE3 hash of `input`.
+pub fn blake3_hash(input: &[u8]) -> [u8; OUT_LEN] {
+    Blake3::new().update(input).finalize()
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_blake3_matches_reference() {
+    let input: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    for len in [0, 1, 64, 65, 1023, 1024, 1025, 2048, 3073, 8193, 10_000] {
+        let mut hasher = Blake3::new();
+        hasher
+            .update(&input[..len / 2])
+            .update(&input[len / 2..len]);
+        let expected = ::blake3::hash(&input[..len]);
+        assert_eq!(hasher.finalize(), *expected.as_bytes(), "length {len}");
+        assert_eq!(blake3_hash(&input[..len]), *expected.as_bytes());
+    }
+}
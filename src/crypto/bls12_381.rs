@@ -0,0 +1,164 @@
+//! The BLS12-381 curve, as used by BLS signatures and Zcash.
+//!
+//! Points use the uncompressed encoding of the `bls12_381` crate (and Zcash): 96 bytes for a G1
+//! point and 192 bytes for a G2 point, with flag bits marking the point at infinity. Scalars are
+//! 32-byte big-endian integers, reduced modulo the group order.
+
+/// An encoded G1 point.
+pub type G1 = [u8; 96];
+/// An encoded G2 point.
+pub type G2 = [u8; 192];
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_bls12_381_g1_add(a: *const u8, b: *const u8, out: *mut u8) -> u32;
+    fn valida_bls12_381_g1_mul(point: *const u8, scalar: *const u8, out: *mut u8) -> u32;
+    fn valida_bls12_381_g2_add(a: *const u8, b: *const u8, out: *mut u8) -> u32;
+    fn valida_bls12_381_g2_mul(point: *const u8, scalar: *const u8, out: *mut u8) -> u32;
+    fn valida_bls12_381_pairing_check(pairs: *const u8, count: usize) -> i32;
+}
+
+/// Add two G1 points, or return `None` if either is not a point of the G1 subgroup.
+pub fn g1_add(a: &G1, b: &G1) -> Option<G1> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 96];
+        let ok = unsafe { valida_bls12_381_g1_add(a.as_ptr(), b.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use bls12_381::{G1Affine, G1Projective};
+
+        let sum = G1Projective::from(decode_g1(a)?) + decode_g1(b)?;
+        Some(G1Affine::from(sum).to_uncompressed())
+    }
+}
+
+/// Multiply a G1 point by a scalar, or return `None` if the point is not in the G1 subgroup.
+pub fn g1_mul(point: &G1, scalar: &[u8; 32]) -> Option<G1> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 96];
+        let ok =
+            unsafe { valida_bls12_381_g1_mul(point.as_ptr(), scalar.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        let product = decode_g1(point)? * decode_scalar(scalar);
+        Some(bls12_381::G1Affine::from(product).to_uncompressed())
+    }
+}
+
+/// Add two G2 points, or return `None` if either is not a point of the G2 subgroup.
+pub fn g2_add(a: &G2, b: &G2) -> Option<G2> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 192];
+        let ok = unsafe { valida_bls12_381_g2_add(a.as_ptr(), b.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use bls12_381::{G2Affine, G2Projective};
+
+        let sum = G2Projective::from(decode_g2(a)?) + decode_g2(b)?;
+        Some(G2Affine::from(sum).to_uncompressed())
+    }
+}
+
+/// Multiply a G2 point by a scalar, or return `None` if the point is not in the G2 subgroup.
+pub fn g2_mul(point: &G2, scalar: &[u8; 32]) -> Option<G2> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 192];
+        let ok =
+            unsafe { valida_bls12_381_g2_mul(point.as_ptr(), scalar.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        let product = decode_g2(point)? * decode_scalar(scalar);
+        Some(bls12_381::G2Affine::from(product).to_uncompressed())
+    }
+}
+
+/// Check that the product of the pairings `e(g1, g2)` of `pairs` is one. Returns `None` if any
+/// point is invalid.
+///
+/// A BLS signature `sig` of the message hash `h` by the public key `pk` is valid if
+/// `pairing_check(&[(pk, h), (-g1, sig)])` holds.
+pub fn pairing_check(pairs: &[(G1, G2)]) -> Option<bool> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let input: Vec<u8> = pairs
+            .iter()
+            .flat_map(|(g1, g2)| g1.iter().chain(g2))
+            .copied()
+            .collect();
+        match unsafe { valida_bls12_381_pairing_check(input.as_ptr(), pairs.len()) } {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use bls12_381::{multi_miller_loop, G2Prepared, Gt};
+
+        let pairs = pairs
+            .iter()
+            .map(|(g1, g2)| Some((decode_g1(g1)?, G2Prepared::from(decode_g2(g2)?))))
+            .collect::<Option<Vec<_>>>()?;
+        let terms: Vec<_> = pairs.iter().map(|(g1, g2)| (g1, g2)).collect();
+        Some(multi_miller_loop(&terms).final_exponentiation() == Gt::identity())
+    }
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decode_scalar(bytes: &[u8; 32]) -> bls12_381::Scalar {
+    // `from_bytes_wide` takes a little-endian integer and reduces it.
+    let mut wide = [0; 64];
+    wide[..32].copy_from_slice(bytes);
+    wide[..32].reverse();
+    bls12_381::Scalar::from_bytes_wide(&wide)
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decode_g1(bytes: &G1) -> Option<bls12_381::G1Affine> {
+    bls12_381::G1Affine::from_uncompressed(bytes).into()
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decode_g2(bytes: &G2) -> Option<bls12_381::G2Affine> {
+    bls12_381::G2Affine::from_uncompressed(bytes).into()
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_bls12_381() {
+    use bls12_381::{G1Affine, G2Affine};
+
+    let g1 = G1Affine::generator().to_uncompressed();
+    let g2 = G2Affine::generator().to_uncompressed();
+    let neg_g1 = (-G1Affine::generator()).to_uncompressed();
+    let mut two = [0; 32];
+    two[31] = 2;
+
+    assert_eq!(g1_add(&g1, &g1), g1_mul(&g1, &two));
+    assert_eq!(g2_add(&g2, &g2), g2_mul(&g2, &two));
+    assert_eq!(g1_add(&g1, &[1; 96]), None);
+
+    // A BLS signature with secret key 2: pk = 2 * g1, sig = 2 * h.
+    let h = g2_mul(&g2, &[7; 32]).unwrap();
+    let pk = g1_mul(&g1, &two).unwrap();
+    let sig = g2_mul(&h, &two).unwrap();
+    assert_eq!(pairing_check(&[(pk, h), (neg_g1, sig)]), Some(true));
+    assert_eq!(pairing_check(&[(pk, h), (neg_g1, h)]), Some(false));
+}
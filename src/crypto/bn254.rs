@@ -0,0 +1,191 @@
+//! The BN254 (alt_bn128) curve, as used by Ethereum's precompiles and Groth16 verifiers.
+//!
+//! Points use Ethereum's encoding: a G1 point is `x || y` and a G2 point is
+//! `x.imaginary || x.real || y.imaginary || y.real`, each coordinate a 32-byte big-endian
+//! integer, with the point at infinity encoded as all zeros. Scalars are 32-byte big-endian
+//! integers, reduced modulo the group order.
+
+/// An encoded G1 point.
+pub type G1 = [u8; 64];
+/// An encoded G2 point.
+pub type G2 = [u8; 128];
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_bn254_g1_add(a: *const u8, b: *const u8, out: *mut u8) -> u32;
+    fn valida_bn254_g1_mul(point: *const u8, scalar: *const u8, out: *mut u8) -> u32;
+    fn valida_bn254_g2_add(a: *const u8, b: *const u8, out: *mut u8) -> u32;
+    fn valida_bn254_g2_mul(point: *const u8, scalar: *const u8, out: *mut u8) -> u32;
+    fn valida_bn254_pairing_check(pairs: *const u8, count: usize) -> i32;
+}
+
+/// Add two G1 points, or return `None` if either is not on the curve.
+pub fn g1_add(a: &G1, b: &G1) -> Option<G1> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 64];
+        let ok = unsafe { valida_bn254_g1_add(a.as_ptr(), b.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    Some(encode_g1(decode_g1(a)? + decode_g1(b)?))
+}
+
+/// Multiply a G1 point by a scalar, or return `None` if the point is not on the curve.
+pub fn g1_mul(point: &G1, scalar: &[u8; 32]) -> Option<G1> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 64];
+        let ok = unsafe { valida_bn254_g1_mul(point.as_ptr(), scalar.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    Some(encode_g1(decode_g1(point)? * decode_scalar(scalar)))
+}
+
+/// Add two G2 points, or return `None` if either is not a point of the G2 subgroup.
+pub fn g2_add(a: &G2, b: &G2) -> Option<G2> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 128];
+        let ok = unsafe { valida_bn254_g2_add(a.as_ptr(), b.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    Some(encode_g2(decode_g2(a)? + decode_g2(b)?))
+}
+
+/// Multiply a G2 point by a scalar, or return `None` if the point is not in the G2 subgroup.
+pub fn g2_mul(point: &G2, scalar: &[u8; 32]) -> Option<G2> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut out = [0; 128];
+        let ok = unsafe { valida_bn254_g2_mul(point.as_ptr(), scalar.as_ptr(), out.as_mut_ptr()) };
+        (ok != 0).then_some(out)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    Some(encode_g2(decode_g2(point)? * decode_scalar(scalar)))
+}
+
+/// Check that the product of the pairings `e(g1, g2)` of `pairs` is one, as Ethereum's `ecPairing`
+/// precompile does. Returns `None` if any point is invalid.
+pub fn pairing_check(pairs: &[(G1, G2)]) -> Option<bool> {
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let input: Vec<u8> = pairs
+            .iter()
+            .flat_map(|(g1, g2)| g1.iter().chain(g2))
+            .copied()
+            .collect();
+        match unsafe { valida_bn254_pairing_check(input.as_ptr(), pairs.len()) } {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        let pairs = pairs
+            .iter()
+            .map(|(g1, g2)| Some((decode_g1(g1)?, decode_g2(g2)?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(bn::pairing_batch(&pairs) == bn::Gt::one())
+    }
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decode_scalar(bytes: &[u8; 32]) -> bn::Fr {
+    // unwrap is safe because only the slice length is checked
+    bn::Fr::from_slice(bytes).unwrap()
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decode_fq(bytes: &[u8]) -> Option<bn::Fq> {
+    bn::Fq::from_slice(bytes).ok()
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decode_g1(bytes: &G1) -> Option<bn::G1> {
+    use bn::Group;
+
+    if bytes.iter().all(|&b| b == 0) {
+        return Some(bn::G1::zero());
+    }
+    let point = bn::AffineG1::new(decode_fq(&bytes[..32])?, decode_fq(&bytes[32..])?).ok()?;
+    Some(point.into())
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn decode_g2(bytes: &G2) -> Option<bn::G2> {
+    use bn::Group;
+
+    if bytes.iter().all(|&b| b == 0) {
+        return Some(bn::G2::zero());
+    }
+    let x = bn::Fq2::new(decode_fq(&bytes[32..64])?, decode_fq(&bytes[..32])?);
+    let y = bn::Fq2::new(decode_fq(&bytes[96..])?, decode_fq(&bytes[64..96])?);
+    Some(bn::AffineG2::new(x, y).ok()?.into())
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn encode_g1(point: bn::G1) -> G1 {
+    let mut out = [0; 64];
+    if let Some(point) = bn::AffineG1::from_jacobian(point) {
+        // unwraps are safe because the slices are 32 bytes
+        point.x().to_big_endian(&mut out[..32]).unwrap();
+        point.y().to_big_endian(&mut out[32..]).unwrap();
+    }
+    out
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn encode_g2(point: bn::G2) -> G2 {
+    let mut out = [0; 128];
+    if let Some(point) = bn::AffineG2::from_jacobian(point) {
+        let coordinates = [
+            point.x().imaginary(),
+            point.x().real(),
+            point.y().imaginary(),
+            point.y().real(),
+        ];
+        for (chunk, coordinate) in out.as_chunks_mut::<32>().0.iter_mut().zip(coordinates) {
+            // unwrap is safe because the chunk is 32 bytes
+            coordinate.to_big_endian(chunk).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_bn254() {
+    use bn::Group;
+
+    let g1 = encode_g1(bn::G1::one());
+    let g2 = encode_g2(bn::G2::one());
+    let mut two = [0; 32];
+    two[31] = 2;
+
+    assert_eq!(g1_add(&g1, &g1), g1_mul(&g1, &two));
+    assert_eq!(g2_add(&g2, &g2), g2_mul(&g2, &two));
+    assert_eq!(g1_add(&g1, &[1; 64]), None);
+
+    // e(2 * g1, g2) * e(-g1, 2 * g2) == 1
+    let double_g1 = g1_mul(&g1, &two).unwrap();
+    let double_g2 = g2_mul(&g2, &two).unwrap();
+    let neg_g1 = encode_g1(-bn::G1::one());
+    assert_eq!(
+        pairing_check(&[(double_g1, g2), (neg_g1, double_g2)]),
+        Some(true)
+    );
+    assert_eq!(
+        pairing_check(&[(double_g1, g2), (g1, double_g2)]),
+        Some(false)
+    );
+    assert_eq!(pairing_check(&[]), Some(true));
+}
@@ -0,0 +1,94 @@
+//! Ed25519 signatures, as used by Solana and SSH.
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_ed25519_verify(
+        msg: *const u8,
+        msg_len: usize,
+        signature: *const u8,
+        pubkey: *const u8,
+    ) -> u32;
+}
+
+/// Verify an Ed25519 signature of `msg`.
+///
+/// Verification is strict: non-canonical encodings and small-order public keys are rejected, as
+/// with `ed25519_dalek::VerifyingKey::verify_strict`.
+pub fn ed25519_verify(msg: &[u8], signature: &[u8; 64], pubkey: &[u8; 32]) -> bool {
+    #[cfg(all(valida, feature = "precompiles"))]
+    return unsafe {
+        valida_ed25519_verify(msg.as_ptr(), msg.len(), signature.as_ptr(), pubkey.as_ptr()) != 0
+    };
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use ed25519_dalek::{Signature, VerifyingKey};
+
+        let Ok(key) = VerifyingKey::from_bytes(pubkey) else {
+            return false;
+        };
+        key.verify_strict(msg, &Signature::from_bytes(signature))
+            .is_ok()
+    }
+}
+
+/// Verify many Ed25519 signatures at once, returning `true` only if all of them are valid.
+///
+/// `messages`, `signatures` and `pubkeys` are matched up by index, and must have the same length.
+/// Without the VM's precompile this uses batch verification, which is faster than verifying each
+/// signature but, unlike [`ed25519_verify`], does not reject small-order public keys.
+pub fn ed25519_verify_batch(
+    messages: &[&[u8]],
+    signatures: &[[u8; 64]],
+    pubkeys: &[[u8; 32]],
+) -> bool {
+    if messages.len() != signatures.len() || messages.len() != pubkeys.len() {
+        return false;
+    }
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    return messages
+        .iter()
+        .zip(signatures)
+        .zip(pubkeys)
+        .all(|((msg, signature), pubkey)| ed25519_verify(msg, signature, pubkey));
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use ed25519_dalek::{Signature, VerifyingKey};
+
+        let Ok(keys) = pubkeys
+            .iter()
+            .map(VerifyingKey::from_bytes)
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return false;
+        };
+        let signatures: Vec<Signature> = signatures.iter().map(Signature::from_bytes).collect();
+        ed25519_dalek::verify_batch(messages, &signatures, &keys).is_ok()
+    }
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_ed25519_verify() {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let keys: Vec<SigningKey> = (1..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+    let messages: [&[u8]; 3] = [b"one", b"two", b"three"];
+    let signatures: Vec<[u8; 64]> = keys
+        .iter()
+        .zip(messages)
+        .map(|(key, msg)| key.sign(msg).to_bytes())
+        .collect();
+    let pubkeys: Vec<[u8; 32]> = keys.iter().map(|k| k.verifying_key().to_bytes()).collect();
+
+    assert!(ed25519_verify(b"one", &signatures[0], &pubkeys[0]));
+    assert!(!ed25519_verify(b"two", &signatures[0], &pubkeys[0]));
+    assert!(ed25519_verify_batch(&messages, &signatures, &pubkeys));
+    assert!(!ed25519_verify_batch(
+        &[b"one", b"two", b"four"],
+        &signatures,
+        &pubkeys
+    ));
+}
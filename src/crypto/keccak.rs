@@ -0,0 +1,34 @@
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_keccak256(input: *const u8, len: usize, output: *mut u8);
+}
+
+/// The Keccak-256 hash of `input`, as used by Ethereum.
+pub fn keccak256(input: &[u8]) -> [u8; 32] {
+    let mut output = [0; 32];
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    unsafe {
+        valida_keccak256(input.as_ptr(), input.len(), output.as_mut_ptr());
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use tiny_keccak::Hasher;
+
+        let mut hasher = tiny_keccak::Keccak::v256();
+        hasher.update(input);
+        hasher.finalize(&mut output);
+    }
+
+    output
+}
+
+#[test]
+fn test_keccak256_known_answers() {
+    assert_eq!(
+        keccak256(b""),
+        *b"\xc5\xd2\x46\x01\x86\xf7\x23\x3c\x92\x7e\x7d\xb2\xdc\xc7\x03\xc0\
+           \xe5\x00\xb6\x53\xca\x82\x27\x3b\x7b\xfa\xd8\x04\x5d\x85\xa4\x70"
+    );
+}
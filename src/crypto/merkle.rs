@@ -0,0 +1,315 @@
+//! Binary Merkle trees over the accelerated hashes.
+//!
+//! Leaves and interior nodes are hashed differently (with [`MerkleHasher::hash_leaf`] and
+//! [`MerkleHasher::hash_pair`]), so a leaf cannot be passed off as a node. When a level has an odd
+//! number of nodes the last one is promoted to the next level unchanged, rather than paired with
+//! itself.
+//! ```rust
+//! use valida_rs::crypto::merkle::{verify_proof, MerkleProof, MerkleTree, Sha256};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let tree = MerkleTree::<Sha256>::new(&[b"a", b"b", b"c"]);
+//! # let mut input = tree.root().to_vec();
+//! # input.extend(tree.proof(1).unwrap().to_bytes());
+//! # valida_rs::io::testing::set_input(input);
+//! // The host wrote the root and a proof that "b" is the second leaf.
+//! let root: [u8; 32] = valida_rs::io::read_n(32)?.try_into().unwrap();
+//! let proof = MerkleProof::read()?;
+//! assert!(verify_proof::<Sha256>(&root, b"b", &proof));
+//! # valida_rs::io::testing::reset();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::marker::PhantomData;
+
+use crate::felt::Felt;
+
+/// A 32-byte Merkle tree node.
+pub type Digest = [u8; 32];
+
+/// A hash function that Merkle trees can be built with.
+pub trait MerkleHasher {
+    /// The hash of a leaf's data.
+    fn hash_leaf(data: &[u8]) -> Digest;
+    /// The hash of an interior node from its children.
+    fn hash_pair(left: &Digest, right: &Digest) -> Digest;
+}
+
+/// Byte-oriented hashes prefix leaves with 0 and nodes with 1, as in RFC 6962.
+macro_rules! prefixed_hasher {
+    ($(#[$doc:meta])* $name:ident, $hash:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl MerkleHasher for $name {
+            fn hash_leaf(data: &[u8]) -> Digest {
+                let mut input = Vec::with_capacity(1 + data.len());
+                input.push(0);
+                input.extend_from_slice(data);
+                $hash(&input)
+            }
+
+            fn hash_pair(left: &Digest, right: &Digest) -> Digest {
+                let mut input = [1; 65];
+                input[1..33].copy_from_slice(left);
+                input[33..].copy_from_slice(right);
+                $hash(&input)
+            }
+        }
+    };
+}
+
+prefixed_hasher!(
+    /// Merkle trees with [`keccak256`](super::keccak256).
+    Keccak256,
+    super::keccak256
+);
+prefixed_hasher!(
+    /// Merkle trees with [`sha256`](super::sha256).
+    Sha256,
+    super::sha256
+);
+prefixed_hasher!(
+    /// Merkle trees with [`blake3_hash`](super::blake3_hash).
+    Blake3,
+    super::blake3_hash
+);
+
+/// Merkle trees with Poseidon2: leaves are hashed with the sponge, three bytes per field element,
+/// and nodes with [`poseidon2_compress`](super::poseidon2_compress). A digest is its eight field
+/// elements as little-endian `u32`s.
+#[derive(Debug, Clone, Copy)]
+pub struct Poseidon2;
+
+impl Poseidon2 {
+    fn to_felts(digest: &Digest) -> [Felt; super::POSEIDON2_RATE] {
+        let words = digest.as_chunks::<4>().0;
+        core::array::from_fn(|i| Felt::new(u32::from_le_bytes(words[i])))
+    }
+
+    fn from_felts(felts: impl IntoIterator<Item = Felt>) -> Digest {
+        let mut digest = [0; 32];
+        for (chunk, felt) in digest.as_chunks_mut::<4>().0.iter_mut().zip(felts) {
+            *chunk = felt.as_canonical_u32().to_le_bytes();
+        }
+        digest
+    }
+}
+
+impl MerkleHasher for Poseidon2 {
+    fn hash_leaf(data: &[u8]) -> Digest {
+        let mut sponge = super::Poseidon2Sponge::new();
+        for chunk in data.chunks(3) {
+            let mut bytes = [0; 4];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            sponge.absorb(&[Felt::new(u32::from_le_bytes(bytes))]);
+        }
+        Self::from_felts((0..super::POSEIDON2_RATE).map(|_| sponge.squeeze()))
+    }
+
+    fn hash_pair(left: &Digest, right: &Digest) -> Digest {
+        Self::from_felts(super::poseidon2_compress(
+            Self::to_felts(left),
+            Self::to_felts(right),
+        ))
+    }
+}
+
+/// A proof that a leaf is in a Merkle tree.
+///
+/// Its compact serialization is the leaf index and the number of leaves as little-endian `u32`s,
+/// followed by the sibling digests from the leaf up; the number of siblings follows from the
+/// index and the number of leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub index: u32,
+    pub leaf_count: u32,
+    pub siblings: Vec<Digest>,
+}
+
+impl MerkleProof {
+    /// The number of siblings in a proof for leaf `index` of a tree of `leaf_count` leaves.
+    pub fn sibling_count(index: u32, leaf_count: u32) -> usize {
+        let (mut index, mut count, mut siblings) = (index, leaf_count, 0);
+        while count > 1 {
+            if index ^ 1 < count {
+                siblings += 1;
+            }
+            index >>= 1;
+            count = count.div_ceil(2);
+        }
+        siblings
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + 32 * self.siblings.len());
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.extend_from_slice(&self.leaf_count.to_le_bytes());
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        bytes
+    }
+
+    /// Parse a proof in the compact serialization, or return `None` if it is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (header, siblings) = bytes.split_at_checked(8)?;
+        let index = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let leaf_count = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let (siblings, rest) = siblings.as_chunks::<32>();
+        if !rest.is_empty() || siblings.len() != Self::sibling_count(index, leaf_count) {
+            return None;
+        }
+        Some(Self {
+            index,
+            leaf_count,
+            siblings: siblings.to_vec(),
+        })
+    }
+
+    /// Read a proof in the compact serialization from the input tape.
+    #[cfg(feature = "guest")]
+    pub fn read() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut bytes = crate::io::read_n(8)?;
+        let index = u32::from_le_bytes(bytes[..4].try_into()?);
+        let leaf_count = u32::from_le_bytes(bytes[4..].try_into()?);
+        bytes.extend(crate::io::read_n(
+            32 * Self::sibling_count(index, leaf_count),
+        )?);
+        Ok(Self::from_bytes(&bytes).ok_or("malformed Merkle proof")?)
+    }
+}
+
+/// The root of the Merkle tree with the given leaves; all zeros for an empty tree.
+pub fn compute_root<H: MerkleHasher>(leaves: &[impl AsRef<[u8]>]) -> Digest {
+    let mut level: Vec<Digest> = leaves.iter().map(|l| H::hash_leaf(l.as_ref())).collect();
+    while level.len() > 1 {
+        level = next_level::<H>(&level);
+    }
+    level.first().copied().unwrap_or_default()
+}
+
+/// Check that `proof` shows `leaf` is in the tree with root `root`.
+pub fn verify_proof<H: MerkleHasher>(root: &Digest, leaf: &[u8], proof: &MerkleProof) -> bool {
+    if proof.index >= proof.leaf_count {
+        return false;
+    }
+
+    let mut node = H::hash_leaf(leaf);
+    let mut siblings = proof.siblings.iter();
+    let (mut index, mut count) = (proof.index, proof.leaf_count);
+    while count > 1 {
+        if index ^ 1 < count {
+            let Some(sibling) = siblings.next() else {
+                return false;
+            };
+            node = if index & 1 == 0 {
+                H::hash_pair(&node, sibling)
+            } else {
+                H::hash_pair(sibling, &node)
+            };
+        }
+        index >>= 1;
+        count = count.div_ceil(2);
+    }
+    siblings.next().is_none() && node == *root
+}
+
+fn next_level<H: MerkleHasher>(level: &[Digest]) -> Vec<Digest> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => H::hash_pair(left, right),
+            [promoted] => *promoted,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// A Merkle tree that keeps every level, to produce proofs.
+#[derive(Debug, Clone)]
+pub struct MerkleTree<H> {
+    /// The levels from the leaf hashes up to the root.
+    levels: Vec<Vec<Digest>>,
+    hasher: PhantomData<H>,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    pub fn new(leaves: &[impl AsRef<[u8]>]) -> Self {
+        let mut levels = vec![leaves
+            .iter()
+            .map(|l| H::hash_leaf(l.as_ref()))
+            .collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            levels.push(next_level::<H>(levels.last().unwrap()));
+        }
+        Self {
+            levels,
+            hasher: PhantomData,
+        }
+    }
+
+    /// The number of leaves.
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The root of the tree; all zeros if it is empty.
+    pub fn root(&self) -> Digest {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// A proof that the leaf at `index` is in the tree, or `None` if there is no such leaf.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut i = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(i ^ 1) {
+                siblings.push(*sibling);
+            }
+            i >>= 1;
+        }
+        Some(MerkleProof {
+            index: index as u32,
+            leaf_count: self.len() as u32,
+            siblings,
+        })
+    }
+}
+
+#[test]
+fn test_merkle_proofs() {
+    fn check<H: MerkleHasher>() {
+        for count in 1..=7 {
+            let leaves: Vec<Vec<u8>> = (0..count).map(|i| vec![i; i as usize + 1]).collect();
+            let tree = MerkleTree::<H>::new(&leaves);
+            assert_eq!(tree.root(), compute_root::<H>(&leaves));
+
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(i).unwrap();
+                assert!(verify_proof::<H>(&tree.root(), leaf, &proof));
+                assert!(!verify_proof::<H>(&tree.root(), b"other", &proof));
+                assert_eq!(MerkleProof::from_bytes(&proof.to_bytes()), Some(proof));
+            }
+        }
+    }
+
+    check::<Keccak256>();
+    check::<Sha256>();
+    check::<Blake3>();
+    check::<Poseidon2>();
+    assert_eq!(compute_root::<Sha256>(&[] as &[&[u8]]), [0; 32]);
+}
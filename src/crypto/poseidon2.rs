@@ -0,0 +1,369 @@
+//! Poseidon2 over [`Felt`], with a width of 16, an x^7 S-box, 8 full rounds and 13 partial
+//! rounds: the instance Plonky3's `p3-baby-bear` builds with `default_babybear_poseidon2_16`. Its
+//! round constants are those of the Horizen Labs reference implementation
+//! (`poseidon2_instance_babybear.rs`), and its linear layers are Plonky3's 4x4 MDS matrix and
+//! internal diagonal. The tests check the permutation against Plonky3's known-answer vector.
+
+use crate::felt::Felt;
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_poseidon2_permute(state: *mut u32);
+}
+
+/// The number of field elements in the permutation's state.
+pub const WIDTH: usize = 16;
+/// The number of elements absorbed or squeezed per permutation by the sponge.
+pub const RATE: usize = 8;
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+const FULL_ROUNDS: usize = 8;
+#[cfg(not(all(valida, feature = "precompiles")))]
+const PARTIAL_ROUNDS: usize = 13;
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+struct Constants {
+    initial: [[Felt; WIDTH]; FULL_ROUNDS / 2],
+    terminal: [[Felt; WIDTH]; FULL_ROUNDS / 2],
+    internal: [Felt; PARTIAL_ROUNDS],
+}
+
+/// The Horizen Labs round constants, as Plonky3 lists them in `BABYBEAR_RC16_EXTERNAL_INITIAL`,
+/// `BABYBEAR_RC16_EXTERNAL_FINAL` and `BABYBEAR_RC16_INTERNAL`.
+#[cfg(not(all(valida, feature = "precompiles")))]
+const CONSTANTS: Constants = Constants {
+    initial: rounds([
+        [
+            0x69cbb6af, 0x46ad93f9, 0x60a00f4e, 0x6b1297cd, 0x23189afe, 0x732e7bef, 0x72c246de,
+            0x2c941900, 0x0557eede, 0x1580496f, 0x3a3ea77b, 0x54f3f271, 0x0f49b029, 0x47872fe1,
+            0x221e2e36, 0x1ab7202e,
+        ],
+        [
+            0x487779a6, 0x3851c9d8, 0x38dc17c0, 0x209f8849, 0x268dcee8, 0x350c48da, 0x5b9ad32e,
+            0x0523272b, 0x3f89055b, 0x01e894b2, 0x13ddedde, 0x1b2ef334, 0x7507d8b4, 0x6ceeb94e,
+            0x52eb6ba2, 0x50642905,
+        ],
+        [
+            0x05453f3f, 0x06349efc, 0x6922787c, 0x04bfff9c, 0x768c714a, 0x3e9ff21a, 0x15737c9c,
+            0x2229c807, 0x0d47f88c, 0x097e0ecc, 0x27eadba0, 0x2d7d29e4, 0x3502aaa0, 0x0f475fd7,
+            0x29fbda49, 0x018afffd,
+        ],
+        [
+            0x0315b618, 0x6d4497d1, 0x1b171d9e, 0x52861abd, 0x2e5d0501, 0x3ec8646c, 0x6e5f250a,
+            0x148ae8e6, 0x17f5fa4a, 0x3e66d284, 0x0051aa3b, 0x483f7913, 0x2cfe5f15, 0x023427ca,
+            0x2cc78315, 0x1e36ea47,
+        ],
+    ]),
+    terminal: rounds([
+        [
+            0x7290a80d, 0x6f7e5329, 0x598ec8a8, 0x76a859a0, 0x6559e868, 0x657b83af, 0x13271d3f,
+            0x1f876063, 0x0aeeae37, 0x706e9ca6, 0x46400cee, 0x72a05c26, 0x2c589c9e, 0x20bd37a7,
+            0x6a2d3d10, 0x20523767,
+        ],
+        [
+            0x5b8fe9c4, 0x2aa501d6, 0x1e01ac3e, 0x1448bc54, 0x5ce5ad1c, 0x4918a14d, 0x2c46a83f,
+            0x4fcf6876, 0x61d8d5c8, 0x6ddf4ff9, 0x11fda4d3, 0x02933a8f, 0x170eaf81, 0x5a9c314f,
+            0x49a12590, 0x35ec52a1,
+        ],
+        [
+            0x58eb1611, 0x5e481e65, 0x367125c9, 0x0eba33ba, 0x1fc28ded, 0x066399ad, 0x0cbec0ea,
+            0x75fd1af0, 0x50f5bf4e, 0x643d5f41, 0x6f4fe718, 0x5b3cbbde, 0x1e3afb3e, 0x296fb027,
+            0x45e1547b, 0x4a8db2ab,
+        ],
+        [
+            0x59986d19, 0x30bcdfa3, 0x1db63932, 0x1d7c2824, 0x53b33681, 0x0673b747, 0x038a98a3,
+            0x2c5bce60, 0x351979cd, 0x5008fb73, 0x547bca78, 0x711af481, 0x3f93bf64, 0x644d987b,
+            0x3c8bcd87, 0x608758b8,
+        ],
+    ]),
+    internal: felts([
+        0x5a8053c0, 0x693be639, 0x3858867d, 0x19334f6b, 0x128f0fd8, 0x4e2b1ccb, 0x61210ce0,
+        0x3c318939, 0x0b5b2f22, 0x2edb11d5, 0x213effdf, 0x0cac4606, 0x241af16d,
+    ]),
+};
+
+/// The diagonal `V` of the internal layer, which multiplies the state by `1 + Diag(V)`:
+/// `[-2, 1, 2, 1/2, 3, 4, -1/2, -3, -4, 1/2^8, 1/4, 1/8, 1/2^27, -1/2^8, -1/16, -1/2^27]`, as in
+/// Plonky3's `BabyBearInternalLayerParameters`.
+#[cfg(not(all(valida, feature = "precompiles")))]
+const INTERNAL_DIAGONAL: [Felt; WIDTH] = felts([
+    0x77ffffff, 0x00000001, 0x00000002, 0x3c000001, 0x00000003, 0x00000004, 0x3c000000, 0x77fffffe,
+    0x77fffffd, 0x77880001, 0x5a000001, 0x69000001, 0x77fffff2, 0x00780000, 0x07800000, 0x0000000f,
+]);
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+const fn felts<const N: usize>(values: [u32; N]) -> [Felt; N] {
+    let mut felts = [Felt::ZERO; N];
+    let mut i = 0;
+    while i < N {
+        felts[i] = Felt::new(values[i]);
+        i += 1;
+    }
+    felts
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+const fn rounds<const N: usize>(values: [[u32; WIDTH]; N]) -> [[Felt; WIDTH]; N] {
+    let mut rounds = [[Felt::ZERO; WIDTH]; N];
+    let mut i = 0;
+    while i < N {
+        rounds[i] = felts(values[i]);
+        i += 1;
+    }
+    rounds
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn sbox(x: Felt) -> Felt {
+    let x2 = x * x;
+    let x3 = x2 * x;
+    x3 * x3 * x
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+/// Multiply each group of four elements by Plonky3's `MDSMat4`, `[[2, 3, 1, 1], [1, 2, 3, 1],
+/// [1, 1, 2, 3], [3, 1, 1, 2]]`, then add the column sums, giving the external layer
+/// `circ(2 M4, M4, M4, M4)`.
+fn external_linear_layer(state: &mut [Felt; WIDTH]) {
+    for chunk in state.as_chunks_mut::<4>().0 {
+        let [a, b, c, d] = *chunk;
+        let t01 = a + b;
+        let t23 = c + d;
+        let t0123 = t01 + t23;
+        let t01123 = t0123 + b;
+        let t01233 = t0123 + d;
+        *chunk = [t01123 + t01, t01123 + c + c, t01233 + t23, t01233 + a + a];
+    }
+
+    let mut sums = [Felt::ZERO; 4];
+    for chunk in state.as_chunks::<4>().0 {
+        for (sum, x) in sums.iter_mut().zip(chunk) {
+            *sum += *x;
+        }
+    }
+    for (i, x) in state.iter_mut().enumerate() {
+        *x += sums[i % 4];
+    }
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn internal_linear_layer(state: &mut [Felt; WIDTH]) {
+    let sum = state.iter().fold(Felt::ZERO, |acc, x| acc + *x);
+    for (x, d) in state.iter_mut().zip(&INTERNAL_DIAGONAL) {
+        *x = sum + *d * *x;
+    }
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn permute_with(state: &mut [Felt; WIDTH], constants: &Constants) {
+    let full_round = |state: &mut [Felt; WIDTH], round_constants: &[Felt; WIDTH]| {
+        for (x, c) in state.iter_mut().zip(round_constants) {
+            *x = sbox(*x + *c);
+        }
+        external_linear_layer(state);
+    };
+
+    external_linear_layer(state);
+    for round_constants in &constants.initial {
+        full_round(state, round_constants);
+    }
+    for c in &constants.internal {
+        state[0] = sbox(state[0] + *c);
+        internal_linear_layer(state);
+    }
+    for round_constants in &constants.terminal {
+        full_round(state, round_constants);
+    }
+}
+
+/// Apply the Poseidon2 permutation to `state`.
+pub fn poseidon2_permute(state: &mut [Felt; WIDTH]) {
+    #[cfg(all(valida, feature = "precompiles"))]
+    unsafe {
+        // Felt is a canonical u32 internally, and the precompile keeps values canonical.
+        valida_poseidon2_permute(state.as_mut_ptr().cast());
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    permute_with(state, &CONSTANTS);
+}
+
+/// A Poseidon2 sponge that absorbs and squeezes field elements.
+///
+/// Absorbing is padded with a single one followed by zeros when switching to squeezing, so inputs
+/// of different lengths hash differently.
+#[derive(Debug, Clone, Default)]
+pub struct Poseidon2Sponge {
+    state: [Felt; WIDTH],
+    /// The number of elements absorbed into, or squeezed from, the rate since the last
+    /// permutation.
+    position: usize,
+    squeezing: bool,
+}
+
+impl Poseidon2Sponge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Absorb `input` into the sponge.
+    ///
+    /// # Panics
+    /// If called after [`Poseidon2Sponge::squeeze`].
+    pub fn absorb(&mut self, input: &[Felt]) {
+        assert!(!self.squeezing, "cannot absorb after squeezing");
+        for x in input {
+            if self.position == RATE {
+                poseidon2_permute(&mut self.state);
+                self.position = 0;
+            }
+            self.state[self.position] += *x;
+            self.position += 1;
+        }
+    }
+
+    /// Squeeze the next element out of the sponge.
+    pub fn squeeze(&mut self) -> Felt {
+        if !self.squeezing {
+            if self.position == RATE {
+                poseidon2_permute(&mut self.state);
+                self.position = 0;
+            }
+            self.state[self.position] += Felt::ONE;
+            poseidon2_permute(&mut self.state);
+            self.squeezing = true;
+            self.position = 0;
+        } else if self.position == RATE {
+            poseidon2_permute(&mut self.state);
+            self.position = 0;
+        }
+        self.position += 1;
+        self.state[self.position - 1]
+    }
+}
+
+/// The Poseidon2 hash of a variable-length input.
+pub fn poseidon2(input: &[Felt]) -> Felt {
+    let mut sponge = Poseidon2Sponge::new();
+    sponge.absorb(input);
+    sponge.squeeze()
+}
+
+/// Compress two digests into one, as in a Merkle tree: the first [`RATE`] elements of the
+/// permutation of their concatenation, added to the inputs (feed-forward).
+pub fn poseidon2_compress(left: [Felt; RATE], right: [Felt; RATE]) -> [Felt; RATE] {
+    let mut state = [Felt::ZERO; WIDTH];
+    state[..RATE].copy_from_slice(&left);
+    state[RATE..].copy_from_slice(&right);
+    poseidon2_permute(&mut state);
+    core::array::from_fn(|i| state[i] + left[i])
+}
+
+#[test]
+fn test_poseidon2_separates_inputs() {
+    let felts = |values: &[u32]| values.iter().map(|&v| Felt::new(v)).collect::<Vec<_>>();
+
+    let hash = poseidon2(&felts(&[1, 2, 3]));
+    assert_eq!(hash, poseidon2(&felts(&[1, 2, 3])));
+    assert_ne!(hash, poseidon2(&felts(&[1, 2, 3, 0])));
+    assert_ne!(poseidon2(&[]), poseidon2(&felts(&[0])));
+
+    // Absorbing in pieces and across several permutations gives the same hash.
+    let long = felts(&(0..20).collect::<Vec<_>>());
+    let mut sponge = Poseidon2Sponge::new();
+    sponge.absorb(&long[..7]);
+    sponge.absorb(&long[7..]);
+    assert_eq!(sponge.squeeze(), poseidon2(&long));
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+#[test]
+fn test_poseidon2_known_answers() {
+    let felts = |values: [u32; WIDTH]| values.map(Felt::new);
+
+    // Plonky3's `test_poseidon2_width_16_random`, whose round constants are drawn from
+    // `Xoroshiro128Plus::seed_from_u64(1)` as `Poseidon2BabyBear::new_from_rng_128` draws them:
+    // the top 31 bits of each output below the modulus, read in Montgomery form.
+    let mut splitmix = 1u64;
+    let mut next_splitmix = || {
+        splitmix = splitmix.wrapping_add(0x9e3779b97f4a7c15);
+        let z = (splitmix ^ (splitmix >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    let mut xoroshiro = [next_splitmix(), next_splitmix()];
+    let montgomery = Felt::from(1u64 << 32).inverse().unwrap();
+    let mut sample = || loop {
+        let [s0, s1] = &mut xoroshiro;
+        let output = s0.wrapping_add(*s1);
+        *s1 ^= *s0;
+        *s0 = s0.rotate_left(24) ^ *s1 ^ (*s1 << 16);
+        *s1 = s1.rotate_left(37);
+        let value = (output >> 33) as u32;
+        if value < crate::felt::MODULUS {
+            return Felt::new(value) * montgomery;
+        }
+    };
+    let constants = Constants {
+        initial: core::array::from_fn(|_| core::array::from_fn(|_| sample())),
+        terminal: core::array::from_fn(|_| core::array::from_fn(|_| sample())),
+        internal: core::array::from_fn(|_| sample()),
+    };
+    let mut state = felts([
+        894848333, 1437655012, 1200606629, 1690012884, 71131202, 1749206695, 1717947831, 120589055,
+        19776022, 42382981, 1831865506, 724844064, 171220207, 1299207443, 227047920, 1783754913,
+    ]);
+    permute_with(&mut state, &constants);
+    let expected = felts([
+        1255099308, 941729227, 93609187, 112406640, 492658670, 1824768948, 812517469, 1055381989,
+        670973674, 1407235524, 891397172, 1003245378, 1381303998, 1564172645, 1399931635,
+        1005462965,
+    ]);
+    assert_eq!(state, expected);
+
+    // Plonky3 publishes no vector for the Horizen Labs constants; this pins the permutation with
+    // them so it cannot drift.
+    let mut state = core::array::from_fn(|i| Felt::new(i as u32));
+    poseidon2_permute(&mut state);
+    let expected = felts([
+        1906786279, 1737026427, 1959749225, 700325316, 1638050605, 1021608788, 1726691001,
+        1761127344, 1552405120, 417318995, 36799261, 1215172152, 614923223, 1300746575, 957311597,
+        304856115,
+    ]);
+    assert_eq!(state, expected);
+}
+
+#[cfg(not(all(valida, feature = "precompiles")))]
+#[test]
+fn test_internal_diagonal() {
+    let small = |v: i32| Felt::new(v.unsigned_abs()) * if v < 0 { -Felt::ONE } else { Felt::ONE };
+    let inverse = |v: i32| small(v).inverse().unwrap();
+    let expected = [
+        small(-2),
+        small(1),
+        small(2),
+        inverse(2),
+        small(3),
+        small(4),
+        -inverse(2),
+        small(-3),
+        small(-4),
+        inverse(1 << 8),
+        inverse(4),
+        inverse(8),
+        inverse(1 << 27),
+        -inverse(1 << 8),
+        -inverse(16),
+        -inverse(1 << 27),
+    ];
+    assert_eq!(INTERNAL_DIAGONAL, expected);
+
+    // The entries are distinct and nonzero, and by the matrix determinant lemma `1 + Diag(V)` has
+    // determinant `prod(V) * (1 + sum(1 / V))`, which must not vanish for the layer to invert.
+    let distinct: std::collections::BTreeSet<_> = INTERNAL_DIAGONAL.iter().collect();
+    assert_eq!(distinct.len(), WIDTH);
+    assert!(!distinct.contains(&Felt::ZERO));
+    let product: Felt = INTERNAL_DIAGONAL.iter().copied().product();
+    let inverses: Felt = INTERNAL_DIAGONAL.iter().map(|v| v.inverse().unwrap()).sum();
+    assert_ne!(product * (Felt::ONE + inverses), Felt::ZERO);
+}
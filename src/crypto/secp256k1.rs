@@ -0,0 +1,142 @@
+//! secp256k1 ECDSA, as used by Bitcoin and Ethereum.
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_secp256k1_verify(
+        msg_hash: *const u8,
+        signature: *const u8,
+        pubkey: *const u8,
+        pubkey_len: usize,
+    ) -> u32;
+    fn valida_secp256k1_recover(
+        msg_hash: *const u8,
+        signature: *const u8,
+        recovery_id: u8,
+        pubkey: *mut u8,
+    ) -> u32;
+}
+
+/// Verify an ECDSA signature `r || s` of a 32-byte message hash, with a SEC1-encoded public key
+/// (33 bytes compressed or 65 bytes uncompressed).
+///
+/// Like `k256`, signatures with a high `s` are rejected.
+pub fn secp256k1_verify(msg_hash: &[u8; 32], signature: &[u8; 64], pubkey: &[u8]) -> bool {
+    #[cfg(all(valida, feature = "precompiles"))]
+    return unsafe {
+        valida_secp256k1_verify(
+            msg_hash.as_ptr(),
+            signature.as_ptr(),
+            pubkey.as_ptr(),
+            pubkey.len(),
+        ) != 0
+    };
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use k256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey};
+
+        let (Ok(key), Ok(signature)) = (
+            VerifyingKey::from_sec1_bytes(pubkey),
+            Signature::from_slice(signature),
+        ) else {
+            return false;
+        };
+        key.verify_prehash(msg_hash, &signature).is_ok()
+    }
+}
+
+/// Recover the uncompressed SEC1 public key that signed a 32-byte message hash.
+///
+/// `recovery_id` is the parity bit of the signature's `R` point, 0 or 1. Signatures with a high
+/// `s` are accepted, as by Ethereum's `ecrecover`.
+pub fn secp256k1_recover(
+    msg_hash: &[u8; 32],
+    signature: &[u8; 64],
+    recovery_id: u8,
+) -> Option<[u8; 65]> {
+    if recovery_id > 1 {
+        return None;
+    }
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    {
+        let mut pubkey = [0; 65];
+        let recovered = unsafe {
+            valida_secp256k1_recover(
+                msg_hash.as_ptr(),
+                signature.as_ptr(),
+                recovery_id,
+                pubkey.as_mut_ptr(),
+            )
+        };
+        (recovered != 0).then_some(pubkey)
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+        let mut signature = Signature::from_slice(signature).ok()?;
+        let mut recovery_id = RecoveryId::from_byte(recovery_id)?;
+        // Negating `s` gives the same signature for the other `R` parity.
+        if let Some(normalized) = signature.normalize_s() {
+            signature = normalized;
+            recovery_id = RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced());
+        }
+
+        let key = VerifyingKey::recover_from_prehash(msg_hash, &signature, recovery_id).ok()?;
+        key.to_encoded_point(false).as_bytes().try_into().ok()
+    }
+}
+
+/// Ethereum's `ecrecover`: the address that signed a 32-byte message hash, given the signature
+/// as `r || s || v` with `v` either 0/1 or 27/28.
+pub fn ecrecover(msg_hash: &[u8; 32], signature: &[u8; 65]) -> Option<[u8; 20]> {
+    let v = match signature[64] {
+        v @ (0 | 1) => v,
+        v @ (27 | 28) => v - 27,
+        _ => return None,
+    };
+    // unwrap is safe because the slice is 64 bytes long
+    let pubkey = secp256k1_recover(msg_hash, signature[..64].try_into().unwrap(), v)?;
+    Some(eth_address(&pubkey))
+}
+
+/// The Ethereum address of an uncompressed SEC1 public key.
+pub fn eth_address(pubkey: &[u8; 65]) -> [u8; 20] {
+    let hash = super::keccak256(&pubkey[1..]);
+    // unwrap is safe because the slice is 20 bytes long
+    hash[12..].try_into().unwrap()
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_secp256k1_verify_and_recover() {
+    use k256::ecdsa::SigningKey;
+
+    let key = SigningKey::from_slice(&[7; 32]).unwrap();
+    let pubkey: [u8; 65] = key
+        .verifying_key()
+        .to_encoded_point(false)
+        .as_bytes()
+        .try_into()
+        .unwrap();
+    let msg_hash = super::keccak256(b"hello valida");
+    let (signature, recovery_id) = key.sign_prehash_recoverable(&msg_hash).unwrap();
+    let signature: [u8; 64] = signature.to_bytes().into();
+
+    assert!(secp256k1_verify(&msg_hash, &signature, &pubkey));
+    assert!(!secp256k1_verify(
+        &super::keccak256(b"other"),
+        &signature,
+        &pubkey
+    ));
+
+    let mut eth_signature = [0; 65];
+    eth_signature[..64].copy_from_slice(&signature);
+    eth_signature[64] = 27 + recovery_id.to_byte();
+    assert_eq!(
+        ecrecover(&msg_hash, &eth_signature),
+        Some(eth_address(&pubkey))
+    );
+}
@@ -0,0 +1,146 @@
+use digest::{
+    consts::U32, FixedOutput, FixedOutputReset, HashMarker, Output, OutputSizeUser, Reset, Update,
+};
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_sha256_compress(state: *mut u32, block: *const u8);
+}
+
+const BLOCK_SIZE: usize = 64;
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// A SHA-256 hasher implementing [`digest::Digest`].
+///
+/// A drop-in replacement for `sha2::Sha256`: the message is buffered and padded in Rust, and each
+/// block is compressed by the VM's accelerator with the `precompiles` feature.
+#[derive(Debug, Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: [u8; BLOCK_SIZE],
+    buffered: usize,
+    /// The number of bytes hashed so far.
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self {
+            state: INITIAL_STATE,
+            buffer: [0; BLOCK_SIZE],
+            buffered: 0,
+            len: 0,
+        }
+    }
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8; BLOCK_SIZE]) {
+    #[cfg(all(valida, feature = "precompiles"))]
+    unsafe {
+        valida_sha256_compress(state.as_mut_ptr(), block.as_ptr());
+    }
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    sha2::compress256(state, &[(*block).into()]);
+}
+
+impl Sha256 {
+    fn finish(&mut self) -> [u8; 32] {
+        let bit_len = self.len.wrapping_mul(8);
+
+        // Append the 1 bit, then pad with zeros so the length fits at the end of a block.
+        self.buffer[self.buffered] = 0x80;
+        self.buffer[self.buffered + 1..].fill(0);
+        if self.buffered + 1 > BLOCK_SIZE - 8 {
+            compress(&mut self.state, &self.buffer);
+            self.buffer.fill(0);
+        }
+        self.buffer[BLOCK_SIZE - 8..].copy_from_slice(&bit_len.to_be_bytes());
+        compress(&mut self.state, &self.buffer);
+
+        let mut output = [0; 32];
+        for (chunk, word) in output.as_chunks_mut::<4>().0.iter_mut().zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        output
+    }
+}
+
+impl HashMarker for Sha256 {}
+
+impl OutputSizeUser for Sha256 {
+    type OutputSize = U32;
+}
+
+impl Update for Sha256 {
+    fn update(&mut self, mut data: &[u8]) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+
+        if self.buffered > 0 {
+            let take = data.len().min(BLOCK_SIZE - self.buffered);
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < BLOCK_SIZE {
+                return;
+            }
+            compress(&mut self.state, &self.buffer);
+            self.buffered = 0;
+        }
+
+        let (blocks, rest) = data.as_chunks::<BLOCK_SIZE>();
+        for block in blocks {
+            compress(&mut self.state, block);
+        }
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+}
+
+impl FixedOutput for Sha256 {
+    fn finalize_into(mut self, out: &mut Output<Self>) {
+        out.copy_from_slice(&self.finish());
+    }
+}
+
+impl Reset for Sha256 {
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl FixedOutputReset for Sha256 {
+    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+        out.copy_from_slice(&self.finish());
+        self.reset();
+    }
+}
+
+/// The SHA-256 hash of `input`.
+pub fn sha256(input: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::default();
+    Update::update(&mut hasher, input);
+    hasher.finish()
+}
+
+#[test]
+fn test_sha256_matches_sha2() {
+    use digest::Digest;
+
+    let message: Vec<u8> = (0..200u8).collect();
+    for len in [0, 1, 55, 56, 63, 64, 65, 200] {
+        let mut hasher = Sha256::new();
+        // Split the input to exercise buffering across updates.
+        Digest::update(&mut hasher, &message[..len / 3]);
+        Digest::update(&mut hasher, &message[len / 3..len]);
+        assert_eq!(
+            hasher.finalize(),
+            sha2::Sha256::digest(&message[..len]),
+            "length {len}"
+        );
+    }
+    assert_eq!(sha256(b"abc")[..4], [0xba, 0x78, 0x16, 0xbf]);
+}
@@ -0,0 +1,442 @@
+//! Valida's native field: the BabyBear prime field of order `2^31 - 2^27 + 1`.
+//!
+//! A [`Felt`] is serialized as its canonical representative, a `u32`, so it can be read off and
+//! written to the tapes with the functions in `io`; deserializing rejects non-canonical values.
+//!
+//! [`batch_add`], [`batch_mul`] and [`dot_product`] operate on whole vectors. With the
+//! `precompiles` feature they use the VM's vectorized field arithmetic; otherwise, and always on
+//! the host, they loop over the elements. [`ntt`] and [`intt`] likewise use the VM's NTT
+//! precompile.
+//! ```rust
+//! use valida_rs::felt::{dot_product, Felt, MODULUS};
+//!
+//! let x = Felt::new(MODULUS - 1);
+//! assert_eq!(x + Felt::ONE, Felt::ZERO);
+//! assert_eq!(x * x.inverse().unwrap(), Felt::ONE);
+//!
+//! let a = [Felt::new(1), Felt::new(2)];
+//! let b = [Felt::new(3), Felt::new(4)];
+//! assert_eq!(dot_product(&a, &b), Felt::new(11));
+//! ```
+
+use core::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(all(valida, feature = "precompiles"))]
+extern "C" {
+    fn valida_felt_batch_add(a: *const u32, b: *const u32, out: *mut u32, len: usize);
+    fn valida_felt_batch_mul(a: *const u32, b: *const u32, out: *mut u32, len: usize);
+    fn valida_felt_dot_product(a: *const u32, b: *const u32, len: usize) -> u32;
+    fn valida_felt_ntt(values: *mut u32, log_len: u32);
+    fn valida_felt_intt(values: *mut u32, log_len: u32);
+}
+
+/// The order of the field.
+pub const MODULUS: u32 = 0x7800_0001;
+
+/// A generator of the field's multiplicative group.
+pub const GENERATOR: Felt = Felt(31);
+
+/// The largest `k` such that the field has a primitive `2^k`-th root of unity.
+pub const TWO_ADICITY: u32 = 27;
+
+/// An element of Valida's base field, stored in canonical form.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(transparent)]
+pub struct Felt(u32);
+
+impl Felt {
+    pub const ZERO: Felt = Felt(0);
+    pub const ONE: Felt = Felt(1);
+
+    /// The element congruent to `value`.
+    pub const fn new(value: u32) -> Self {
+        Self(value % MODULUS)
+    }
+
+    /// The element whose canonical representative is `value`, or `None` if `value` is not below
+    /// [`MODULUS`].
+    pub const fn from_canonical_u32(value: u32) -> Option<Self> {
+        if value < MODULUS {
+            Some(Self(value))
+        } else {
+            None
+        }
+    }
+
+    /// The canonical representative, in `0..MODULUS`.
+    pub const fn as_canonical_u32(self) -> u32 {
+        self.0
+    }
+
+    /// `self` raised to the power `exp`.
+    pub fn pow(self, mut exp: u64) -> Self {
+        let mut base = self;
+        let mut result = Felt::ONE;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result *= base;
+            }
+            base *= base;
+            exp >>= 1;
+        }
+        result
+    }
+
+    /// A primitive `2^log_n`-th root of unity.
+    ///
+    /// # Panics
+    /// If `log_n` exceeds [`TWO_ADICITY`].
+    pub fn two_adic_root(log_n: u32) -> Self {
+        assert!(log_n <= TWO_ADICITY, "no root of unity of order 2^{log_n}");
+        GENERATOR.pow(((MODULUS - 1) >> log_n) as u64)
+    }
+
+    /// The multiplicative inverse, or `None` for zero.
+    pub fn inverse(self) -> Option<Self> {
+        (self != Felt::ZERO).then(|| self.pow(MODULUS as u64 - 2))
+    }
+}
+
+impl core::fmt::Debug for Felt {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Felt({})", self.0)
+    }
+}
+
+impl core::fmt::Display for Felt {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u32> for Felt {
+    fn from(value: u32) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<u64> for Felt {
+    fn from(value: u64) -> Self {
+        Self((value % MODULUS as u64) as u32)
+    }
+}
+
+impl From<Felt> for u32 {
+    fn from(value: Felt) -> Self {
+        value.0
+    }
+}
+
+impl From<Felt> for u64 {
+    fn from(value: Felt) -> Self {
+        value.0 as u64
+    }
+}
+
+impl Serialize for Felt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Felt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u32::deserialize(deserializer)?;
+        Felt::from_canonical_u32(value).ok_or_else(|| {
+            de::Error::invalid_value(de::Unexpected::Unsigned(value as u64), &"a canonical felt")
+        })
+    }
+}
+
+impl Add for Felt {
+    type Output = Felt;
+
+    fn add(self, rhs: Felt) -> Felt {
+        // Both operands are below 2^31, so the sum cannot overflow.
+        let sum = self.0 + rhs.0;
+        Felt(if sum >= MODULUS { sum - MODULUS } else { sum })
+    }
+}
+
+impl Sub for Felt {
+    type Output = Felt;
+
+    fn sub(self, rhs: Felt) -> Felt {
+        let (difference, borrow) = self.0.overflowing_sub(rhs.0);
+        Felt(if borrow {
+            difference.wrapping_add(MODULUS)
+        } else {
+            difference
+        })
+    }
+}
+
+impl Mul for Felt {
+    type Output = Felt;
+
+    fn mul(self, rhs: Felt) -> Felt {
+        Felt(((self.0 as u64 * rhs.0 as u64) % MODULUS as u64) as u32)
+    }
+}
+
+impl Neg for Felt {
+    type Output = Felt;
+
+    fn neg(self) -> Felt {
+        Felt::ZERO - self
+    }
+}
+
+impl core::iter::Sum for Felt {
+    fn sum<I: Iterator<Item = Felt>>(iter: I) -> Felt {
+        iter.fold(Felt::ZERO, Add::add)
+    }
+}
+
+impl core::iter::Product for Felt {
+    fn product<I: Iterator<Item = Felt>>(iter: I) -> Felt {
+        iter.fold(Felt::ONE, Mul::mul)
+    }
+}
+
+impl AddAssign for Felt {
+    fn add_assign(&mut self, rhs: Felt) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Felt {
+    fn sub_assign(&mut self, rhs: Felt) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for Felt {
+    fn mul_assign(&mut self, rhs: Felt) {
+        *self = *self * rhs;
+    }
+}
+
+/// Write `a[i] + b[i]` to `out[i]` for every `i`.
+///
+/// # Panics
+/// If the slices have different lengths.
+pub fn batch_add(a: &[Felt], b: &[Felt], out: &mut [Felt]) {
+    assert_batch_lengths(a, b, out);
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    unsafe {
+        // Felt is a transparent wrapper around its canonical u32.
+        valida_felt_batch_add(
+            a.as_ptr().cast(),
+            b.as_ptr().cast(),
+            out.as_mut_ptr().cast(),
+            out.len(),
+        )
+    };
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = *a + *b;
+    }
+}
+
+/// Write `a[i] * b[i]` to `out[i]` for every `i`.
+///
+/// # Panics
+/// If the slices have different lengths.
+pub fn batch_mul(a: &[Felt], b: &[Felt], out: &mut [Felt]) {
+    assert_batch_lengths(a, b, out);
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    unsafe {
+        valida_felt_batch_mul(
+            a.as_ptr().cast(),
+            b.as_ptr().cast(),
+            out.as_mut_ptr().cast(),
+            out.len(),
+        )
+    };
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = *a * *b;
+    }
+}
+
+/// The sum of `a[i] * b[i]`.
+///
+/// # Panics
+/// If the slices have different lengths.
+pub fn dot_product(a: &[Felt], b: &[Felt]) -> Felt {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "dot product of vectors of different lengths"
+    );
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    return Felt(unsafe { valida_felt_dot_product(a.as_ptr().cast(), b.as_ptr().cast(), a.len()) });
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        // Accumulate unreduced products; each is below 2^62, so four fit in a u64.
+        a.chunks(4)
+            .zip(b.chunks(4))
+            .map(|(a, b)| {
+                let partial: u64 = a.iter().zip(b).map(|(a, b)| a.0 as u64 * b.0 as u64).sum();
+                Felt::from(partial)
+            })
+            .sum()
+    }
+}
+
+/// Replace the coefficients of a polynomial with its evaluations at the powers of
+/// [`Felt::two_adic_root`], in natural order: `values[k]` becomes `sum_j values[j] * w^(j * k)`.
+///
+/// # Panics
+/// If the length is not a power of two of at most `2^TWO_ADICITY`.
+pub fn ntt(values: &mut [Felt]) {
+    let log_len = ntt_log_len(values);
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    unsafe {
+        valida_felt_ntt(values.as_mut_ptr().cast(), log_len)
+    };
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    ntt_in_place(values, Felt::two_adic_root(log_len));
+}
+
+/// The inverse of [`ntt`]: interpolate evaluations back into coefficients.
+///
+/// # Panics
+/// If the length is not a power of two of at most `2^TWO_ADICITY`.
+pub fn intt(values: &mut [Felt]) {
+    let log_len = ntt_log_len(values);
+
+    #[cfg(all(valida, feature = "precompiles"))]
+    unsafe {
+        valida_felt_intt(values.as_mut_ptr().cast(), log_len)
+    };
+
+    #[cfg(not(all(valida, feature = "precompiles")))]
+    {
+        // unwrap is safe because a root of unity is not zero
+        ntt_in_place(values, Felt::two_adic_root(log_len).inverse().unwrap());
+        // unwrap is safe because the length is below the modulus
+        let len_inverse = Felt::from(values.len() as u64).inverse().unwrap();
+        values.iter_mut().for_each(|value| *value *= len_inverse);
+    }
+}
+
+fn ntt_log_len(values: &[Felt]) -> u32 {
+    let log_len = values.len().trailing_zeros();
+    assert!(
+        values.len().is_power_of_two() && log_len <= TWO_ADICITY,
+        "NTT of length {}, which is not a power of two up to 2^{TWO_ADICITY}",
+        values.len()
+    );
+    log_len
+}
+
+/// The iterative radix-2 Cooley-Tukey transform with `root` as the primitive root of unity.
+#[cfg(not(all(valida, feature = "precompiles")))]
+fn ntt_in_place(values: &mut [Felt], root: Felt) {
+    let n = values.len();
+    let bits = n.trailing_zeros();
+    if bits == 0 {
+        return;
+    }
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let step = root.pow((n / len) as u64);
+        for chunk in values.chunks_mut(len) {
+            let (lo, hi) = chunk.split_at_mut(len / 2);
+            let mut twiddle = Felt::ONE;
+            for (a, b) in lo.iter_mut().zip(hi) {
+                let t = *b * twiddle;
+                *b = *a - t;
+                *a += t;
+                twiddle *= step;
+            }
+        }
+        len *= 2;
+    }
+}
+
+fn assert_batch_lengths(a: &[Felt], b: &[Felt], out: &[Felt]) {
+    assert!(
+        a.len() == out.len() && b.len() == out.len(),
+        "batch operation on vectors of different lengths"
+    );
+}
+
+#[test]
+fn test_felt_arithmetic() {
+    let minus_one = Felt::new(MODULUS - 1);
+    assert_eq!(minus_one + Felt::ONE, Felt::ZERO);
+    assert_eq!(Felt::ZERO - Felt::ONE, minus_one);
+    assert_eq!(minus_one * minus_one, Felt::ONE);
+    assert_eq!(Felt::new(MODULUS + 5), Felt::new(5));
+    // Fermat's little theorem.
+    assert_eq!(Felt::new(12345).pow(MODULUS as u64 - 1), Felt::ONE);
+}
+
+#[test]
+fn test_felt_conversions_and_serde() {
+    assert_eq!(-Felt::ONE, Felt::new(MODULUS - 1));
+    assert_eq!(-Felt::ZERO, Felt::ZERO);
+    assert_eq!(Felt::from(u64::from(MODULUS) * 3 + 7), Felt::new(7));
+    assert_eq!(u64::from(Felt::new(9)), 9);
+    assert_eq!(Felt::new(3).inverse().unwrap() * Felt::new(3), Felt::ONE);
+    assert_eq!(Felt::ZERO.inverse(), None);
+    assert_eq!((1..=4u32).map(Felt::new).product::<Felt>(), Felt::new(24));
+
+    let bytes = bincode::serialize(&Felt::new(42)).unwrap();
+    assert_eq!(bincode::deserialize::<Felt>(&bytes).unwrap(), Felt::new(42));
+    let non_canonical = bincode::serialize(&MODULUS).unwrap();
+    assert!(bincode::deserialize::<Felt>(&non_canonical).is_err());
+}
+
+#[test]
+fn test_batch_operations() {
+    let a: Vec<Felt> = (0..10).map(|i| Felt::new(MODULUS - 1 - i)).collect();
+    let b: Vec<Felt> = (0..10).map(|i| Felt::new(i * 7 + 3)).collect();
+    let mut out = vec![Felt::ZERO; 10];
+
+    batch_add(&a, &b, &mut out);
+    assert!((0..10).all(|i| out[i] == a[i] + b[i]));
+    batch_mul(&a, &b, &mut out);
+    assert!((0..10).all(|i| out[i] == a[i] * b[i]));
+    assert_eq!(dot_product(&a, &b), out.iter().copied().sum());
+}
+
+#[test]
+fn test_ntt() {
+    let root = Felt::two_adic_root(TWO_ADICITY);
+    assert_eq!(root.pow(1 << (TWO_ADICITY - 1)), -Felt::ONE);
+
+    let coefficients: Vec<Felt> = (0..8).map(|i| Felt::new(i * i + 1)).collect();
+    let mut values = coefficients.clone();
+    ntt(&mut values);
+    let w = Felt::two_adic_root(3);
+    for (k, value) in values.iter().enumerate() {
+        let expected: Felt = (coefficients.iter())
+            .enumerate()
+            .map(|(j, c)| *c * w.pow((j * k) as u64))
+            .sum();
+        assert_eq!(*value, expected);
+    }
+
+    intt(&mut values);
+    assert_eq!(values, coefficients);
+}
@@ -0,0 +1,393 @@
+//! Numbers that compute the same on the host and in the VM.
+//!
+//! `f32` and `f64` arithmetic in the VM goes through the toolchain's software routines, whose
+//! rounding and transcendental functions can differ from the host's in the last bits. A test that prints or compares floats can then pass natively and fail in the VM. The
+//! fixed-point [`I64F64`] and [`Decimal`] are plain integer arithmetic and agree everywhere;
+//! where floats are unavoidable, compare them with [`assert_float_eq!`], which allows
+//! [`DEFAULT_MAX_ULPS`] (or `ulps = n`) [ULPs](ulps_between) of difference. The test runner's native-vs-VM output diff
+//! (`diff-output`) allows the same difference in the numbers it finds in the output.
+//!
+//! # Linting for floats
+//! To find the floats in a guest, deny clippy's float lints in its crate root:
+//! ```rust
+//! #![deny(clippy::float_arithmetic, clippy::float_cmp, clippy::lossy_float_literal)]
+//! ```
+//! `float_arithmetic` flags every float operation, so allow it where a float is intended and
+//! compared with [`assert_float_eq!`]; `float_cmp` flags `==` between floats.
+
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+pub use crate::__float_assert_eq as assert_float_eq;
+
+/// How many ULPs apart two floats may be to be considered equal, unless told otherwise.
+pub const DEFAULT_MAX_ULPS: u64 = 4;
+
+/// How many representable `f64`s lie between `a` and `b`, or `None` if either is NaN.
+///
+/// `0.0` and `-0.0` are 0 ULPs apart.
+pub fn ulps_between(a: f64, b: f64) -> Option<u64> {
+    if a.is_nan() || b.is_nan() {
+        return None;
+    }
+    // Map the bits to integers that are ordered like the floats, with both zeros at 0.
+    let ordered = |x: f64| {
+        let bits = x.to_bits() as i64;
+        if bits < 0 {
+            i64::MIN - bits
+        } else {
+            bits
+        }
+    };
+    Some(ordered(a).abs_diff(ordered(b)))
+}
+
+/// Whether `a` and `b` are at most `max_ulps` ULPs apart. NaN equals nothing.
+pub fn float_eq(a: f64, b: f64, max_ulps: u64) -> bool {
+    ulps_between(a, b).is_some_and(|ulps| ulps <= max_ulps)
+}
+
+/// Whether two texts are the same, except for numbers with a decimal point or exponent that are
+/// at most `max_ulps` ULPs apart.
+///
+/// The texts are compared line by line and word by word, so they must have the same layout.
+pub fn text_eq(a: &str, b: &str, max_ulps: u64) -> bool {
+    let is_float = |word: &str| word.contains(['.', 'e', 'E']) && word.parse::<f64>().is_ok();
+    let words_eq = |a: &str, b: &str| {
+        a == b
+            || (is_float(a)
+                && is_float(b)
+                && float_eq(a.parse().unwrap(), b.parse().unwrap(), max_ulps))
+    };
+    a.lines().count() == b.lines().count()
+        && a.lines().zip(b.lines()).all(|(a, b)| {
+            a == b
+                || (a.split_whitespace().count() == b.split_whitespace().count()
+                    && a.split_whitespace()
+                        .zip(b.split_whitespace())
+                        .all(|(a, b)| words_eq(a, b)))
+        })
+}
+
+/// `a * b / d` for the magnitudes of signed numbers, rounded towards zero, or `None` if `d` is
+/// zero or the result does not fit.
+fn mul_div(a: i128, b: i128, d: i128) -> Option<i128> {
+    let negative = (a < 0) ^ (b < 0) ^ (d < 0);
+    let magnitude = mul_div_unsigned(a.unsigned_abs(), b.unsigned_abs(), d.unsigned_abs())?;
+    let magnitude = i128::try_from(magnitude).ok()?;
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+fn mul_div_unsigned(a: u128, b: u128, d: u128) -> Option<u128> {
+    if d == 0 {
+        return None;
+    }
+    let (high, low) = widening_mul(a, b);
+    if high >= d {
+        return None;
+    }
+    // Long division of the 256-bit product, one bit at a time.
+    let (mut remainder, mut quotient) = (high, 0u128);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = remainder << 1 | (low >> bit) & 1;
+        quotient <<= 1;
+        if carry == 1 || remainder >= d {
+            remainder = remainder.wrapping_sub(d);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+/// The 256-bit product of `a` and `b`, as its high and low halves.
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+    let (middle, middle_carry) = (a_high * b_low).overflowing_add(a_low * b_high);
+    let (low, low_carry) = (a_low * b_low).overflowing_add(middle << 64);
+    let high =
+        a_high * b_high + (middle >> 64) + (u128::from(middle_carry) << 64) + u128::from(low_carry);
+    (high, low)
+}
+
+/// A signed binary fixed-point number with 64 integer and 64 fractional bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct I64F64(i128);
+
+impl I64F64 {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << 64);
+    pub const MIN: Self = Self(i128::MIN);
+    pub const MAX: Self = Self(i128::MAX);
+
+    pub const fn from_int(value: i64) -> Self {
+        Self((value as i128) << 64)
+    }
+
+    /// The number whose bits are `bits`, that is `bits / 2^64`.
+    pub const fn from_bits(bits: i128) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> i128 {
+        self.0
+    }
+
+    /// The nearest number to `value`, saturating at [`MIN`](Self::MIN) and [`MAX`](Self::MAX).
+    pub fn from_f64(value: f64) -> Self {
+        Self((value * 2f64.powi(64)) as i128)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 2f64.powi(64)
+    }
+
+    /// The integer part, rounded towards negative infinity.
+    pub const fn floor(self) -> i64 {
+        (self.0 >> 64) as i64
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// The product, rounded towards zero.
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        mul_div(self.0, other.0, Self::ONE.0).map(Self)
+    }
+
+    /// The quotient, rounded towards zero.
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        mul_div(self.0, Self::ONE.0, other.0).map(Self)
+    }
+}
+
+impl From<i64> for I64F64 {
+    fn from(value: i64) -> Self {
+        Self::from_int(value)
+    }
+}
+
+/// Prints the exact value, or as many fractional digits as the precision asks for.
+impl fmt::Display for I64F64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MASK: u128 = u64::MAX as u128;
+        let magnitude = self.0.unsigned_abs();
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", magnitude >> 64)?;
+        let mut fraction = magnitude & MASK;
+        let digits = match f.precision() {
+            Some(digits) => digits,
+            None if fraction == 0 => 0,
+            None => usize::MAX,
+        };
+        if digits > 0 {
+            f.write_str(".")?;
+        }
+        for _ in 0..digits {
+            if fraction == 0 && f.precision().is_none() {
+                break;
+            }
+            fraction *= 10;
+            write!(f, "{}", fraction >> 64)?;
+            fraction &= MASK;
+        }
+        Ok(())
+    }
+}
+
+/// A signed decimal fixed-point number with [`Decimal::DIGITS`] fractional digits, for values
+/// such as amounts of money that must round like they are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Decimal(i128);
+
+impl Decimal {
+    /// The number of fractional digits.
+    pub const DIGITS: u32 = 18;
+    const SCALE: i128 = 10i128.pow(Self::DIGITS);
+
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(Self::SCALE);
+
+    pub const fn from_int(value: i64) -> Self {
+        Self(value as i128 * Self::SCALE)
+    }
+
+    /// The number `scaled / 10^DIGITS`.
+    pub const fn from_scaled(scaled: i128) -> Self {
+        Self(scaled)
+    }
+
+    pub const fn scaled(self) -> i128 {
+        self.0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Self::SCALE as f64
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// The product, rounded towards zero.
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        mul_div(self.0, other.0, Self::SCALE).map(Self)
+    }
+
+    /// The quotient, rounded towards zero.
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        mul_div(self.0, Self::SCALE, other.0).map(Self)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Self::from_int(value)
+    }
+}
+
+/// Prints the exact value, without trailing zeros.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let magnitude = self.0.unsigned_abs();
+        let scale = Self::SCALE.unsigned_abs();
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", magnitude / scale)?;
+        let fraction = magnitude % scale;
+        if fraction != 0 {
+            let digits = format!("{fraction:0width$}", width = Self::DIGITS as usize);
+            write!(f, ".{}", digits.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a string is not a [`Decimal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError(String);
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+/// Parses `[-]digits[.digits]` with at most [`Decimal::DIGITS`] fractional digits.
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseDecimalError(s.to_string());
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (int, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if int.is_empty()
+            || !all_digits(int)
+            || !all_digits(fraction)
+            || fraction.len() > Self::DIGITS as usize
+        {
+            return Err(invalid());
+        }
+        let int: i128 = int.parse().map_err(|_| invalid())?;
+        let fraction: i128 = format!("{fraction:0<width$}", width = Self::DIGITS as usize)
+            .parse()
+            .map_err(|_| invalid())?;
+        let magnitude = int
+            .checked_mul(Self::SCALE)
+            .and_then(|int| int.checked_add(fraction))
+            .ok_or_else(invalid)?;
+        Ok(Self(if negative { -magnitude } else { magnitude }))
+    }
+}
+
+macro_rules! impl_fixed_arithmetic {
+    ($($fixed:ident),*) => {$(
+        impl Add for $fixed {
+            type Output = $fixed;
+
+            fn add(self, other: $fixed) -> $fixed {
+                self.checked_add(other).expect("attempt to add with overflow")
+            }
+        }
+
+        impl Sub for $fixed {
+            type Output = $fixed;
+
+            fn sub(self, other: $fixed) -> $fixed {
+                self.checked_sub(other).expect("attempt to subtract with overflow")
+            }
+        }
+
+        impl Mul for $fixed {
+            type Output = $fixed;
+
+            fn mul(self, other: $fixed) -> $fixed {
+                self.checked_mul(other).expect("attempt to multiply with overflow")
+            }
+        }
+
+        impl Div for $fixed {
+            type Output = $fixed;
+
+            fn div(self, other: $fixed) -> $fixed {
+                self.checked_div(other)
+                    .expect("attempt to divide by zero or with overflow")
+            }
+        }
+
+        impl Neg for $fixed {
+            type Output = $fixed;
+
+            fn neg(self) -> $fixed {
+                $fixed(self.0.checked_neg().expect("attempt to negate with overflow"))
+            }
+        }
+    )*};
+}
+
+impl_fixed_arithmetic!(I64F64, Decimal);
+
+#[test]
+fn test_fixed_point_arithmetic() {
+    let third = I64F64::ONE / I64F64::from_int(3);
+    assert_eq!(format!("{third:.5}"), "0.33333");
+    assert_eq!((I64F64::from_int(-6) * I64F64::from_f64(0.5)).floor(), -3);
+    assert_eq!(
+        I64F64::from_int(1 << 40).checked_mul(I64F64::from_int(1 << 30)),
+        None
+    );
+
+    let price: Decimal = "19.99".parse().unwrap();
+    assert_eq!((price * Decimal::from_int(3)).to_string(), "59.97");
+    assert_eq!((-price / Decimal::from_int(2)).to_string(), "-9.995");
+    assert!("1.2.3".parse::<Decimal>().is_err());
+
+    assert_eq!(ulps_between(0.0, -0.0), Some(0));
+    assert_eq!(ulps_between(1.0, 1.0 + f64::EPSILON), Some(1));
+    assert!(text_eq("x = 0.30000000000000004\n", "x = 0.3\n", 1));
+    assert!(!text_eq("x = 1\n", "x = 2\n", DEFAULT_MAX_ULPS));
+    assert_float_eq!(0.1 + 0.2, 0.3);
+}
@@ -0,0 +1,160 @@
+//! Fuzz targets that run under `cargo fuzz` on the host and in the Valida VM.
+//!
+//! A target declared with [`valida_fuzz_target!`](crate::valida_fuzz_target) is a libFuzzer
+//! target on the host, so `cargo fuzz run` drives it as usual. Built for Valida, the same file is
+//! a guest that reads one input off the input tape and runs the target on it once.
+//! [`reproduce_in_valida`] then checks whether a crash found on the host also crashes in the VM,
+//! which it may not, e.g. for overflows that depend on the pointer width:
+#![cfg_attr(
+    feature = "host",
+    doc = r#"
+```rust,no_run
+use std::{path::Path, time::Duration};
+
+# fn main() -> Result<(), Box<dyn std::error::Error>> {
+let outcomes = valida_rs::fuzz::reproduce_artifacts_in_valida(
+    Path::new("target/valida-unknown-baremetal-gnu/release/parse"),
+    Path::new("fuzz/artifacts/parse"),
+    Duration::from_secs(60),
+)?;
+for (input, outcome) in outcomes {
+    println!("{}: {outcome}", input.display());
+}
+# Ok(())
+# }
+```
+"#
+)]
+//! The fuzz crate needs `libfuzzer-sys` as a dependency on the host only, since it does not build
+//! for the VM.
+
+#[cfg(all(feature = "host", not(valida)))]
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::Duration,
+};
+
+#[cfg(all(feature = "host", not(valida)))]
+use crate::host::{RunError, Runner};
+
+/// Run `target` on the whole input tape, as the guest of a
+/// [`valida_fuzz_target!`](crate::valida_fuzz_target) does.
+///
+/// # Panics
+/// If the input cannot be read.
+#[cfg(feature = "guest")]
+pub fn run_tape_input(target: impl FnOnce(&[u8])) {
+    let input = crate::io::read().expect("failed to read the fuzz input");
+    target(&input);
+}
+
+/// What a fuzz input did when the target ran on it in the VM.
+#[cfg(all(feature = "host", not(valida)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FuzzOutcome {
+    /// The target returned normally.
+    Passed,
+    /// The target panicked or the VM failed, with the panic message if it was printed.
+    Crashed {
+        exit: ExitStatus,
+        message: Option<String>,
+    },
+    /// The run did not finish within the timeout.
+    TimedOut,
+}
+
+#[cfg(all(feature = "host", not(valida)))]
+impl FuzzOutcome {
+    /// Returns `true` if the input crashed or hung in the VM, as it did on the host.
+    pub fn reproduced(&self) -> bool {
+        !matches!(self, FuzzOutcome::Passed)
+    }
+}
+
+#[cfg(all(feature = "host", not(valida)))]
+impl fmt::Display for FuzzOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FuzzOutcome::Passed => write!(f, "passed in the VM"),
+            FuzzOutcome::Crashed {
+                exit,
+                message: Some(message),
+            } => write!(f, "crashed in the VM ({exit}): {message}"),
+            FuzzOutcome::Crashed {
+                exit,
+                message: None,
+            } => write!(f, "crashed in the VM ({exit})"),
+            FuzzOutcome::TimedOut => write!(f, "timed out in the VM"),
+        }
+    }
+}
+
+/// Run the fuzz target built for Valida at `binary` on `input` in the VM.
+///
+/// The `valida` command comes from `$PATH`.
+#[cfg(all(feature = "host", not(valida)))]
+pub fn reproduce_in_valida(
+    binary: &Path,
+    input: &[u8],
+    timeout: Duration,
+) -> Result<FuzzOutcome, RunError> {
+    let result = match Runner::new(binary).stdin(input).timeout(timeout).run() {
+        Ok(result) => result,
+        Err(RunError::TimedOut { .. }) => return Ok(FuzzOutcome::TimedOut),
+        Err(e) => return Err(e),
+    };
+    if result.exit.success() {
+        return Ok(FuzzOutcome::Passed);
+    }
+    Ok(FuzzOutcome::Crashed {
+        exit: result.exit,
+        message: panic_message(&result.stderr).or_else(|| panic_message(&result.stdout)),
+    })
+}
+
+/// Run the fuzz target at `binary` in the VM on each input `cargo fuzz` saved in `artifacts`,
+/// such as `fuzz/artifacts/<target>`, in the order of their file names.
+#[cfg(all(feature = "host", not(valida)))]
+pub fn reproduce_artifacts_in_valida(
+    binary: &Path,
+    artifacts: &Path,
+    timeout: Duration,
+) -> Result<Vec<(PathBuf, FuzzOutcome)>, RunError> {
+    let mut inputs = std::fs::read_dir(artifacts)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    inputs.retain(|path| path.is_file());
+    inputs.sort();
+
+    inputs
+        .into_iter()
+        .map(|path| {
+            let outcome = reproduce_in_valida(binary, &std::fs::read(&path)?, timeout)?;
+            Ok((path, outcome))
+        })
+        .collect()
+}
+
+/// The message of a panic printed by the default hook, on the line after `panicked at`.
+#[cfg(all(feature = "host", not(valida)))]
+fn panic_message(output: &[u8]) -> Option<String> {
+    let output = String::from_utf8_lossy(output);
+    let mut lines = output
+        .lines()
+        .skip_while(|line| !line.contains("panicked at"));
+    lines.next()?;
+    lines.next().map(str::to_string)
+}
+
+#[cfg(all(feature = "host", not(valida)))]
+#[test]
+fn test_panic_message() {
+    let stderr = b"thread 'main' panicked at fuzz_targets/parse.rs:9:5:\nindex out of bounds\n";
+    assert_eq!(
+        panic_message(stderr).as_deref(),
+        Some("index out of bounds")
+    );
+    assert_eq!(panic_message(b"ok\n"), None);
+}
@@ -0,0 +1,151 @@
+//! A key-value store of hints the host hands to the guest, for oracle patterns such as
+//! precomputed lookups or database rows.
+//!
+//! The host builds a [`Map`] and puts its [framed encoding](Map::to_bytes) at the start of the
+//! input tape. The guest looks values up with [`get`], which reads the map off the tape the first
+//! time it is called.
+#![cfg_attr(
+    feature = "guest",
+    doc = r#"
+```rust
+// host
+let mut hints = valida_rs::hints::Map::new();
+hints.insert(b"balance:alice", 100u64.to_le_bytes());
+let input = hints.to_bytes();
+# valida_rs::io::testing::set_input(input);
+
+// guest
+let balance = valida_rs::hints::get(b"balance:alice").expect("no balance hint");
+assert_eq!(balance, 100u64.to_le_bytes());
+# valida_rs::io::testing::reset();
+```
+"#
+)]
+//!
+//! [`Map`] is also available to host-only builds; reading it needs the `guest` feature.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "guest")]
+use std::{error::Error, sync::OnceLock};
+
+/// Keys mapped to byte strings.
+///
+/// The framed encoding is the number of entries, then each key and value in key order, each
+/// preceded by its length; all lengths are little-endian `u32`s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Map {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.entries.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The framed encoding, to put on the guest's input tape.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.entries.len() as u32).to_le_bytes().to_vec();
+        for (key, value) in &self.entries {
+            for field in [key, value] {
+                bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(field);
+            }
+        }
+        bytes
+    }
+
+    /// Read a map in the framed encoding from the input tape.
+    #[cfg(feature = "guest")]
+    pub fn read() -> Result<Self, Box<dyn Error>> {
+        // Unlike `io::read_n`, which pads past EOF, fail on a truncated tape rather than read
+        // the padding as a count or length.
+        fn read_exact(n: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+            let bytes: Vec<u8> = std::iter::from_fn(crate::io::tape_read_byte)
+                .take(n)
+                .collect();
+            if bytes.len() < n {
+                return Err(format!(
+                    "hint map truncated: expected {n} bytes, read {}",
+                    bytes.len()
+                )
+                .into());
+            }
+            Ok(bytes)
+        }
+        fn read_u32() -> Result<u32, Box<dyn Error>> {
+            Ok(u32::from_le_bytes(read_exact(4)?.as_slice().try_into()?))
+        }
+
+        let mut map = Self::new();
+        for _ in 0..read_u32()? {
+            let key = read_exact(read_u32()? as usize)?;
+            let value = read_exact(read_u32()? as usize)?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(feature = "guest")]
+static HINTS: OnceLock<Map> = OnceLock::new();
+
+/// The hint for `key`, reading the map off the input tape on first use.
+///
+/// # Panics
+/// If the input tape does not start with a map in the framed encoding.
+#[cfg(feature = "guest")]
+pub fn get(key: &[u8]) -> Option<&'static [u8]> {
+    HINTS
+        .get_or_init(|| Map::read().expect("failed to read hints from the input tape"))
+        .get(key)
+}
+
+#[cfg(feature = "guest")]
+#[test]
+fn test_map_framing() {
+    let mut map = Map::new();
+    map.insert(b"b".to_vec(), b"two".to_vec())
+        .insert(b"a".to_vec(), Vec::new());
+
+    crate::io::testing::set_input(map.to_bytes());
+    let read = Map::read();
+    crate::io::testing::reset();
+
+    let read = read.unwrap();
+    assert_eq!(read, map);
+    assert_eq!(read.get(b"a"), Some(&[][..]));
+    assert_eq!(read.get(b"b"), Some(&b"two"[..]));
+    assert_eq!(read.get(b"c"), None);
+}
+
+#[cfg(feature = "guest")]
+#[test]
+fn test_map_truncated() {
+    let mut map = Map::new();
+    map.insert(b"key".to_vec(), b"value".to_vec());
+    let bytes = map.to_bytes();
+
+    for input in [&[][..], &bytes[..2], &bytes[..bytes.len() - 1]] {
+        crate::io::testing::set_input(input);
+        let read = Map::read();
+        crate::io::testing::reset();
+        assert!(read.unwrap_err().to_string().contains("truncated"));
+    }
+}
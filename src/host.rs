@@ -0,0 +1,401 @@
+//! Run guest programs in the Valida VM from a host application.
+//!
+//! [`Runner`] wraps `valida run`: it feeds the guest's input tape, enforces an optional timeout
+//! and collects what the guest printed and committed. [`Prover`] wraps `valida prove` and
+//! `valida verify`.
+//! ```rust,no_run
+//! use valida_rs::host::Runner;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let result = Runner::new("target/valida-unknown-baremetal-gnu/release/guest")
+//!     .stdin(b"42\n".to_vec())
+//!     .timeout(std::time::Duration::from_secs(60))
+//!     .run()?;
+//! assert!(result.exit.success());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`InputBuilder`] builds the input from typed values in the framings the guest reads, and
+//! [`OutputReader`] reads typed values and records back from what it printed. A guest
+//! can also ask the host for data while it runs, with `io::query`; [`Runner::on_query`]
+//! registers the callback that answers.
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+mod input;
+mod output;
+mod prover;
+mod version;
+
+pub use input::InputBuilder;
+pub use output::OutputReader;
+pub use prover::{Proof, ProveError, Prover, Task};
+pub use version::{check_valida, ValidaVersion, VersionError, MIN_VALIDA_VERSION};
+
+/// The command used to run guests when none is configured.
+pub const DEFAULT_VALIDA_COMMAND: &str = "valida";
+
+/// The prefix of the lines on which the guest's `io::query` writes its requests.
+///
+/// This mirrors `io::QUERY_PREFIX`, since `io` is only compiled with the `guest` feature.
+pub(crate) const QUERY_PREFIX: &str = "valida-query:";
+
+/// Runs a guest program in the Valida VM.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    program: PathBuf,
+    valida: PathBuf,
+    stdin: Vec<u8>,
+    selector: Option<String>,
+    start_time: Option<Duration>,
+    timeout: Option<Duration>,
+    query_handler: Option<QueryHandler>,
+}
+
+type QueryFn = dyn FnMut(&[u8]) -> Vec<u8> + Send;
+
+/// A callback answering the guest's queries, shared between clones of a [`Runner`].
+#[derive(Clone)]
+pub(crate) struct QueryHandler(pub(crate) Arc<Mutex<QueryFn>>);
+
+impl std::fmt::Debug for QueryHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("QueryHandler")
+    }
+}
+
+impl QueryHandler {
+    /// Answer the queries on the complete lines of `stdout` past `cursor`, writing each response
+    /// to `stdin`, and move `cursor` past them.
+    pub(crate) fn answer(
+        &self,
+        stdout: &[u8],
+        cursor: &mut usize,
+        stdin: &mut impl Write,
+    ) -> std::io::Result<()> {
+        while let Some(len) = stdout[*cursor..].iter().position(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(&stdout[*cursor..*cursor + len]);
+            *cursor += len + 1;
+
+            let Some(hex) = line.trim_end().strip_prefix(QUERY_PREFIX) else {
+                continue;
+            };
+            let request = crate::snapshot::from_hex(hex).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed query")
+            })?;
+            let response = self.0.lock().unwrap()(&request);
+            writeln!(stdin, "{}", response.len())?;
+            stdin.write_all(&response)?;
+            stdin.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of a guest run that finished.
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    /// What the VM printed to stdout.
+    pub stdout: Vec<u8>,
+    /// What the VM printed to stderr.
+    pub stderr: Vec<u8>,
+    /// The output file `valida run` wrote.
+    pub output: Vec<u8>,
+    /// How the VM exited.
+    pub exit: ExitStatus,
+    /// The number of cycles the run took, if the VM reported it.
+    pub cycles: Option<u64>,
+}
+
+impl RunResult {
+    /// A reader of the frames and records in what the VM printed.
+    pub fn output_reader(&self) -> OutputReader<'_> {
+        OutputReader::new(&self.stdout)
+    }
+}
+
+/// Why a guest run did not finish.
+#[derive(Debug)]
+pub enum RunError {
+    /// The `valida` command could not be started.
+    Spawn {
+        command: PathBuf,
+        source: std::io::Error,
+    },
+    /// The `valida` command is too old to drive.
+    Version(VersionError),
+    /// Communicating with the VM process failed.
+    Io(std::io::Error),
+    /// The run did not finish within the timeout and was killed.
+    TimedOut { timeout: Duration, stdout: Vec<u8> },
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Spawn { command, source } => write!(
+                f,
+                "Failed to start {}: {source}. Are you sure it is in your `$PATH`?",
+                command.display()
+            ),
+            RunError::Version(e) => write!(f, "{e}"),
+            RunError::Io(e) => write!(f, "Failed to communicate with the valida process: {e}"),
+            RunError::TimedOut { timeout, .. } => write!(f, "Guest timed out after {timeout:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RunError::Spawn { source, .. } | RunError::Io(source) => Some(source),
+            RunError::Version(e) => Some(e),
+            RunError::TimedOut { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for RunError {
+    fn from(e: std::io::Error) -> Self {
+        RunError::Io(e)
+    }
+}
+
+impl Runner {
+    /// A runner for the guest binary at `program`, with empty input and no timeout.
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            valida: PathBuf::from(DEFAULT_VALIDA_COMMAND),
+            stdin: Vec::new(),
+            selector: None,
+            start_time: None,
+            timeout: None,
+            query_handler: None,
+        }
+    }
+
+    /// Set the bytes on the guest's input tape.
+    pub fn stdin(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.stdin = bytes.into();
+        self
+    }
+
+    /// Run the program registered as `name` in a guest with several programs, see
+    /// `entrypoint!(select: { ... })`. The name is written to the input tape before the
+    /// [`stdin`](Self::stdin) bytes.
+    pub fn select(mut self, name: impl Into<String>) -> Self {
+        self.selector = Some(name.into());
+        self
+    }
+
+    /// Give the guest `now` as the current time, for `time::read_start_time`. It is written to
+    /// the input tape after the selector and before the [`stdin`](Self::stdin) bytes.
+    pub fn start_time(mut self, now: SystemTime) -> Self {
+        self.start_time = Some(now.duration_since(UNIX_EPOCH).unwrap_or_default());
+        self
+    }
+
+    /// Kill the run if it takes longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Answer the guest's `io::query` requests with `handler`, which gets the request's bytes
+    /// and returns the response's.
+    ///
+    /// The input tape stays open until the guest exits so responses can be written to it, so a
+    /// guest that also reads its input until EOF blocks forever; frame the input instead.
+    pub fn on_query(mut self, handler: impl FnMut(&[u8]) -> Vec<u8> + Send + 'static) -> Self {
+        self.query_handler = Some(QueryHandler(Arc::new(Mutex::new(handler))));
+        self
+    }
+
+    /// Use a `valida` executable other than the one in `$PATH`.
+    pub fn valida_command(mut self, command: impl Into<PathBuf>) -> Self {
+        self.valida = command.into();
+        self
+    }
+
+    /// The guest binary this runner runs.
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    /// Start `valida run` with piped stdio, writing the output file to `output_path`.
+    pub(crate) fn spawn(&self, output_path: &Path) -> Result<Child, RunError> {
+        let version = match check_valida(&self.valida) {
+            Ok(version) => version.unwrap_or(MIN_VALIDA_VERSION),
+            // Starting the command below reports that it is missing.
+            Err(VersionError::Spawn { .. }) => MIN_VALIDA_VERSION,
+            Err(e) => return Err(RunError::Version(e)),
+        };
+        Command::new(&self.valida)
+            .args(version.run_args(&self.program, output_path))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| RunError::Spawn {
+                command: self.valida.clone(),
+                source,
+            })
+    }
+
+    /// Run the guest to completion.
+    ///
+    /// A guest that exits with a failure status is not an error; check [`RunResult::exit`].
+    pub fn run(&self) -> Result<RunResult, RunError> {
+        let output_file = tempfile::NamedTempFile::new()?;
+        let mut child = self.spawn(output_file.path())?;
+
+        // Write and read on separate threads so a guest that fills a pipe cannot deadlock us.
+        // unwrap is safe because we know stdio is piped
+        let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let input = self.input();
+        let writer_stdin = Arc::clone(&stdin);
+        let writer = std::thread::spawn(move || {
+            // The pipe breaks if the guest exits without reading all its input.
+            let _ = writer_stdin.lock().unwrap().write_all(&input);
+        });
+        // Without a query handler, dropping the last reference closes the guest's input.
+        let stdout = match &self.query_handler {
+            Some(handler) => read_and_answer(child.stdout.take().unwrap(), handler.clone(), stdin),
+            None => {
+                drop(stdin);
+                read_to_end(child.stdout.take().unwrap())
+            }
+        };
+        let stderr = read_to_end(child.stderr.take().unwrap());
+
+        let start_time = Instant::now();
+        let exit = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if let Some(timeout) = self.timeout.filter(|t| start_time.elapsed() >= *t) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(RunError::TimedOut {
+                    timeout,
+                    stdout: stdout.join().unwrap_or_default(),
+                });
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+
+        let _ = writer.join();
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        let cycles = parse_cycles(&stderr).or_else(|| parse_cycles(&stdout));
+        let output = std::fs::read(output_file.path())?;
+
+        Ok(RunResult {
+            stdout,
+            stderr,
+            output,
+            exit,
+            cycles,
+        })
+    }
+
+    /// The bytes written to the guest's input tape: the framed selector and the start time, if
+    /// any, then the input.
+    fn input(&self) -> Vec<u8> {
+        let mut input = match &self.selector {
+            Some(name) => format!("{}\n{name}", name.len()).into_bytes(),
+            None => Vec::new(),
+        };
+        if let Some(now) = self.start_time {
+            input.extend_from_slice(format!("{}\n", now.as_nanos()).as_bytes());
+        }
+        input.extend_from_slice(&self.stdin);
+        input
+    }
+}
+
+fn read_to_end(mut reader: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = reader.read_to_end(&mut buffer);
+        buffer
+    })
+}
+
+/// Read `reader` to the end like [`read_to_end`], answering the queries on it through `stdin`.
+fn read_and_answer(
+    mut reader: impl Read + Send + 'static,
+    handler: QueryHandler,
+    stdin: Arc<Mutex<ChildStdin>>,
+) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let mut segment = [0; 256];
+        let mut cursor = 0;
+        while let Ok(n @ 1..) = reader.read(&mut segment) {
+            buffer.extend_from_slice(&segment[..n]);
+            // The guest is gone if the response cannot be written; keep its remaining output.
+            let _ = handler.answer(&buffer, &mut cursor, &mut *stdin.lock().unwrap());
+        }
+        buffer
+    })
+}
+
+/// Find the cycle count in the VM's report, a line mentioning cycles and ending with a number.
+pub(crate) fn parse_cycles(report: &[u8]) -> Option<u64> {
+    String::from_utf8_lossy(report).lines().find_map(|line| {
+        if !line.to_lowercase().contains("cycles") {
+            return None;
+        }
+        line.rsplit(|c: char| !c.is_ascii_digit())
+            .find(|word| !word.is_empty())?
+            .parse()
+            .ok()
+    })
+}
+
+#[test]
+fn test_query_handler_answers_query_lines() {
+    let handler = QueryHandler(Arc::new(Mutex::new(|request: &[u8]| request.repeat(2))));
+    let mut cursor = 0;
+    let mut stdin = Vec::new();
+    handler
+        .answer(
+            b"hello\nvalida-query:6162\nvalida-qu",
+            &mut cursor,
+            &mut stdin,
+        )
+        .unwrap();
+    assert_eq!(stdin, b"4\nabab");
+    assert_eq!(cursor, 24);
+}
+
+#[test]
+fn test_selector_is_framed_before_input() {
+    let runner = Runner::new("guest").stdin(b"42\n".to_vec());
+    assert_eq!(runner.input(), b"42\n");
+    assert_eq!(runner.clone().select("sum").input(), b"3\nsum42\n");
+    let now = UNIX_EPOCH + Duration::from_secs(5);
+    assert_eq!(
+        runner.select("sum").start_time(now).input(),
+        b"3\nsum5000000000\n42\n"
+    );
+}
+
+#[test]
+fn test_parse_cycles() {
+    assert_eq!(
+        parse_cycles(b"proving...\nTotal cycles: 1234\n"),
+        Some(1234)
+    );
+    assert_eq!(parse_cycles(b"no count here\n"), None);
+}
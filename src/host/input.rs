@@ -0,0 +1,153 @@
+//! Building a guest's input tape from typed values.
+
+use std::fmt::Display;
+
+use bincode::Options;
+use serde::Serialize;
+
+/// An input tape, built value by value in the framings the guest's `io` functions read.
+///
+/// Each method mirrors a guest-side read, so the guest reads back exactly what was written as
+/// long as it reads in the same order:
+///
+/// | host                                     | guest                            |
+/// |------------------------------------------|----------------------------------|
+/// | [`write`](Self::write)                   | `io::read_serde::<Bincode, T>`   |
+/// | [`write_with`](Self::write_with)         | `io::read_serde::<C, T>`         |
+/// | [`write_io`](Self::write_io)             | `ValidaIo::read_from_tape`       |
+/// | [`write_frame`](Self::write_frame)       | `io::read_serde::<Raw, Vec<u8>>` |
+/// | [`write_line`](Self::write_line)         | `io::read_line`                  |
+/// | [`write_hint_map`](Self::write_hint_map) | `hints::Map::read`, `hints::get` |
+/// | [`write_raw`](Self::write_raw)           | `io::read_n`, `io::read`         |
+/// ```rust,no_run
+/// use valida_rs::{hints, host::{InputBuilder, Runner}};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let guest = "target/valida-unknown-baremetal-gnu/release/guest";
+/// let mut hints = hints::Map::new();
+/// hints.insert(b"difficulty", 4u32.to_le_bytes());
+/// let mut input = InputBuilder::new();
+/// input.write_hint_map(&hints).write_line(42).write(&vec![1u8, 2, 3]);
+/// let result = Runner::new(guest).stdin(input).run()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputBuilder {
+    bytes: Vec<u8>,
+}
+
+impl InputBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `value` encoded with bincode (fixed-width little-endian integers) and framed with
+    /// its length.
+    ///
+    /// # Panics
+    /// If `value` cannot be encoded, which only happens for sequences whose length is not known
+    /// up front.
+    pub fn write<T: Serialize + ?Sized>(&mut self, value: &T) -> &mut Self {
+        let bytes = bincode::options()
+            .with_fixint_encoding()
+            .with_little_endian()
+            .serialize(value)
+            .expect("input value cannot be encoded");
+        self.write_frame(bytes)
+    }
+
+    /// Append `value` in its [`ValidaIo`](crate::schema::ValidaIo) encoding, framed with its
+    /// length.
+    ///
+    /// # Panics
+    /// If a field of `value` cannot be encoded.
+    pub fn write_io<T: crate::schema::ValidaIo>(&mut self, value: &T) -> &mut Self {
+        let bytes = value.to_bytes().expect("input value cannot be encoded");
+        self.write_frame(bytes)
+    }
+
+    /// Append `value` encoded and framed by the codec `C`.
+    ///
+    /// # Panics
+    /// If `C` cannot encode `value`.
+    #[cfg(feature = "guest")]
+    pub fn write_with<C: crate::io::TapeCodec<T>, T: ?Sized>(&mut self, value: &T) -> &mut Self {
+        let framed = C::frame(value).expect("input value cannot be encoded");
+        self.write_raw(framed)
+    }
+
+    /// Append `bytes` framed with their length.
+    pub fn write_frame(&mut self, bytes: impl AsRef<[u8]>) -> &mut Self {
+        let bytes = bytes.as_ref();
+        self.write_line(bytes.len()).write_raw(bytes)
+    }
+
+    /// Append `value` and a newline.
+    pub fn write_line(&mut self, value: impl Display) -> &mut Self {
+        self.bytes
+            .extend_from_slice(format!("{value}\n").as_bytes());
+        self
+    }
+
+    /// Append a map of hints in its framed encoding.
+    pub fn write_hint_map(&mut self, hints: &crate::hints::Map) -> &mut Self {
+        self.write_raw(hints.to_bytes())
+    }
+
+    /// Append `bytes` as they are.
+    pub fn write_raw(&mut self, bytes: impl AsRef<[u8]>) -> &mut Self {
+        self.bytes.extend_from_slice(bytes.as_ref());
+        self
+    }
+
+    /// The tape built so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl From<InputBuilder> for Vec<u8> {
+    fn from(input: InputBuilder) -> Self {
+        input.into_bytes()
+    }
+}
+
+#[cfg(feature = "guest")]
+#[test]
+fn test_input_builder_matches_guest_reads() {
+    use crate::io;
+
+    let mut hints = crate::hints::Map::new();
+    hints.insert(b"key".to_vec(), b"value".to_vec());
+    let mut input = InputBuilder::new();
+    input
+        .write_hint_map(&hints)
+        .write_line(42)
+        .write(&(7u32, "seven".to_string()))
+        .write_frame(b"frame")
+        .write_with::<io::Raw, _>(&b"raw".to_vec());
+
+    io::testing::set_input(input.into_bytes());
+    let read = (|| -> Result<_, Box<dyn std::error::Error>> {
+        Ok((
+            crate::hints::Map::read()?,
+            io::read_line::<u32>()?,
+            io::read_serde::<io::Bincode, (u32, String)>()?,
+            io::read_serde::<io::Raw, Vec<u8>>()?,
+            io::read_serde::<io::Raw, Vec<u8>>()?,
+        ))
+    })();
+    io::testing::reset();
+
+    let (read_hints, line, value, frame, raw) = read.unwrap();
+    assert_eq!(read_hints, hints);
+    assert_eq!(line, 42);
+    assert_eq!(value, (7, "seven".to_string()));
+    assert_eq!(frame, b"frame");
+    assert_eq!(raw, b"raw");
+}
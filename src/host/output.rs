@@ -0,0 +1,192 @@
+//! Splitting what a guest printed into its records, frames and plain output.
+
+use std::error::Error;
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    bench::BenchSummary,
+    profile::{IoRecord, SpanRecord},
+    test_utils::PanicRecord,
+};
+
+/// The prefixes of the lines the crate prints for the host rather than for the user.
+const RECORD_PREFIXES: [&str; 6] = [
+    crate::bench::REPORT_PREFIX,
+    crate::profile::REPORT_PREFIX,
+    crate::snapshot::RECORD_PREFIX,
+    crate::test_utils::PANIC_RECORD_PREFIX,
+    crate::test_utils::TEST_CYCLES_PREFIX,
+    super::QUERY_PREFIX,
+];
+
+/// Reads a guest's stdout, the counterpart of [`InputBuilder`](super::InputBuilder).
+///
+/// The records the crate prints (profiles, benchmarks, panics) are found on lines of their own
+/// anywhere in the output. Frames written with `io::write` or `io::write_serde` are read in
+/// order with [`read`](Self::read) and its siblings, skipping the record lines and any text
+/// printed in between; text that is a bare number would be taken for a frame's length, so guests
+/// that mix frames with printed numbers should frame everything.
+/// ```rust,no_run
+/// # use valida_rs::host::Runner;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let (guest, input) = ("target/valida-unknown-baremetal-gnu/release/guest", Vec::new());
+/// let result = Runner::new(guest).stdin(input).run()?;
+/// let mut output = result.output_reader();
+/// let root: [u8; 32] = output.read()?;
+/// println!("{}", output.debug_output());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct OutputReader<'a> {
+    stdout: &'a [u8],
+    /// Where the next frame is searched from.
+    cursor: usize,
+}
+
+impl<'a> OutputReader<'a> {
+    pub fn new(stdout: &'a [u8]) -> Self {
+        Self { stdout, cursor: 0 }
+    }
+
+    fn lines(&self) -> impl Iterator<Item = String> + 'a {
+        String::from_utf8_lossy(self.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// The profile records of `profile::span!`, in the order they were printed.
+    pub fn spans(&self) -> Vec<SpanRecord> {
+        self.lines()
+            .filter_map(|line| SpanRecord::parse_line(&line))
+            .collect()
+    }
+
+    /// The tape usage printed with the profile, if the guest profiled.
+    pub fn io_record(&self) -> Option<IoRecord> {
+        self.lines().find_map(|line| IoRecord::parse_line(&line))
+    }
+
+    /// The summaries of the benchmarks that ran.
+    pub fn bench_summaries(&self) -> Vec<BenchSummary> {
+        self.lines()
+            .filter_map(|line| BenchSummary::parse_line(&line))
+            .collect()
+    }
+
+    /// The record the test panic hook printed, if a test panicked.
+    pub fn panic(&self) -> Option<PanicRecord> {
+        self.lines().find_map(|line| PanicRecord::parse_line(&line))
+    }
+
+    /// The cycles the test runner's test took, if the VM has a cycle counter.
+    pub fn test_cycles(&self) -> Option<u64> {
+        self.lines().find_map(|line| {
+            let cycles = line
+                .trim()
+                .strip_prefix(crate::test_utils::TEST_CYCLES_PREFIX)?;
+            cycles.trim().parse().ok()
+        })
+    }
+
+    /// The output without the record and query lines, as the user printed it. Frames are
+    /// included, decoded as text.
+    pub fn debug_output(&self) -> String {
+        self.lines()
+            .filter(|line| !is_record(line))
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    /// Read the next frame, the bytes after a line with their length.
+    pub fn read_frame(&mut self) -> Result<&'a [u8], Box<dyn Error>> {
+        while self.cursor < self.stdout.len() {
+            let rest = &self.stdout[self.cursor..];
+            let line_len = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            let line = String::from_utf8_lossy(&rest[..line_len]);
+            self.cursor += (line_len + 1).min(rest.len());
+            if is_record(&line) {
+                continue;
+            }
+            let Ok(len) = line.trim().parse::<usize>() else {
+                continue;
+            };
+            let frame = self
+                .stdout
+                .get(self.cursor..self.cursor + len)
+                .ok_or_else(|| format!("frame of {len} bytes is cut off"))?;
+            self.cursor += len;
+            return Ok(frame);
+        }
+        Err("no more frames in the output".into())
+    }
+
+    /// Read the next frame and decode it with bincode, as written by `io::write` or
+    /// `io::write_serde::<Bincode, T>`.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, Box<dyn Error>> {
+        let frame = self.read_frame()?;
+        Ok(bincode::options()
+            .with_fixint_encoding()
+            .with_little_endian()
+            .deserialize(frame)?)
+    }
+
+    /// Read the next frame and decode it with the codec `C`, as written by
+    /// `io::write_serde::<C, T>`.
+    #[cfg(feature = "guest")]
+    pub fn read_with<C: crate::io::TapeCodec<T>, T>(&mut self) -> Result<T, Box<dyn Error>> {
+        C::decode(self.read_frame()?)
+    }
+
+    /// Read the next frame and decode it as a [`ValidaIo`](crate::schema::ValidaIo) type, as
+    /// written by `ValidaIo::write_to_tape`.
+    pub fn read_io<T: crate::schema::ValidaIo>(&mut self) -> Result<T, Box<dyn Error>> {
+        T::from_bytes(self.read_frame()?)
+    }
+
+    /// The output after the last frame read.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.stdout[self.cursor..]
+    }
+}
+
+fn is_record(line: &str) -> bool {
+    let line = line.trim_start();
+    RECORD_PREFIXES
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+#[cfg(feature = "guest")]
+#[test]
+fn test_output_reader_splits_records_and_frames() {
+    use crate::io;
+
+    io::testing::set_input(Vec::new());
+    let record = crate::profile::IoRecord {
+        bytes_in: 3,
+        bytes_out: 4,
+    };
+    io::write_vec(format!("starting\n{record}\n")).unwrap();
+    io::write(&(1u32, "one".to_string())).unwrap();
+    io::write_serde::<io::Raw, _>(&b"raw\nbytes".to_vec()).unwrap();
+    let stdout = io::testing::take_output();
+    io::testing::reset();
+
+    let mut reader = OutputReader::new(&stdout);
+    assert_eq!(
+        reader.read::<(u32, String)>().unwrap(),
+        (1, "one".to_string())
+    );
+    assert_eq!(reader.read_frame().unwrap(), b"raw\nbytes");
+    assert!(reader.read_frame().is_err());
+    assert_eq!(reader.io_record().map(|io| io.bytes_out), Some(4));
+    assert!(reader.debug_output().starts_with("starting\n"));
+    assert!(!reader
+        .debug_output()
+        .contains(crate::profile::REPORT_PREFIX));
+}
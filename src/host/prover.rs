@@ -0,0 +1,294 @@
+//! Proving and verifying guest runs with `valida prove` and `valida verify`.
+
+use std::{
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    pin::Pin,
+    process::{Command, Output, Stdio},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::DEFAULT_VALIDA_COMMAND;
+
+/// A proof of a guest run, with the output the run committed to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proof {
+    /// The proof file `valida prove` wrote.
+    pub proof: Vec<u8>,
+    /// The output file the proved run wrote, which the proof attests to.
+    pub output: Vec<u8>,
+}
+
+impl Proof {
+    /// Read a proof and the output it attests to from the files `valida prove` wrote.
+    pub fn from_files(
+        proof: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+    ) -> Result<Self, ProveError> {
+        Ok(Self {
+            proof: std::fs::read(proof)?,
+            output: std::fs::read(output)?,
+        })
+    }
+
+    /// Save the proof and its output to a single file, to be read with [`Proof::load`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProveError> {
+        let bytes = bincode::serialize(self).map_err(|e| ProveError::Malformed(e.to_string()))?;
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Load a proof saved with [`Proof::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProveError> {
+        let bytes = std::fs::read(path)?;
+        bincode::deserialize(&bytes).map_err(|e| ProveError::Malformed(e.to_string()))
+    }
+}
+
+/// Why proving or verifying failed.
+#[derive(Debug)]
+pub enum ProveError {
+    /// The `valida` command could not be started.
+    Spawn {
+        command: PathBuf,
+        source: std::io::Error,
+    },
+    /// Reading or writing a proof or its files failed.
+    Io(std::io::Error),
+    /// `valida prove` exited with a failure status.
+    ProvingFailed { code: Option<i32>, stderr: String },
+    /// `valida verify` rejected the proof.
+    VerificationFailed { code: Option<i32>, stderr: String },
+    /// A saved proof could not be decoded.
+    Malformed(String),
+    /// The thread running an asynchronous operation panicked.
+    Panicked,
+}
+
+impl std::fmt::Display for ProveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProveError::Spawn { command, source } => write!(
+                f,
+                "Failed to start {}: {source}. Are you sure it is in your `$PATH`?",
+                command.display()
+            ),
+            ProveError::Io(e) => write!(f, "Failed to access proof files: {e}"),
+            ProveError::ProvingFailed { code, stderr } => {
+                write!(f, "Proving failed with exit code: {code:?}\n\n{stderr}")
+            }
+            ProveError::VerificationFailed { code, stderr } => {
+                write!(f, "Proof rejected with exit code: {code:?}\n\n{stderr}")
+            }
+            ProveError::Malformed(e) => write!(f, "Malformed proof: {e}"),
+            ProveError::Panicked => write!(f, "The proving thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for ProveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProveError::Spawn { source, .. } | ProveError::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ProveError {
+    fn from(e: std::io::Error) -> Self {
+        ProveError::Io(e)
+    }
+}
+
+/// Proves and verifies runs of guest programs.
+#[derive(Debug, Clone)]
+pub struct Prover {
+    valida: PathBuf,
+}
+
+impl Default for Prover {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Prover {
+    /// A prover using the `valida` command in `$PATH`.
+    pub fn new() -> Self {
+        Self {
+            valida: PathBuf::from(DEFAULT_VALIDA_COMMAND),
+        }
+    }
+
+    /// Use a `valida` executable other than the one in `$PATH`.
+    pub fn valida_command(mut self, command: impl Into<PathBuf>) -> Self {
+        self.valida = command.into();
+        self
+    }
+
+    /// Run the guest at `elf` on `input` and prove the run.
+    pub fn prove(&self, elf: impl AsRef<Path>, input: &[u8]) -> Result<Proof, ProveError> {
+        let dir = tempfile::tempdir()?;
+        let output_path = dir.path().join("output");
+        let proof_path = dir.path().join("proof");
+
+        let mut command = self.command("prove", elf.as_ref(), &output_path, &proof_path);
+        let mut child = command
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|source| self.spawn_error(source))?;
+        // unwrap is safe because we know the stdin is piped
+        let mut stdin = child.stdin.take().unwrap();
+        // The pipe breaks if the guest exits without reading all its input.
+        let _ = stdin.write_all(input);
+        drop(stdin);
+
+        let result = child.wait_with_output()?;
+        if !result.status.success() {
+            return Err(ProveError::ProvingFailed {
+                code: result.status.code(),
+                stderr: String::from_utf8_lossy(&result.stderr).into_owned(),
+            });
+        }
+        Proof::from_files(proof_path, output_path)
+    }
+
+    /// Verify a proof of a run of the guest at `elf`.
+    pub fn verify(&self, elf: impl AsRef<Path>, proof: &Proof) -> Result<(), ProveError> {
+        let dir = tempfile::tempdir()?;
+        let output_path = dir.path().join("output");
+        let proof_path = dir.path().join("proof");
+        std::fs::write(&output_path, &proof.output)?;
+        std::fs::write(&proof_path, &proof.proof)?;
+
+        let result: Output = self
+            .command("verify", elf.as_ref(), &output_path, &proof_path)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|source| self.spawn_error(source))?;
+        if result.status.success() {
+            Ok(())
+        } else {
+            Err(ProveError::VerificationFailed {
+                code: result.status.code(),
+                stderr: String::from_utf8_lossy(&result.stderr).into_owned(),
+            })
+        }
+    }
+
+    /// [`Prover::prove`] on a background thread. The returned task can be awaited on any
+    /// executor, or waited on with [`Task::wait`].
+    pub fn prove_async(&self, elf: impl Into<PathBuf>, input: Vec<u8>) -> Task<Proof> {
+        let prover = self.clone();
+        let elf = elf.into();
+        Task::spawn(move || prover.prove(elf, &input))
+    }
+
+    /// [`Prover::verify`] on a background thread.
+    pub fn verify_async(&self, elf: impl Into<PathBuf>, proof: Proof) -> Task<()> {
+        let prover = self.clone();
+        let elf = elf.into();
+        Task::spawn(move || prover.verify(elf, &proof))
+    }
+
+    fn command(&self, subcommand: &str, elf: &Path, output: &Path, proof: &Path) -> Command {
+        let mut command = Command::new(&self.valida);
+        command
+            .arg(subcommand)
+            .arg(elf)
+            .arg(output)
+            .arg(proof)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        command
+    }
+
+    fn spawn_error(&self, source: std::io::Error) -> ProveError {
+        ProveError::Spawn {
+            command: self.valida.clone(),
+            source,
+        }
+    }
+}
+
+/// A proving operation running on a background thread.
+///
+/// Implements [`Future`] without depending on a particular async runtime.
+pub struct Task<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+struct TaskState<T> {
+    result: Option<Result<T, ProveError>>,
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    fn spawn(f: impl FnOnce() -> Result<T, ProveError> + Send + 'static) -> Self {
+        let state = Arc::new(Mutex::new(TaskState {
+            result: None,
+            waker: None,
+        }));
+
+        let thread_state = Arc::clone(&state);
+        std::thread::spawn(move || {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+                .unwrap_or(Err(ProveError::Panicked));
+            let mut state = thread_state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        Self { state }
+    }
+}
+
+impl<T> Task<T> {
+    /// Returns `true` once the operation has finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().result.is_some()
+    }
+
+    /// Block the current thread until the operation finishes.
+    pub fn wait(self) -> Result<T, ProveError> {
+        loop {
+            if let Some(result) = self.state.lock().unwrap().result.take() {
+                return result;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = Result<T, ProveError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[test]
+fn test_failed_proving_is_reported() {
+    let task = Prover::new()
+        .valida_command("false")
+        .prove_async("guest", Vec::new());
+    assert!(matches!(
+        task.wait(),
+        Err(ProveError::ProvingFailed { code: Some(1), .. })
+    ));
+}
@@ -0,0 +1,149 @@
+//! Detecting which release of the `valida` CLI is installed.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+/// A release of the `valida` CLI, as reported by `valida --version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ValidaVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+/// The oldest `valida` the runner can drive, the one shipped with `valida-toolchain` v0.7.0-alpha.
+pub const MIN_VALIDA_VERSION: ValidaVersion = ValidaVersion::new(0, 7, 0);
+
+impl ValidaVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Find the first `major.minor.patch` in the output of `valida --version`, ignoring
+    /// pre-release and build suffixes.
+    pub fn parse(output: &str) -> Option<Self> {
+        output.split_whitespace().find_map(|word| {
+            let word = word.trim_start_matches('v');
+            let mut parts = word.splitn(3, '.');
+            let major = parts.next()?.parse().ok()?;
+            let minor = parts.next()?.parse().ok()?;
+            let patch = parts.next()?;
+            let end = patch
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(patch.len());
+            Some(Self::new(major, minor, patch[..end].parse().ok()?))
+        })
+    }
+
+    /// The arguments of `valida run` for `program`, writing its output file to `output`.
+    ///
+    /// Every supported release takes the same arguments; this is where a release that changes
+    /// them is handled.
+    pub(crate) fn run_args(self, program: &Path, output: &Path) -> Vec<OsString> {
+        vec!["run".into(), program.into(), output.into()]
+    }
+}
+
+impl std::fmt::Display for ValidaVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Why a `valida` cannot be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionError {
+    /// The command could not be started.
+    Spawn { command: PathBuf, message: String },
+    /// The command is older than [`MIN_VALIDA_VERSION`].
+    Unsupported {
+        command: PathBuf,
+        version: ValidaVersion,
+    },
+}
+
+impl std::fmt::Display for VersionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionError::Spawn { command, message } => write!(
+                f,
+                "Failed to start {}: {message}. Install valida-toolchain v0.7.0-alpha or newer \
+                 and make sure `valida` is in your `$PATH`, or set VALIDA_COMMAND to its path.",
+                command.display()
+            ),
+            VersionError::Unsupported { command, version } => write!(
+                f,
+                "{} is valida {version}, but at least {MIN_VALIDA_VERSION} is required. Install \
+                 a newer valida-toolchain, or set VALIDA_COMMAND to a newer valida.",
+                command.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
+
+/// Check that `command` is a `valida` the runner can drive, returning its version if it reports
+/// one.
+///
+/// The version is probed with `--version` once per command. A `valida` whose version cannot be
+/// determined is assumed to be compatible, with a warning.
+pub fn check_valida(command: &Path) -> Result<Option<ValidaVersion>, VersionError> {
+    type Probed = Result<Option<ValidaVersion>, VersionError>;
+    static PROBED: Mutex<BTreeMap<PathBuf, Probed>> = Mutex::new(BTreeMap::new());
+
+    PROBED
+        .lock()
+        .unwrap()
+        .entry(command.to_path_buf())
+        .or_insert_with(|| probe(command))
+        .clone()
+}
+
+fn probe(command: &Path) -> Result<Option<ValidaVersion>, VersionError> {
+    let output = Command::new(command)
+        .arg("--version")
+        .output()
+        .map_err(|e| VersionError::Spawn {
+            command: command.to_path_buf(),
+            message: e.to_string(),
+        })?;
+    let reported = String::from_utf8_lossy(&output.stdout);
+    let Some(version) = ValidaVersion::parse(&reported).filter(|_| output.status.success()) else {
+        eprintln!(
+            "warning: could not determine the version of {}; assuming it is compatible",
+            command.display()
+        );
+        return Ok(None);
+    };
+    if version < MIN_VALIDA_VERSION {
+        return Err(VersionError::Unsupported {
+            command: command.to_path_buf(),
+            version,
+        });
+    }
+    Ok(Some(version))
+}
+
+#[test]
+fn test_parse_version() {
+    assert_eq!(
+        ValidaVersion::parse("valida 0.7.0-alpha\n"),
+        Some(ValidaVersion::new(0, 7, 0))
+    );
+    assert_eq!(
+        ValidaVersion::parse("valida-cli v1.12.3+abc"),
+        Some(ValidaVersion::new(1, 12, 3))
+    );
+    assert_eq!(ValidaVersion::parse("valida (unknown)"), None);
+    assert!(ValidaVersion::new(0, 6, 9) < MIN_VALIDA_VERSION);
+}
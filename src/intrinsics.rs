@@ -0,0 +1,54 @@
+//! Bindings to VM facilities beyond the C library.
+//!
+//! These are only linked in when the `intrinsics` feature is enabled, since older toolchains do
+//! not provide them. Without the feature, or on the host, each function reports the facility as
+//! unavailable.
+
+#[cfg(all(valida, feature = "intrinsics"))]
+extern "C" {
+    fn valida_cycle_count() -> u64;
+    fn valida_halt(code: u32) -> !;
+}
+
+/// The number of cycles the VM has executed so far, if the VM exposes a cycle counter.
+pub fn cycle_count() -> Option<u64> {
+    #[cfg(all(valida, feature = "intrinsics"))]
+    return Some(unsafe { valida_cycle_count() });
+
+    #[cfg(not(all(valida, feature = "intrinsics")))]
+    None
+}
+
+/// Whether the VM can halt with an exit code, see [`halt`].
+pub const fn can_halt() -> bool {
+    cfg!(all(valida, feature = "intrinsics"))
+}
+
+/// Stop the VM with exit status `code`.
+///
+/// Returns only if the VM has no halt intrinsic (and always on the host), in which case the caller
+/// has to signal the outcome some other way.
+pub fn halt(code: u32) {
+    #[cfg(all(valida, feature = "intrinsics"))]
+    unsafe {
+        valida_halt(code)
+    }
+
+    #[cfg(not(all(valida, feature = "intrinsics")))]
+    let _ = code;
+}
+
+/// Make a panic halt the VM with exit status 101, as a native Rust program exits on panic.
+///
+/// Guests are built with `panic = "abort"`, and the VM has no way to report an abort, so without
+/// this a panic is only visible in the output. The default hook still prints the message first,
+/// followed by the recent [`breadcrumbs`](crate::trace). If the VM cannot [`halt`], only the
+/// breadcrumbs are added. `entrypoint!` calls this before the guest's main.
+pub fn halt_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        crate::trace::print_recent();
+        halt(101);
+    }));
+}
@@ -1,20 +1,115 @@
 use bincode::Options;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{error::Error, io::Read};
+use std::{
+    cell::RefCell,
+    error::Error,
+    io::Read,
+    marker::PhantomData,
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+mod codec;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod compression;
+mod public_values;
+pub mod recorder;
+
+#[cfg(feature = "postcard")]
+pub use codec::Postcard;
+pub use codec::{Bincode, Raw, TapeCodec};
+#[cfg(feature = "gzip")]
+pub use compression::{GzDecoder, GzEncoder};
+#[cfg(feature = "zstd")]
+pub use compression::{ZstdDecoder, ZstdEncoder};
+pub use public_values::{PublicValues, PublicValuesReader};
 
 extern "C" {
+    #[deprecated(note = "use the functions in `io`, which wrap the syscall")]
     pub fn getchar() -> u32;
+    #[deprecated(note = "use the functions in `io`, which wrap the syscall")]
     pub fn putchar(c: u32) -> u32;
 }
 
+#[cfg(all(valida, feature = "intrinsics"))]
+extern "C" {
+    fn valida_map_input(len: *mut usize) -> *const u8;
+}
+
+/// Environment variable naming the file [`input_slice`] reads on the host.
+pub const VALIDA_INPUT_FILE_ENV: &str = "VALIDA_INPUT_FILE";
+
+static BYTES_IN: AtomicU64 = AtomicU64::new(0);
+static BYTES_OUT: AtomicU64 = AtomicU64::new(0);
+
+/// Read the next byte off the input tape, or `None` at EOF.
+pub(crate) fn tape_read_byte() -> Option<u8> {
+    let byte = testing::mock_read_byte().unwrap_or_else(crate::sys::read_byte);
+    if let Some(byte) = byte {
+        BYTES_IN.fetch_add(1, Ordering::Relaxed);
+        recorder::record(&[byte], false);
+    }
+    byte
+}
+
+/// Write `bytes` to the output tape.
+fn tape_write(bytes: &[u8]) {
+    if capture_write(bytes) {
+        return;
+    }
+    BYTES_OUT.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+    recorder::record(bytes, true);
+    if testing::mock_write(bytes) {
+        return;
+    }
+    crate::sys::write_bytes(bytes);
+}
+
+/// How much of the tapes the guest has used, see [`stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TapeStats {
+    /// The bytes read off the input tape.
+    pub bytes_in: u64,
+    /// The bytes written to the output tape, not counting captured output.
+    pub bytes_out: u64,
+}
+
+/// The bytes read and written through this module so far.
+///
+/// A guest can check it consumed its whole input, which catches framing mismatches between the
+/// host and the guest:
+/// ```rust
+/// # valida_rs::io::testing::set_input(b"42\n".to_vec());
+/// # let expected_input_len = 3;
+/// let answer: u32 = valida_rs::io::read_line().unwrap();
+/// assert_eq!(valida_rs::io::stats().bytes_in, expected_input_len);
+/// # valida_rs::io::testing::reset();
+/// # assert_eq!(answer, 42);
+/// ```
+/// Reads of a mapped [`input_slice`] do not go through the tape and are not counted; mocked tapes
+/// are counted like the real ones.
+pub fn stats() -> TapeStats {
+    TapeStats {
+        bytes_in: BYTES_IN.load(Ordering::Relaxed),
+        bytes_out: BYTES_OUT.load(Ordering::Relaxed),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct InputTape;
 
 impl Read for InputTape {
+    /// Fill `buf` from the input tape, stopping short at EOF.
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        (0..buf.len()).for_each(|i| {
-            buf[i] = unsafe { getchar() as u8 };
-        });
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let Some(input) = tape_read_byte() else {
+                return Ok(i);
+            };
+            *byte = input;
+        }
         Ok(buf.len())
     }
 }
@@ -24,13 +119,21 @@ pub struct OutputTape;
 
 impl OutputTape {
     pub fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        (0..buf.len()).for_each(|i| unsafe {
-            putchar(buf[i] as u32);
-        });
+        tape_write(buf);
         Ok(buf.len())
     }
 }
 
+impl std::io::Write for OutputTape {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        OutputTape::write(self, buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Reads a single line of input from stdin and returns it as a generic type T.
 pub fn read_line<T>() -> Result<T, Box<dyn Error>>
 where
@@ -45,50 +148,161 @@ where
     }
 }
 
+/// Reads the next whitespace-delimited token off the input tape and parses it as a T.
+///
+/// Leading whitespace is skipped, and the whitespace byte ending the token is consumed.
+pub fn read_token<T>() -> Result<T, Box<dyn Error>>
+where
+    T: std::str::FromStr,
+    <T as std::str::FromStr>::Err: std::error::Error + 'static,
+{
+    let mut token = Vec::new();
+    while let Some(input_byte) = tape_read_byte() {
+        if input_byte.is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            break;
+        }
+        token.push(input_byte);
+    }
+    if token.is_empty() {
+        return Err("unexpected end of input".into());
+    }
+    Ok(std::str::from_utf8(&token)?.parse()?)
+}
+
+/// Reads a line of whitespace-separated values off the input tape, such as integers.
+pub fn read_ints_line<T>() -> Result<Vec<T>, Box<dyn Error>>
+where
+    T: std::str::FromStr,
+    <T as std::str::FromStr>::Err: std::error::Error + 'static,
+{
+    let line = read_until(b'\n')?;
+    std::str::from_utf8(&line)?
+        .split_whitespace()
+        .map(|word| word.parse().map_err(|e| Box::new(e) as Box<dyn Error>))
+        .collect()
+}
+
+/// The rest of the input tape decoded as UTF-8, one character at a time.
+///
+/// An invalid sequence yields an [`InvalidData`](std::io::ErrorKind::InvalidData) error and
+/// decoding continues after it. A sequence cut short by a byte that is not a continuation byte
+/// ends before that byte, which starts the next character; the iterator holds on to it, so
+/// dropping the iterator right after such an error loses it.
+pub fn chars() -> impl Iterator<Item = std::io::Result<char>> {
+    let mut next = None;
+    std::iter::from_fn(move || {
+        let mut bytes = [next.take().or_else(tape_read_byte)?, 0, 0, 0];
+        let len = match bytes[0] {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 0,
+        };
+        let mut read = 1;
+        while read < len {
+            match tape_read_byte() {
+                Some(c) if c & 0xc0 == 0x80 => bytes[read] = c,
+                Some(c) => {
+                    next = Some(c);
+                    break;
+                }
+                None => break,
+            }
+            read += 1;
+        }
+        let decoded = std::str::from_utf8(&bytes[..read])
+            .ok()
+            .and_then(|s| s.chars().next());
+        Some(decoded.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid UTF-8 on the input tape",
+            )
+        }))
+    })
+}
+
 /// Mimic std::fs::read https://doc.rust-lang.org/std/fs/fn.read.html
 /// Read from the input tape until EOF and return the contents as a Vec<u8>.
 pub fn read() -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut result = Vec::new();
-    loop {
-        let input = unsafe { getchar() as u32 };
-        if input == u32::MAX {
-            // EOF reached
-            break;
+    Ok(std::iter::from_fn(tape_read_byte).collect())
+}
+
+/// The whole input, for parsing large inputs in place.
+///
+/// In the VM with the `intrinsics` feature, the input tape is mapped read-only into guest memory,
+/// so nothing is copied. Otherwise the input is read once, on the first call, from the file named
+/// by `VALIDA_INPUT_FILE` if it is set on the host, or else from what remains of the input tape,
+/// which is then at EOF for the other functions in this module.
+pub fn input_slice() -> &'static [u8] {
+    static INPUT: OnceLock<&'static [u8]> = OnceLock::new();
+
+    INPUT.get_or_init(|| {
+        #[cfg(all(valida, feature = "intrinsics"))]
+        return unsafe {
+            let mut len = 0;
+            let ptr = valida_map_input(&mut len);
+            std::slice::from_raw_parts(ptr, len)
+        };
+
+        #[cfg(not(all(valida, feature = "intrinsics")))]
+        {
+            let input = match std::env::var_os(VALIDA_INPUT_FILE_ENV) {
+                Some(path) if !crate::target::is_valida() => std::fs::read(&path)
+                    .unwrap_or_else(|e| panic!("failed to read {}: {e}", path.display())),
+                _ => read().unwrap_or_default(),
+            };
+            input.leak()
         }
-        result.push(input as u8);
-    }
-    Ok(result)
+    })
 }
 
 /// Read from the input tape until we hit EOF or a specific character.
 pub fn read_until(stop_char: u8) -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut result = Vec::new();
-    loop {
-        let input = unsafe { getchar() as u32 };
-        if input == u32::MAX {
-            // EOF reached
-            break;
-        }
-        let input_byte = input as u8;
-        if input_byte == stop_char {
-            // Found the character to stop at
-            break;
-        }
-        result.push(input_byte);
-    }
-    Ok(result)
+    Ok(std::iter::from_fn(tape_read_byte)
+        .take_while(|&byte| byte != stop_char)
+        .collect())
 }
 
 /// Read n bytes from the input tape.
 pub fn read_n(n: usize) -> Result<Vec<u8>, Box<dyn Error>> {
-    Ok((0..n).map(|_| unsafe { getchar() as u8 }).collect())
+    // Past EOF, the bytes read as 0xff, as the raw `getchar` result truncated to a byte.
+    Ok((0..n)
+        .map(|_| tape_read_byte().unwrap_or(u8::MAX))
+        .collect())
+}
+
+/// Read the rest of the input tape in chunks of `chunk_size` bytes, the last of which may be
+/// shorter, so large inputs can be processed without holding them in memory.
+///
+/// # Panics
+/// If `chunk_size` is zero.
+pub fn chunks(chunk_size: usize) -> impl Iterator<Item = std::io::Result<Vec<u8>>> {
+    assert!(chunk_size > 0, "chunk size must be positive");
+    let mut at_eof = false;
+    std::iter::from_fn(move || {
+        if at_eof {
+            return None;
+        }
+        let mut chunk = Vec::with_capacity(chunk_size);
+        while chunk.len() < chunk_size {
+            let Some(input) = tape_read_byte() else {
+                at_eof = true;
+                break;
+            };
+            chunk.push(input);
+        }
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    })
 }
 
 /// Write the contents of a vector to the output tape.
 pub fn write_vec(v: impl AsRef<[u8]>) -> Result<(), Box<dyn Error>> {
-    v.as_ref().iter().for_each(|c| unsafe {
-        putchar(*c as u32);
-    });
+    tape_write(v.as_ref());
     Ok(())
 }
 
@@ -126,3 +340,298 @@ pub fn write<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
     write_vec(&bytes)?;
     Ok(())
 }
+
+/// Read a value framed as by [`write_serde`] off the input tape, decoding it with `C`.
+pub fn read_serde<C: TapeCodec<T>, T>() -> Result<T, Box<dyn Error>> {
+    let len = read_line::<usize>()?;
+    C::decode(&read_n(len)?)
+}
+
+/// Encode a value with `C` and write it to the output tape, preceded by its length and a newline.
+pub fn write_serde<C: TapeCodec<T>, T: ?Sized>(value: &T) -> Result<(), Box<dyn Error>> {
+    write_vec(C::frame(value)?)
+}
+
+/// The prefix of the line [`query`] writes a request on.
+pub const QUERY_PREFIX: &str = "valida-query:";
+
+/// Ask the host for data the guest cannot compute itself, such as an oracle's answer.
+///
+/// The request is written to the output tape as a line of `valida-query:` followed by its bytes in
+/// hex. The call then blocks until the host writes the response to the input tape, framed as a
+/// decimal length, a newline and the bytes. `host::Runner::on_query` answers
+/// queries from a host application. In native tests, a handler registered with
+/// [`testing::set_query_handler`] answers them in-process, and answers the same test's queries
+/// when it is run in the VM.
+///
+/// # Panics
+/// If the response is not framed correctly.
+pub fn query(request: &[u8]) -> Vec<u8> {
+    #[cfg(not(valida))]
+    if let Some(handler) = testing::query_handler() {
+        return handler.lock().unwrap()(request);
+    }
+
+    let line = format!("{QUERY_PREFIX}{}\n", crate::snapshot::to_hex(request));
+    write_vec(line).expect("failed to write query");
+    let len = read_line::<usize>().expect("malformed query response length");
+    read_n(len).expect("failed to read query response")
+}
+
+thread_local! {
+    /// Buffers that output is being captured into, innermost last.
+    static CAPTURES: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f`, capturing everything it writes to the output tape instead of writing it.
+///
+/// This lets a guest post-process a sub-computation's output, for example to hash it before
+/// committing. Captures nest; the output tape is restored when `f` returns or panics.
+/// ```rust
+/// # valida_rs::io::testing::set_input(Vec::new());
+/// let output = valida_rs::io::capture(|| valida_rs::io::write_vec(b"result").unwrap());
+/// assert_eq!(output, b"result");
+/// valida_rs::io::write_vec(output.len().to_string()).unwrap();
+/// # assert_eq!(valida_rs::io::testing::take_output(), b"6");
+/// # valida_rs::io::testing::reset();
+/// ```
+pub fn capture(f: impl FnOnce()) -> Vec<u8> {
+    let capture = Capture::start();
+    f();
+    capture.finish()
+}
+
+/// Redirects the output tape into an in-memory buffer until it is finished or dropped.
+///
+/// Prefer [`capture`] unless the captured code does not fit in a closure.
+#[must_use = "the output tape is restored when the capture is dropped"]
+#[derive(Debug)]
+pub struct Capture {
+    /// The index of this capture's buffer in `CAPTURES`.
+    index: usize,
+    /// The buffers are thread-local.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Capture {
+    /// Start capturing the output tape.
+    pub fn start() -> Self {
+        let index = CAPTURES.with_borrow_mut(|captures| {
+            captures.push(Vec::new());
+            captures.len() - 1
+        });
+        Self {
+            index,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Stop capturing, returning the captured output.
+    pub fn finish(self) -> Vec<u8> {
+        let output = self.end();
+        mem::forget(self);
+        output
+    }
+
+    /// Remove this capture's buffer, and those of any capture started after it.
+    fn end(&self) -> Vec<u8> {
+        CAPTURES.with_borrow_mut(|captures| {
+            captures
+                .drain(self.index.min(captures.len())..)
+                .next()
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+/// Capture `bytes` if a capture is active, returning whether they were.
+fn capture_write(bytes: &[u8]) -> bool {
+    CAPTURES.with_borrow_mut(|captures| match captures.last_mut() {
+        Some(buffer) => {
+            buffer.extend_from_slice(bytes);
+            true
+        }
+        None => false,
+    })
+}
+
+#[test]
+fn test_chunks() {
+    testing::set_input(b"abcdefg".to_vec());
+    let read: Vec<Vec<u8>> = chunks(3).map(Result::unwrap).collect();
+    assert_eq!(read, [&b"abc"[..], b"def", b"g"]);
+
+    testing::set_input(b"abcdef".to_vec());
+    assert_eq!(chunks(3).count(), 2);
+    testing::reset();
+}
+
+#[test]
+fn test_text_utilities() {
+    testing::set_input("  12 -3\n4 5 6\nhé€𝄞".as_bytes().to_vec());
+    assert_eq!(read_token::<u32>().unwrap(), 12);
+    assert_eq!(read_token::<i32>().unwrap(), -3);
+    assert_eq!(read_ints_line::<u64>().unwrap(), [4, 5, 6]);
+    let text: String = chars().map(Result::unwrap).collect();
+    assert_eq!(text, "hé€𝄞");
+    assert!(read_token::<u32>().is_err());
+
+    testing::set_input(vec![b'a', 0xff, b'b']);
+    let decoded: Vec<_> = chars().map(|c| c.ok()).collect();
+    assert_eq!(decoded, [Some('a'), None, Some('b')]);
+
+    testing::set_input(vec![0xe2, b'a']);
+    let mut decoded = chars();
+    assert!(decoded.next().unwrap().is_err());
+    assert_eq!(decoded.next().unwrap().unwrap(), 'a');
+    assert!(decoded.next().is_none());
+    testing::reset();
+}
+
+#[test]
+fn test_query() {
+    testing::set_input(b"3\nabc".to_vec());
+    assert_eq!(query(b"\x01"), b"abc");
+    assert_eq!(testing::take_output(), b"valida-query:01\n");
+    testing::reset();
+
+    // Answered in-process natively, and by the test runner in the VM.
+    testing::set_query_handler(|request| request.iter().rev().copied().collect());
+    assert_eq!(query(b"oracle"), b"elcaro");
+}
+
+#[test]
+fn host_only_capture_restores_tape_on_panic() {
+    testing::set_input(Vec::new());
+    let outer = capture(|| {
+        write_vec(b"outer").unwrap();
+        let inner = std::panic::catch_unwind(|| {
+            let _capture = Capture::start();
+            write_vec(b"lost").unwrap();
+            panic!("inner");
+        });
+        assert!(inner.is_err());
+        write_vec(b"!").unwrap();
+    });
+    assert_eq!(outer, b"outer!");
+    write_vec(b"tape").unwrap();
+    assert_eq!(testing::take_output(), b"tape");
+    testing::reset();
+}
+
+#[test]
+fn test_stats() {
+    testing::set_input(b"12\nrest".to_vec());
+    let before = stats();
+    assert_eq!(read_line::<u32>().unwrap(), 12);
+    write_vec(b"ok").unwrap();
+    capture(|| write_vec(b"captured").unwrap());
+    let after = stats();
+    assert_eq!(after.bytes_in - before.bytes_in, 3);
+    assert_eq!(after.bytes_out - before.bytes_out, 2);
+    testing::reset();
+}
+
+/// In-memory tapes for unit testing tape-reading code without the VM.
+///
+/// Once [`set_input`](testing::set_input) is called, every function in this module reads from the
+/// given buffer and writes to an in-memory buffer instead of the real tapes (stdin and stdout on
+/// the host). The mock is local to the current thread, and works the same way in the VM so tests
+/// using it run in both environments.
+///
+/// ```rust
+/// valida_rs::io::testing::set_input(b"42\n".to_vec());
+/// assert_eq!(valida_rs::io::read_line::<u32>().unwrap(), 42);
+/// valida_rs::io::write_vec(b"ok").unwrap();
+/// assert_eq!(valida_rs::io::testing::take_output(), b"ok");
+/// valida_rs::io::testing::reset();
+/// ```
+pub mod testing {
+    use std::{
+        cell::RefCell,
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Default)]
+    struct MockTapes {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    }
+
+    thread_local! {
+        static MOCK: RefCell<Option<MockTapes>> = const { RefCell::new(None) };
+    }
+
+    /// Replace the input tape with `input` and start capturing the output tape.
+    ///
+    /// Output captured so far is kept.
+    pub fn set_input(input: impl Into<Vec<u8>>) {
+        MOCK.with_borrow_mut(|mock| {
+            mock.get_or_insert_with(MockTapes::default).input = input.into().into();
+        });
+    }
+
+    /// Take the output written since the mock was set up or last taken from.
+    ///
+    /// Returns an empty buffer if the tapes are not mocked.
+    pub fn take_output() -> Vec<u8> {
+        MOCK.with_borrow_mut(|mock| {
+            mock.as_mut()
+                .map(|mock| std::mem::take(&mut mock.output))
+                .unwrap_or_default()
+        })
+    }
+
+    /// Go back to the real input and output tapes.
+    pub fn reset() {
+        MOCK.with_borrow_mut(|mock| *mock = None);
+    }
+
+    /// A handler that answers [`query`](super::query) requests.
+    pub(crate) type QueryHandler = Arc<Mutex<dyn FnMut(&[u8]) -> Vec<u8> + Send>>;
+
+    static QUERY_HANDLER: Mutex<Option<QueryHandler>> = Mutex::new(None);
+
+    /// Answer [`query`](super::query) requests with `handler` instead of the host.
+    ///
+    /// Unlike the mocked tapes, the handler is shared by all threads. When the test that set it
+    /// runs in the VM, the test runner answers the VM's queries with the same handler, so a test
+    /// can simulate an interactive oracle in both environments.
+    pub fn set_query_handler(handler: impl FnMut(&[u8]) -> Vec<u8> + Send + 'static) {
+        *QUERY_HANDLER.lock().unwrap() = Some(Arc::new(Mutex::new(handler)));
+    }
+
+    /// Remove the handler set with [`set_query_handler`], so the next test starts without one.
+    #[cfg(all(not(valida), feature = "host"))]
+    pub(crate) fn clear_query_handler() {
+        QUERY_HANDLER.lock().unwrap().take();
+    }
+
+    #[cfg(not(valida))]
+    pub(crate) fn query_handler() -> Option<QueryHandler> {
+        QUERY_HANDLER.lock().unwrap().clone()
+    }
+
+    /// The next mocked input byte (`Some(None)` at EOF), or `None` if the input is not mocked.
+    pub(super) fn mock_read_byte() -> Option<Option<u8>> {
+        MOCK.with_borrow_mut(|mock| mock.as_mut().map(|mock| mock.input.pop_front()))
+    }
+
+    /// Capture `bytes` if the output is mocked, returning whether they were.
+    pub(super) fn mock_write(bytes: &[u8]) -> bool {
+        MOCK.with_borrow_mut(|mock| match mock {
+            Some(mock) => {
+                mock.output.extend_from_slice(bytes);
+                true
+            }
+            None => false,
+        })
+    }
+}
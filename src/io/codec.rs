@@ -0,0 +1,96 @@
+use std::error::Error;
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A wire format for values passed on the tapes.
+///
+/// [`super::read_serde`] and [`super::write_serde`] frame each encoded value the way
+/// [`super::write`] does, with its length in decimal on a line of its own; hosts can build input
+/// the same way with [`TapeCodec::frame`].
+pub trait TapeCodec<T: ?Sized> {
+    fn encode(value: &T) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    fn decode(bytes: &[u8]) -> Result<T, Box<dyn Error>>
+    where
+        T: Sized;
+
+    /// The framed encoding of `value`, as read by [`super::read_serde`].
+    fn frame(value: &T) -> Result<Vec<u8>, Box<dyn Error>> {
+        let bytes = Self::encode(value)?;
+        let mut framed = format!("{}\n", bytes.len()).into_bytes();
+        framed.extend(bytes);
+        Ok(framed)
+    }
+}
+
+/// bincode with fixed-width little-endian integers, the format of [`super::write`] and
+/// [`super::read_and_deserialize`].
+#[derive(Debug, Clone, Copy)]
+pub struct Bincode;
+
+impl<T: Serialize + DeserializeOwned> TapeCodec<T> for Bincode {
+    fn encode(value: &T) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(bincode_options().serialize(value)?)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T, Box<dyn Error>> {
+        Ok(bincode_options().deserialize(bytes)?)
+    }
+}
+
+fn bincode_options() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .with_little_endian()
+}
+
+/// postcard, a compact format with variable-length integers. Requires the `postcard` feature.
+#[cfg(feature = "postcard")]
+#[derive(Debug, Clone, Copy)]
+pub struct Postcard;
+
+#[cfg(feature = "postcard")]
+impl<T: Serialize + DeserializeOwned> TapeCodec<T> for Postcard {
+    fn encode(value: &T) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(postcard::to_allocvec(value)?)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T, Box<dyn Error>> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+}
+
+/// Bytes passed through unchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct Raw;
+
+impl TapeCodec<Vec<u8>> for Raw {
+    fn encode(value: &Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(value.clone())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+#[test]
+fn test_codecs_round_trip() {
+    fn round_trip<C: TapeCodec<(u32, String)>>() {
+        let value = (7, "seven".to_string());
+        super::testing::set_input(C::frame(&value).unwrap());
+        let read = super::read_serde::<C, (u32, String)>();
+        super::testing::reset();
+        assert_eq!(read.unwrap(), value);
+    }
+
+    round_trip::<Bincode>();
+    #[cfg(feature = "postcard")]
+    round_trip::<Postcard>();
+
+    super::testing::set_input(Vec::new());
+    super::write_serde::<Raw, _>(&b"raw".to_vec()).unwrap();
+    assert_eq!(super::testing::take_output(), b"3\nraw");
+    super::testing::reset();
+}
@@ -0,0 +1,330 @@
+//! Gzip and zstd adapters, for inputs shipped to the VM compressed.
+//!
+//! The decoders wrap any [`Read`], such as [`InputTape`](super::InputTape), and decompress as
+//! they are read. The encoders buffer what is written and compress it when finished.
+//! ```rust
+//! use std::io::Read;
+//! use valida_rs::io::{GzDecoder, InputTape};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let gzip = [
+//! #     0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+//! #     0x07, 0x00, 0x86, 0xa6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00,
+//! # ];
+//! # valida_rs::io::testing::set_input(gzip.to_vec());
+//! let mut input = Vec::new();
+//! GzDecoder::new(InputTape).read_to_end(&mut input)?;
+//! # assert_eq!(input, b"hello");
+//! # valida_rs::io::testing::reset();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, Read, Write};
+
+const BUFFER_SIZE: usize = 8 * 1024;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Decompresses a gzip stream (RFC 1952) read from `R`.
+#[cfg(feature = "gzip")]
+pub struct GzDecoder<R> {
+    inner: R,
+    state: Box<miniz_oxide::inflate::stream::InflateState>,
+    /// Compressed bytes read from `inner` and not yet consumed.
+    buffer: Vec<u8>,
+    position: usize,
+    header_read: bool,
+    finished: bool,
+    crc: u32,
+    size: u32,
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> GzDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        use miniz_oxide::{inflate::stream::InflateState, DataFormat};
+
+        Self {
+            inner,
+            state: InflateState::new_boxed(DataFormat::Raw),
+            buffer: Vec::new(),
+            position: 0,
+            header_read: false,
+            finished: false,
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    /// Make sure there are unconsumed bytes in the buffer, returning `false` at the end of input.
+    fn fill_buffer(&mut self) -> io::Result<bool> {
+        if self.position == self.buffer.len() {
+            self.buffer.resize(BUFFER_SIZE, 0);
+            let read = self.inner.read(&mut self.buffer)?;
+            self.buffer.truncate(read);
+            self.position = 0;
+        }
+        Ok(self.position < self.buffer.len())
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if !self.fill_buffer()? {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.position += 1;
+        Ok(self.buffer[self.position - 1])
+    }
+
+    fn read_u32_le(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        for byte in &mut bytes {
+            *byte = self.read_byte()?;
+        }
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        const FHCRC: u8 = 1 << 1;
+        const FEXTRA: u8 = 1 << 2;
+        const FNAME: u8 = 1 << 3;
+        const FCOMMENT: u8 = 1 << 4;
+
+        let mut header = [0; 10];
+        for byte in &mut header {
+            *byte = self.read_byte()?;
+        }
+        if header[..3] != [0x1f, 0x8b, 8] {
+            return Err(invalid_data("not a gzip stream"));
+        }
+        let flags = header[3];
+        if flags & FEXTRA != 0 {
+            let len = u16::from_le_bytes([self.read_byte()?, self.read_byte()?]);
+            for _ in 0..len {
+                self.read_byte()?;
+            }
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                while self.read_byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            self.read_byte()?;
+            self.read_byte()?;
+        }
+        self.header_read = true;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use miniz_oxide::{inflate::stream::inflate, MZFlush, MZStatus};
+
+        if !self.header_read {
+            self.read_header()?;
+        }
+        while !self.finished && !buf.is_empty() {
+            if !self.fill_buffer()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let result = inflate(
+                &mut self.state,
+                &self.buffer[self.position..],
+                buf,
+                MZFlush::None,
+            );
+            self.position += result.bytes_consumed;
+            let written = &buf[..result.bytes_written];
+            self.crc = crc32_update(self.crc, written);
+            self.size = self.size.wrapping_add(written.len() as u32);
+
+            match result.status {
+                Ok(MZStatus::StreamEnd) => {
+                    if self.read_u32_le()? != self.crc || self.read_u32_le()? != self.size {
+                        return Err(invalid_data("gzip checksum mismatch"));
+                    }
+                    self.finished = true;
+                }
+                Ok(_) => {}
+                Err(_) => return Err(invalid_data("corrupt deflate stream")),
+            }
+            if result.bytes_written > 0 {
+                return Ok(result.bytes_written);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Compresses what is written into a gzip stream, written to `W` by [`GzEncoder::finish`].
+#[cfg(feature = "gzip")]
+pub struct GzEncoder<W> {
+    inner: W,
+    data: Vec<u8>,
+    level: u8,
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> GzEncoder<W> {
+    /// An encoder with compression level `level`, from 0 (none) to 10 (best).
+    pub fn new(inner: W, level: u8) -> Self {
+        Self {
+            inner,
+            data: Vec::new(),
+            level,
+        }
+    }
+
+    /// Compress everything written and write it to the underlying writer, returning it.
+    pub fn finish(mut self) -> io::Result<W> {
+        // ID1, ID2, deflate, no flags, no modification time, no extra flags, unknown OS.
+        self.inner
+            .write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff])?;
+        self.inner
+            .write_all(&miniz_oxide::deflate::compress_to_vec(
+                &self.data, self.level,
+            ))?;
+        self.inner
+            .write_all(&crc32_update(0, &self.data).to_le_bytes())?;
+        self.inner
+            .write_all(&(self.data.len() as u32).to_le_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The CRC-32 (IEEE) of `data`, continuing from the CRC `crc` of the preceding bytes.
+#[cfg(feature = "gzip")]
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Decompresses a zstd stream read from `R`.
+#[cfg(feature = "zstd")]
+pub struct ZstdDecoder<R: Read>(
+    ruzstd::decoding::StreamingDecoder<R, ruzstd::decoding::FrameDecoder>,
+);
+
+#[cfg(feature = "zstd")]
+impl<R: Read> ZstdDecoder<R> {
+    /// A decoder of the zstd frame at the start of `inner`, failing if its header is invalid.
+    pub fn new(inner: R) -> io::Result<Self> {
+        ruzstd::decoding::StreamingDecoder::new(inner)
+            .map(Self)
+            .map_err(|e| invalid_data(&e.to_string()))
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read> Read for ZstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+/// Compresses what is written into a zstd frame, written to `W` by [`ZstdEncoder::finish`].
+#[cfg(feature = "zstd")]
+pub struct ZstdEncoder<W> {
+    inner: W,
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> ZstdEncoder<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            data: Vec::new(),
+        }
+    }
+
+    /// Compress everything written and write it to the underlying writer, returning it.
+    pub fn finish(mut self) -> io::Result<W> {
+        use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+        self.inner.write_all(&compress_to_vec(
+            self.data.as_slice(),
+            CompressionLevel::Fastest,
+        ))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Write for ZstdEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gzip_round_trip() {
+    let data: Vec<u8> = (0..50_000u32)
+        .flat_map(|i| (i % 251).to_le_bytes())
+        .collect();
+    let mut encoder = GzEncoder::new(Vec::new(), 6);
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+    assert!(compressed.len() < data.len());
+
+    let mut decompressed = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, data);
+
+    let mut corrupt = compressed.clone();
+    let crc_position = corrupt.len() - 8;
+    corrupt[crc_position] ^= 1;
+    assert!(GzDecoder::new(corrupt.as_slice())
+        .read_to_end(&mut Vec::new())
+        .is_err());
+    assert_eq!(crc32_update(0, b"123456789"), 0xcbf4_3926);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_round_trip() {
+    let data = b"valida valida valida valida valida".repeat(100);
+    let mut encoder = ZstdEncoder::new(Vec::new());
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut decompressed = Vec::new();
+    ZstdDecoder::new(compressed.as_slice())
+        .unwrap()
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, data);
+}
@@ -0,0 +1,115 @@
+use std::error::Error;
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The public values of a run, built up field by field and committed to the output tape.
+///
+/// Each field is encoded the way [`super::write`] encodes values (bincode, fixed-width
+/// little-endian integers) without a length prefix, so the journal is exactly the concatenated
+/// fields and can be read back in order with [`PublicValuesReader`].
+/// ```rust
+/// use valida_rs::io::{PublicValues, PublicValuesReader};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # valida_rs::io::testing::set_input(Vec::new());
+/// let (block_number, state_root) = (7u64, [0u8; 32]);
+/// let mut public_values = PublicValues::new();
+/// public_values.push(&block_number).push(&state_root);
+/// public_values.commit()?;
+/// # valida_rs::io::testing::reset();
+///
+/// // On the host, from the journal the guest committed.
+/// let mut reader = PublicValuesReader::new(public_values.as_bytes());
+/// assert_eq!(reader.read::<u64>()?, 7);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublicValues {
+    bytes: Vec<u8>,
+}
+
+fn options() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .with_little_endian()
+}
+
+impl PublicValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a field.
+    ///
+    /// # Panics
+    /// If `value` cannot be encoded, which only happens for sequences whose length is not known
+    /// up front.
+    pub fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> &mut Self {
+        options()
+            .serialize_into(&mut self.bytes, value)
+            .expect("public value cannot be encoded");
+        self
+    }
+
+    /// The encoded fields, as they will appear in the journal.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The SHA-256 digest of the encoded fields, for verifiers that take a single public input.
+    #[cfg(feature = "crypto")]
+    pub fn digest(&self) -> [u8; 32] {
+        crate::crypto::sha256(&self.bytes)
+    }
+
+    /// Write the encoded fields to the output tape.
+    pub fn commit(&self) -> Result<(), Box<dyn Error>> {
+        super::write_vec(&self.bytes)
+    }
+}
+
+/// Reads the fields of committed [`PublicValues`] back, in the order they were pushed.
+#[derive(Debug, Clone)]
+pub struct PublicValuesReader<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> PublicValuesReader<'a> {
+    /// A reader over a journal, such as the output of a run.
+    pub fn new(journal: &'a [u8]) -> Self {
+        Self { remaining: journal }
+    }
+
+    /// Decode the next field.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, Box<dyn Error>> {
+        Ok(options().deserialize_from(&mut self.remaining)?)
+    }
+
+    /// Whether every field has been read.
+    pub fn is_empty(&self) -> bool {
+        self.remaining.is_empty()
+    }
+}
+
+#[test]
+fn test_public_values_round_trip() {
+    super::testing::set_input(Vec::new());
+    let mut public_values = PublicValues::new();
+    public_values
+        .push(&7u64)
+        .push("root")
+        .push(&vec![1u8, 2, 3]);
+    public_values.commit().unwrap();
+    let journal = super::testing::take_output();
+    super::testing::reset();
+    assert_eq!(journal, public_values.as_bytes());
+
+    let mut reader = PublicValuesReader::new(&journal);
+    assert_eq!(reader.read::<u64>().unwrap(), 7);
+    assert_eq!(reader.read::<String>().unwrap(), "root");
+    assert_eq!(reader.read::<Vec<u8>>().unwrap(), [1, 2, 3]);
+    assert!(reader.is_empty());
+    assert!(reader.read::<u8>().is_err());
+}
@@ -0,0 +1,276 @@
+//! Recording a run's tape traffic and replaying it as a regression test.
+//!
+//! Record a run on the host once, with its real input:
+//! ```rust,no_run
+//! # fn guest_main() {}
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let recorder = valida_rs::io::recorder::start("tests/recordings/block.rec");
+//! guest_main();
+//! recorder.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+//! and replay it in a test, which feeds the recorded input back and checks the guest writes
+//! exactly what it wrote before:
+//! ```rust,no_run
+//! # use valida_rs::io::recorder::Recording;
+//! # fn guest_main() {}
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let recording = Recording::load("tests/recordings/block.rec")?;
+//! recording.replay(guest_main)?;
+//! # Ok(())
+//! # }
+//! ```
+//! In the VM, run the guest with [`Recording::input`] as its input and compare what it printed
+//! with [`Recording::check_output`].
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+/// A read off the input tape or a write to the output tape, of consecutive bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TapeEvent {
+    Read(Vec<u8>),
+    Write(Vec<u8>),
+}
+
+/// The tape traffic of a run, in the order it happened.
+///
+/// Writes inside an [`io::capture`](super::capture) do not reach the tape and are not recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    pub events: Vec<TapeEvent>,
+}
+
+thread_local! {
+    /// The recording in progress on this thread, if any.
+    static RECORDING: RefCell<Option<Recording>> = const { RefCell::new(None) };
+}
+
+fn options() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .with_little_endian()
+}
+
+impl Recording {
+    /// Everything the run read off the input tape.
+    pub fn input(&self) -> Vec<u8> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                TapeEvent::Read(bytes) => Some(bytes.as_slice()),
+                TapeEvent::Write(_) => None,
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Everything the run wrote to the output tape.
+    pub fn output(&self) -> Vec<u8> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                TapeEvent::Write(bytes) => Some(bytes.as_slice()),
+                TapeEvent::Read(_) => None,
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        options()
+            .serialize(self)
+            .expect("a recording can always be encoded")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Ok(options().deserialize(bytes)?)
+    }
+
+    /// Read a recording saved by [`Recorder::finish`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Run `f` with the recorded input on mocked tapes, and check it writes the recorded output
+    /// and reads the whole input.
+    ///
+    /// The tapes are reset to the real ones afterwards, see [`testing`](super::testing).
+    pub fn replay(&self, f: impl FnOnce()) -> Result<(), ReplayMismatch> {
+        let input = self.input();
+        let input_len = input.len() as u64;
+        super::testing::set_input(input);
+        super::testing::take_output();
+        let bytes_in = super::stats().bytes_in;
+        f();
+        let read = super::stats().bytes_in - bytes_in;
+        let output = super::testing::take_output();
+        super::testing::reset();
+
+        self.check_output(&output)?;
+        if read < input_len {
+            return Err(ReplayMismatch::UnreadInput {
+                bytes: input_len - read,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that `output`, e.g. the stdout of a VM run fed [`input`](Self::input), is the
+    /// recorded output.
+    pub fn check_output(&self, output: &[u8]) -> Result<(), ReplayMismatch> {
+        let expected = self.output();
+        if output == expected {
+            return Ok(());
+        }
+        let offset = expected
+            .iter()
+            .zip(output)
+            .position(|(a, b)| a != b)
+            .unwrap_or(expected.len().min(output.len()));
+        let around = |bytes: &[u8]| bytes[offset..bytes.len().min(offset + 32)].to_vec();
+        Err(ReplayMismatch::Output {
+            offset,
+            expected: around(&expected),
+            actual: around(output),
+        })
+    }
+}
+
+/// How a replay differed from its [`Recording`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayMismatch {
+    /// The output differs from byte `offset` on; up to 32 bytes from there are kept.
+    Output {
+        offset: usize,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    /// The output matched, but `bytes` of the recorded input were not read.
+    UnreadInput { bytes: u64 },
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayMismatch::Output {
+                offset,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "output differs from the recording at byte {offset}: expected {:?}, got {:?}",
+                String::from_utf8_lossy(expected),
+                String::from_utf8_lossy(actual)
+            ),
+            ReplayMismatch::UnreadInput { bytes } => {
+                write!(f, "{bytes} bytes of the recorded input were not read")
+            }
+        }
+    }
+}
+
+impl Error for ReplayMismatch {}
+
+/// Records this thread's tape traffic until it is finished or dropped, see [`start`].
+#[must_use = "recording stops when the recorder is dropped"]
+#[derive(Debug)]
+pub struct Recorder {
+    path: Option<PathBuf>,
+    /// The recording is thread-local.
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Start recording every read and write of this thread's tapes, to be saved to `path` when the
+/// recorder is finished or dropped. A recording already in progress is discarded.
+pub fn start(path: impl Into<PathBuf>) -> Recorder {
+    let mut recorder = start_in_memory();
+    recorder.path = Some(path.into());
+    recorder
+}
+
+/// Start recording every read and write of this thread's tapes, without saving them to a file.
+pub fn start_in_memory() -> Recorder {
+    RECORDING.with_borrow_mut(|recording| *recording = Some(Recording::default()));
+    Recorder {
+        path: None,
+        _not_send: PhantomData,
+    }
+}
+
+impl Recorder {
+    /// Stop recording, saving the recording to the file given to [`start`], and return it.
+    pub fn finish(mut self) -> Result<Recording, Box<dyn Error>> {
+        let recording = RECORDING.with_borrow_mut(Option::take).unwrap_or_default();
+        if let Some(path) = self.path.take() {
+            std::fs::write(path, recording.to_bytes())?;
+        }
+        Ok(recording)
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let recording = RECORDING.with_borrow_mut(Option::take);
+        if let (Some(path), Some(recording)) = (&self.path, recording) {
+            let _ = std::fs::write(path, recording.to_bytes());
+        }
+    }
+}
+
+/// Record that `bytes` were read (`write == false`) or written, if recording.
+pub(super) fn record(bytes: &[u8], write: bool) {
+    RECORDING.with_borrow_mut(|recording| {
+        let Some(recording) = recording else {
+            return;
+        };
+        match (recording.events.last_mut(), write) {
+            (Some(TapeEvent::Read(last)), false) | (Some(TapeEvent::Write(last)), true) => {
+                last.extend_from_slice(bytes)
+            }
+            (_, false) => recording.events.push(TapeEvent::Read(bytes.to_vec())),
+            (_, true) => recording.events.push(TapeEvent::Write(bytes.to_vec())),
+        }
+    });
+}
+
+#[test]
+fn test_record_and_replay() {
+    let guest = || {
+        let n = super::read_line::<u32>().unwrap();
+        super::write_vec(format!("{}\n", n * 2)).unwrap();
+        let name = super::read_line::<String>().unwrap();
+        super::write_vec(format!("hello {name}\n")).unwrap();
+    };
+
+    super::testing::set_input(b"21\nvalida\n".to_vec());
+    let recorder = start_in_memory();
+    guest();
+    let recording = recorder.finish().unwrap();
+    super::testing::reset();
+
+    assert_eq!(recording.events.len(), 4);
+    assert_eq!(recording.input(), b"21\nvalida\n");
+    let recording = Recording::from_bytes(&recording.to_bytes()).unwrap();
+    assert_eq!(recording.replay(guest), Ok(()));
+
+    let changed = || {
+        let n = super::read_line::<u32>().unwrap();
+        super::write_vec(format!("{}\n", n * 3)).unwrap();
+    };
+    assert!(matches!(
+        recording.replay(changed),
+        Err(ReplayMismatch::Output { offset: 0, .. })
+    ));
+}
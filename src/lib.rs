@@ -1,10 +1,67 @@
-#![feature(once_cell_get_mut)]
-#![feature(custom_test_frameworks, test)]
-#![test_runner(test_utils::test_runner)]
+#![cfg_attr(feature = "guest", feature(once_cell_get_mut))]
+#![feature(test)]
+#![cfg_attr(feature = "host", feature(internal_output_capture, thread_spawn_hook))]
+#![cfg_attr(
+    any(valida, feature = "host"),
+    feature(custom_test_frameworks),
+    test_runner(test_utils::test_runner)
+)]
+
+extern crate test;
+
+#[cfg(not(any(feature = "guest", feature = "host")))]
+compile_error!(
+    "valida-rs needs the `guest` feature (for programs that run in the VM), the `host` feature \
+     (for running and testing them), or both"
+);
+
+#[cfg(all(valida, not(feature = "guest")))]
+compile_error!(
+    "valida-rs is being compiled for the Valida VM without the `guest` feature, which provides \
+     the guest runtime; enable it, or only use the `host` feature in host-side crates"
+);
 
 pub use getrandom;
+pub use target::is_valida;
 
+pub mod bench;
+#[cfg(feature = "bigint")]
+pub mod bigint;
+#[cfg(all(feature = "host", not(valida)))]
+pub mod build;
+#[cfg(all(feature = "cli", not(valida)))]
+pub mod cli;
+pub mod collections;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+pub mod felt;
+pub mod float;
+pub mod fuzz;
+pub mod hints;
+#[cfg(all(feature = "host", not(valida)))]
+pub mod host;
+pub mod intrinsics;
+#[cfg(feature = "guest")]
 pub mod io;
 pub mod macros;
+pub mod mem;
+#[cfg(feature = "guest")]
+pub mod prelude;
+pub mod profile;
+pub mod program;
+#[cfg(all(feature = "proptest", any(valida, feature = "host")))]
+pub mod property;
+#[cfg(feature = "guest")]
 pub mod rand;
+pub mod schema;
+pub mod snapshot;
+#[cfg(feature = "guest")]
+mod sys;
+pub mod target;
+#[cfg(any(valida, feature = "host"))]
 pub mod test_utils;
+pub mod thread;
+#[cfg(feature = "guest")]
+pub mod time;
+pub mod trace;
+pub mod verify;
@@ -1,5 +1,53 @@
+/// Defines the program's entry point.
+///
+/// `entrypoint!(main)` calls `fn main()`. `entrypoint!(main, Codec)` calls `fn main(input) ->
+/// output`, reading the input off the input tape and writing the output to the output tape with
+/// [`read_serde`](crate::io::read_serde) and [`write_serde`](crate::io::write_serde) in the
+/// given [`TapeCodec`](crate::io::TapeCodec).
+///
+/// ```rust,ignore
+/// valida_rs::entrypoint!(main, valida_rs::io::Bincode);
+///
+/// fn main(numbers: Vec<u64>) -> u64 {
+///     numbers.iter().sum()
+/// }
+/// ```
+///
+/// One binary can also host several programs. `entrypoint!(select: { ... })` reads the name of
+/// the program to run off the input tape, framed as by [`Raw`](crate::io::Raw), and calls the
+/// function registered under it; the host selects a program with `host::Runner::select`.
+///
+/// ```rust,ignore
+/// valida_rs::entrypoint!(select: { "sum" => sum_main, "product" => product_main });
+/// ```
+#[cfg(feature = "guest")]
 #[macro_export]
 macro_rules! entrypoint {
+    (select: { $($name:literal => $path:path),+ $(,)? }) => {
+        $crate::entrypoint!(valida_select_main);
+
+        fn valida_select_main() {
+            let selector = $crate::io::read_serde::<$crate::io::Raw, Vec<u8>>()
+                .expect("failed to read the program selector");
+            match selector.as_slice() {
+                $(name if name == $name.as_bytes() => $path(),)+
+                other => panic!(
+                    "unknown program {:?}, expected one of {:?}",
+                    String::from_utf8_lossy(other),
+                    [$($name),+]
+                ),
+            }
+        }
+    };
+    ($path:path, $codec:ty) => {
+        $crate::entrypoint!(valida_typed_main);
+
+        fn valida_typed_main() {
+            let input = $crate::io::read_serde::<$codec, _>().expect("failed to read the input");
+            let output = $path(input);
+            $crate::io::write_serde::<$codec, _>(&output).expect("failed to write the output");
+        }
+    };
     ($path:path) => {
         const VALIDA_ENTRY: fn() = $path;
 
@@ -11,8 +59,500 @@ macro_rules! entrypoint {
 
             #[cfg_attr(not(test), no_mangle)]
             fn main() {
+                $crate::intrinsics::halt_on_panic();
                 super::VALIDA_ENTRY()
             }
         }
     };
 }
+
+/// Registers a test that runs the crate's doctests.
+///
+/// Doctests are built by rustdoc with the standard test harness, so they bypass the custom test
+/// runner. Invoke this once in the root of a library crate to run them as part of the host phase;
+/// see [`run_doctests`](crate::test_utils::run_doctests).
+///
+/// ```rust
+/// valida_rs::doctests!();
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! doctests {
+    () => {
+        #[test]
+        fn valida_doctests() {
+            $crate::test_utils::run_doctests(env!("CARGO_PKG_NAME"));
+        }
+    };
+}
+
+/// Registers a benchmark that runs on both the host and the Valida VM.
+///
+/// Unlike `#[bench]`, this does not need `#![feature(test)]`. The body is given a
+/// [`Bencher`](crate::bench::Bencher) and is measured over
+/// [`DEFAULT_ITERATIONS`](crate::bench::DEFAULT_ITERATIONS) unless an iteration count is given.
+///
+/// ```rust
+/// valida_rs::valida_bench!(bench_sum, |b| b.iter(|| (0..1000u64).sum::<u64>()));
+/// valida_rs::valida_bench!(bench_sum_long, 100, |b| b.iter(|| (0..1000u64).sum::<u64>()));
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! valida_bench {
+    ($name:ident, $body:expr) => {
+        $crate::valida_bench!($name, $crate::bench::DEFAULT_ITERATIONS, $body);
+    };
+    ($name:ident, $iterations:expr, $body:expr) => {
+        #[test]
+        fn $name() {
+            $crate::bench::run(stringify!($name), $iterations, $body);
+        }
+    };
+}
+
+/// Expands a table of inputs into one registered test per case.
+///
+/// The cases are generated in a module named after the function, so each shows up separately in
+/// the native and Valida results as `<function>::<case>`. Attributes on the function (such as
+/// `#[should_panic]`) apply to every case; attributes on a case apply to that case only.
+///
+/// ```rust
+/// valida_rs::valida_test_cases! {
+///     fn doubles(input: u32, expected: u32) {
+///         assert_eq!(input * 2, expected);
+///     }
+///     zero: (0, 0),
+///     one: (1, 2),
+///     #[ignore]
+///     big: (1 << 20, 1 << 21),
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! valida_test_cases {
+    (
+        $(#[$meta:meta])*
+        fn $name:ident($($arg:ident : $ty:ty),* $(,)?) $body:block
+        $($cases:tt)+
+    ) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            fn $name($($arg: $ty),*) $body
+
+            $crate::valida_test_cases!(@cases [$(#[$meta])*] $name; $($cases)+);
+        }
+    };
+    (@cases [$($attrs:tt)*] $name:ident; $(,)?) => {};
+    (
+        @cases [$($attrs:tt)*] $name:ident;
+        $(#[$case_meta:meta])* $case:ident : ($($value:expr),* $(,)?)
+        $(, $($rest:tt)*)?
+    ) => {
+        #[test]
+        $($attrs)*
+        $(#[$case_meta])*
+        fn $case() {
+            $name($($value),*);
+        }
+
+        $crate::valida_test_cases!(@cases [$($attrs)*] $name; $($($rest)*)?);
+    };
+}
+
+/// Declares property-based tests whose failing inputs are replayed in the Valida VM.
+///
+/// Requires the `proptest` feature. Each argument is drawn from a `proptest` strategy, and the
+/// `proptest` prelude is in scope for the strategy expressions. Argument types must implement
+/// `serde::Serialize` and `serde::Deserialize` so a failing input can be sent to the VM; see
+/// [`property::check`](crate::property::check).
+///
+/// ```rust
+/// valida_rs::valida_proptest! {
+///     fn add_commutes(a in any::<u32>(), b in any::<u32>()) {
+///         assert_eq!(a.wrapping_add(b), b.wrapping_add(a));
+///     }
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! valida_proptest {
+    ($(
+        $(#[$meta:meta])*
+        fn $name:ident($($arg:pat in $strategy:expr),+ $(,)?) $body:block
+    )*) => {$(
+        #[test]
+        $(#[$meta])*
+        fn $name() {
+            #[allow(unused_imports)]
+            use $crate::property::proptest::prelude::*;
+
+            $crate::property::check(
+                concat!(module_path!(), "::", stringify!($name)),
+                ($($strategy,)+),
+                |($($arg,)+)| $body,
+            );
+        }
+    )*};
+}
+
+/// Declares a fuzz target that `cargo fuzz` drives on the host and that runs in the Valida VM on
+/// an input given on the input tape; see the host's definition.
+#[cfg(valida)]
+#[macro_export]
+macro_rules! valida_fuzz_target {
+    (|$data:ident: &[u8]| $body:expr) => {
+        $crate::entrypoint!(valida_fuzz_main);
+
+        fn valida_fuzz_main() {
+            $crate::fuzz::run_tape_input(|$data: &[u8]| {
+                $body;
+            });
+        }
+    };
+}
+
+/// Declares a fuzz target that `cargo fuzz` drives on the host and that runs in the Valida VM on
+/// an input given on the input tape.
+///
+/// On the host this is `libfuzzer_sys::fuzz_target!`, so the fuzz crate must depend on
+/// `libfuzzer-sys` there. Built for Valida, it is the guest's entry point: it reads the whole
+/// input tape and calls the target once, so a crash found by the fuzzer can be checked in the VM
+/// with [`fuzz::reproduce_in_valida`](crate::fuzz).
+///
+/// ```rust,ignore
+/// #![no_main]
+///
+/// valida_rs::valida_fuzz_target!(|data: &[u8]| {
+///     let _ = my_crate::parse(data);
+/// });
+/// ```
+#[cfg(not(valida))]
+#[macro_export]
+macro_rules! valida_fuzz_target {
+    (|$data:ident: &[u8]| $body:expr) => {
+        ::libfuzzer_sys::fuzz_target!(|$data: &[u8]| {
+            $body;
+        });
+    };
+}
+
+/// Asserts that the output a block commits to the output tape matches a checked-in snapshot.
+///
+/// The snapshot is stored as `snapshots/<name>.snap` in the crate being tested. The output is
+/// captured rather than written, and the block's value is returned. See
+/// [`snapshot`](crate::snapshot) for how snapshots are checked in the VM and updated.
+///
+#[cfg_attr(
+    feature = "guest",
+    doc = r#"
+```rust,no_run
+valida_rs::valida_snapshot!("greeting", {
+    valida_rs::io::write_vec(b"hello").unwrap();
+});
+```
+"#
+)]
+#[macro_export]
+macro_rules! valida_snapshot {
+    ($name:expr, $body:expr) => {{
+        let (value, output) = $crate::snapshot::capture(|| $body);
+        $crate::snapshot::assert_snapshot(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/snapshots"),
+            $name,
+            &output,
+        );
+        value
+    }};
+}
+
+/// Declares tests that only run in the Valida VM, e.g. because they use the tape externs.
+///
+/// The tests are placed in a `valida_only` module, which the runner recognizes from their names;
+/// the host reports them as skipped. Invoke this once per module, or name individual tests
+/// `valida_only_*` instead. See [`TestEnvironment`](crate::test_utils::TestEnvironment).
+///
+/// ```rust,no_run
+/// valida_rs::valida_only! {
+///     #[test]
+///     fn reads_the_input_tape() {
+///         let _ = valida_rs::io::read_line::<String>();
+///     }
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! valida_only {
+    ($($item:item)*) => {
+        mod valida_only {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $($item)*
+        }
+    };
+}
+
+/// Declares tests that only run on the host, e.g. because they use threads.
+///
+/// The tests are placed in a `host_only` module, which the runner recognizes from their names;
+/// the VM phase reports them as skipped. Invoke this once per module, or name individual tests
+/// `host_only_*` instead. See [`TestEnvironment`](crate::test_utils::TestEnvironment).
+#[macro_export]
+macro_rules! host_only {
+    ($($item:item)*) => {
+        mod host_only {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $($item)*
+        }
+    };
+}
+
+/// Declares that tests run in a given order, e.g. after a test that writes shared fixtures.
+///
+/// The tests are named by their paths relative to the module the macro is invoked in. The runner
+/// runs each test after the tests it is declared to follow, natively and in the VM, whatever the
+/// shuffle order or the number of VM workers, and assigns tests ordered together to the same
+/// shard. The order only applies among the tests that run; a filter does not pull in the tests a
+/// selected test follows. Invoke this once per module, listing all of its pairs.
+///
+/// ```rust
+/// valida_rs::valida_test_order!(
+///     write_fixtures before reads_fixtures,
+///     write_fixtures before parse::reads_fixtures,
+/// );
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! valida_test_order {
+    ($($first:ident $(:: $first_rest:ident)* before $second:ident $(:: $second_rest:ident)*),+ $(,)?) => {
+        #[test]
+        fn valida_test_order() {
+            $crate::test_utils::declare_test_order(&[$((
+                stringify!($first $(:: $first_rest)*),
+                stringify!($second $(:: $second_rest)*),
+            )),+]);
+        }
+    };
+}
+
+/// Declares substrings tests must print in the VM, checked whether they pass or panic as
+/// `#[should_panic]` expects.
+///
+/// This catches a `should_panic` test that panicked for the wrong reason, before it committed
+/// what it should have. The tests are named by their paths relative to the module the macro is
+/// invoked in, and a test may be given several patterns. Invoke this once per module, listing
+/// all of its tests.
+///
+/// ```rust
+/// valida_rs::valida_expect_output!(
+///     rejects_overdraft, "balance: 10";
+///     parse::rejects_empty_block, "committed header";
+/// );
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! valida_expect_output {
+    ($($test:ident $(:: $rest:ident)*, $pattern:literal);+ $(;)?) => {
+        #[test]
+        fn valida_expect_output() {
+            $crate::test_utils::declare_expected_output(&[$((
+                stringify!($test $(:: $rest)*),
+                $pattern,
+            )),+]);
+        }
+    };
+}
+
+/// Includes a guest binary built by [`build_guest`](crate::build::build_guest) in the build
+/// script, as a `&'static [u8]`.
+///
+/// ```rust,ignore
+/// const GUEST: &[u8] = valida_rs::embed_guest!("guest");
+/// ```
+#[macro_export]
+macro_rules! embed_guest {
+    ($name:literal) => {
+        include_bytes!(env!(concat!("VALIDA_GUEST_", $name)))
+    };
+}
+
+/// Embeds a file, relative to the current file like `include_bytes!`, in the binary as a
+/// `&'static [u8]` aligned to 16 bytes, or to `align`.
+///
+/// Use it for data that should not come from the input tape, e.g. fixed test vectors. The bytes
+/// are placed with the other read-only data, so no linker section needs to be named, and the
+/// build audit reports their size as embedded inputs; see [`audit`](crate::build::audit).
+/// ```rust,ignore
+/// static VECTORS: &[u8] = valida_rs::include_input!("../data/vectors.bin");
+/// let words = valida_rs::include_input!("../data/words.bin", align = 64);
+/// ```
+#[macro_export]
+macro_rules! include_input {
+    ($path:literal) => {
+        $crate::include_input!($path, align = 16)
+    };
+    ($path:literal, align = $align:literal) => {{
+        #[repr(C, align($align))]
+        struct Aligned<T>(T);
+        // The audit finds embedded inputs by this name.
+        static VALIDA_INCLUDED_INPUT: Aligned<[u8; include_bytes!($path).len()]> =
+            Aligned(*include_bytes!($path));
+        let input: &'static [u8] = &VALIDA_INCLUDED_INPUT.0;
+        input
+    }};
+}
+
+/// Compiles the items inside only when building for the Valida VM.
+///
+/// This is the stable way for downstream crates to branch on the target: it follows the crate's
+/// own detection in [`target`](crate::target), which knows every name the VM target has been
+/// published under, instead of `cfg(target_arch = "...")` checks that break when the target is
+/// renamed. Use [`is_valida`](crate::is_valida) to branch in expressions.
+/// ```rust
+/// valida_rs::cfg_valida! {
+///     fn read_input() -> Vec<u8> { valida_rs::io::read().unwrap() }
+/// }
+/// valida_rs::cfg_host! {
+///     fn read_input() -> Vec<u8> { std::fs::read("input.bin").unwrap_or_default() }
+/// }
+/// # fn main() { let _ = read_input; }
+/// ```
+#[cfg(valida)]
+#[macro_export]
+macro_rules! cfg_valida {
+    ($($item:item)*) => {
+        $($item)*
+    };
+}
+
+/// Compiles the items inside only when building for the Valida VM; see [`cfg_valida!`].
+#[cfg(not(valida))]
+#[macro_export]
+macro_rules! cfg_valida {
+    ($($item:item)*) => {};
+}
+
+/// Compiles the items inside only when not building for the Valida VM; see [`cfg_valida!`].
+#[cfg(valida)]
+#[macro_export]
+macro_rules! cfg_host {
+    ($($item:item)*) => {};
+}
+
+/// Compiles the items inside only when not building for the Valida VM; see [`cfg_valida!`].
+#[cfg(not(valida))]
+#[macro_export]
+macro_rules! cfg_host {
+    ($($item:item)*) => {
+        $($item)*
+    };
+}
+
+/// Prints and returns the value of an expression, like [`std::dbg!`], in the host and the VM.
+///
+/// The file, line and expression are printed with the value's `Debug` representation to stderr,
+/// keeping them off the output tape. With the `release-silent` feature nothing is printed or
+/// formatted, so calls can be left in code without adding to the cost of proving it; the
+/// expression is still evaluated.
+/// ```rust
+/// # let tree = vec![1u32, 2, 3];
+/// let root = valida_rs::valida_dbg!(tree[0]);
+/// # assert_eq!(root, 1);
+/// ```
+#[cfg(not(feature = "release-silent"))]
+#[macro_export]
+macro_rules! valida_dbg {
+    () => {
+        ::std::eprintln!("[{}:{}:{}]", ::std::file!(), ::std::line!(), ::std::column!())
+    };
+    ($val:expr $(,)?) => {
+        // `match` keeps the temporaries of `$val` alive, as in `std::dbg!`.
+        match $val {
+            tmp => {
+                ::std::eprintln!(
+                    "[{}:{}:{}] {} = {:#?}",
+                    ::std::file!(),
+                    ::std::line!(),
+                    ::std::column!(),
+                    ::std::stringify!($val),
+                    &tmp
+                );
+                tmp
+            }
+        }
+    };
+    ($($val:expr),+ $(,)?) => {
+        ($($crate::valida_dbg!($val)),+,)
+    };
+}
+
+/// Returns the value of an expression; printing is compiled out by the `release-silent` feature.
+#[cfg(feature = "release-silent")]
+#[macro_export]
+macro_rules! valida_dbg {
+    () => {
+        ()
+    };
+    ($val:expr $(,)?) => {
+        match $val {
+            tmp => tmp,
+        }
+    };
+    ($($val:expr),+ $(,)?) => {
+        ($($crate::valida_dbg!($val)),+,)
+    };
+}
+
+/// Measures the cycles spent until the end of the enclosing scope; see
+/// [`profile::span!`](crate::profile::span).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __profile_span {
+    ($name:expr) => {
+        $crate::profile::Span::enter($name)
+    };
+}
+
+/// Records a phase for the panic hooks to print; see
+/// [`trace::breadcrumb!`](crate::trace::breadcrumb).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_breadcrumb {
+    ($phase:expr) => {
+        $crate::trace::record($crate::trace::Breadcrumb {
+            phase: $phase,
+            file: file!(),
+            line: line!(),
+        })
+    };
+}
+
+/// Asserts that two floats are at most a few ULPs apart; see
+/// [`float::assert_float_eq!`](crate::float::assert_float_eq).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __float_assert_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__float_assert_eq!($left, $right, ulps = $crate::float::DEFAULT_MAX_ULPS)
+    };
+    ($left:expr, $right:expr, ulps = $ulps:expr $(,)?) => {
+        match (f64::from($left), f64::from($right), $ulps) {
+            (left, right, ulps) => {
+                if !$crate::float::float_eq(left, right, ulps) {
+                    panic!(
+                        "assertion `left ≈ right` failed: {:?} ULPs apart, at most {} allowed\n  \
+                         left: {:?}\n right: {:?}",
+                        $crate::float::ulps_between(left, right),
+                        ulps,
+                        left,
+                        right,
+                    );
+                }
+            }
+        }
+    };
+}
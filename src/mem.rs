@@ -0,0 +1,39 @@
+//! Queries of the guest's memory, so guests can size buffers to what is left and tests can check
+//! their headroom before running out becomes a VM failure.
+//!
+//! Like [`crate::intrinsics`], these require the `intrinsics` feature in the VM; without it, and
+//! on the host, they return `None`.
+
+#[cfg(all(valida, feature = "intrinsics"))]
+extern "C" {
+    fn valida_heap_free() -> usize;
+    fn valida_memory_size() -> usize;
+    fn valida_image_size() -> usize;
+}
+
+/// The number of bytes the heap can still grow by.
+pub fn heap_free() -> Option<usize> {
+    #[cfg(all(valida, feature = "intrinsics"))]
+    return Some(unsafe { valida_heap_free() });
+
+    #[cfg(not(all(valida, feature = "intrinsics")))]
+    None
+}
+
+/// The size of the VM's memory, in bytes.
+pub fn total_memory() -> Option<usize> {
+    #[cfg(all(valida, feature = "intrinsics"))]
+    return Some(unsafe { valida_memory_size() });
+
+    #[cfg(not(all(valida, feature = "intrinsics")))]
+    None
+}
+
+/// The size of the loaded program image (code and static data), in bytes.
+pub fn image_size() -> Option<usize> {
+    #[cfg(all(valida, feature = "intrinsics"))]
+    return Some(unsafe { valida_image_size() });
+
+    #[cfg(not(all(valida, feature = "intrinsics")))]
+    None
+}
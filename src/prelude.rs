@@ -0,0 +1,20 @@
+//! The items most guest programs use, so they can start with a single import:
+//! ```rust
+//! use valida_rs::prelude::*;
+//!
+//! let mut seen: HashSet<u32> = HashSet::default();
+//! seen.insert(Felt::new(7).as_canonical_u32());
+//! let _span = profile::span!("main");
+//! ```
+
+pub use crate::{
+    collections::{HashMap, HashSet},
+    entrypoint,
+    felt::Felt,
+    hints,
+    io::{self, read_and_deserialize, read_line, write_vec, PublicValues},
+    profile,
+};
+
+#[cfg(feature = "crypto")]
+pub use crate::crypto::{blake3_hash, keccak256, poseidon2, sha256, Blake3, Sha256};
@@ -0,0 +1,243 @@
+//! Cycle-scoped profiling spans.
+//!
+//! A span measures the cycles spent from where it is entered until it is dropped. Spans nest, and
+//! the cycles of each distinct stack of span names are accumulated in memory until [`flush`]
+//! prints them, one [`SpanRecord`] line per stack. The test runner flushes after each test run in
+//! the VM and prints a per-span breakdown; other guests call [`flush`] before exiting and can
+//! read their output with [`SpanRecord::parse_line`] and [`format_breakdown`]. Alongside the
+//! spans, [`flush`] prints an [`IoRecord`] of how many bytes the guest read and wrote.
+//!
+//! Spans measure cycles, so they only record anything in the VM with the cycle counter (see
+//! [`crate::intrinsics`]).
+//! ```rust
+//! fn checksum(data: &[u8]) -> u32 {
+//!     let _span = valida_rs::profile::span!("checksum");
+//!     data.iter().map(|&byte| u32::from(byte)).sum()
+//! }
+//! # assert_eq!(checksum(b"ab"), 195);
+//! ```
+
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+/// Prefix of the lines [`flush`] prints.
+pub const REPORT_PREFIX: &str = "valida-profile:";
+
+/// Separates the span names of a stack in report lines, as in folded flame graph stacks.
+const STACK_SEPARATOR: char = ';';
+
+pub use crate::__profile_span as span;
+
+#[derive(Default)]
+struct Profile {
+    /// The names of the spans entered and not yet dropped, outermost first.
+    stack: Vec<&'static str>,
+    /// The calls and cycles of each stack of span names.
+    totals: BTreeMap<Vec<&'static str>, (u64, u64)>,
+}
+
+static PROFILE: Mutex<Profile> = Mutex::new(Profile {
+    stack: Vec::new(),
+    totals: BTreeMap::new(),
+});
+
+/// A span being measured; created by [`span!`].
+#[must_use = "a span measures until it is dropped"]
+pub struct Span {
+    /// The cycle count when the span was entered, if the cycle counter is available.
+    start: Option<u64>,
+}
+
+impl Span {
+    pub fn enter(name: &'static str) -> Self {
+        let start = crate::intrinsics::cycle_count();
+        if start.is_some() {
+            PROFILE.lock().unwrap().stack.push(name);
+        }
+        Self { start }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let (Some(start), Some(end)) = (self.start, crate::intrinsics::cycle_count()) else {
+            return;
+        };
+        let mut profile = PROFILE.lock().unwrap();
+        let stack = profile.stack.clone();
+        let totals = profile.totals.entry(stack).or_default();
+        totals.0 += 1;
+        totals.1 += end - start;
+        profile.stack.pop();
+    }
+}
+
+/// Print the spans recorded since the last flush, and forget them.
+///
+/// If any spans were recorded, an [`IoRecord`] of the tape statistics is printed after them.
+pub fn flush() {
+    let totals = std::mem::take(&mut PROFILE.lock().unwrap().totals);
+    #[cfg(feature = "guest")]
+    let io = (!totals.is_empty()).then(|| {
+        let stats = crate::io::stats();
+        IoRecord {
+            bytes_in: stats.bytes_in,
+            bytes_out: stats.bytes_out,
+        }
+    });
+    for (stack, (calls, cycles)) in totals {
+        let record = SpanRecord {
+            stack: stack.iter().map(|name| name.to_string()).collect(),
+            calls,
+            cycles,
+        };
+        println!("{record}");
+    }
+    #[cfg(feature = "guest")]
+    if let Some(io) = io {
+        println!("{io}");
+    }
+}
+
+/// The cycles spent in one stack of spans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanRecord {
+    /// The span names, outermost first.
+    pub stack: Vec<String>,
+    /// The number of times the innermost span was entered.
+    pub calls: u64,
+    /// The cycles spent in the innermost span, including its children.
+    pub cycles: u64,
+}
+
+impl fmt::Display for SpanRecord {
+    /// Formats the record as the report line parsed by [`SpanRecord::parse_line`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = STACK_SEPARATOR.to_string();
+        write!(
+            f,
+            "{REPORT_PREFIX} {} calls={} cycles={}",
+            self.stack.join(&separator),
+            self.calls,
+            self.cycles
+        )
+    }
+}
+
+impl SpanRecord {
+    /// Parse a report line printed by [`flush`].
+    pub fn parse_line(line: &str) -> Option<Self> {
+        let line = line.trim().strip_prefix(REPORT_PREFIX)?.trim_start();
+        let (rest, cycles) = line.rsplit_once(" cycles=")?;
+        let (stack, calls) = rest.rsplit_once(" calls=")?;
+        Some(Self {
+            stack: stack.split(STACK_SEPARATOR).map(str::to_string).collect(),
+            calls: calls.parse().ok()?,
+            cycles: cycles.parse().ok()?,
+        })
+    }
+}
+
+/// The bytes a guest read off and wrote to its tapes, printed by [`flush`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoRecord {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl fmt::Display for IoRecord {
+    /// Formats the record as the report line parsed by [`IoRecord::parse_line`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{REPORT_PREFIX} io bytes_in={} bytes_out={}",
+            self.bytes_in, self.bytes_out
+        )
+    }
+}
+
+impl IoRecord {
+    /// Parse a report line printed by [`flush`].
+    pub fn parse_line(line: &str) -> Option<Self> {
+        let line = line.trim().strip_prefix(REPORT_PREFIX)?.trim_start();
+        let (bytes_in, bytes_out) = line
+            .strip_prefix("io bytes_in=")?
+            .split_once(" bytes_out=")?;
+        Some(Self {
+            bytes_in: bytes_in.parse().ok()?,
+            bytes_out: bytes_out.parse().ok()?,
+        })
+    }
+
+    /// A line summarizing the record for a breakdown.
+    pub fn describe(&self) -> String {
+        format!(
+            "io: {} bytes in, {} bytes out",
+            self.bytes_in, self.bytes_out
+        )
+    }
+}
+
+/// A tree of the spans in `records`, with each span's cycles and share of the total.
+pub fn format_breakdown(records: &[SpanRecord]) -> String {
+    let mut records: Vec<&SpanRecord> = records.iter().collect();
+    records.sort_by(|a, b| a.stack.cmp(&b.stack));
+    let total: u64 = records
+        .iter()
+        .filter(|r| r.stack.len() == 1)
+        .map(|r| r.cycles)
+        .sum();
+
+    let mut breakdown = String::new();
+    for record in records {
+        let depth = record.stack.len().saturating_sub(1);
+        let name = record.stack.last().map_or("", String::as_str);
+        let share = if total == 0 {
+            0.0
+        } else {
+            record.cycles as f64 * 100.0 / total as f64
+        };
+        breakdown.push_str(&format!(
+            "{:indent$}{name:<width$} {:>12} cycles {share:>5.1}%  ({} calls)\n",
+            "",
+            record.cycles,
+            record.calls,
+            indent = 2 * depth,
+            width = 32usize.saturating_sub(2 * depth),
+        ));
+    }
+    breakdown
+}
+
+#[test]
+fn test_span_records_round_trip() {
+    let records = [
+        SpanRecord {
+            stack: vec!["verify".to_string()],
+            calls: 2,
+            cycles: 1000,
+        },
+        SpanRecord {
+            stack: vec!["verify".to_string(), "hash pair".to_string()],
+            calls: 40,
+            cycles: 750,
+        },
+    ];
+    for record in &records {
+        assert_eq!(
+            SpanRecord::parse_line(&record.to_string()).as_ref(),
+            Some(record)
+        );
+    }
+
+    let io = IoRecord {
+        bytes_in: 12,
+        bytes_out: 34,
+    };
+    assert_eq!(IoRecord::parse_line(&io.to_string()), Some(io));
+    assert_eq!(SpanRecord::parse_line(&io.to_string()), None);
+
+    let breakdown = format_breakdown(&records);
+    assert!(breakdown.starts_with("verify "));
+    assert!(breakdown.contains("\n  hash pair "));
+    assert!(breakdown.contains(" 75.0%  (40 calls)"));
+}
@@ -0,0 +1,56 @@
+//! The identity of the running guest program.
+
+/// Environment variable that sets the program id [`self_id`] returns on the host, as 64 hex
+/// digits.
+pub const VALIDA_PROGRAM_ID_ENV: &str = "VALIDA_PROGRAM_ID";
+
+#[cfg(all(valida, feature = "intrinsics"))]
+extern "C" {
+    fn valida_program_id(out: *mut u8);
+}
+
+/// The commitment to the running program's code that the VM binds its proofs to, so a guest can
+/// tie its output to its own identity (for example, to check a proof of a previous run of itself
+/// with [`crate::verify::verify_valida_proof`]).
+///
+/// In the VM this requires the `intrinsics` feature. On the host the id is read from
+/// `VALIDA_PROGRAM_ID`. When the id is not known it is all zeros.
+pub fn self_id() -> [u8; 32] {
+    #[cfg(all(valida, feature = "intrinsics"))]
+    {
+        let mut id = [0; 32];
+        unsafe { valida_program_id(id.as_mut_ptr()) };
+        id
+    }
+
+    #[cfg(not(all(valida, feature = "intrinsics")))]
+    std::env::var(VALIDA_PROGRAM_ID_ENV)
+        .ok()
+        .and_then(|hex| parse_id(&hex))
+        .unwrap_or_default()
+}
+
+#[cfg(not(all(valida, feature = "intrinsics")))]
+fn parse_id(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut id = [0; 32];
+    for (byte, digits) in id.iter_mut().zip(hex.as_bytes().as_chunks::<2>().0) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(id)
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_parse_id() {
+    let hex = "0x".to_string() + &"ab".repeat(31) + "01";
+    let mut expected = [0xab; 32];
+    expected[31] = 1;
+    assert_eq!(parse_id(&hex), Some(expected));
+    assert_eq!(parse_id("abcd"), None);
+    assert_eq!(parse_id(&"zz".repeat(32)), None);
+}
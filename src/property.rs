@@ -0,0 +1,77 @@
+//! Property-based tests with replay of failing inputs in the Valida VM.
+//!
+//! Inputs are generated and shrunk with `proptest` on the host. When a property fails, the
+//! minimized input is recorded and, if tests also run on Valida, the test runner re-runs the
+//! property in the VM with just that input and reports whether the host and the VM agree.
+//!
+//! The VM phase of a property that passes natively, or panics as `#[should_panic]` expects,
+//! runs the property with the first generated input, or with the minimized one that panicked, so
+//! the VM checks a case the host checked.
+//!
+//! Properties are declared with [`valida_proptest!`](crate::valida_proptest).
+
+use bincode::Options;
+pub use proptest;
+use proptest::{
+    strategy::Strategy,
+    test_runner::{Config, TestCaseError, TestError, TestRunner},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Check `property` against inputs generated by `strategy`.
+///
+/// On the host this runs the generated cases and panics with the minimized input if the property
+/// fails. In the VM the property runs with the input the host sent, see the module
+/// documentation; run without the host runner, the VM has no input and checks nothing.
+///
+/// # Panics
+/// If the property fails for any input.
+pub fn check<S>(name: &str, strategy: S, property: impl Fn(S::Value))
+where
+    S: Strategy,
+    S::Value: Serialize + DeserializeOwned,
+{
+    if crate::target::is_valida() {
+        if let Some(input) = crate::test_utils::take_replay_input() {
+            let value = codec()
+                .deserialize(&input)
+                .expect("Failed to decode the replayed input");
+            property(value);
+        }
+        return;
+    }
+
+    let config = Config {
+        failure_persistence: None,
+        ..Config::default()
+    };
+    let mut runner = TestRunner::new(config);
+    let sampled = std::cell::Cell::new(false);
+    let result = runner.run(&strategy, |value| {
+        if !sampled.replace(true) {
+            if let Ok(input) = codec().serialize(&value) {
+                crate::test_utils::record_sample(input);
+            }
+        }
+        property(value);
+        Ok::<(), TestCaseError>(())
+    });
+
+    match result {
+        Ok(()) => {}
+        Err(TestError::Fail(reason, value)) => {
+            if let Ok(input) = codec().serialize(&value) {
+                crate::test_utils::record_counterexample(input);
+            }
+            panic!("Property {name} failed for minimal input {value:?}: {reason}");
+        }
+        Err(TestError::Abort(reason)) => panic!("Property {name} aborted: {reason}"),
+    }
+}
+
+/// The encoding used to hand inputs from the host to the VM.
+fn codec() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .with_little_endian()
+}
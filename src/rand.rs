@@ -1,4 +1,4 @@
-use std::cell::OnceCell;
+use std::{cell::OnceCell, ptr::addr_of_mut};
 
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
@@ -13,9 +13,9 @@ static mut RNG: OnceCell<StdRng> = OnceCell::new();
 /// Generates random bytes.
 pub fn valida_rand(s: &mut [u8]) -> Result<(), getrandom::Error> {
     unsafe {
-        let rng = RNG.get_mut_or_init(|| StdRng::seed_from_u64(PRNG_SEED));
-        for i in 0..s.len() {
-            s[i] = rng.gen();
+        let rng = (*addr_of_mut!(RNG)).get_mut_or_init(|| StdRng::seed_from_u64(PRNG_SEED));
+        for b in s.iter_mut() {
+            *b = rng.gen();
         }
     }
 
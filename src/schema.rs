@@ -0,0 +1,132 @@
+//! Types shared by a guest and its host, encoded the same way on both sides.
+//!
+//! `#[derive(ValidaIo)]` on a struct encodes it as a version byte followed by its fields in
+//! declaration order, each in bincode with fixed-width little-endian integers. The guest reads
+//! and writes it with [`ValidaIo::read_from_tape`] and [`ValidaIo::write_to_tape`], and the host
+//! with `host::InputBuilder::write_io` and `host::OutputReader::read_io`, all framed the same way.
+//! ```rust
+//! use valida_rs::schema::ValidaIo;
+//!
+//! #[derive(Debug, PartialEq, ValidaIo)]
+//! #[valida_io(version = 2)]
+//! pub struct Block {
+//!     pub number: u64,
+//!     pub transactions: Vec<Vec<u8>>,
+//! }
+//!
+//! // Pinned from the printed `Block::SCHEMA_HASH`; changing the fields then fails to compile.
+//! const _: () = assert!(Block::SCHEMA_HASH == 0x2a05_41d4_5d0a_a580);
+//!
+//! let block = Block { number: 7, transactions: vec![b"tx".to_vec()] };
+//! let bytes = block.to_bytes().unwrap();
+//! assert_eq!(bytes[0], 2);
+//! assert_eq!(Block::from_bytes(&bytes).unwrap(), block);
+//! ```
+//!
+//! Decoding a value written with another [`VERSION`](ValidaIo::VERSION) fails, so a guest and
+//! host built from different definitions report the mismatch instead of misreading the fields.
+//! [`SCHEMA_HASH`](ValidaIo::SCHEMA_HASH) covers the type's name, version, and field names and
+//! types, so pinning it in a `const` assertion turns a change into a compile error.
+
+use std::error::Error;
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+#[doc(hidden)]
+pub use serde;
+pub use valida_rs_derive::ValidaIo;
+
+/// A type with a stable encoding shared between guest and host; derive it with
+/// `#[derive(ValidaIo)]` rather than implementing it by hand.
+pub trait ValidaIo: Sized {
+    /// The version byte the encoding starts with, from `#[valida_io(version = N)]`, 0 by default.
+    const VERSION: u8;
+    /// A hash of the type's name, version, field names and field types.
+    const SCHEMA_HASH: u64;
+
+    fn encode_fields(&self, encoder: &mut FieldEncoder) -> Result<(), Box<dyn Error>>;
+
+    fn decode_fields(decoder: &mut FieldDecoder<'_>) -> Result<Self, Box<dyn Error>>;
+
+    /// The version byte followed by the fields.
+    fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut encoder = FieldEncoder {
+            bytes: vec![Self::VERSION],
+        };
+        self.encode_fields(&mut encoder)?;
+        Ok(encoder.bytes)
+    }
+
+    /// Decode what [`to_bytes`](Self::to_bytes) encoded, failing if the version differs or
+    /// bytes are left over.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let type_name = std::any::type_name::<Self>();
+        let (&version, fields) = bytes
+            .split_first()
+            .ok_or_else(|| format!("empty encoding of {type_name}"))?;
+        if version != Self::VERSION {
+            return Err(format!(
+                "{type_name} was encoded with schema version {version}, but this build has \
+                 version {}",
+                Self::VERSION
+            )
+            .into());
+        }
+        let mut decoder = FieldDecoder { remaining: fields };
+        let value = Self::decode_fields(&mut decoder)?;
+        if !decoder.remaining.is_empty() {
+            return Err(format!(
+                "{} bytes left over after decoding {type_name}",
+                decoder.remaining.len()
+            )
+            .into());
+        }
+        Ok(value)
+    }
+
+    /// Read a value off the input tape, framed with its length as written by
+    /// `host::InputBuilder::write_io`.
+    #[cfg(feature = "guest")]
+    fn read_from_tape() -> Result<Self, Box<dyn Error>> {
+        let len = crate::io::read_line::<usize>()?;
+        Self::from_bytes(&crate::io::read_n(len)?)
+    }
+
+    /// Write the value to the output tape, framed with its length for
+    /// `host::OutputReader::read_io`.
+    #[cfg(feature = "guest")]
+    fn write_to_tape(&self) -> Result<(), Box<dyn Error>> {
+        crate::io::write_serde::<crate::io::Raw, _>(&self.to_bytes()?)
+    }
+}
+
+fn options() -> impl Options {
+    bincode::options()
+        .with_fixint_encoding()
+        .with_little_endian()
+}
+
+/// Encodes the fields of a [`ValidaIo`] type, one after the other.
+#[derive(Debug)]
+pub struct FieldEncoder {
+    bytes: Vec<u8>,
+}
+
+impl FieldEncoder {
+    pub fn field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Box<dyn Error>> {
+        Ok(options().serialize_into(&mut self.bytes, value)?)
+    }
+}
+
+/// Decodes the fields of a [`ValidaIo`] type, in the order they were encoded.
+#[derive(Debug)]
+pub struct FieldDecoder<'a> {
+    remaining: &'a [u8],
+}
+
+impl FieldDecoder<'_> {
+    pub fn field<T: DeserializeOwned>(&mut self) -> Result<T, Box<dyn Error>> {
+        Ok(options().deserialize_from(&mut self.remaining)?)
+    }
+}
@@ -0,0 +1,117 @@
+//! Golden tests of the output a guest commits to the output tape.
+//!
+//! [`valida_snapshot!`](crate::valida_snapshot) captures what a block writes to the output tape
+//! and compares it with a snapshot file checked into the repository. On the host the comparison
+//! happens in the test itself. The VM cannot read files, so there the captured output is printed
+//! as a record that the host test runner checks once the VM run finishes.
+//!
+//! Set `UPDATE_SNAPSHOTS=1` to write the snapshots instead of comparing against them. Missing
+//! snapshots are always written.
+#![cfg_attr(
+    feature = "guest",
+    doc = r#"
+```rust
+use valida_rs::snapshot::{assert_snapshot, capture};
+
+# let dir = std::env::temp_dir().join(format!("valida-snapshot-{}", std::process::id()));
+# let dir = dir.to_str().unwrap();
+let ((), output) = capture(|| valida_rs::io::write_vec(b"hello").unwrap());
+assert_snapshot(dir, "greeting", &output);
+# std::fs::remove_dir_all(dir).unwrap();
+```
+"#
+)]
+
+use std::path::Path;
+
+/// Environment variable that makes snapshot assertions overwrite the snapshot files.
+pub const UPDATE_ENV: &str = "UPDATE_SNAPSHOTS";
+
+/// Prefix of the line a snapshot record is printed on in the VM.
+pub const RECORD_PREFIX: &str = "valida-snapshot:";
+
+/// Run `f`, capturing what it writes to the output tape.
+#[cfg(feature = "guest")]
+pub fn capture<R>(f: impl FnOnce() -> R) -> (R, Vec<u8>) {
+    let capture = crate::io::Capture::start();
+    let value = f();
+    (value, capture.finish())
+}
+
+/// Assert that `actual` matches the snapshot `name` in `dir`.
+///
+/// # Panics
+/// On the host, if the snapshot exists, differs from `actual`, and `UPDATE_SNAPSHOTS` is not set.
+pub fn assert_snapshot(dir: &str, name: &str, actual: &[u8]) {
+    let path = Path::new(dir).join(format!("{name}.snap"));
+
+    if crate::target::is_valida() {
+        println!("{RECORD_PREFIX} {} {}", path.display(), to_hex(actual));
+        return;
+    }
+
+    if let Err(msg) = check(&path, actual) {
+        panic!("{msg}");
+    }
+}
+
+/// Compare `actual` with the snapshot at `path`, writing it if it is missing or being updated.
+fn check(path: &Path, actual: &[u8]) -> Result<(), String> {
+    let update = std::env::var(UPDATE_ENV).is_ok_and(|v| v == "1" || v == "true");
+
+    match std::fs::read(path) {
+        Ok(expected) if !update => {
+            if expected == actual {
+                Ok(())
+            } else {
+                Err(format!(
+                    "Snapshot {} does not match.\n\nexpected:\n{}\n\nactual:\n{}\n\n\
+                    Run with {UPDATE_ENV}=1 to update it.",
+                    path.display(),
+                    String::from_utf8_lossy(&expected),
+                    String::from_utf8_lossy(actual)
+                ))
+            }
+        }
+        _ => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+            }
+            std::fs::write(path, actual)
+                .map_err(|e| format!("Failed to write snapshot {}: {e}", path.display()))
+        }
+    }
+}
+
+/// Check the snapshot records printed by a test that ran in the VM.
+#[cfg(any(valida, feature = "host"))]
+pub(crate) fn check_vm_output(stdout: &str) -> Result<(), String> {
+    for line in stdout.lines() {
+        let Some(record) = line.trim().strip_prefix(RECORD_PREFIX) else {
+            continue;
+        };
+        let (path, hex) = record
+            .trim()
+            .rsplit_once(' ')
+            .unwrap_or((record.trim(), ""));
+        let actual = from_hex(hex).ok_or_else(|| format!("Malformed snapshot record: {line}"))?;
+        check(Path::new(path), &actual)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(any(valida, feature = "host"))]
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
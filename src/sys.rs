@@ -0,0 +1,60 @@
+//! Safe wrappers over the VM's system calls.
+//!
+//! This is the only module that calls the raw externs, so a change to the VM's syscall interface
+//! only has to be handled here. Off the VM the tapes are emulated with the process's stdin and
+//! stdout, so guest code that uses `io` runs natively without any `#[cfg]`.
+
+#[cfg(valida)]
+extern "C" {
+    fn getchar() -> u32;
+    fn putchar(c: u32) -> u32;
+}
+
+/// The value `getchar` returns at the end of the input tape.
+#[cfg(valida)]
+const EOF: u32 = u32::MAX;
+
+/// Read the next byte off the input tape, or `None` at EOF.
+#[cfg(valida)]
+pub(crate) fn read_byte() -> Option<u8> {
+    match unsafe { getchar() } {
+        EOF => None,
+        c => Some(c as u8),
+    }
+}
+
+/// Write `bytes` to the output tape.
+#[cfg(valida)]
+pub(crate) fn write_bytes(bytes: &[u8]) {
+    for &byte in bytes {
+        unsafe {
+            putchar(u32::from(byte));
+        }
+    }
+}
+
+/// Read the next byte of stdin, or `None` at EOF or on an error.
+#[cfg(not(valida))]
+pub(crate) fn read_byte() -> Option<u8> {
+    use std::io::Read;
+
+    let mut byte = 0;
+    match std::io::stdin()
+        .lock()
+        .read(std::slice::from_mut(&mut byte))
+    {
+        Ok(1) => Some(byte),
+        _ => None,
+    }
+}
+
+/// Write `bytes` to stdout, flushing so they interleave with `print!` output and reach a host
+/// waiting on them, such as one answering an `io::query`.
+#[cfg(not(valida))]
+pub(crate) fn write_bytes(bytes: &[u8]) {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout().lock();
+    // Like the VM's output tape, writes cannot fail; a closed stdout drops them.
+    let _ = stdout.write_all(bytes).and_then(|()| stdout.flush());
+}
@@ -0,0 +1,81 @@
+//! Detection of the Valida compilation target.
+//!
+//! Valida toolchains have shipped the VM target under both `delendum-*` and `valida-*` triples.
+//! Everything in this crate that needs to know which target it is building for, or running on,
+//! goes through this module so a future rename only has to be handled in one place.
+
+use std::{env, path::Path};
+
+/// The target triple used by current Valida toolchains.
+pub const VALIDA_TARGET_TRIPLE: &str = "valida-unknown-baremetal-gnu";
+
+/// The target triple used by older toolchains, before the target was renamed.
+pub const LEGACY_TARGET_TRIPLE: &str = "delendum-unknown-baremetal-gnu";
+
+/// Every target triple known to refer to the Valida VM.
+pub const KNOWN_TARGET_TRIPLES: &[&str] = &[VALIDA_TARGET_TRIPLE, LEGACY_TARGET_TRIPLE];
+
+/// Environment variable that overrides the triple the test harness builds for.
+pub const TARGET_TRIPLE_ENV: &str = "VALIDA_TARGET_TRIPLE";
+
+/// Returns `true` when the crate was compiled for the Valida VM.
+pub const fn is_valida() -> bool {
+    cfg!(valida)
+}
+
+/// The target triple to build guest code for.
+///
+/// This is [`VALIDA_TARGET_TRIPLE`] unless overridden by the `VALIDA_TARGET_TRIPLE` environment
+/// variable.
+pub fn target_triple() -> String {
+    env::var(TARGET_TRIPLE_ENV)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| VALIDA_TARGET_TRIPLE.to_string())
+}
+
+/// Returns `true` if `triple` is one of the [`KNOWN_TARGET_TRIPLES`].
+pub fn is_valida_triple(triple: &str) -> bool {
+    KNOWN_TARGET_TRIPLES.contains(&triple)
+}
+
+/// The form of `triple` cargo uses in per-target environment variables, e.g. `CC_<triple>`.
+pub fn env_var_suffix(triple: &str) -> String {
+    triple.replace(['-', '.'], "_")
+}
+
+/// The entry point object Valida toolchains ship.
+pub const ENTRY_POINT_OBJECT: &str = "DelendumEntryPoint.o";
+
+/// The name the entry point object may be renamed to along with the triple.
+pub const RENAMED_ENTRY_POINT_OBJECT: &str = "validaEntryPoint.o";
+
+/// The name of the entry point object in the toolchain installed in `toolchain_dir`.
+///
+/// This is [`ENTRY_POINT_OBJECT`], unless the toolchain only ships
+/// [`RENAMED_ENTRY_POINT_OBJECT`]. The triple does not decide it, since current toolchains use
+/// the new triple with the old object name.
+pub fn entry_point_object(toolchain_dir: &Path) -> &'static str {
+    if !toolchain_dir.join(ENTRY_POINT_OBJECT).exists()
+        && toolchain_dir.join(RENAMED_ENTRY_POINT_OBJECT).exists()
+    {
+        RENAMED_ENTRY_POINT_OBJECT
+    } else {
+        ENTRY_POINT_OBJECT
+    }
+}
+
+#[cfg(all(feature = "host", not(valida)))]
+#[test]
+fn test_entry_point_object() {
+    let toolchain = tempfile::tempdir().unwrap();
+    assert_eq!(entry_point_object(toolchain.path()), ENTRY_POINT_OBJECT);
+    std::fs::write(toolchain.path().join(RENAMED_ENTRY_POINT_OBJECT), b"").unwrap();
+    assert_eq!(
+        entry_point_object(toolchain.path()),
+        RENAMED_ENTRY_POINT_OBJECT
+    );
+    std::fs::write(toolchain.path().join(ENTRY_POINT_OBJECT), b"").unwrap();
+    assert_eq!(entry_point_object(toolchain.path()), ENTRY_POINT_OBJECT);
+}
@@ -15,19 +15,156 @@
 //! ```
 //!
 //! You can run tests on both the host and Valida by setting the `VALIDA_TEST` environment variable to `1`.
+//! Set `VALIDA_TEST_RETRIES=n` to retry tests whose VM run fails for environmental reasons, such as
+//! the VM crashing or timing out, up to `n` times; tests that pass on a retry are reported as flaky
+//! rather than failing the run.
+//!
+//! Pass `--list` to list the tests with their kind and whether the Valida phase supports them.
+//!
+//! Ignored tests are reported with the reason given in `#[ignore = "reason"]`, and the summary
+//! counts them by reason. Pass `--include-ignored` to run them, natively and in the VM, along
+//! with the other tests.
+//!
+//! The runner understands the libtest arguments `cargo nextest` uses (`--list --format terse`,
+//! `--exact` and `--ignored`), so `cargo nextest run` can schedule each test in its own process
+//! and, with `VALIDA_TEST` set, in the VM.
+//!
+//! Pass `--shuffle` (or `--shuffle-seed <seed>` to reproduce a previous order) to run the tests
+//! in a random order. The seed is printed so failures caused by test order can be reproduced.
+//!
+//! Tests declared with [`valida_only!`](crate::valida_only) or [`host_only!`](crate::host_only),
+//! or named `valida_only_*` / `host_only_*`, run in only one environment and are reported as
+//! skipped in the other.
+//!
+//! Set `VALIDA_TEST_SHARD=k/n` to run only the `k`th of `n` shards of the tests, e.g. to split a
+//! slow suite across CI machines. Tests are assigned to shards by a stable hash of their names.
+//!
+//! Tests that must run after others, e.g. after a test that writes shared fixtures, are declared
+//! with [`valida_test_order!`](crate::valida_test_order). The runner keeps that order natively
+//! and in the VM, even when shuffling or with workers, and puts ordered tests in the same shard.
+//!
+//! A `should_panic` test that times out in the VM fails, since the VM reports panics through its
+//! exit status or a sentinel. Set `VALIDA_TEST_TIMEOUT_AS_PANIC=1` for VMs that hang on panic.
+//!
+//! Set `VALIDA_TEST_MEMORY_LIMIT` (bytes, with an optional `K`/`M`/`G` suffix; Linux only) or
+//! `VALIDA_TEST_TIME_LIMIT` (seconds) to kill VM runs that exceed them, see [`ResourceLimits`].
+//!
+//! Starting `valida` dominates the runtime of small tests. Set `VALIDA_TEST_WORKERS=n` to instead
+//! keep `n` test binaries running in the VM and dispatch the tests to whichever is idle, in
+//! parallel with the native runs. A worker that panics or fails is replaced by a fresh one.
+//!
+//! Pressing Ctrl-C stops the VMs that are running, prints the results so far and exits with
+//! [`INTERRUPTED_EXIT_CODE`]; press it again to exit straight away.
+//!
+//! Pass `--fail-fast` or set `VALIDA_TEST_FAIL_FAST=1` to stop in the same way at the first test
+//! that fails natively or in the VM, cancelling the runs still pending, and print the summary of
+//! the tests that completed.
+//!
+//! The native and VM durations of each test that passes in the VM, and its cycles when the VM has
+//! a cycle counter, are kept in `<target dir>/valida-test-times.json`. A test whose cycles (or VM
+//! time, without a cycle counter) grew by more than `VALIDA_TEST_REGRESSION_THRESHOLD` percent
+//! (25 by default) since its previous run is reported with a warning and counted in the summary.
+//!
+//! With the `progress` feature, a progress bar with the number of VM runs done, the current test
+//! and an estimate of the time left is shown while the Valida tests run, or a line every tenth of
+//! the runs when stderr is not a terminal.
+//!
+//! Set `VALIDA_TEST_DIFF_OUTPUT=1` to compare what each test prints natively and in the VM, and
+//! print a unified diff when they differ, e.g. because of float formatting or endianness.
+//!
+//! The Valida tests are built in `<target dir>/valida-tests`, for the crate under test only, and
+//! with the feature, package and profile arguments given to `cargo test`. Set `VALIDA_TEST_CARGO_ARGS` to pass other arguments, e.g. on platforms where the
+//! `cargo test` command line cannot be read.
+//!
+//! Failures in the VM are classified by [`ValidaTestError`] and tallied by kind in the summary.
+//! Set `VALIDA_TEST_JSON=<path>` to also append each failure to a file as a JSON line.
+//!
+//! Results are reported through [`TestReporter`]s. Set `VALIDA_TEST_JUNIT=<path>` to also write
+//! a JUnit XML report, or `VALIDA_TEST_JSON_EVENTS=<path>` to write every result and the summary
+//! as JSON lines. Other tools can add their own reporter with [`test_runner_with_reporter`].
+//!
+//! Set `VALIDA_TEST_ARTIFACTS=<dir>` to record each test's VM run in `<dir>/<test name>`: the
+//! exact input, the output, the exit status and cycle count, and a `valida run` command line that
+//! reproduces the run, which is printed with the failure message. Then set
+//! `VALIDA_TEST_REPLAY=<test name>` to skip the build and re-run just that run with live output.
+//!
+//! Pass `--coverage` to measure the coverage of the host phase, e.g. with
+//! `cargo llvm-cov -- --coverage`; it is implied when `cargo llvm-cov` runs the tests. Test
+//! output is then printed rather than captured, and the Valida tests are built without the
+//! instrumentation flags, which the VM target does not support.
+//!
+//! As with libtest, what a test prints natively is captured per test, from `print!`, `eprint!`
+//! and panic messages on the test's thread and the threads it spawns, without redirecting the
+//! process's streams. Output written to them directly, e.g. by C code or child processes, is not
+//! captured.
+//!
+//! The VM binary names the valida-rs version and [`PROTOCOL_VERSION`] it was built with in its
+//! first line of output. The runner warns when they differ from its own, e.g. when a stale VM
+//! binary is run after upgrading valida-rs, rather than misreading the binary's output.
+//!
+//! To run a VM test binary's tests without the runner, send it [`RUN_ALL_TESTS`] as its input,
+//! e.g. `echo '*' | valida run <test binary> log`. It runs the tests that are not ignored one
+//! after another in the VM and prints a summary. Follow the `*` with a space and a filter, e.g.
+//! `* io::`, to only run the tests that match it, see [`matches_test_filter`];
+//! [`run_matching_in_valida`] does this from the host.
+//!
+//! Declare what a test must print in the VM with
+//! [`valida_expect_output!`](crate::valida_expect_output). The runner checks it whether the test
+//! passes or panics as `#[should_panic]` expects, so a test that panicked before committing its
+//! output, i.e. for the wrong reason, fails.
+//!
+//! Tests that read data files declare them with [`fixtures`]. Natively the files are copied to a
+//! temporary directory; in the VM, which has no file system, the runner sends them along with the
+//! test's request, so [`Fixtures::read`] returns the same contents in both.
+//!
+//! When building or running the tests for Valida fails, [`check_environment`] prints which parts
+//! of the Valida installation are missing and how to install them.
+//!
+//! To run Valida test binaries from other tools, use [`run_in_valida`]. To run a suite from
+//! another tool and get each test's result, durations and output back as a serializable
+//! [`SuiteReport`], use [`run_all`].
+//!
+//! Each of these settings can also be given a per-crate default in a `[package.metadata.valida]`
+//! section of the crate's `Cargo.toml`; see [`ValidaTestConfig`].
+//!
+//! # Doctests
+//! Doctests are run by rustdoc outside of the custom test runner. To include them in the host
+//! phase (and check that they compile for Valida), add this to the root of a library crate:
+//! ```rust
+//! valida_rs::doctests!();
+//! # fn main() {}
+//! ```
+//!
+//! # Benchmarks
+//! Benchmarks registered with [`valida_bench!`](crate::valida_bench) or `#[bench]` report their
+//! per-iteration cost on the host in nanoseconds and, in the VM, in cycles when the `intrinsics`
+//! feature provides a cycle counter. `#[bench]` functions only run once under `cargo test`; they
+//! are measured under `cargo bench`.
+//!
+//! # Dynamic tests
+//! Tests with dynamic functions or names (`TestFn::DynTestFn` and friends), e.g. from a runner
+//! that generates test cases and hands them to [`test_runner`], are supported. The VM binary must
+//! generate the same names for the host to select them.
+//!
+//! # Property tests
+//! With the `proptest` feature, [`valida_proptest!`](crate::valida_proptest) declares property
+//! tests. When a property fails on the host, its minimized input is replayed in the VM and the
+//! runner reports whether the host and Valida agree. Other adapters can use the same mechanism
+//! through [`record_counterexample`] and [`take_replay_input`].
 //!
 //! # Caveats
-//! Testing examples, benchmarks, or any dynamic tests are not supported yet.
+//! Testing examples is not supported yet.
 
 #![allow(unexpected_cfgs)]
 
-extern crate test;
-
-#[cfg_attr(target_arch = "valida", allow(unused_imports))]
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Serialize;
+#[cfg_attr(valida, allow(unused_imports))]
 use std::{
+    collections::BTreeMap,
     env,
-    io::{BufRead, Seek, Write},
-    panic::{self, AssertUnwindSafe},
+    io::{BufRead, Write},
+    panic,
     path::{Path, PathBuf},
     process::{Command, Stdio},
     time::{Duration, Instant},
@@ -36,138 +173,800 @@ use std::{
     io::Read,
     mem,
     ops::{Deref, DerefMut},
-    process::Child,
-    sync::mpsc,
+    process::{Child, ChildStdin},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+};
+// Panics cannot be caught in the VM, where guests are built with `panic = "abort"`.
+#[cfg(not(valida))]
+use expect::ExpectedOutput;
+#[cfg(not(valida))]
+use order::TestOrder;
+#[cfg(not(valida))]
+use progress::Progress;
+#[cfg(not(valida))]
+use std::panic::AssertUnwindSafe;
+#[cfg_attr(valida, allow(unused_imports))]
+use test::{ShouldPanic, TestDesc, TestDescAndFn, TestFn};
+#[cfg(not(valida))]
+use timings::{TestTiming, Timings};
+
+#[cfg(not(valida))]
+mod artifacts;
+#[cfg(not(valida))]
+mod config;
+#[cfg(not(valida))]
+mod declaration;
+#[cfg(not(valida))]
+mod doctor;
+#[cfg(not(valida))]
+mod expect;
+mod fixtures;
+#[cfg(not(valida))]
+mod interrupt;
+#[cfg(not(valida))]
+mod order;
+#[cfg(not(valida))]
+mod progress;
+mod reporter;
+#[cfg(not(valida))]
+mod timings;
+
+#[cfg(not(valida))]
+pub use artifacts::{VALIDA_TEST_ARTIFACTS_ENV, VALIDA_TEST_REPLAY_ENV};
+#[cfg(not(valida))]
+pub use config::{
+    ValidaTestConfig, VALIDA_COMMAND_ENV, VALIDA_TEST_FAIL_FAST_ENV, VALIDA_TEST_FLOAT_ULPS_ENV,
+    VALIDA_TEST_JSON_EVENTS_ENV, VALIDA_TEST_JUNIT_ENV, VALIDA_TEST_MIN_TIMEOUT_ENV,
+    VALIDA_TEST_REGRESSION_THRESHOLD_ENV, VALIDA_TEST_SIZE_REPORT_ENV,
+    VALIDA_TEST_TIMEOUT_MULTIPLIER_ENV, VALIDA_TEST_WORKERS_ENV,
+};
+#[cfg(not(valida))]
+pub use doctor::{check_environment, EnvironmentProblem};
+pub use fixtures::{fixtures, Fixtures};
+#[cfg(not(valida))]
+pub use interrupt::INTERRUPTED_EXIT_CODE;
+#[cfg(not(valida))]
+pub use reporter::JsonReporter;
+pub use reporter::{
+    ConsoleReporter, JunitReporter, Phase, PhaseCounts, PhaseDurations, RunSummary, SuiteReport,
+    TestOutput, TestReporter, TestResult, TestRunReport, TestStatus,
 };
-#[cfg_attr(target_arch = "valida", allow(unused_imports))]
-use test::{ShouldPanic, TestDescAndFn, TestFn};
+
+/// The exit status a test that panicked in the VM halts with, as with Rust's default panic exit.
+pub const PANIC_EXIT_CODE: i32 = 101;
 
 /// A random sentinel value is printed by the panic hook.
 /// This is used to detect if a test running in valida has panicked.
+///
+/// It is only printed when the VM cannot halt with [`PANIC_EXIT_CODE`], which the host detects
+/// from the exit status instead.
 pub const MAGIC_TERMINATOR: &str = "\n\n\n\nvalida_rs_panic_terminator_YMYGE2otWHIAZ5IKtvT\
 kCnt7B/aNTisJtmkNu9/H0C2pZp7XTeGIO2RZypwus7wvKyG9f4/nwrEP1vEy+YJJqS6ulJqks25EgHbZXQIZIWVfVK\
 +HgmFvaINl49axeKZgk2SNIDAayGhmO5a0okHc9qFzOZhDIblXdybCoVCVaZfX/5G9T4FbbX8ktLV0nLI/nns1fakAp\
 i2eHTxP/+lWlXFznl+eipFNQg9h3ZS7VX6i3EGTOYO86TJmAUyLAfqKWuQFTvNHeFFofd4nhUiek2FuI939T3L5uFc7\
 A9oQClGmLTSaGytDNT8slxuaRvQM99ntk+CLK+X8eNVQdKh0xA\n\n\n\n";
 
+/// The prefix of the line the panic hook prints with a [`PanicRecord`], so the host can report
+/// the panic and check `#[should_panic(expected = "...")]` for tests run in the VM.
+pub const PANIC_RECORD_PREFIX: &str = "valida-panic:";
+
+/// The prefix of the line with the cycles a test took in the VM, printed after it returns when
+/// the VM has a cycle counter.
+pub const TEST_CYCLES_PREFIX: &str = "valida-test-cycles:";
+
+/// What the panic hook reports about a test that panicked in the VM.
+///
+/// It is printed as [`PANIC_RECORD_PREFIX`] followed by the record as JSON on one line, before
+/// the VM halts or, on VMs that cannot halt, before [`MAGIC_TERMINATOR`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, serde::Deserialize)]
+pub struct PanicRecord {
+    /// The name of the test.
+    pub test: String,
+    /// Where the test is declared.
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// Where the panic happened, as `file:line:column`.
+    pub location: String,
+    /// The panic message.
+    pub message: String,
+    /// The cycles the VM had executed when the test panicked, if it has a cycle counter.
+    pub cycles: Option<u64>,
+}
+
+impl PanicRecord {
+    /// Parse a line printed by the panic hook.
+    #[cfg(not(valida))]
+    pub fn parse_line(line: &str) -> Option<Self> {
+        serde_json::from_str(line.trim().strip_prefix(PANIC_RECORD_PREFIX)?).ok()
+    }
+
+    /// The record in what a test printed in the VM, if it panicked.
+    #[cfg(not(valida))]
+    pub fn find(output: &str) -> Option<Self> {
+        output.lines().find_map(Self::parse_line)
+    }
+}
+
+/// JSON is written by hand, since `serde_json` is not available in the VM.
+impl std::fmt::Display for PanicRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+            f.write_str("\"")?;
+            for c in s.chars() {
+                match c {
+                    '"' => f.write_str("\\\"")?,
+                    '\\' => f.write_str("\\\\")?,
+                    '\n' => f.write_str("\\n")?,
+                    '\r' => f.write_str("\\r")?,
+                    '\t' => f.write_str("\\t")?,
+                    c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                    c => write!(f, "{c}")?,
+                }
+            }
+            f.write_str("\"")
+        }
+
+        write!(f, "{PANIC_RECORD_PREFIX} {{\"test\":")?;
+        string(f, &self.test)?;
+        f.write_str(",\"file\":")?;
+        string(f, &self.file)?;
+        write!(
+            f,
+            ",\"line\":{},\"column\":{},\"location\":",
+            self.line, self.column
+        )?;
+        string(f, &self.location)?;
+        f.write_str(",\"message\":")?;
+        string(f, &self.message)?;
+        match self.cycles {
+            Some(cycles) => write!(f, ",\"cycles\":{cycles}}}"),
+            None => f.write_str(",\"cycles\":null}"),
+        }
+    }
+}
+
 pub fn test_runner(tests: &[&TestDescAndFn]) {
-    #[allow(unexpected_cfgs)]
-    if cfg!(target_arch = "valida") | cfg!(target = "valida") {
-        run_single_test_in_valida(tests);
-    } else {
-        #[cfg(not(target_arch = "valida"))]
-        host_runner(tests);
+    #[cfg(feature = "guest")]
+    if crate::target::is_valida() {
+        return run_single_test_in_valida(tests);
+    }
+
+    #[cfg(not(valida))]
+    exit_with(tests, None);
+}
+
+/// [`test_runner`], also reporting to `reporter` on the host; see [`TestReporter`].
+pub fn test_runner_with_reporter(tests: &[&TestDescAndFn], reporter: impl TestReporter + 'static) {
+    #[cfg(feature = "guest")]
+    if crate::target::is_valida() {
+        return run_single_test_in_valida(tests);
+    }
+
+    #[cfg(not(valida))]
+    exit_with(tests, Some(Box::new(reporter)));
+    #[cfg(valida)]
+    drop(reporter);
+}
+
+/// Run `tests` natively and, if `config` enables it, in the VM, as [`test_runner`] does, and
+/// return what happened to each instead of exiting.
+///
+/// For tools that embed the runner, e.g. to run the tests they generate and show the results
+/// themselves, without scraping its output. All of `tests` run except the ignored ones, so
+/// filter them beforehand to run fewer. The results are still printed, and written to the report
+/// files `config` names. `config` replaces the configuration from the environment and the
+/// manifest for the rest of the process.
+#[cfg(not(valida))]
+pub fn run_all(tests: &[&TestDescAndFn], config: ValidaTestConfig) -> SuiteReport {
+    config.install();
+    run_tests(tests, &RunnerArgs::default(), None)
+}
+
+/// Run the host runner, or the replay the configuration asks for, and exit with its status if
+/// it failed.
+///
+/// Only the entry points the test harness calls exit, once everything else has been dropped.
+/// Returning on success lets the profiling runtime of a coverage build write its data the usual
+/// way.
+#[cfg(not(valida))]
+fn exit_with(tests: &[&TestDescAndFn], reporter: Option<Box<dyn TestReporter>>) {
+    let code = match &ValidaTestConfig::get().replay {
+        Some(test_name) => artifacts::replay(test_name).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            1
+        }),
+        None => host_runner(tests, reporter).exit_code(),
+    };
+    if code != 0 {
+        let _ = std::io::stdout().flush();
+        std::process::exit(code);
+    }
+}
+
+/// Run or list the tests as the test binary's arguments ask, reporting to the configured
+/// reporters and `reporter`.
+#[cfg(not(valida))]
+fn host_runner(tests: &[&TestDescAndFn], reporter: Option<Box<dyn TestReporter>>) -> SuiteReport {
+    let args = RunnerArgs::from_env();
+    if args.list {
+        let (_, selected) = select_tests(tests, &args);
+        list_tests(&selected, args.terse);
+        return SuiteReport::default();
     }
+    run_tests(tests, &args, reporter)
+}
+
+/// The tests `args` and the configured shard select, with the order declared between them.
+#[cfg(not(valida))]
+fn select_tests<'a>(
+    tests: &'a [&'a TestDescAndFn],
+    args: &RunnerArgs,
+) -> (TestOrder, Vec<&'a &'a TestDescAndFn>) {
+    let shard = ValidaTestConfig::get().shard;
+    let order = TestOrder::declared(tests);
+    let selected = tests
+        .iter()
+        .filter(|t| !declaration::is_declaration(t.desc.name.as_slice()))
+        .filter(|t| args.selects(t))
+        .filter(|t| {
+            shard.is_none_or(|shard| shard.contains(order.shard_key(t.desc.name.as_slice())))
+        })
+        .collect();
+    (order, selected)
 }
 
-#[cfg(not(target_arch = "valida"))]
-fn host_runner(tests: &[&TestDescAndFn]) {
-    let run_tests_on_valida = env::var("VALIDA_TEST").map(|s| s.to_lowercase());
-    let run_tests_on_valida = match run_tests_on_valida {
-        Ok(val) => val == "1" || val == "true" || val == "yes" || val == "on",
-        Err(_) => false,
+/// Run the tests `args` selects, reporting to the configured reporters and `extra`, and return
+/// the report of each.
+#[cfg(not(valida))]
+fn run_tests(
+    tests: &[&TestDescAndFn],
+    args: &RunnerArgs,
+    extra: Option<Box<dyn TestReporter>>,
+) -> SuiteReport {
+    let config = ValidaTestConfig::get();
+    let run_tests_on_valida = config.run_on_valida;
+    let bench_mode = args.bench;
+    let retries = config.retries;
+    let shard = config.shard;
+    interrupt::install();
+
+    let fail_fast = args.fail_fast || config.fail_fast;
+    let mut valida = ValidaTally {
+        expected_output: ExpectedOutput::declared(tests),
+        timings: (run_tests_on_valida && !bench_mode).then(Timings::load),
+        fail_fast,
+        ..Default::default()
+    };
+
+    let filter = args.filter.clone();
+
+    let (order, mut filtered_tests) = select_tests(tests, args);
+    let mut report = SuiteReport::default();
+    let mut reporter = reporters(config, extra);
+    reporter.push(Box::new(&mut report));
+    let mut summary = RunSummary {
+        tests: filtered_tests.len(),
+        shard,
+        ..Default::default()
     };
 
+    if let Some(seed) = args.shuffle_seed {
+        println!("shuffling tests with seed {seed}");
+        filtered_tests.shuffle(&mut StdRng::seed_from_u64(seed));
+    }
+    order.sort(&mut filtered_tests);
+
+    if run_tests_on_valida {
+        if let Err(e) = crate::host::check_valida(&config.valida_command) {
+            eprintln!("{e}\n");
+            check_environment();
+            summary.error = Some(e.to_string());
+            reporter.run_finished(&summary);
+            drop(reporter);
+            return report;
+        }
+    }
+
     let test_paths = if run_tests_on_valida {
         println!("Building tests for valida");
-        build_tests_for_valida()
+        build_tests_for_valida(args.coverage)
     } else {
         vec![]
     };
 
-    let mut passed = 0;
-    let mut valida_passed = 0;
-    let mut ignored = 0;
-    let mut failed = 0;
-    let mut valida_failed = 0;
-    let mut unsupported = 0;
-
-    // Get test filter from environment variable, just like rustc does
-    let filter = env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let mut pool = (run_tests_on_valida && config.workers > 0)
+        .then(|| VmPool::new(config.workers, &test_paths, retries));
+    // The tests sent to the pool whose results have not been reported yet.
+    let mut in_pool = std::collections::HashSet::new();
 
-    let filtered_tests: Vec<&&TestDescAndFn> = match &filter {
-        Some(f) => tests
-            .iter()
-            .filter(|t| t.desc.name.as_slice().contains(f))
-            .collect(),
-        None => tests.iter().collect(),
-    };
+    reporter.run_started(filtered_tests.len(), filter.as_deref(), shard);
 
-    if let Some(f) = &filter {
-        println!("Running tests matching '{}'", f);
-    }
-    println!("running {} tests", filtered_tests.len());
+    let vm_runs = filtered_tests
+        .iter()
+        .filter(|t| !t.desc.ignore || args.runs_ignored())
+        .filter(|t| TestEnvironment::of(t.desc.name.as_slice()) != TestEnvironment::HostOnly)
+        .count();
+    let mut progress = Progress::new(if run_tests_on_valida { vm_runs } else { 0 });
 
     for t in filtered_tests.iter() {
-        print!("test {} on native ... ", t.desc.name);
-
-        if t.desc.ignore {
-            println!("ignored");
-            ignored += 1;
+        progress.clear();
+        if fail_fast && summary.native.failed > 0 {
+            interrupt::stop();
+        }
+        if interrupt::requested() {
+            break;
+        }
+        let name = t.desc.name.as_slice();
+        let environment = TestEnvironment::of(name);
+        let finished = |phase, status, duration| TestResult {
+            name: name.to_string(),
+            phase,
+            status,
+            duration,
+            cycles: None,
+            output: None,
+        };
+        reporter.test_started(name, Phase::Native);
+
+        if t.desc.ignore && !args.runs_ignored() {
+            let reason = t.desc.ignore_message;
+            let status = TestStatus::Ignored { reason };
+            reporter.test_finished(&finished(Phase::Native, status, None));
+            summary.ignored += 1;
+            if let Some(reason) = reason {
+                *summary.ignore_reasons.entry(reason).or_insert(0) += 1;
+            }
             continue;
         }
 
-        let r = run_test_on_host(t);
-        match r {
-            TestOutcome::Passed(test_time) => {
-                println!("ok");
-                passed += 1;
-
-                if run_tests_on_valida {
-                    print!("test {} on valida ... ", t.desc.name);
-                    match run_test_on_valida(t, &test_paths, test_time) {
-                        Ok(()) => {
-                            println!("ok");
-                            valida_passed += 1;
-                        }
-                        Err(msg) => {
-                            println!("FAILED");
-                            eprintln!("\n\ntest {} failure message: {}\n\n", t.desc.name, msg);
-                            valida_failed += 1;
+        // Every test starts without the state the previous one declared, even if it does not run
+        // natively, so none of it is sent to the VM for this test.
+        #[cfg(feature = "guest")]
+        crate::io::testing::clear_query_handler();
+        COUNTEREXAMPLE.lock().unwrap().take();
+        SAMPLE.lock().unwrap().take();
+        fixtures::clear();
+
+        let mut host_output = None;
+        // The input the test recorded natively, which the VM runs it with.
+        let mut replay_input = None;
+        // The time the test took on the host, used to scale the VM timeout, if it should run there.
+        let host_test_time = if environment == TestEnvironment::ValidaOnly {
+            let status = TestStatus::Skipped {
+                reason: "valida only",
+            };
+            reporter.test_finished(&finished(Phase::Native, status, None));
+            summary.native.skipped += 1;
+            Some(VALIDA_ONLY_HOST_TIME)
+        } else {
+            let (outcome, output) = run_test_on_host(t, bench_mode, !args.coverage);
+            match outcome {
+                TestOutcome::Passed(test_time) => {
+                    reporter.test_finished(&TestResult {
+                        output: output.clone(),
+                        ..finished(Phase::Native, TestStatus::Passed, Some(test_time))
+                    });
+                    summary.native.passed += 1;
+                    for summary in crate::bench::take_host_reports() {
+                        println!("bench {} on native: {}", t.desc.name, summary.describe());
+                    }
+                    host_output = output;
+                    // A `should_panic` property passes natively with its counterexample.
+                    replay_input = COUNTEREXAMPLE.lock().unwrap().take();
+                    replay_input = replay_input.or_else(|| SAMPLE.lock().unwrap().take());
+                    Some(test_time)
+                }
+                TestOutcome::Failed(message) => {
+                    let status = TestStatus::Failed {
+                        message,
+                        kind: None,
+                    };
+                    reporter.test_finished(&TestResult {
+                        output,
+                        ..finished(Phase::Native, status, None)
+                    });
+                    summary.native.failed += 1;
+
+                    let counterexample = COUNTEREXAMPLE.lock().unwrap().take();
+                    if let (true, Some(input)) = (run_tests_on_valida, counterexample) {
+                        print!(
+                            "test {} on valida (replaying counterexample) ... ",
+                            t.desc.name
+                        );
+                        let mode = RunMode::Replay(&input);
+                        match run_test_on_valida(t, &test_paths, Duration::from_secs(1), mode) {
+                            Ok(_) => println!("passed, host and valida disagree"),
+                            Err(msg) => {
+                                println!("failed, host and valida agree");
+                                eprintln!("\n\ntest {} failure message: {}\n\n", t.desc.name, msg);
+                            }
                         }
                     }
+                    None
+                }
+                TestOutcome::ShouldPanicButPassed => {
+                    let status = TestStatus::Failed {
+                        message: "test did not panic as expected".to_string(),
+                        kind: None,
+                    };
+                    reporter.test_finished(&TestResult {
+                        output,
+                        ..finished(Phase::Native, status, None)
+                    });
+                    summary.native.failed += 1;
+                    None
+                }
+                TestOutcome::Unsupported => {
+                    reporter.test_finished(&finished(Phase::Native, TestStatus::Unsupported, None));
+                    summary.unsupported += 1;
+                    None
                 }
             }
-            TestOutcome::Failed(msg) => {
-                println!("FAILED");
-                eprintln!("\ntest {} on native failure message: {}", t.desc.name, msg);
-                failed += 1;
+        };
+
+        let Some(test_time) = host_test_time.filter(|_| run_tests_on_valida) else {
+            if environment != TestEnvironment::HostOnly {
+                progress.skipped();
             }
-            TestOutcome::ShouldPanicButPassed => {
-                println!("FAILED");
-                eprintln!("\nfailure message: test did not panic as expected");
-                failed += 1;
+            continue;
+        };
+
+        if environment == TestEnvironment::HostOnly {
+            let status = TestStatus::Skipped {
+                reason: "host only",
+            };
+            reporter.test_started(name, Phase::Valida);
+            reporter.test_finished(&finished(Phase::Valida, status, None));
+            summary.valida.skipped += 1;
+            continue;
+        }
+
+        if let Some(pool) = &mut pool {
+            // Wait for the tests this one is ordered after, and report those already done.
+            loop {
+                let waiting = order.prerequisites(name).any(|p| in_pool.contains(p));
+                let done = if waiting {
+                    pool.next()
+                } else {
+                    pool.try_next()
+                };
+                let Some(done) = done.filter(|_| !interrupt::requested()) else {
+                    break;
+                };
+                in_pool.remove(done.job.desc.name.as_slice());
+                valida.record(
+                    &mut reporter,
+                    &done.job.desc,
+                    done.attempt,
+                    done.result,
+                    done.job.host_output,
+                    [done.job.host_test_time, done.duration],
+                );
+                progress.finished();
             }
-            TestOutcome::Unsupported => {
-                println!("unsupported");
-                unsupported += 1;
+            pool.submit(VmJob {
+                desc: t.desc.clone(),
+                host_test_time: test_time,
+                bench: bench_mode,
+                query_handler: query_handler(),
+                fixtures: fixtures::declared(),
+                replay_input,
+                host_output,
+            });
+            in_pool.insert(name);
+            progress.running(name);
+            continue;
+        }
+
+        // The test's line is printed once it is done, so the progress bar can be shown meanwhile.
+        progress.running(name);
+        progress.draw();
+        let mode = RunMode::new(replay_input.as_deref(), bench_mode);
+        let mut attempt = 0;
+        let mut start = Instant::now();
+        let mut result = run_test_on_valida(t, &test_paths, test_time, mode);
+        while let Some(msg) = retryable(&result, attempt, retries) {
+            attempt += 1;
+            eprintln!(
+                "\ntest {} failed on valida, retrying ({attempt}/{retries}): {msg}",
+                t.desc.name
+            );
+            start = Instant::now();
+            result = run_test_on_valida(t, &test_paths, test_time, mode);
+        }
+        let valida_time = start.elapsed();
+        progress.clear();
+        if interrupt::requested() {
+            reporter.test_started(name, Phase::Valida);
+            reporter.test_finished(&finished(Phase::Valida, TestStatus::Interrupted, None));
+            break;
+        }
+        valida.record(
+            &mut reporter,
+            &t.desc,
+            attempt,
+            result,
+            host_output,
+            [test_time, valida_time],
+        );
+        progress.finished();
+    }
+
+    if let Some(mut pool) = pool {
+        progress.draw();
+        while let Some(done) = pool.next() {
+            progress.clear();
+            if interrupt::requested() {
+                continue;
             }
+            valida.record(
+                &mut reporter,
+                &done.job.desc,
+                done.attempt,
+                done.result,
+                done.job.host_output,
+                [done.job.host_test_time, done.duration],
+            );
+            progress.finished();
+            progress.draw();
         }
     }
+    progress.clear();
+    if let Some(timings) = &valida.timings {
+        timings.save();
+    }
 
-    let test_result = failed == 0 && valida_failed == 0;
+    summary.interrupted = interrupt::interrupted();
+    summary.stopped_early = interrupt::requested() && !summary.interrupted;
+    summary.valida.passed = valida.passed;
+    summary.valida.failed = valida.failed;
+    summary.valida_failure_kinds = valida.failure_kinds;
+    summary.flaky = valida.flaky;
+    summary.diverged = config.diff_output.then_some(valida.diverged);
+    summary.regressed = valida.regressed;
+    if filtered_tests.is_empty() {
+        summary.unmatched_filter = filter;
+    }
+    reporter.run_finished(&summary);
+    drop(reporter);
+    report
+}
 
-    if filtered_tests.is_empty() && filter.is_some() {
-        println!("\nno tests matched filter '{}'", filter.unwrap());
-    } else {
+/// The console reporter, the reporters of the files in `config`, and `extra`.
+#[cfg(not(valida))]
+fn reporters<'a>(
+    config: &ValidaTestConfig,
+    extra: Option<Box<dyn TestReporter + 'a>>,
+) -> Vec<Box<dyn TestReporter + 'a>> {
+    let mut reporters: Vec<Box<dyn TestReporter + 'a>> = vec![Box::new(ConsoleReporter)];
+    let create = |path: &Path| {
+        std::fs::File::create(path)
+            .map(std::io::BufWriter::new)
+            .map_err(|e| eprintln!("Failed to create {}: {e}", path.display()))
+            .ok()
+    };
+    if let Some(file) = config.json_events.as_deref().and_then(create) {
+        reporters.push(Box::new(JsonReporter::new(file)));
+    }
+    if let Some(file) = config.junit_report.as_deref().and_then(create) {
+        reporters.push(Box::new(JunitReporter::new(file)));
+    }
+    reporters.extend(extra);
+    reporters
+}
+
+/// The results of the tests run in the VM, for the summary.
+#[cfg(not(valida))]
+#[derive(Debug, Default)]
+struct ValidaTally {
+    passed: usize,
+    failed: usize,
+    flaky: usize,
+    failure_kinds: BTreeMap<&'static str, usize>,
+    diverged: usize,
+    /// What tests are declared to print in the VM.
+    expected_output: ExpectedOutput,
+    /// Stop the run at the first failure.
+    fail_fast: bool,
+    /// The timings of previous runs, which passing tests are compared with and update, unless
+    /// benchmarking.
+    timings: Option<Timings>,
+    regressed: usize,
+}
+
+#[cfg(not(valida))]
+impl ValidaTally {
+    /// Report the result of the test `desc` in the VM, which passed or failed on its `attempt`th
+    /// retry, comparing its output with `host_output` if it ran natively. `[host_time,
+    /// valida_time]` are how long it took natively and in the VM.
+    fn record(
+        &mut self,
+        reporter: &mut dyn TestReporter,
+        desc: &TestDesc,
+        attempt: u32,
+        result: Result<Vec<u8>, ValidaTestError>,
+        host_output: Option<String>,
+        [host_time, valida_time]: [Duration; 2],
+    ) {
+        let name = desc.name.as_slice();
+        let result = result.and_then(|stdout| {
+            let stdout = String::from_utf8_lossy(&stdout).into_owned();
+            crate::snapshot::check_vm_output(&stdout)
+                .map_err(|message| ValidaTestError::SnapshotMismatch { message })?;
+            self.expected_output.check(name, &vm_test_output(&stdout))?;
+            Ok(stdout)
+        });
+        reporter.test_started(name, Phase::Valida);
+        match result {
+            Ok(stdout) => {
+                let output = crate::host::OutputReader::new(stdout.as_bytes());
+                let status = if attempt == 0 {
+                    TestStatus::Passed
+                } else {
+                    self.flaky += 1;
+                    TestStatus::Flaky { attempt }
+                };
+                reporter.test_finished(&TestResult {
+                    name: name.to_string(),
+                    phase: Phase::Valida,
+                    status,
+                    duration: Some(valida_time),
+                    cycles: output.test_cycles(),
+                    output: Some(stdout.clone()),
+                });
+                self.passed += 1;
+                for summary in output.bench_summaries() {
+                    println!("bench {} on valida: {}", desc.name, summary.describe());
+                }
+                let spans = output.spans();
+                if !spans.is_empty() {
+                    let io = output
+                        .io_record()
+                        .map(|io| io.describe() + "\n")
+                        .unwrap_or_default();
+                    println!(
+                        "profile {} on valida:\n{}{io}",
+                        desc.name,
+                        crate::profile::format_breakdown(&spans)
+                    );
+                }
+                if let (true, Some(host_output)) =
+                    (ValidaTestConfig::get().diff_output, host_output)
+                {
+                    self.diverged += usize::from(report_output_diff(desc, &host_output, &stdout));
+                }
+                if let Some(timings) = &mut self.timings {
+                    let valida_only = TestEnvironment::of(name) == TestEnvironment::ValidaOnly;
+                    let timing = TestTiming {
+                        native: (!valida_only).then_some(host_time.as_secs_f64()),
+                        valida: Some(valida_time.as_secs_f64()),
+                        cycles: output.test_cycles(),
+                    };
+                    let threshold = ValidaTestConfig::get().regression_threshold;
+                    if let Some(regression) = timings.record(name, timing, threshold) {
+                        eprintln!("warning: test {name} got slower on valida: {regression}");
+                        self.regressed += 1;
+                    }
+                }
+            }
+            Err(err) => {
+                reporter.test_finished(&TestResult {
+                    name: name.to_string(),
+                    phase: Phase::Valida,
+                    status: TestStatus::Failed {
+                        message: err.to_string(),
+                        kind: Some(err.kind()),
+                    },
+                    duration: Some(valida_time),
+                    cycles: None,
+                    output: err.output().map(str::to_string),
+                });
+                if let Some(command) = artifacts::reproduce_command(name) {
+                    eprintln!("reproduce the run with:\n    {command}\n");
+                }
+                self.failed += 1;
+                *self.failure_kinds.entry(err.kind()).or_insert(0) += 1;
+                if self.fail_fast {
+                    interrupt::stop();
+                }
+                write_json_failure(name, &err);
+            }
+        }
+    }
+}
+
+/// Where a test runs, from the `valida_only` / `host_only` naming convention.
+///
+/// A test runs in only one environment if a segment of its path is `valida_only` or `host_only`,
+/// or starts with `valida_only_` or `host_only_`; see [`valida_only!`](crate::valida_only) and
+/// [`host_only!`](crate::host_only).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestEnvironment {
+    /// The test runs natively and in the VM.
+    Both,
+    /// The test only runs natively, e.g. because it uses threads.
+    HostOnly,
+    /// The test only runs in the VM, e.g. because it uses the tape externs.
+    ValidaOnly,
+}
+
+impl TestEnvironment {
+    /// Classify a test by its name.
+    pub fn of(test_name: &str) -> Self {
+        let has_marker = |marker: &str| {
+            test_name.split("::").any(|segment| {
+                segment
+                    .strip_prefix(marker)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('_'))
+            })
+        };
+        if has_marker("valida_only") {
+            TestEnvironment::ValidaOnly
+        } else if has_marker("host_only") {
+            TestEnvironment::HostOnly
+        } else {
+            TestEnvironment::Both
+        }
+    }
+}
+
+/// The host test time assumed for `valida_only` tests, which scales the VM timeout.
+#[cfg(not(valida))]
+const VALIDA_ONLY_HOST_TIME: Duration = Duration::from_secs(1);
+
+/// Print the tests with their kind and whether the Valida phase can run them, for `--list`.
+///
+/// With `--format terse` only the `<name>: <kind>` lines libtest prints are written, so tools
+/// such as `cargo nextest` can discover the tests.
+#[cfg(not(valida))]
+fn list_tests(tests: &[&&TestDescAndFn], terse: bool) {
+    let mut benches = 0;
+    let mut ignored = 0;
+    for t in tests {
+        let (kind, valida) = match t.testfn {
+            TestFn::StaticTestFn(_) => ("test", "supported"),
+            TestFn::StaticBenchFn(_) | TestFn::StaticBenchAsTestFn(_) => ("bench", "supported"),
+            TestFn::DynTestFn(_) => ("test", "supported if the VM generates the same name"),
+            TestFn::DynBenchFn(_) | TestFn::DynBenchAsTestFn(_) => {
+                ("bench", "supported if the VM generates the same name")
+            }
+        };
+        if terse {
+            println!("{}: {kind}", t.desc.name);
+            continue;
+        }
+        benches += usize::from(kind == "bench");
+        ignored += usize::from(t.desc.ignore);
+        let valida = match TestEnvironment::of(t.desc.name.as_slice()) {
+            TestEnvironment::HostOnly => "skipped (host only)",
+            _ => valida,
+        };
+        let ignore_note = match (t.desc.ignore, t.desc.ignore_message) {
+            (true, Some(reason)) => format!(", ignored: {reason}"),
+            (true, None) => ", ignored".to_string(),
+            (false, _) => String::new(),
+        };
         println!(
-            "\ntest result: {}\n\
-            on native:      {passed} passed; {failed} failed\n\
-            on valida:      {valida_passed} passed; {valida_failed} failed\n\
-            {ignored} ignored;\n\
-            {unsupported} unsupported\n\n",
-            if test_result { "ok" } else { "FAILED" },
+            "{}: {kind} ({}{ignore_note}; valida: {valida})",
+            t.desc.name, t.desc.source_file
         );
     }
-
-    if test_result {
-        std::process::exit(0);
-    } else {
-        std::process::exit(1);
+    if terse {
+        return;
     }
+    println!(
+        "\n{} tests, {benches} benchmarks; {ignored} ignored",
+        tests.len() - benches
+    );
 }
 
-#[derive(Debug)]
+/// How a test ended natively; see [`TestRunReport`] for both phases.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", content = "detail", rename_all = "snake_case")]
 pub enum TestOutcome {
     Passed(Duration),
     Failed(String),
@@ -175,133 +974,974 @@ pub enum TestOutcome {
     Unsupported,
 }
 
-#[cfg(not(target_arch = "valida"))]
-fn run_test_on_host(test: &TestDescAndFn) -> TestOutcome {
-    use std::os::fd::AsRawFd;
+/// Why a test failed in the Valida VM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValidaTestError {
+    /// No test binaries were built for Valida.
+    NoBinaries,
+    /// None of the Valida test binaries contain the test.
+    NotFound { binaries: Vec<PathBuf> },
+    /// The test panicked but was not expected to.
+    Panicked {
+        /// What the panic hook reported, if it could.
+        panic: Option<Box<PanicRecord>>,
+        /// What the test printed, without the panic record.
+        output: String,
+    },
+    /// The test was expected to panic but completed.
+    DidNotPanic { output: String },
+    /// The test panicked without the message it was expected to panic with.
+    WrongPanicMessage {
+        expected: String,
+        actual: Option<String>,
+        output: String,
+    },
+    /// The VM exited with a failure status.
+    ExitFailure { code: Option<i32>, output: String },
+    /// The test did not finish within the timeout.
+    TimedOut {
+        timeout: Duration,
+        /// The test was expected to panic, and the VM gave no sign that it did.
+        expected_panic: bool,
+        output: String,
+    },
+    /// The VM exceeded a limit from [`ResourceLimits`] and was killed.
+    ResourceLimitExceeded { limit: String, output: String },
+    /// The VM process could not be waited on.
+    ProcessError { message: String, output: String },
+    /// The committed output did not match a snapshot.
+    SnapshotMismatch { message: String },
+    /// The test did not print what [`valida_expect_output!`](crate::valida_expect_output)
+    /// declared, e.g. because it panicked before committing it.
+    MissingOutput { expected: String, output: String },
+}
+
+impl ValidaTestError {
+    /// The error for a test that panicked unexpectedly, from what it printed in the VM.
+    #[cfg(not(valida))]
+    fn panicked(stdout: &[u8]) -> Self {
+        let stdout = String::from_utf8_lossy(stdout);
+        ValidaTestError::Panicked {
+            panic: PanicRecord::find(&stdout).map(Box::new),
+            output: stdout
+                .lines()
+                .filter(|line| !line.starts_with(PANIC_RECORD_PREFIX))
+                .map(|line| format!("{line}\n"))
+                .collect(),
+        }
+    }
+
+    /// What the VM printed before the failure, if it ran.
+    pub fn output(&self) -> Option<&str> {
+        match self {
+            ValidaTestError::Panicked { output, .. }
+            | ValidaTestError::DidNotPanic { output }
+            | ValidaTestError::WrongPanicMessage { output, .. }
+            | ValidaTestError::ExitFailure { output, .. }
+            | ValidaTestError::TimedOut { output, .. }
+            | ValidaTestError::ResourceLimitExceeded { output, .. }
+            | ValidaTestError::ProcessError { output, .. }
+            | ValidaTestError::MissingOutput { output, .. } => Some(output),
+            ValidaTestError::NoBinaries
+            | ValidaTestError::NotFound { .. }
+            | ValidaTestError::SnapshotMismatch { .. } => None,
+        }
+    }
+
+    /// A short, stable name for the kind of failure.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ValidaTestError::NoBinaries => "no_binaries",
+            ValidaTestError::NotFound { .. } => "not_found",
+            ValidaTestError::Panicked { .. } => "panicked",
+            ValidaTestError::DidNotPanic { .. } => "did_not_panic",
+            ValidaTestError::WrongPanicMessage { .. } => "wrong_panic_message",
+            ValidaTestError::ExitFailure { .. } => "exit_failure",
+            ValidaTestError::TimedOut { .. } => "timed_out",
+            ValidaTestError::ResourceLimitExceeded { .. } => "resource_limit_exceeded",
+            ValidaTestError::ProcessError { .. } => "process_error",
+            ValidaTestError::SnapshotMismatch { .. } => "snapshot_mismatch",
+            ValidaTestError::MissingOutput { .. } => "missing_output",
+        }
+    }
+}
+
+impl std::fmt::Display for ValidaTestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidaTestError::NoBinaries => write!(f, "No test binaries found for valida"),
+            ValidaTestError::NotFound { binaries } => write!(
+                f,
+                "Test not found in any test binary\n looked in: {binaries:?}"
+            ),
+            ValidaTestError::Panicked {
+                panic: Some(panic),
+                output,
+            } => {
+                write!(f, "Test panicked at {}: {}", panic.location, panic.message)?;
+                if let Some(cycles) = panic.cycles {
+                    write!(f, " (after {cycles} cycles)")?;
+                }
+                write!(f, "\n\n{output}\n\n")
+            }
+            ValidaTestError::Panicked {
+                panic: None,
+                output,
+            } => {
+                write!(f, "Test panicked unexpectedly.\n\n{output}\n\n")
+            }
+            ValidaTestError::DidNotPanic { output } => {
+                write!(f, "Test did not panic as expected.\n\n{output}\n\n")
+            }
+            ValidaTestError::WrongPanicMessage {
+                expected,
+                actual,
+                output,
+            } => write!(
+                f,
+                "Expected panic message containing '{expected}', got '{}'\n\n{output}\n\n",
+                actual.as_deref().unwrap_or("no panic message")
+            ),
+            ValidaTestError::ExitFailure { code, output } => {
+                write!(f, "Test failed with exit code: {code:?}\n\n{output}\n\n")
+            }
+            ValidaTestError::TimedOut {
+                timeout,
+                expected_panic: false,
+                output,
+            } => write!(f, "Test timed out after {timeout:?}\n\n{output}"),
+            ValidaTestError::TimedOut {
+                timeout,
+                expected_panic: true,
+                output,
+            } => write!(
+                f,
+                "Test timed out after {timeout:?} without a panic being observed. \
+                 Set {VALIDA_TEST_TIMEOUT_AS_PANIC_ENV}=1 if this VM hangs on panic.\n\n{output}"
+            ),
+            ValidaTestError::ResourceLimitExceeded { limit, output } => {
+                write!(f, "Resource limit exceeded: {limit}\n\n{output}\n\n")
+            }
+            ValidaTestError::ProcessError { message, output } => {
+                write!(f, "{message}\n\n{output}\n\n")
+            }
+            ValidaTestError::SnapshotMismatch { message } => write!(f, "{message}"),
+            ValidaTestError::MissingOutput { expected, output } => {
+                write!(f, "Expected output containing '{expected}'\n\n{output}\n\n")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidaTestError {}
+
+#[cfg(not(valida))]
+/// Run a test natively, returning its outcome and, if `capture`, everything it printed to stdout
+/// and stderr with `print!`, `eprint!` or a panic message. Otherwise the output is printed as the
+/// test runs.
+fn run_test_on_host(
+    test: &TestDescAndFn,
+    bench_mode: bool,
+    capture: bool,
+) -> (TestOutcome, Option<String>) {
+    let Some(f) = runnable(test, bench_mode) else {
+        return (TestOutcome::Unsupported, None);
+    };
+
+    let start_time = Instant::now();
+
+    if !capture {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        let outcome = host_test_outcome(test, result, start_time.elapsed(), || {});
+        return (outcome, None);
+    }
+
+    // Like libtest, capture what the test prints through `std` on this thread and the threads it
+    // spawns, rather than redirecting the process's streams, so tests can run in parallel.
+    inherit_output_capture();
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let previous = std::io::set_output_capture(Some(buffer.clone()));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    std::io::set_output_capture(previous);
+    let output = String::from_utf8_lossy(&mem::take(&mut *buffer.lock().unwrap())).into_owned();
+
+    let log_test_failure = || {
+        eprintln!("\n\nTest {} failed on native, output:\n\n", test.desc.name);
+        output.lines().for_each(|line| eprintln!("{line}"));
+    };
+    let outcome = host_test_outcome(test, result, start_time.elapsed(), log_test_failure);
+    (outcome, Some(output))
+}
+
+/// Make the threads the runner's thread spawns from now on capture their output where it does,
+/// as libtest does.
+#[cfg(not(valida))]
+fn inherit_output_capture() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+    INSTALLED.call_once(|| {
+        std::thread::add_spawn_hook(|_| {
+            let capture = std::io::set_output_capture(None);
+            std::io::set_output_capture(capture.clone());
+            move || {
+                std::io::set_output_capture(capture);
+            }
+        });
+    });
+}
+
+/// The outcome of a test that ran natively with `result`, calling `log_test_failure` to print its
+/// output if it failed.
+#[cfg(not(valida))]
+fn host_test_outcome(
+    test: &TestDescAndFn,
+    result: std::thread::Result<Result<(), String>>,
+    duration: Duration,
+    log_test_failure: impl Fn(),
+) -> TestOutcome {
+    match (result, &test.desc.should_panic) {
+        // Test succeeded and wasn't supposed to panic
+        (Ok(Ok(())), ShouldPanic::No) => TestOutcome::Passed(duration),
+
+        // Test panicked and was supposed to panic
+        (Err(_), ShouldPanic::Yes) => TestOutcome::Passed(duration),
+
+        // Test panicked and was supposed to panic with specific message
+        (Err(e), ShouldPanic::YesWithMessage(msg)) => {
+            let panic_msg = e
+                .downcast_ref::<String>()
+                .map(|s| s.as_str())
+                .or_else(|| e.downcast_ref::<&str>().copied());
+
+            if panic_msg.map(|s| s.contains(msg)).unwrap_or(false) {
+                TestOutcome::Passed(duration)
+            } else {
+                log_test_failure();
+                TestOutcome::Failed(format!(
+                    "Expected panic message containing '{}', got '{}'",
+                    msg,
+                    panic_msg.unwrap_or("Non string panic value")
+                ))
+            }
+        }
+
+        // Test panicked but shouldn't have
+        (Err(_e), ShouldPanic::No) => {
+            log_test_failure();
+            TestOutcome::Failed("Test panicked unexpectedly".to_string())
+        }
+
+        // Test succeeded but should have panicked
+        (Ok(Ok(())), ShouldPanic::Yes | ShouldPanic::YesWithMessage(_)) => {
+            log_test_failure();
+            TestOutcome::ShouldPanicButPassed
+        }
+
+        // Test returned Err - this means the test function itself failed
+        (Ok(Err(_e)), _) => {
+            log_test_failure();
+            TestOutcome::Failed("Test returned error: {:?}".to_string())
+        }
+    }
+}
+
+/// The version of the protocol between the runner on the host and the test binary in the VM: the
+/// lines each sends the other to select, run and report a test.
+///
+/// Bump it whenever the protocol changes, so runners and test binaries built from different
+/// versions warn about the mismatch.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+/// The start of the first line a test binary prints, which lists its tests after the version.
+const AVAILABLE_TESTS: &str = "Available tests";
+
+/// The first line sent to a test binary that should run tests one after another until its input
+/// ends, see [`ValidaTestConfig::workers`].
+///
+/// Encoded test names only contain the escapes `\\`, `\n` and `\r`, so it is not a test name.
+const WORKER_MODE: &str = "\\worker";
+
+/// The first line sent to a test binary to run all of its tests one after another, without the
+/// host, e.g. `echo '*' | valida run <test binary> log`. Rust test paths cannot be `*`.
+///
+/// Followed by a space and an encoded filter, only the tests matching the filter run, see
+/// [`matches_test_filter`].
+pub const RUN_ALL_TESTS: &str = "*";
+
+/// Returns `true` if the test `name` is selected by `filter` in the VM.
+///
+/// A filter with `*` wildcards must match the whole name, e.g. `io::*_round_trip`. Any other
+/// filter selects the names that contain it, like `cargo test io::` does natively.
+pub fn matches_test_filter(name: &str, filter: &str) -> bool {
+    let mut parts = filter.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(last) = parts.next_back() else {
+        return name.contains(filter);
+    };
+
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// The line a test binary running as a worker prints after each test, when it is ready for the
+/// next one.
+pub const TEST_DONE_LINE: &str = "valida-test-done";
+
+/// The mode line sent to the VM when tests should run once.
+const TEST_MODE: &str = "test";
+
+/// The mode line sent to the VM when benchmarks should be measured.
+const BENCH_MODE: &str = "bench";
+
+/// The mode line sent to the VM when a test should be re-run with a recorded input.
+///
+/// The input follows the mode line, framed as its length on a line of its own and then the bytes.
+const REPLAY_MODE: &str = "replay";
+
+/// Follows the mode on its line, with a count, when the test has [`Fixtures`]. The files follow
+/// the mode line and any replay input; see `fixtures::receive`.
+const FIXTURES: &str = "fixtures";
+
+/// How the VM should run the selected test.
+#[derive(Debug, Clone, Copy)]
+pub enum RunMode<'a> {
+    /// Run the test once.
+    Test,
+    /// Measure benchmarks instead of running them once.
+    Bench,
+    /// Run the test once, making `input` available through [`take_replay_input`].
+    Replay(&'a [u8]),
+}
+
+#[cfg(not(valida))]
+impl<'a> RunMode<'a> {
+    /// The mode that replays `input` if the test recorded one natively, and otherwise measures
+    /// benchmarks if `bench` or runs the test once.
+    fn new(input: Option<&'a [u8]>, bench: bool) -> Self {
+        match (input, bench) {
+            (Some(input), _) => RunMode::Replay(input),
+            (None, true) => RunMode::Bench,
+            (None, false) => RunMode::Test,
+        }
+    }
+}
+
+/// The input recorded by a failing host test, to be replayed in the VM.
+static COUNTEREXAMPLE: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// An input a passing host test ran with, for the VM to run it with as well.
+static SAMPLE: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// The input the host asked the VM to replay the current test with.
+static REPLAY_INPUT: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Record the input a failing test failed on.
+///
+/// If the test fails on the host and tests are also run on Valida, the runner re-runs just this
+/// input in the VM and reports whether the host and the VM agree. The test reads it back with
+/// [`take_replay_input`].
+pub fn record_counterexample(input: Vec<u8>) {
+    *COUNTEREXAMPLE.lock().unwrap() = Some(input);
+}
+
+/// Record an input the running test checked, for the VM to run the test with.
+///
+/// When the test passes natively, the VM phase replays the counterexample it recorded, if it
+/// panicked as `#[should_panic]` expects, or else this input, so the VM checks the same case as
+/// the host rather than nothing. The test reads it back with [`take_replay_input`].
+pub fn record_sample(input: Vec<u8>) {
+    *SAMPLE.lock().unwrap() = Some(input);
+}
+
+/// The order pairs the running [`valida_test_order!`](crate::valida_test_order) declared.
+static DECLARED_ORDER: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Declare that each first test runs before the second, by their paths relative to the calling
+/// module; see [`valida_test_order!`](crate::valida_test_order).
+pub fn declare_test_order(pairs: &[(&str, &str)]) {
+    let path = |test: &str| test.split_whitespace().collect::<String>();
+    DECLARED_ORDER.lock().unwrap().extend(
+        pairs
+            .iter()
+            .map(|(first, second)| (path(first), path(second))),
+    );
+}
+
+/// The output the running [`valida_expect_output!`](crate::valida_expect_output) declared, by
+/// test.
+static DECLARED_OUTPUT: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Declare that each test, by its path relative to the calling module, prints a pattern in the
+/// VM; see [`valida_expect_output!`](crate::valida_expect_output).
+pub fn declare_expected_output(pairs: &[(&str, &str)]) {
+    let path = |test: &str| test.split_whitespace().collect::<String>();
+    DECLARED_OUTPUT.lock().unwrap().extend(
+        pairs
+            .iter()
+            .map(|(test, pattern)| (path(test), pattern.to_string())),
+    );
+}
+
+/// The input recorded by [`record_counterexample`] or [`record_sample`] that the VM is
+/// replaying, if any.
+pub fn take_replay_input() -> Option<Vec<u8>> {
+    REPLAY_INPUT.lock().unwrap().take()
+}
+
+/// The number of iterations `#[bench]` functions are measured over.
+///
+/// The VM has no environment, so this is fixed rather than configurable.
+const fn bench_iterations() -> u32 {
+    crate::bench::DEFAULT_ITERATIONS
+}
+
+/// Turn a test into a closure that runs it, or `None` if the kind of test is not supported.
+///
+/// Benchmarks are measured in `bench_mode`, otherwise they run once like a regular test.
+fn runnable(
+    test: &TestDescAndFn,
+    bench_mode: bool,
+) -> Option<Box<dyn FnOnce() -> Result<(), String> + '_>> {
+    let name = test.desc.name.as_slice();
+    let run_bench = move |f: &dyn Fn(&mut test::Bencher) -> Result<(), String>| {
+        if bench_mode {
+            crate::bench::run_bench_fn(name, bench_iterations(), f).map(|_| ())
+        } else {
+            test::bench::run_once(f)
+        }
+    };
 
     match &test.testfn {
-        TestFn::StaticTestFn(f) => {
-            let start_time = Instant::now();
+        TestFn::StaticTestFn(f) => Some(Box::new(f)),
+        TestFn::StaticBenchFn(f) | TestFn::StaticBenchAsTestFn(f) => {
+            Some(Box::new(move || run_bench(f)))
+        }
+        TestFn::DynTestFn(f) => Some(Box::new(move || f())),
+        TestFn::DynBenchFn(f) | TestFn::DynBenchAsTestFn(f) => {
+            Some(Box::new(move || run_bench(f.as_ref())))
+        }
+    }
+}
 
-            let mut tempfile = tempfile::tempfile().expect("Failed to create tempfile");
+/// Escape a field of the host to VM protocol so it fits on a single line.
+///
+/// Dynamically generated test names may contain arbitrary characters, including newlines.
+fn encode_protocol_field(field: &str) -> String {
+    let mut encoded = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
 
-            let g1 =
-                gag::Redirect::stdout(tempfile.as_raw_fd()).expect("Failed to redirect stdout");
-            let g2 =
-                gag::Redirect::stderr(tempfile.as_raw_fd()).expect("Failed to redirect stderr");
+/// Reverse [`encode_protocol_field`].
+fn decode_protocol_field(encoded: &str) -> String {
+    let mut decoded = String::with_capacity(encoded.len());
+    let mut chars = encoded.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => decoded.push('\n'),
+            Some('r') => decoded.push('\r'),
+            Some(other) => decoded.push(other),
+            None => decoded.push('\\'),
+        }
+    }
+    decoded
+}
 
-            let result = panic::catch_unwind(AssertUnwindSafe(f));
+/// Environment variable that enables running tests on Valida in addition to the host.
+pub const VALIDA_TEST_ENV: &str = "VALIDA_TEST";
+
+/// The command line arguments understood by the host runner.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunnerArgs {
+    /// Only run tests whose names contain this string.
+    pub filter: Option<String>,
+    /// Measure benchmarks; `cargo bench` passes `--bench`.
+    pub bench: bool,
+    /// Run the tests in a random order derived from this seed.
+    pub shuffle_seed: Option<u64>,
+    /// List the tests instead of running them.
+    pub list: bool,
+    /// Only select the test whose name is exactly the filter.
+    pub exact: bool,
+    /// Only select, and run, ignored tests.
+    pub ignored: bool,
+    /// Run ignored tests as well as the others, natively and in the VM.
+    pub include_ignored: bool,
+    /// Stop at the first failure, cancelling the tests still to run natively and in the VM.
+    pub fail_fast: bool,
+    /// List tests in libtest's terse `<name>: test` format, as `cargo nextest` expects.
+    pub terse: bool,
+    /// Run for coverage measurement, printing test output instead of capturing it.
+    pub coverage: bool,
+}
 
-            drop(g1);
-            drop(g2);
+impl RunnerArgs {
+    /// Returns `true` if the test is selected by the filter and `--ignored`.
+    pub fn selects(&self, test: &TestDescAndFn) -> bool {
+        let name = test.desc.name.as_slice();
+        let matches_filter = match &self.filter {
+            Some(f) if self.exact => name == f,
+            Some(f) => name.contains(f.as_str()),
+            None => true,
+        };
+        matches_filter && (!self.ignored || test.desc.ignore)
+    }
 
-            let log_test_failure = move || {
-                eprintln!("\n\nTest {} failed on native, output:\n\n", test.desc.name);
+    /// Returns `true` if selected ignored tests are run rather than reported as ignored.
+    pub fn runs_ignored(&self) -> bool {
+        self.ignored || self.include_ignored
+    }
 
-                tempfile.seek(std::io::SeekFrom::Start(0)).unwrap();
-                std::io::BufReader::new(tempfile)
-                    .lines()
-                    .for_each(|line| eprintln!("{}", line.expect("Failed to read line")));
-            };
+    /// Parse the arguments the test binary was started with.
+    ///
+    /// Like libtest, `--shuffle` and `--shuffle-seed` can also be given through the
+    /// `RUST_TEST_SHUFFLE` and `RUST_TEST_SHUFFLE_SEED` environment variables.
+    pub fn from_env() -> Self {
+        let mut args = Self::parse(env::args().skip(1));
+        // `cargo llvm-cov` sets this for the processes it runs.
+        args.coverage |= env::var_os("CARGO_LLVM_COV").is_some();
+        if args.shuffle_seed.is_none() {
+            if let Ok(seed) = env::var("RUST_TEST_SHUFFLE_SEED") {
+                args.shuffle_seed = seed.trim().parse().ok();
+            } else if env::var_os("RUST_TEST_SHUFFLE").is_some() {
+                args.shuffle_seed = Some(random_seed());
+            }
+        }
+        args
+    }
 
-            let duration = start_time.elapsed();
-
-            match (result, &test.desc.should_panic) {
-                // Test succeeded and wasn't supposed to panic
-                (Ok(Ok(())), ShouldPanic::No) => TestOutcome::Passed(duration),
-
-                // Test panicked and was supposed to panic
-                (Err(_), ShouldPanic::Yes) => TestOutcome::Passed(duration),
-
-                // Test panicked and was supposed to panic with specific message
-                (Err(e), ShouldPanic::YesWithMessage(msg)) => {
-                    let panic_msg = e
-                        .downcast_ref::<String>()
-                        .map(|s| s.as_str())
-                        .or_else(|| e.downcast_ref::<&str>().copied());
-
-                    if panic_msg.map(|s| s.contains(msg)).unwrap_or(false) {
-                        TestOutcome::Passed(duration)
-                    } else {
-                        log_test_failure();
-                        TestOutcome::Failed(format!(
-                            "Expected panic message containing '{}', got '{}'",
-                            msg,
-                            panic_msg.unwrap_or("Non string panic value")
-                        ))
-                    }
-                }
+    /// Parse test binary arguments, ignoring any that are not understood.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut parsed = Self::default();
+        let mut shuffle = false;
+        let mut args = args.into_iter();
 
-                // Test panicked but shouldn't have
-                (Err(_e), ShouldPanic::No) => {
-                    log_test_failure();
-                    TestOutcome::Failed("Test panicked unexpectedly".to_string())
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            match flag {
+                "--bench" => parsed.bench = true,
+                "--shuffle" => shuffle = true,
+                "--list" => parsed.list = true,
+                "--exact" => parsed.exact = true,
+                "--ignored" => parsed.ignored = true,
+                "--include-ignored" => parsed.include_ignored = true,
+                "--fail-fast" => parsed.fail_fast = true,
+                "--coverage" => parsed.coverage = true,
+                "--format" => {
+                    parsed.terse = inline_value.or_else(|| args.next()).as_deref() == Some("terse");
                 }
-
-                // Test succeeded but should have panicked
-                (Ok(Ok(())), ShouldPanic::Yes | ShouldPanic::YesWithMessage(_)) => {
-                    log_test_failure();
-                    TestOutcome::ShouldPanicButPassed
+                // Skip the values of other libtest options so they are not taken as the filter.
+                "--test-threads" | "--color" | "--logfile" | "--skip" | "-Z"
+                    if inline_value.is_none() =>
+                {
+                    args.next();
                 }
-
-                // Test returned Err - this means the test function itself failed
-                (Ok(Err(_e)), _) => {
-                    log_test_failure();
-                    TestOutcome::Failed("Test returned error: {:?}".to_string())
+                "--shuffle-seed" => {
+                    let value = inline_value.or_else(|| args.next());
+                    parsed.shuffle_seed = value.and_then(|v| v.trim().parse().ok());
+                }
+                _ if !arg.starts_with('-') && parsed.filter.is_none() => {
+                    parsed.filter = Some(arg.clone());
                 }
+                _ => {}
             }
         }
 
-        _ => TestOutcome::Unsupported,
+        if shuffle && parsed.shuffle_seed.is_none() {
+            parsed.shuffle_seed = Some(random_seed());
+        }
+        parsed
+    }
+}
+
+/// A seed for shuffling when none was given.
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Environment variable that selects a shard of the tests to run, as `k/n`.
+pub const VALIDA_TEST_SHARD_ENV: &str = "VALIDA_TEST_SHARD";
+
+/// One of `count` disjoint parts of a test suite, selected by hashing test names.
+///
+/// Running every shard from `1/n` to `n/n` covers each test exactly once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// The 1-based index of this shard.
+    pub index: u64,
+    /// The total number of shards.
+    pub count: u64,
+}
+
+impl Shard {
+    /// The shard selected by `VALIDA_TEST_SHARD`, if any.
+    ///
+    /// # Panics
+    /// If the variable is set but not of the form `k/n` with `1 <= k <= n`.
+    pub fn from_env() -> Option<Self> {
+        let value = env::var(VALIDA_TEST_SHARD_ENV).ok()?;
+        match value.parse() {
+            Ok(shard) => Some(shard),
+            Err(e) => panic!("Invalid {VALIDA_TEST_SHARD_ENV}: {e}"),
+        }
+    }
+
+    /// Whether the test named `name` belongs to this shard.
+    pub fn contains(&self, name: &str) -> bool {
+        // FNV-1a, so the assignment of tests to shards is stable across toolchains.
+        let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        hash % self.count == self.index - 1
+    }
+}
+
+impl std::str::FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected `k/n` with 1 <= k <= n, got '{s}'");
+        let (index, count) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let index: u64 = index.trim().parse().map_err(|_| invalid())?;
+        let count: u64 = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Environment variable that sets how many times a VM run that failed for environmental reasons is
+/// retried.
+pub const VALIDA_TEST_RETRIES_ENV: &str = "VALIDA_TEST_RETRIES";
+
+/// Environment variable naming a file that VM test failures are appended to as JSON lines.
+pub const VALIDA_TEST_JSON_ENV: &str = "VALIDA_TEST_JSON";
+
+/// Append a failure to the `VALIDA_TEST_JSON` file, if one was requested.
+///
+/// Each line is an object with the test name and the [`ValidaTestError`], tagged by its `kind`.
+#[cfg(not(valida))]
+fn write_json_failure(test_name: &str, err: &ValidaTestError) {
+    let Some(path) = &ValidaTestConfig::get().json_report else {
+        return;
+    };
+
+    let record = serde_json::json!({ "test": test_name, "error": err });
+    let written = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{record}"));
+    if let Err(e) = written {
+        eprintln!("Failed to write test failure to {}: {e}", path.display());
+    }
+}
+
+/// Environment variable that caps the resident memory of each `valida run` process, in bytes
+/// with an optional `K`, `M` or `G` suffix.
+pub const VALIDA_TEST_MEMORY_LIMIT_ENV: &str = "VALIDA_TEST_MEMORY_LIMIT";
+
+/// Environment variable that caps the wall-clock time of each `valida run` process, in seconds.
+pub const VALIDA_TEST_TIME_LIMIT_ENV: &str = "VALIDA_TEST_TIME_LIMIT";
+
+/// Limits on the `valida run` process of a single test. A VM that exceeds one is killed and the
+/// test fails with [`ValidaTestError::ResourceLimitExceeded`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The maximum resident memory in bytes. Only enforced on Linux.
+    pub memory: Option<u64>,
+    /// The maximum wall-clock time, independent of the timeout used to detect hung panics.
+    pub wall_clock: Option<Duration>,
+}
+
+impl ResourceLimits {
+    /// Read the limits from `VALIDA_TEST_MEMORY_LIMIT` and `VALIDA_TEST_TIME_LIMIT`.
+    ///
+    /// # Panics
+    /// If either variable is set to an invalid value.
+    pub fn from_env() -> Self {
+        let memory = env::var(VALIDA_TEST_MEMORY_LIMIT_ENV).ok().map(|value| {
+            parse_byte_size(&value)
+                .unwrap_or_else(|| panic!("Invalid {VALIDA_TEST_MEMORY_LIMIT_ENV}: {value:?}"))
+        });
+        let wall_clock = env::var(VALIDA_TEST_TIME_LIMIT_ENV).ok().map(|value| {
+            value
+                .trim()
+                .parse()
+                .map(Duration::from_secs_f64)
+                .unwrap_or_else(|_| panic!("Invalid {VALIDA_TEST_TIME_LIMIT_ENV}: {value:?}"))
+        });
+        Self { memory, wall_clock }
+    }
+
+    /// Describe the first limit the process has exceeded, if any.
+    #[cfg(not(valida))]
+    fn exceeded(&self, child: &Child, elapsed: Duration) -> Option<String> {
+        if let Some(limit) = self.wall_clock.filter(|limit| elapsed >= *limit) {
+            return Some(format!("wall-clock time limit of {limit:?}"));
+        }
+        let limit = self.memory?;
+        let used = resident_memory(child)?;
+        (used > limit).then(|| format!("memory limit of {limit} bytes ({used} bytes resident)"))
+    }
+}
+
+/// Parse a byte count such as `4096`, `512K`, `256M` or `2G`.
+fn parse_byte_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.char_indices().last()? {
+        (i, 'k' | 'K') => (&value[..i], 1 << 10),
+        (i, 'm' | 'M') => (&value[..i], 1 << 20),
+        (i, 'g' | 'G') => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// The resident memory of a process in bytes, where the platform reports it.
+#[cfg(not(valida))]
+fn resident_memory(child: &Child) -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
     }
+    let status = std::fs::read_to_string(format!("/proc/{}/status", child.id())).ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Environment variable that makes a `should_panic` test timing out in the VM count as a panic.
+///
+/// VMs that can neither halt nor print the panic sentinel loop forever on panic, so the timeout
+/// is the only sign of a panic they give.
+pub const VALIDA_TEST_TIMEOUT_AS_PANIC_ENV: &str = "VALIDA_TEST_TIMEOUT_AS_PANIC";
+
+/// Environment variable that diffs what each test prints natively against what it prints in the VM.
+pub const VALIDA_TEST_DIFF_OUTPUT_ENV: &str = "VALIDA_TEST_DIFF_OUTPUT";
+
+/// The output a test printed in the VM, without the runner's protocol lines and records.
+#[cfg(not(valida))]
+fn vm_test_output(stdout: &str) -> String {
+    // Skip the "Available tests" and "Running test" lines.
+    let output = stdout.splitn(3, '\n').nth(2).unwrap_or_default();
+    crate::host::OutputReader::new(output.as_bytes()).debug_output()
+}
+
+/// Print a unified diff of a test's native and VM output if they differ, returning whether they did.
+///
+/// Floats in the output that are at most [`ValidaTestConfig::float_ulps`] apart count as equal.
+#[cfg(not(valida))]
+fn report_output_diff(desc: &TestDesc, host_output: &str, vm_stdout: &str) -> bool {
+    let vm_output = vm_test_output(vm_stdout);
+    if crate::float::text_eq(host_output, &vm_output, ValidaTestConfig::get().float_ulps) {
+        return false;
+    }
+
+    let diff = similar::TextDiff::from_lines(host_output, vm_output.as_str());
+    eprintln!(
+        "\ntest {} printed different output on native and valida:\n{}",
+        desc.name,
+        diff.unified_diff().header("native", "valida")
+    );
+    true
+}
+
+/// The cargo target directory the current test binary was built into.
+#[cfg(not(valida))]
+fn cargo_target_dir() -> PathBuf {
+    if let Ok(dir) = env::var("CARGO_TARGET_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    // Cargo marks the root of the target directory with a `CACHEDIR.TAG` file.
+    env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            exe.ancestors()
+                .find(|p| p.join("CACHEDIR.TAG").is_file())
+                .map(Path::to_path_buf)
+        })
+        .unwrap_or_else(|| PathBuf::from("target"))
+}
+
+/// Environment variable with the cargo arguments to build the Valida tests with, e.g.
+/// `--features foo --no-default-features`. Overrides the arguments taken from `cargo test`.
+pub const VALIDA_TEST_CARGO_ARGS_ENV: &str = "VALIDA_TEST_CARGO_ARGS";
+
+/// The arguments of the `cargo test` run that also apply to the Valida build, so feature-gated
+/// tests are the same in the host and VM binaries.
+///
+/// They come from `VALIDA_TEST_CARGO_ARGS` if it is set, or else from the command line of the
+/// cargo process that started this test binary, where the platform exposes it.
+#[cfg(not(valida))]
+fn cargo_passthrough_args() -> Vec<String> {
+    if let Some(args) = &ValidaTestConfig::get().cargo_args {
+        return args.clone();
+    }
+    parent_cargo_args()
+        .map(select_build_args)
+        .unwrap_or_default()
+}
+
+/// The arguments of the parent process, if it is cargo.
+#[cfg(not(valida))]
+fn parent_cargo_args() -> Option<Vec<String>> {
+    #[cfg(unix)]
+    {
+        let pid = std::os::unix::process::parent_id();
+        let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+        let mut args = cmdline
+            .split(|&b| b == 0)
+            .map(|arg| String::from_utf8_lossy(arg).into_owned());
+        let program = PathBuf::from(args.next()?);
+        let is_cargo = program
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("cargo"));
+        is_cargo.then(|| args.collect())
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Select the feature, package and profile arguments from a cargo command line.
+fn select_build_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    const FLAGS: &[&str] = &["--all-features", "--no-default-features", "--release", "-r"];
+    const WITH_VALUE: &[&str] = &["--features", "-F", "--package", "-p", "--profile"];
+
+    let mut selected = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Arguments after `--` are for the test binary.
+        if arg == "--" {
+            break;
+        }
+        let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+        if FLAGS.contains(&arg.as_str()) || (WITH_VALUE.contains(&flag) && flag != arg) {
+            selected.push(arg);
+        } else if WITH_VALUE.contains(&flag) {
+            selected.push(arg);
+            selected.extend(args.next());
+        }
+    }
+    selected
+}
+
+/// Returns `true` if the arguments select a profile, which `--release` would conflict with.
+fn selects_profile(args: &[String]) -> bool {
+    args.iter()
+        .any(|arg| arg == "--release" || arg == "-r" || arg.starts_with("--profile"))
+}
+
+/// The options matching the profile the host tests were built with.
+#[cfg(not(valida))]
+fn host_build_options() -> crate::build::GuestBuildOptions {
+    // if the current exe is in a release directory, build the valida tests in release too
+    let release = env::current_exe()
+        .map(|path| path.components().any(|c| c.as_os_str() == "release"))
+        .unwrap_or(false);
+    if release {
+        crate::build::GuestBuildOptions::release()
+    } else {
+        crate::build::GuestBuildOptions::debug()
+    }
+}
+
+/// The subdirectory of the cargo target directory the Valida tests are built in, so the host and
+/// Valida artifacts do not invalidate each other.
+pub const VALIDA_TESTS_DIR: &str = "valida-tests";
+
+/// Where the Valida tests are built.
+#[cfg(not(valida))]
+fn valida_tests_target_dir() -> PathBuf {
+    cargo_target_dir().join(VALIDA_TESTS_DIR)
 }
 
 /// Build tests for valida and return the test program paths.
 ///
 /// # Panics
 /// This function will panic if the cargo cannot build the tests.
-#[cfg(not(target_arch = "valida"))]
-fn build_tests_for_valida() -> Vec<PathBuf> {
-    let mut command = Command::new("cargo");
+#[cfg(not(valida))]
+fn build_tests_for_valida(coverage: bool) -> Vec<PathBuf> {
+    let passthrough = cargo_passthrough_args();
+    let mut options = if selects_profile(&passthrough) {
+        crate::build::GuestBuildOptions::debug()
+    } else {
+        host_build_options()
+    };
+    options.size_report = ValidaTestConfig::get().size_report;
+    let mut command = options.cargo_command("test");
+    if coverage {
+        // The instrumentation flags would replace the Valida target's flags and cannot build for it.
+        command
+            .env_remove("RUSTFLAGS")
+            .env_remove("LLVM_PROFILE_FILE");
+    }
+
+    // Only build the crate under test, unless the user selected packages.
+    let selects_package = passthrough
+        .iter()
+        .any(|arg| arg == "-p" || arg.starts_with("--package"));
+    if let (false, Ok(package)) = (selects_package, env::var("CARGO_PKG_NAME")) {
+        command.arg("--package").arg(package);
+    }
 
     command
-    .arg("+valida")
-    .arg("test")
-    .arg("--target=valida-unknown-baremetal-gnu")
-    .arg("--config")
-    .arg("build.target=\"valida-unknown-baremetal-gnu\"")
-    .arg("--config")
-    .arg("target.valida-unknown-baremetal-gnu.runner=\"echo\"")
-    .arg("--config") 
-    .arg("target.valida-unknown-baremetal-gnu.linker=\"/valida-toolchain/bin/ld.lld\"")
-    .arg("--config")
-     .arg(concat!(
-        "target.valida-unknown-baremetal-gnu.rustflags=[",
-        "\"-C\",\"link-arg=/valida-toolchain/DelendumEntryPoint.o\",",
-        "\"-C\",\"link-arg=--script=/valida-toolchain/valida.ld\",",
-        "\"-C\",\"link-arg=/valida-toolchain/lib/valida-unknown-baremetal-gnu/libc.a\",",
-        "\"-C\",\"link-arg=/valida-toolchain/lib/valida-unknown-baremetal-gnu/libm.a\",",
-        "\"-C\",\"link-arg=--noinhibit-exec\"",
-        "]"
-    ))
-    .arg("--config")
-    .arg("env.CC_delendum_unknown_baremetal_gnu=\"/valida-toolchain/bin/clang\"")
-    .arg("--config")
-    .arg("env.CFLAGS_delendum_unknown_baremetal_gnu=\"--sysroot=/valida-toolchain/ -isystem /valida-toolchain/include\"")
+        .arg("--target-dir")
+        .arg(valida_tests_target_dir())
+        .args(&passthrough)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
-    // if the current exe path contains release use add --release arg
-    if let Ok(path) = env::current_exe() {
-        if path.to_str().unwrap().contains("/release/") {
-            command.arg("--release");
-        }
-    }
-
-    let output = command.spawn().unwrap().wait_with_output().unwrap();
+    let output = command
+        .spawn()
+        .and_then(|child| child.wait_with_output())
+        .unwrap_or_else(|e| {
+            check_environment();
+            panic!("Failed to run cargo to build tests for valida: {e}")
+        });
 
-    let paths = output
+    let paths: Vec<_> = output
         .stdout
         .lines()
         .map(|line| line.unwrap())
@@ -310,100 +1950,588 @@ fn build_tests_for_valida() -> Vec<PathBuf> {
         .collect();
 
     if output.status.success() {
+        if let Some(top) = options.size_report {
+            print_size_reports(&paths, top);
+        }
         paths
     } else {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        check_environment();
+        panic!("Failed to build tests for valida, see the errors above");
+    }
+}
+
+/// Print the size of each test binary built for Valida, with its `top` biggest symbols and what
+/// [`build::audit`](crate::build::audit) finds in it.
+#[cfg(not(valida))]
+fn print_size_reports(paths: &[PathBuf], top: usize) {
+    for path in paths {
+        match crate::build::SizeReport::of(path) {
+            Ok(report) => {
+                eprintln!("size of {}:\n{}", path.display(), report.table(top));
+                for finding in crate::build::audit_report(&report) {
+                    eprintln!("{finding}\n");
+                }
+            }
+            Err(e) => eprintln!("Failed to analyze {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Run the doctests of `package`.
+///
+/// Doctests are compiled by rustdoc with the standard test harness, so the custom test runner
+/// never sees them. This runs them on the host with `cargo test --doc` and, when `VALIDA_TEST` is
+/// set, also checks that they compile for Valida. Doctests are not executed in the VM.
+///
+/// This is normally registered as a test through the [`doctests!`](crate::doctests) macro. On
+/// Valida it does nothing.
+///
+/// # Panics
+/// If any doctest fails on the host, or the doctests fail to compile for Valida.
+pub fn run_doctests(package: &str) {
+    #[cfg(not(valida))]
+    run_doctests_on_host(package);
+    #[cfg(valida)]
+    let _ = package;
+}
+
+#[cfg(not(valida))]
+fn run_doctests_on_host(package: &str) {
+    // The outer `cargo test` holds the lock on the regular build directory.
+    let target_dir = cargo_target_dir().join("valida-doctests");
+
+    let output = Command::new("cargo")
+        .arg("test")
+        .arg("--doc")
+        .arg("--package")
+        .arg(package)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run `cargo test --doc`");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut failed = Vec::new();
+    for (name, status) in parse_doctest_results(&stdout) {
+        println!("doctest {name} on native ... {status}");
+        if status == "FAILED" {
+            failed.push(name);
+        }
+    }
+
+    if !output.status.success() {
         panic!(
-            "Failed to build tests for valida: {}",
+            "{} doctest(s) failed on native: {}\n\n{}\n{}",
+            failed.len(),
+            failed.join(", "),
+            stdout,
             String::from_utf8_lossy(&output.stderr)
         );
     }
+
+    if ValidaTestConfig::get().run_on_valida {
+        let output = host_build_options()
+            .cargo_command("test")
+            .arg("--doc")
+            .arg("--no-run")
+            .arg("--package")
+            .arg(package)
+            .arg("--target-dir")
+            .arg(valida_tests_target_dir())
+            .stdin(Stdio::null())
+            .output()
+            .expect("Failed to run `cargo test --doc` for valida");
+
+        if !output.status.success() {
+            panic!(
+                "Doctests failed to compile for valida:\n\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        println!("doctests compiled for valida");
+    }
+}
+
+/// Extract `(name, status)` pairs from the libtest output of `cargo test --doc`.
+#[cfg(not(valida))]
+fn parse_doctest_results(stdout: &str) -> Vec<(&str, &str)> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("test ")?.rsplit_once(" ... "))
+        .collect()
+}
+
+#[cfg(not(valida))]
+fn run_test_on_valida(
+    test: &TestDescAndFn,
+    test_paths: &[PathBuf],
+    host_test_time: Duration,
+    mode: RunMode,
+) -> Result<Vec<u8>, ValidaTestError> {
+    if test_paths.is_empty() {
+        return Err(ValidaTestError::NoBinaries);
+    }
+
+    // Try to run the test on each of the test exes
+    for test_path in test_paths.iter() {
+        if let Some(stdout) = run_test_on_valida_inner(test, test_path, host_test_time, mode)? {
+            return Ok(stdout);
+        }
+    }
+
+    Err(ValidaTestError::NotFound {
+        binaries: test_paths.to_vec(),
+    })
+}
+
+/// Run a single test on the Valida VM.
+/// # Arguments
+/// * `test` - The test to run.
+/// * `test_path` - The path to the test binary to look for the test in.
+/// * `host_test_time` - The time taken to run the test on the host.
+/// * `mode` - How the VM should run the test.
+///
+/// # Returns
+/// Err if the test did not have the expected outcome.
+/// Ok(Some(stdout)) if the test passed, with the output it produced.
+/// Ok(None) if the test was not found in the provided test binary.
+///
+/// # Panics
+/// If the `valida` command cannot be found in the `$PATH`.
+/// Or if the `valida` command cannot be started.
+#[cfg(not(valida))]
+fn run_test_on_valida_inner(
+    test: &TestDescAndFn,
+    test_path: &Path,
+    host_test_time: Duration,
+    mode: RunMode,
+) -> Result<Option<Vec<u8>>, ValidaTestError> {
+    let test = VmTest {
+        fixtures: fixtures::declared(),
+        ..VmTest::from(&test.desc)
+    };
+    let timeout = ValidaTestConfig::get().vm_timeout(host_test_time);
+    let query_handler = query_handler();
+
+    let mut process = VmProcess::spawn(test_path);
+    let Some(result) = process.run_test(&test, timeout, mode, query_handler.as_ref()) else {
+        return Ok(None);
+    };
+    process.record_artifacts(&test, &result);
+    result.map(Some)
+}
+
+/// How [`run_in_valida`] runs a test.
+#[cfg(not(valida))]
+#[derive(Debug, Clone, Default)]
+pub struct ValidaRunOptions {
+    expect: ExpectedOutcome,
+    timeout: Option<Duration>,
+    bench: bool,
+    replay_input: Option<Vec<u8>>,
+    query_handler: Option<crate::host::QueryHandler>,
+}
+
+#[cfg(not(valida))]
+impl ValidaRunOptions {
+    /// Options to run a test that should pass, within the configured minimum timeout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what the test is expected to do.
+    pub fn expect(mut self, expect: ExpectedOutcome) -> Self {
+        self.expect = expect;
+        self
+    }
+
+    /// Kill the test if it takes longer than `timeout`, instead of
+    /// [`ValidaTestConfig::min_timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Measure the test as a benchmark instead of running it once.
+    pub fn bench(mut self) -> Self {
+        self.bench = true;
+        self
+    }
+
+    /// Make `input` available to the test through [`take_replay_input`].
+    pub fn replay(mut self, input: Vec<u8>) -> Self {
+        self.replay_input = Some(input);
+        self
+    }
+
+    /// Answer the test's `io::query` requests with `handler`.
+    pub fn on_query(mut self, handler: impl FnMut(&[u8]) -> Vec<u8> + Send + 'static) -> Self {
+        self.query_handler = Some(crate::host::QueryHandler(Arc::new(Mutex::new(handler))));
+        self
+    }
+}
+
+/// The result of a test run with [`run_in_valida`].
+#[cfg(not(valida))]
+#[derive(Debug)]
+pub struct ValidaRunReport {
+    /// What the VM printed, including the runner's protocol lines, if the test had the expected
+    /// outcome, or why it did not.
+    pub result: Result<Vec<u8>, ValidaTestError>,
+    /// What the VM printed to stderr.
+    pub stderr: Vec<u8>,
+    /// The VM's exit code, if it exited rather than being killed.
+    pub exit_code: Option<i32>,
+    /// The number of cycles the run took, if the VM reported it.
+    pub cycles: Option<u64>,
+    /// How long the run took.
+    pub duration: Duration,
+}
+
+/// Run the test `test_name` from `source_file` in the Valida test binary `binary`.
+///
+/// This is what the test runner does for each test in the VM, for tools that orchestrate test
+/// binaries themselves, such as custom CI runners or fuzzing drivers. The binary must have been
+/// built for Valida with [`test_runner`] as its test runner. The `valida` command and resource
+/// limits come from [`ValidaTestConfig`]; retries, reports and artifacts are left to the caller.
+///
+/// # Panics
+/// If the `valida` command cannot be started.
+#[cfg(not(valida))]
+pub fn run_in_valida(
+    binary: &Path,
+    test_name: &str,
+    source_file: &str,
+    options: &ValidaRunOptions,
+) -> ValidaRunReport {
+    let test = VmTest {
+        name: test_name.to_string(),
+        source_file: source_file.to_string(),
+        expect: options.expect.clone(),
+        fixtures: Vec::new(),
+    };
+    let timeout = options
+        .timeout
+        .unwrap_or(ValidaTestConfig::get().min_timeout);
+    let mode = RunMode::new(options.replay_input.as_deref(), options.bench);
+
+    let start_time = Instant::now();
+    let mut process = VmProcess::spawn(binary);
+    let result = process
+        .run_test(&test, timeout, mode, options.query_handler.as_ref())
+        .unwrap_or_else(|| {
+            Err(ValidaTestError::NotFound {
+                binaries: vec![binary.to_path_buf()],
+            })
+        });
+    let duration = start_time.elapsed();
+
+    let stderr = process.take_stderr();
+    let stdout = match &result {
+        Ok(stdout) => stdout.as_slice(),
+        Err(err) => err.output().unwrap_or_default().as_bytes(),
+    };
+    let cycles = crate::host::parse_cycles(&stderr).or_else(|| crate::host::parse_cycles(stdout));
+    ValidaRunReport {
+        exit_code: process.exit_code(),
+        result,
+        stderr,
+        cycles,
+        duration,
+    }
+}
+
+/// The tests a Valida test binary ran for [`run_matching_in_valida`].
+#[cfg(not(valida))]
+#[derive(Debug, Clone)]
+pub struct ValidaBatchReport {
+    pub passed: Vec<String>,
+    pub failed: Vec<String>,
+    /// The tests skipped because they are ignored or `should_panic`.
+    pub ignored: Vec<String>,
+    /// The test that panicked and ended the run, if one did.
+    pub panicked: Option<PanicRecord>,
+    /// The whole run.
+    pub run: crate::host::RunResult,
+}
+
+#[cfg(not(valida))]
+impl ValidaBatchReport {
+    /// Returns `true` if every test that ran passed.
+    pub fn success(&self) -> bool {
+        self.failed.is_empty() && self.panicked.is_none() && self.run.exit.success()
+    }
+
+    fn parse(run: crate::host::RunResult) -> Self {
+        let mut report = Self {
+            passed: Vec::new(),
+            failed: Vec::new(),
+            ignored: Vec::new(),
+            panicked: run.output_reader().panic(),
+            run,
+        };
+        for line in String::from_utf8_lossy(&report.run.stdout).lines() {
+            let Some((name, status)) = line
+                .strip_prefix("test ")
+                .and_then(|line| line.rsplit_once(" ... "))
+            else {
+                continue;
+            };
+            let list = match status {
+                "ok" => &mut report.passed,
+                "FAILED" => &mut report.failed,
+                "ignored" => &mut report.ignored,
+                _ => continue,
+            };
+            list.push(decode_protocol_field(name));
+        }
+        report
+    }
+}
+
+/// Run the tests of the Valida test binary `binary` that match `filter` in a single VM run, see
+/// [`matches_test_filter`]; an empty filter runs them all.
+///
+/// This is faster than running each test with [`run_in_valida`], but panics cannot be caught in
+/// the VM, so `should_panic` tests are skipped and the first test that panics ends the run.
+#[cfg(not(valida))]
+pub fn run_matching_in_valida(
+    binary: &Path,
+    filter: &str,
+    timeout: Duration,
+) -> Result<ValidaBatchReport, crate::host::RunError> {
+    let run = crate::host::Runner::new(binary)
+        .valida_command(&ValidaTestConfig::get().valida_command)
+        .stdin(format!(
+            "{RUN_ALL_TESTS} {}\n",
+            encode_protocol_field(filter)
+        ))
+        .timeout(timeout)
+        .run()?;
+    if let Some(first_line) = String::from_utf8_lossy(&run.stdout).lines().next() {
+        check_guest_versions(first_line);
+    }
+    Ok(ValidaBatchReport::parse(run))
+}
+
+/// What a test run in the VM is expected to do, as with `#[should_panic]`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ExpectedOutcome {
+    /// The test returns normally.
+    #[default]
+    Pass,
+    /// The test panics.
+    Panic,
+    /// The test panics with a message containing this one.
+    PanicWithMessage(String),
+}
+
+/// The test a VM run selects and what it should do, independent of libtest's types.
+#[cfg(not(valida))]
+#[derive(Debug, Clone)]
+struct VmTest {
+    name: String,
+    source_file: String,
+    expect: ExpectedOutcome,
+    /// The [`Fixtures`] the test declared natively, by path.
+    fixtures: Vec<(String, Vec<u8>)>,
+}
+
+#[cfg(not(valida))]
+impl From<&TestDesc> for VmTest {
+    fn from(desc: &TestDesc) -> Self {
+        Self {
+            name: desc.name.as_slice().to_string(),
+            source_file: desc.source_file.to_string(),
+            expect: match desc.should_panic {
+                ShouldPanic::No => ExpectedOutcome::Pass,
+                ShouldPanic::Yes => ExpectedOutcome::Panic,
+                ShouldPanic::YesWithMessage(msg) => ExpectedOutcome::PanicWithMessage(msg.into()),
+            },
+            fixtures: Vec::new(),
+        }
+    }
 }
 
-#[cfg(not(target_arch = "valida"))]
-fn run_test_on_valida(
-    test: &TestDescAndFn,
-    test_paths: &[PathBuf],
-    host_test_time: Duration,
-) -> Result<(), String> {
-    if test_paths.is_empty() {
-        return Err("No test binaries found for valida".to_string());
+/// A test binary running in the VM, with its output read in the background.
+#[cfg(not(valida))]
+struct VmProcess {
+    binary: PathBuf,
+    child: ScopedChild,
+    stdin: RecordedStdin,
+    stdout: mpsc::Receiver<Vec<u8>>,
+    stderr: mpsc::Receiver<Vec<u8>>,
+    /// The output file `valida run` writes, removed when the process is dropped.
+    output_file: tempfile::NamedTempFile,
+}
+
+#[cfg(not(valida))]
+impl VmProcess {
+    /// # Panics
+    /// If the `valida` command cannot be started.
+    fn spawn(test_path: &Path) -> Self {
+        let output_file = tempfile::NamedTempFile::new().expect("Failed to create temp log file");
+
+        let mut child = crate::host::Runner::new(test_path)
+            .valida_command(&ValidaTestConfig::get().valida_command)
+            .spawn(output_file.path())
+            .map(ScopedChild)
+            .unwrap_or_else(|e| {
+                check_environment();
+                panic!("Failed to start test process: {e}")
+            });
+
+        // unwrap is safe because we know stdio is piped
+        let stdin = RecordedStdin {
+            stdin: child.stdin.take().unwrap(),
+            sent: Vec::new(),
+        };
+        let stdout = non_blocking_read(child.stdout.take().unwrap());
+        let stderr = non_blocking_read(child.stderr.take().unwrap());
+
+        Self {
+            binary: test_path.to_path_buf(),
+            child,
+            stdin,
+            stdout,
+            stderr,
+            output_file,
+        }
     }
 
-    // Try to run the test on each of the test exes
-    for test_path in test_paths.iter() {
-        if run_test_on_valida_inner(test, test_path, host_test_time)? {
-            return Ok(());
+    /// Request `test` and wait for it to finish, or `None` if the binary does not contain it.
+    fn run_test(
+        &mut self,
+        test: &VmTest,
+        timeout: Duration,
+        mode: RunMode,
+        query_handler: Option<&crate::host::QueryHandler>,
+    ) -> Option<Result<Vec<u8>, ValidaTestError>> {
+        // The pipe may break if the process exits before we write to it.
+        // This can happen if the test name/filename is not found in this test binary.
+        let _ = write_test_request(&mut self.stdin, test, mode);
+
+        let mut stdout_buffer: Vec<u8> = Vec::with_capacity(1024);
+        if !check_test_started(&mut self.stdout, &mut stdout_buffer, &test.name) {
+            return None;
         }
+        Some(supervise(self, stdout_buffer, test, timeout, query_handler, false).0)
     }
 
-    Err(format!(
-        "Test {} not found in any test binary\n looked in: {:?}",
-        test.desc.name, test_paths
-    ))
-}
+    /// What the VM printed to stderr since the last call, all of it if the VM has exited.
+    fn take_stderr(&mut self) -> Vec<u8> {
+        let mut stderr = Vec::new();
+        if self.exit_code().is_some() {
+            // The reader stops at the end of the exited VM's stderr.
+            stderr.extend(self.stderr.iter().flatten());
+        } else {
+            stderr.extend(self.stderr.try_iter().flatten());
+        }
+        stderr
+    }
 
-/// Run a single test on the Valida VM.
-/// # Arguments
-/// * `test` - The test to run.
-/// * `test_path` - The path to the test binary to look for the test in.
-/// * `host_test_time` - The time taken to run the test on the host.
-///
-/// # Returns
-/// Err if the test did not have the expected outcome.
-/// Ok(true) if the test passed.
-/// Ok(false) if the test was not found in the provided test binary.
-///
-/// # Panics
-/// If the `valida` command cannot be found in the `$PATH`.
-/// Or if the `valida` command cannot be started.
-#[cfg(not(target_arch = "valida"))]
-fn run_test_on_valida_inner(
-    test: &TestDescAndFn,
-    test_path: &Path,
-    host_test_time: Duration,
-) -> Result<bool, String> {
-    let temp_log = tempfile::NamedTempFile::new().expect("Failed to create temp log file");
-    let temp_log_path = temp_log.path();
-
-    // We call try_wait() the process in a loop or kill it after a timeout, so this warning is erroneous.
-    #[allow(clippy::zombie_processes)]
-    let mut child = Command::new("valida")
-        .arg("run")
-        .arg(test_path)
-        .arg(temp_log_path)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| {
-            panic!("Are you sure `valida` is in your `$PATH`?\nFailed to start test process: {e}")
-        })
-        .map(ScopedChild)
-        .unwrap();
+    /// The VM's exit code, if it has exited.
+    fn exit_code(&mut self) -> Option<i32> {
+        match self.child.try_wait() {
+            Ok(Some(status)) => status.code(),
+            _ => None,
+        }
+    }
 
-    // unwrap is safe because we know the stdin is piped
-    let mut valida_stdin = child.stdin.take().unwrap();
+    /// Record the run of `test`, which had `result`, if artifacts are enabled.
+    ///
+    /// The input is what was sent since the test was requested.
+    fn record_artifacts(&mut self, test: &VmTest, result: &Result<Vec<u8>, ValidaTestError>) {
+        let stderr = self.take_stderr();
+        let stdout = match result {
+            Ok(stdout) => stdout.as_slice(),
+            Err(err) => err.output().unwrap_or_default().as_bytes(),
+        };
+        let exit_code = self.exit_code();
+        artifacts::write(
+            &test.name,
+            &artifacts::RunArtifacts {
+                binary: &self.binary,
+                input: &self.stdin.sent,
+                stdout,
+                stderr: &stderr,
+                output_file: self.output_file.path(),
+                exit_code,
+                result,
+            },
+        );
+    }
+}
 
-    // The pipe may break if the process exits before we write to it.
-    // This can happen if the test name/filename is not found in this test binary.
-    let _ = writeln!(valida_stdin, "{}", test.desc.name);
-    let _ = writeln!(valida_stdin, "{}", test.desc.source_file);
+/// A test binary's stdin, keeping what was written to it for the test's artifacts.
+#[cfg(not(valida))]
+struct RecordedStdin {
+    stdin: ChildStdin,
+    sent: Vec<u8>,
+}
 
-    // unwrap is safe because we know the stdout is piped
-    let valida_stdout = child.stdout.take().unwrap();
-    let mut valida_stdout_stream = non_blocking_read(valida_stdout);
+#[cfg(not(valida))]
+impl Write for RecordedStdin {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.stdin.write(buf)?;
+        self.sent.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
 
-    let mut stdout_buffer: Vec<u8> = Vec::with_capacity(1024);
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stdin.flush()
+    }
+}
 
-    if !check_test_started(&mut valida_stdout_stream, &mut stdout_buffer, test) {
-        return Ok(false);
+/// Send the lines selecting `test` and how to run it.
+#[cfg(not(valida))]
+fn write_test_request(stdin: &mut impl Write, test: &VmTest, mode: RunMode) -> std::io::Result<()> {
+    writeln!(stdin, "{}", encode_protocol_field(&test.name))?;
+    writeln!(stdin, "{}", encode_protocol_field(&test.source_file))?;
+    let fixtures = match test.fixtures.len() {
+        0 => String::new(),
+        count => format!(" {FIXTURES} {count}"),
+    };
+    match mode {
+        RunMode::Test => writeln!(stdin, "{TEST_MODE}{fixtures}"),
+        RunMode::Bench => writeln!(stdin, "{BENCH_MODE}{fixtures}"),
+        RunMode::Replay(input) => writeln!(stdin, "{REPLAY_MODE}{fixtures}\n{}", input.len())
+            .and_then(|_| stdin.write_all(input)),
+    }?;
+    for (path, contents) in &test.fixtures {
+        writeln!(stdin, "{}\n{}", encode_protocol_field(path), contents.len())?;
+        stdin.write_all(contents)?;
     }
+    stdin.flush()
+}
 
-    let timeout = std::cmp::max(host_test_time * 20, Duration::from_secs(10));
+/// Wait for `test`, which has started in `process`, to finish within `timeout`, answering its
+/// queries, and check that it had the expected outcome.
+///
+/// A `worker` process runs several tests, so the test has finished when it prints
+/// [`TEST_DONE_LINE`] rather than when the process exits. That line is not part of the output,
+/// and whether the test ended with it is returned alongside the result: only then is the worker
+/// back in its loop, ready for another test, rather than hung after a panic.
+#[cfg(not(valida))]
+fn supervise(
+    process: &mut VmProcess,
+    mut stdout_buffer: Vec<u8>,
+    test: &VmTest,
+    timeout: Duration,
+    query_handler: Option<&crate::host::QueryHandler>,
+    worker: bool,
+) -> (Result<Vec<u8>, ValidaTestError>, bool) {
+    let config = ValidaTestConfig::get();
+    let limits = config.limits;
     let start_time = Instant::now();
 
     let mut searched_cursor = 0;
+    let mut query_cursor = 0;
+    let done_line = format!("\n{TEST_DONE_LINE}\n");
 
+    let valida_stdout_stream = &process.stdout;
     let receive_child_stdout = |stdout_buffer: &mut Vec<u8>| {
         while let Ok(segment) = valida_stdout_stream.try_recv() {
             stdout_buffer.extend(segment);
@@ -413,6 +2541,65 @@ fn run_test_on_valida_inner(
     loop {
         receive_child_stdout(&mut stdout_buffer);
 
+        // Dropping the process kills it and removes its output file.
+        if interrupt::requested() {
+            return (
+                Err(ValidaTestError::ProcessError {
+                    message: "Interrupted.".to_string(),
+                    output: String::from_utf8_lossy(&stdout_buffer).into_owned(),
+                }),
+                false,
+            );
+        }
+
+        if let Some(handler) = query_handler {
+            // The pipe breaks if the test exits, which the exit status below reports.
+            let _ = handler.answer(&stdout_buffer, &mut query_cursor, &mut process.stdin);
+        }
+
+        if worker {
+            if let Some(end) = stdout_buffer
+                .windows(done_line.len())
+                .position(|window| window == done_line.as_bytes())
+            {
+                stdout_buffer.truncate(end + 1);
+                return (exit_result(test, true, Some(0), stdout_buffer), true);
+            }
+        }
+
+        // The exit status is authoritative; VMs that can halt never print the sentinel.
+        let Ok(child_status) = process.child.try_wait() else {
+            receive_child_stdout(&mut stdout_buffer);
+            return (
+                Err(ValidaTestError::ProcessError {
+                    message: "Failed to wait for valida process.".to_string(),
+                    output: String::from_utf8_lossy(&stdout_buffer).into_owned(),
+                }),
+                false,
+            );
+        };
+
+        if let Some(status) = child_status {
+            receive_child_stdout(&mut stdout_buffer);
+            return (
+                exit_result(test, status.success(), status.code(), stdout_buffer),
+                false,
+            );
+        }
+
+        if let Some(limit) = limits.exceeded(&process.child, start_time.elapsed()) {
+            // Dropping the child kills it.
+            receive_child_stdout(&mut stdout_buffer);
+            return (
+                Err(ValidaTestError::ResourceLimitExceeded {
+                    limit,
+                    output: String::from_utf8_lossy(&stdout_buffer).into_owned(),
+                }),
+                false,
+            );
+        }
+
+        // Legacy fallback: a VM that cannot halt prints the sentinel on panic and then hangs.
         let search_end = stdout_buffer
             .len()
             .checked_sub(MAGIC_TERMINATOR.len())
@@ -423,7 +2610,7 @@ fn run_test_on_valida_inner(
         searched_cursor = search_end;
 
         if paniced_with_magic_terminator {
-            if let ShouldPanic::No = test.desc.should_panic {
+            if test.expect == ExpectedOutcome::Pass {
                 // remove the magic terminator if it's the last thing in the buffer
                 // If somthing else is printed after the terminator,
                 // something is broken and I want to the full output.
@@ -432,59 +2619,343 @@ fn run_test_on_valida_inner(
                     .strip_suffix(MAGIC_TERMINATOR.as_bytes().trim_ascii_end())
                     .unwrap_or(&stdout_buffer);
 
-                return Err(format!(
-                    "Test panicked unexpectedly.\n\n{}\n\n",
-                    String::from_utf8_lossy(stdout_buffer)
-                ));
+                return (Err(ValidaTestError::panicked(stdout_buffer)), false);
             } else {
-                return Ok(true);
+                return (check_panic_message(test, stdout_buffer), false);
             }
         }
 
-        let Ok(child_status) = child.try_wait() else {
-            receive_child_stdout(&mut stdout_buffer);
-            return Err(format!(
-                "Failed to wait for cargo process.\n\n{}\n\n",
-                String::from_utf8_lossy(&stdout_buffer)
-            ));
-        };
+        if start_time.elapsed() >= timeout {
+            let expected_panic = test.expect != ExpectedOutcome::Pass;
+            if expected_panic && config.timeout_as_panic {
+                return (check_panic_message(test, stdout_buffer), false);
+            }
+            return (
+                Err(ValidaTestError::TimedOut {
+                    timeout,
+                    expected_panic,
+                    output: String::from_utf8_lossy(&stdout_buffer).into_owned(),
+                }),
+                false,
+            );
+        }
 
-        if let Some(status) = child_status.map(|s| s.success()) {
-            receive_child_stdout(&mut stdout_buffer);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
 
-            match (status, &test.desc.should_panic) {
-                (true, ShouldPanic::No) => return Ok(true),
-                (true, ShouldPanic::Yes | ShouldPanic::YesWithMessage(_)) => {
-                    return Err(format!(
-                        "Test did not panic as expected.\n\n{}\n\n",
-                        String::from_utf8_lossy(&stdout_buffer)
-                    ));
-                }
-                (false, ShouldPanic::No) => {
-                    return Err(format!(
-                        "Test failed with exit code: {:?}\n\n{}\n\n",
-                        status,
-                        String::from_utf8_lossy(&stdout_buffer)
-                    ));
-                }
-                (false, ShouldPanic::Yes | ShouldPanic::YesWithMessage(_)) => return Ok(true),
-            }
+/// Check the outcome of `test` from how its run ended: successfully, or with the exit `code`.
+#[cfg(not(valida))]
+fn exit_result(
+    test: &VmTest,
+    success: bool,
+    code: Option<i32>,
+    stdout_buffer: Vec<u8>,
+) -> Result<Vec<u8>, ValidaTestError> {
+    match (success, &test.expect) {
+        (true, ExpectedOutcome::Pass) => Ok(stdout_buffer),
+        (true, ExpectedOutcome::Panic | ExpectedOutcome::PanicWithMessage(_)) => {
+            Err(ValidaTestError::DidNotPanic {
+                output: String::from_utf8_lossy(&stdout_buffer).into_owned(),
+            })
+        }
+        (false, ExpectedOutcome::Pass) if code == Some(PANIC_EXIT_CODE) => {
+            Err(ValidaTestError::panicked(&stdout_buffer))
+        }
+        (false, ExpectedOutcome::Pass) => Err(ValidaTestError::ExitFailure {
+            code,
+            output: String::from_utf8_lossy(&stdout_buffer).into_owned(),
+        }),
+        (false, ExpectedOutcome::Panic | ExpectedOutcome::PanicWithMessage(_)) => {
+            check_panic_message(test, stdout_buffer)
         }
+    }
+}
 
-        if start_time.elapsed() >= timeout {
-            match &test.desc.should_panic {
-                ShouldPanic::No => {
-                    return Err(format!(
-                        "Test timed out after {:?}\n\n{}",
-                        timeout,
-                        String::from_utf8_lossy(&stdout_buffer)
-                    ));
-                }
-                ShouldPanic::Yes | ShouldPanic::YesWithMessage(_) => {
-                    return Ok(true);
-                }
+/// A test binary kept running in the VM to run one test after another, see
+/// [`ValidaTestConfig::workers`].
+///
+/// A test that panics or fails stops the worker, since the guest may be left in any state.
+#[cfg(not(valida))]
+struct VmWorker {
+    process: VmProcess,
+    /// The "Available tests" line the binary printed when it started.
+    header: Vec<u8>,
+}
+
+#[cfg(not(valida))]
+impl VmWorker {
+    /// Start `test_path` in worker mode, or `None` if it exited without listing its tests.
+    fn spawn(test_path: &Path) -> Option<Self> {
+        let mut process = VmProcess::spawn(test_path);
+        writeln!(process.stdin, "{WORKER_MODE}").ok()?;
+
+        let start_time = Instant::now();
+        let mut header = Vec::new();
+        while !header.contains(&b'\n') {
+            let wait = Duration::from_secs(5).saturating_sub(start_time.elapsed());
+            header.extend(process.stdout.recv_timeout(wait).ok()?);
+        }
+        Some(Self { process, header })
+    }
+
+    /// Run `test`, returning its result like [`run_test_on_valida_inner`] and the worker if it
+    /// can run another test.
+    fn run(
+        mut self,
+        test: &VmTest,
+        timeout: Duration,
+        mode: RunMode,
+        query_handler: Option<&crate::host::QueryHandler>,
+    ) -> (Result<Option<Vec<u8>>, ValidaTestError>, Option<Self>) {
+        self.process.stdin.sent.clear();
+        if write_test_request(&mut self.process.stdin, test, mode).is_err() {
+            return (Ok(None), None);
+        }
+
+        let mut stdout_buffer = self.header.clone();
+        if !check_test_started(&mut self.process.stdout, &mut stdout_buffer, &test.name) {
+            // A binary without the test answers with the done line straight away.
+            let idle = stdout_buffer.ends_with(format!("\n{TEST_DONE_LINE}\n").as_bytes());
+            return (Ok(None), idle.then_some(self));
+        }
+
+        let (result, done) = supervise(
+            &mut self.process,
+            stdout_buffer,
+            test,
+            timeout,
+            query_handler,
+            true,
+        );
+        self.process.record_artifacts(test, &result);
+        // A VM stuck after a panic it reported with the sentinel, or by timing out, would hang
+        // the next test sent to it.
+        let idle = done && result.is_ok();
+        (result.map(Some), idle.then_some(self))
+    }
+}
+
+/// A test to run on a [`VmPool`], after it ran natively.
+#[cfg(not(valida))]
+struct VmJob {
+    desc: TestDesc,
+    host_test_time: Duration,
+    bench: bool,
+    query_handler: Option<crate::host::QueryHandler>,
+    fixtures: Vec<(String, Vec<u8>)>,
+    /// The input the test recorded natively, see [`RunMode::new`].
+    replay_input: Option<Vec<u8>>,
+    host_output: Option<String>,
+}
+
+/// The result of a [`VmJob`], which passed or failed on its `attempt`th retry.
+#[cfg(not(valida))]
+struct VmJobResult {
+    job: VmJob,
+    attempt: u32,
+    result: Result<Vec<u8>, ValidaTestError>,
+    /// How long the last attempt took.
+    duration: Duration,
+}
+
+/// Threads that each keep a [`VmWorker`] per test binary warm and run the tests sent to them, so
+/// starting the VM is paid for once per worker rather than once per test.
+#[cfg(not(valida))]
+struct VmPool {
+    jobs: Option<mpsc::Sender<VmJob>>,
+    results: mpsc::Receiver<VmJobResult>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+    pending: usize,
+}
+
+#[cfg(not(valida))]
+impl VmPool {
+    fn new(workers: usize, test_paths: &[PathBuf], retries: u32) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<VmJob>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (result_sender, results) = mpsc::channel();
+
+        let threads = (0..workers)
+            .map(|_| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                let test_paths = test_paths.to_vec();
+                std::thread::spawn(move || {
+                    let mut warm = BTreeMap::new();
+                    loop {
+                        let Ok(job) = job_receiver.lock().unwrap().recv() else {
+                            break;
+                        };
+                        let (attempt, result, duration) =
+                            run_vm_job(&mut warm, &test_paths, &job, retries);
+                        let result = VmJobResult {
+                            job,
+                            attempt,
+                            result,
+                            duration,
+                        };
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            results,
+            threads,
+            pending: 0,
+        }
+    }
+
+    fn submit(&mut self, job: VmJob) {
+        self.pending += 1;
+        // The workers only stop once the pool is dropped.
+        let _ = self.jobs.as_ref().unwrap().send(job);
+    }
+
+    /// The result of a finished job, without waiting.
+    fn try_next(&mut self) -> Option<VmJobResult> {
+        let result = self.results.try_recv().ok()?;
+        self.pending -= 1;
+        Some(result)
+    }
+
+    /// The result of the next job to finish, or `None` once all have been returned.
+    fn next(&mut self) -> Option<VmJobResult> {
+        if self.pending == 0 {
+            return None;
+        }
+        let result = self.results.recv().expect("a VM worker thread panicked");
+        self.pending -= 1;
+        Some(result)
+    }
+}
+
+#[cfg(not(valida))]
+impl Drop for VmPool {
+    fn drop(&mut self) {
+        // Closing the queue stops the threads, whose workers exit at the end of their input.
+        self.jobs.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Run `job` on the first of `test_paths` that contains it, retrying failures. Returns the last
+/// attempt's number, result and duration.
+#[cfg(not(valida))]
+fn run_vm_job(
+    warm: &mut BTreeMap<PathBuf, VmWorker>,
+    test_paths: &[PathBuf],
+    job: &VmJob,
+    retries: u32,
+) -> (u32, Result<Vec<u8>, ValidaTestError>, Duration) {
+    let mode = RunMode::new(job.replay_input.as_deref(), job.bench);
+    let test = VmTest {
+        fixtures: job.fixtures.clone(),
+        ..VmTest::from(&job.desc)
+    };
+    let timeout = ValidaTestConfig::get().vm_timeout(job.host_test_time);
+    let run = |warm: &mut BTreeMap<PathBuf, VmWorker>| {
+        if interrupt::requested() {
+            return Err(ValidaTestError::ProcessError {
+                message: "Interrupted.".to_string(),
+                output: String::new(),
+            });
+        }
+        if test_paths.is_empty() {
+            return Err(ValidaTestError::NoBinaries);
+        }
+        for test_path in test_paths {
+            let worker = match warm.remove(test_path) {
+                Some(worker) => worker,
+                None => match VmWorker::spawn(test_path) {
+                    Some(worker) => worker,
+                    None => continue,
+                },
+            };
+            let (result, worker) = worker.run(&test, timeout, mode, job.query_handler.as_ref());
+            if let Some(worker) = worker {
+                warm.insert(test_path.clone(), worker);
+            }
+            if let Some(stdout) = result? {
+                return Ok(stdout);
             }
         }
+        Err(ValidaTestError::NotFound {
+            binaries: test_paths.to_vec(),
+        })
+    };
+
+    let mut attempt = 0;
+    let mut start = Instant::now();
+    let mut result = run(warm);
+    while let Some(msg) = retryable(&result, attempt, retries) {
+        attempt += 1;
+        eprintln!(
+            "\ntest {} failed on valida, retrying ({attempt}/{retries}): {msg}",
+            job.desc.name
+        );
+        start = Instant::now();
+        result = run(warm);
+    }
+    (attempt, result, start.elapsed())
+}
+
+/// The failure in `result` if it should be retried, having made `attempt` of `retries` retries.
+///
+/// Only failures of the environment are retried: the VM process failing, exiting with a status
+/// other than [`PANIC_EXIT_CODE`], or timing out under load. A test that panicked, did not panic,
+/// or printed the wrong output would fail the same way again.
+#[cfg(not(valida))]
+fn retryable<T>(
+    result: &Result<T, ValidaTestError>,
+    attempt: u32,
+    retries: u32,
+) -> Option<&ValidaTestError> {
+    let err = result.as_ref().err()?;
+    let environmental = match err {
+        ValidaTestError::ProcessError { .. } | ValidaTestError::TimedOut { .. } => true,
+        ValidaTestError::ExitFailure { code, .. } => *code != Some(PANIC_EXIT_CODE),
+        _ => false,
+    };
+    (environmental && attempt < retries && !interrupt::requested()).then_some(err)
+}
+
+/// The handler the current test registered with `io::testing::set_query_handler` natively, which
+/// answers its queries in the VM.
+#[cfg(not(valida))]
+fn query_handler() -> Option<crate::host::QueryHandler> {
+    #[cfg(feature = "guest")]
+    return crate::io::testing::query_handler().map(crate::host::QueryHandler);
+
+    #[cfg(not(feature = "guest"))]
+    None
+}
+
+/// Check that a test which panicked in the VM did so with the message it was expected to.
+#[cfg(not(valida))]
+fn check_panic_message(test: &VmTest, stdout_buffer: Vec<u8>) -> Result<Vec<u8>, ValidaTestError> {
+    let ExpectedOutcome::PanicWithMessage(expected) = &test.expect else {
+        return Ok(stdout_buffer);
+    };
+
+    let output = String::from_utf8_lossy(&stdout_buffer).into_owned();
+    let actual = PanicRecord::find(&output).map(|panic| panic.message);
+
+    if actual.as_deref().is_some_and(|msg| msg.contains(expected)) {
+        Ok(stdout_buffer)
+    } else {
+        Err(ValidaTestError::WrongPanicMessage {
+            expected: expected.to_string(),
+            actual,
+            output,
+        })
     }
 }
 
@@ -540,6 +3011,7 @@ fn non_blocking_read(mut reader: impl Read + Send + 'static) -> mpsc::Receiver<V
     rx
 }
 
+#[cfg(feature = "guest")]
 fn set_panic_handler(test: &TestDescAndFn) {
     let test_name = test.desc.name.clone();
     let test_file = test.desc.source_file;
@@ -557,53 +3029,166 @@ fn set_panic_handler(test: &TestDescAndFn) {
 
         let location = info.location().unwrap_or_else(|| panic::Location::caller());
 
-        let err = format!(
-            "\n\ntest '{test_name}' in {test_file}:{test_line}:{test_column} panicked at {location} with message:\n{msg}\n\n",
-        );
-
-        println!("{}", &err);
+        let record = PanicRecord {
+            test: test_name.to_string(),
+            file: test_file.to_string(),
+            line: test_line,
+            column: test_column,
+            location: location.to_string(),
+            message: msg.to_string(),
+            cycles: crate::intrinsics::cycle_count(),
+        };
+        crate::trace::print_recent();
+        println!("\n{record}");
 
+        crate::intrinsics::halt(PANIC_EXIT_CODE as u32);
+        // Legacy fallback for VMs without a halt intrinsic.
         println!("{MAGIC_TERMINATOR}");
     }));
 }
 
 // Run's a single specified test.
 // Get's the test name from first line of input.
+#[cfg(feature = "guest")]
 fn run_single_test_in_valida(tests: &[&TestDescAndFn]) {
-    print!("Available tests:");
+    let tests: Vec<&TestDescAndFn> = tests
+        .iter()
+        .copied()
+        .filter(|t| TestEnvironment::of(t.desc.name.as_slice()) != TestEnvironment::HostOnly)
+        .collect();
+
+    print!(
+        "{AVAILABLE_TESTS} (valida-rs {}, protocol {PROTOCOL_VERSION}):",
+        env!("CARGO_PKG_VERSION")
+    );
     for t in tests.iter() {
-        print!(" ({}, {})", t.desc.name, t.desc.source_file)
+        print!(
+            " ({}, {})",
+            encode_protocol_field(t.desc.name.as_slice()),
+            encode_protocol_field(t.desc.source_file)
+        )
     }
     println!();
 
-    let Ok(test_name) = crate::io::read_line::<String>() else {
+    let Ok(first_line) = crate::io::read_line::<String>() else {
         // If no test name is provided the program will hang.
         return;
     };
-
-    let Ok(test_file) = crate::io::read_line::<String>() else {
+    if first_line == RUN_ALL_TESTS {
+        run_all_tests_in_valida(&tests, "");
+        return;
+    }
+    if let Some(filter) = first_line.strip_prefix(&format!("{RUN_ALL_TESTS} ")) {
+        run_all_tests_in_valida(&tests, &decode_protocol_field(filter));
         return;
+    }
+    let worker = first_line == WORKER_MODE;
+    let mut test_name = if worker {
+        crate::io::read_line::<String>().unwrap_or_default()
+    } else {
+        first_line
+    };
+
+    loop {
+        // A worker stops when the host closes its input.
+        if worker && test_name.is_empty() {
+            return;
+        }
+
+        let Ok(test_file) = crate::io::read_line::<String>() else {
+            return;
+        };
+        let mode = crate::io::read_line::<String>().unwrap_or_default();
+        run_requested_test(&tests, &test_name, &test_file, &mode);
+
+        if !worker {
+            return;
+        }
+        println!("{TEST_DONE_LINE}");
+        test_name = crate::io::read_line::<String>().unwrap_or_default();
+    }
+}
+
+/// Run every test that is not ignored and matches `filter`, one after another, and print a
+/// summary like libtest's. Test names are encoded with [`encode_protocol_field`].
+///
+/// Panics cannot be caught in the VM, so `should_panic` tests are skipped and a test that panics
+/// ends the run with its panic record instead of the summary.
+#[cfg(feature = "guest")]
+fn run_all_tests_in_valida(tests: &[&TestDescAndFn], filter: &str) {
+    let tests: Vec<_> = tests
+        .iter()
+        .filter(|t| matches_test_filter(t.desc.name.as_slice(), filter))
+        .collect();
+    println!("\nrunning {} tests", tests.len());
+    let (mut passed, mut failed, mut ignored) = (0, 0, 0);
+    for test in tests {
+        let name = encode_protocol_field(test.desc.name.as_slice());
+        if test.desc.ignore || test.desc.should_panic != ShouldPanic::No {
+            println!("test {name} ... ignored");
+            ignored += 1;
+            continue;
+        }
+
+        set_panic_handler(test);
+        crate::trace::clear();
+        print!("test {name} ... ");
+        match runnable(test, false).map_or(Ok(()), |f| f()) {
+            Ok(()) => {
+                println!("ok");
+                passed += 1;
+            }
+            Err(e) => {
+                println!("FAILED\n{e}");
+                failed += 1;
+            }
+        }
+    }
+
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    println!("\ntest result: {result}. {passed} passed; {failed} failed; {ignored} ignored\n");
+}
+
+/// Run the test the host selected by its encoded name and file, if this binary has it.
+#[cfg(feature = "guest")]
+fn run_requested_test(tests: &[&TestDescAndFn], test_name: &str, test_file: &str, mode: &str) {
+    let (mode, fixture_count) = match mode.split_once(&format!(" {FIXTURES} ")) {
+        Some((mode, count)) => (mode, count.trim().parse().unwrap_or(0)),
+        None => (mode, 0),
     };
+    let bench_mode = mode == BENCH_MODE;
+    if mode == REPLAY_MODE {
+        let input = crate::io::read_line::<usize>().and_then(crate::io::read_n);
+        *REPLAY_INPUT.lock().unwrap() = input.ok();
+    }
+    fixtures::receive(fixture_count);
 
-    let test_name = test_name.trim();
+    let test_name = decode_protocol_field(test_name);
+    let test_file = decode_protocol_field(test_file);
     let test = tests
         .iter()
         .find(|t| t.desc.name.as_slice() == test_name && t.desc.source_file == test_file);
 
     if let Some(test) = test {
         set_panic_handler(test);
+        crate::trace::clear();
 
-        println!("{}", valida_test_second_line_stdout(test).as_str());
-        // TODO catch panics once that works on valida
-        // return 0 or 1 exit code based on test outcome
-        // there's no point in doing this now since panic will cause an infinite loop
-        // The loop has to be detected by the host test runner.
-        // If the test takes 20x longer than expected, we can assume it has panicked.
-        //
-        // TODO support other test types
-        if let TestFn::StaticTestFn(f) = test.testfn {
+        println!(
+            "{}",
+            valida_test_second_line_stdout(test.desc.name.as_slice()).as_str()
+        );
+        // Panics can't be caught on valida. With the halt intrinsic the panic hook exits with
+        // PANIC_EXIT_CODE, which the host sees as the exit status. Without it, the panic causes
+        // an infinite loop that the host detects through the sentinel or, when
+        // VALIDA_TEST_TIMEOUT_AS_PANIC is set, the test taking 20x longer than expected.
+        if let Some(f) = runnable(test, bench_mode) {
+            let start_cycles = crate::intrinsics::cycle_count();
             let _ = f();
+            if let (Some(start), Some(end)) = (start_cycles, crate::intrinsics::cycle_count()) {
+                println!("{TEST_CYCLES_PREFIX} {}", end - start);
+            }
         }
+        crate::profile::flush();
     }
 }
 
@@ -611,10 +3196,11 @@ fn run_single_test_in_valida(tests: &[&TestDescAndFn]) {
 fn check_test_started(
     valida_stdout_stream: &mut mpsc::Receiver<Vec<u8>>,
     valida_stdout_buffer: &mut Vec<u8>,
-    test: &TestDescAndFn,
+    test_name: &str,
 ) -> bool {
     let start_time = Instant::now();
-    let mut lines_seen = 0;
+    // A worker's buffer starts with the "Available tests" line it printed when it started.
+    let mut lines_seen = valida_stdout_buffer.iter().filter(|&&b| b == b'\n').count();
 
     while lines_seen < 2 && start_time.elapsed() < Duration::from_secs(5) {
         match valida_stdout_stream.try_recv() {
@@ -634,19 +3220,295 @@ fn check_test_started(
 
     let stdout_str = String::from_utf8_lossy(valida_stdout_buffer);
     let mut stdout_str = stdout_str.lines();
+    let first_line = stdout_str.next();
+    if let Some(first_line) = first_line {
+        check_guest_versions(first_line);
+    }
 
     #[allow(clippy::match_like_matches_macro)]
-    match (stdout_str.next(), stdout_str.next()) {
-        (Some(_), Some(second_line)) if second_line == valida_test_second_line_stdout(test) => true,
+    match (first_line, stdout_str.next()) {
+        (Some(_), Some(second_line))
+            if second_line == valida_test_second_line_stdout(test_name) =>
+        {
+            true
+        }
         _ => false,
     }
 }
 
-fn valida_test_second_line_stdout(test: &TestDescAndFn) -> String {
-    format!("Running test: {} in valida vm", test.desc.name)
+/// The valida-rs version and [`PROTOCOL_VERSION`] named in a test binary's first line, or `None`
+/// if the line does not name them.
+fn guest_versions(first_line: &str) -> Option<(&str, u32)> {
+    let versions = first_line
+        .strip_prefix(AVAILABLE_TESTS)?
+        .strip_prefix(" (valida-rs ")?;
+    let (versions, _) = versions.split_once("):")?;
+    let (crate_version, protocol) = versions.split_once(", protocol ")?;
+    Some((crate_version, protocol.parse().ok()?))
+}
+
+/// Warn, once per run, if the test binary that printed `first_line` was built with another
+/// version of valida-rs than the runner.
+fn check_guest_versions(first_line: &str) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+
+    if !first_line.starts_with(AVAILABLE_TESTS) {
+        return;
+    }
+    let host_version = env!("CARGO_PKG_VERSION");
+    let warning = match guest_versions(first_line) {
+        Some((version, PROTOCOL_VERSION)) if version == host_version => return,
+        Some((version, PROTOCOL_VERSION)) => format!(
+            "the VM test binary was built with valida-rs {version} and the runner with \
+             {host_version}; they speak the same protocol"
+        ),
+        Some((version, protocol)) => format!(
+            "the VM test binary was built with valida-rs {version} (protocol {protocol}) and the \
+             runner with {host_version} (protocol {PROTOCOL_VERSION}); its results may be \
+             misread, rebuild it"
+        ),
+        // Binaries built before the versions were printed.
+        None => format!(
+            "the VM test binary was built with a valida-rs older than the runner's \
+             {host_version}; its results may be misread, rebuild it"
+        ),
+    };
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!("warning: {warning}");
+    }
+}
+
+fn valida_test_second_line_stdout(test_name: &str) -> String {
+    format!(
+        "Running test: {} in valida vm",
+        encode_protocol_field(test_name)
+    )
 }
 
 #[test]
 fn test_unit_test_in_lib() {
     assert_eq!(1, 1);
 }
+
+#[test]
+fn test_shards_partition_tests() {
+    assert!("0/2".parse::<Shard>().is_err());
+    assert!("3/2".parse::<Shard>().is_err());
+
+    let shards: Vec<Shard> = (1..=3).map(|k| format!("{k}/3").parse().unwrap()).collect();
+    for name in ["a", "io::read", "test_utils::test_unit_test_in_lib", ""] {
+        assert_eq!(shards.iter().filter(|s| s.contains(name)).count(), 1);
+    }
+}
+
+#[test]
+fn test_runner_args() {
+    let args = |args: &[&str]| RunnerArgs::parse(args.iter().map(|s| s.to_string()));
+
+    assert_eq!(args(&["io::"]).filter.as_deref(), Some("io::"));
+    let parsed = args(&["--shuffle-seed", "42", "io::"]);
+    assert_eq!(parsed.shuffle_seed, Some(42));
+    assert_eq!(parsed.filter.as_deref(), Some("io::"));
+    assert_eq!(args(&["--shuffle-seed=7"]).shuffle_seed, Some(7));
+    assert!(args(&["--shuffle"]).shuffle_seed.is_some());
+    assert!(args(&["--bench"]).bench);
+    assert!(args(&["--list"]).list);
+    assert!(args(&["--coverage"]).coverage);
+    let parsed = args(&["--include-ignored"]);
+    assert!(parsed.runs_ignored() && !parsed.ignored);
+    assert!(args(&["--fail-fast"]).fail_fast);
+
+    // The arguments `cargo nextest` runs a single test with.
+    let parsed = args(&["--list", "--format", "terse", "--ignored"]);
+    assert!(parsed.terse && parsed.ignored && parsed.filter.is_none());
+    let parsed = args(&["io::read", "--exact", "--nocapture", "--test-threads", "1"]);
+    assert!(parsed.exact);
+    assert_eq!(parsed.filter.as_deref(), Some("io::read"));
+}
+
+#[test]
+fn test_environment_from_name() {
+    assert_eq!(TestEnvironment::of("io::read"), TestEnvironment::Both);
+    assert_eq!(
+        TestEnvironment::of("io::valida_only::read"),
+        TestEnvironment::ValidaOnly
+    );
+    assert_eq!(
+        TestEnvironment::of("host_only_threads"),
+        TestEnvironment::HostOnly
+    );
+    assert_eq!(TestEnvironment::of("host_onlyish"), TestEnvironment::Both);
+}
+
+#[test]
+fn test_vm_test_output_strips_protocol() {
+    let stdout = "Available tests (valida-rs 0.1.0, protocol 1): (a, a.rs)\nRunning test: a in valida vm\nhello\n\
+                  valida-bench: a iterations=1 min=1 median=1 unit=cycles\n";
+    assert_eq!(vm_test_output(stdout), "hello\n");
+}
+
+#[test]
+fn test_guest_versions() {
+    assert_eq!(
+        guest_versions("Available tests (valida-rs 0.2.0, protocol 3): (a, a.rs)"),
+        Some(("0.2.0", 3))
+    );
+    assert_eq!(guest_versions("Available tests: (a, a.rs)"), None);
+}
+
+#[test]
+fn test_matches_test_filter() {
+    assert!(matches_test_filter("io::tests::read", "io::"));
+    assert!(matches_test_filter("io::tests::read", ""));
+    assert!(!matches_test_filter("hints::read", "io::"));
+    assert!(matches_test_filter(
+        "io::tests::read_round_trip",
+        "io::*_round_trip"
+    ));
+    assert!(!matches_test_filter(
+        "io::tests::read_round_trip_fails",
+        "io::*_round_trip"
+    ));
+    assert!(matches_test_filter("io::read", "*"));
+}
+
+#[test]
+fn test_select_build_args() {
+    let args = |args: &str| select_build_args(args.split_whitespace().map(str::to_string));
+
+    assert_eq!(
+        args("test --features a,b -p foo --no-default-features -- --exact x"),
+        ["--features", "a,b", "-p", "foo", "--no-default-features"]
+    );
+    assert_eq!(
+        args("test --profile=fast --color always"),
+        ["--profile=fast"]
+    );
+    assert!(selects_profile(&args("test -r")));
+}
+
+#[test]
+fn test_parse_byte_size() {
+    assert_eq!(parse_byte_size("4096"), Some(4096));
+    assert_eq!(parse_byte_size("512K"), Some(512 << 10));
+    assert_eq!(parse_byte_size(" 2g "), Some(2 << 30));
+    assert_eq!(parse_byte_size("M"), None);
+    assert_eq!(parse_byte_size("lots"), None);
+}
+
+#[test]
+fn test_protocol_field_round_trip() {
+    let name = "cases::case_1\nwith \\ and \r";
+    let encoded = encode_protocol_field(name);
+    assert!(!encoded.contains('\n'));
+    assert_eq!(decode_protocol_field(&encoded), name);
+    assert_ne!(encode_protocol_field(WORKER_MODE), WORKER_MODE);
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_exit_result() {
+    let test = |expect| VmTest {
+        name: "tests::it".to_string(),
+        source_file: "src/lib.rs".to_string(),
+        expect,
+        fixtures: Vec::new(),
+    };
+    let record = PanicRecord {
+        test: "tests::it".to_string(),
+        file: "src/lib.rs".to_string(),
+        line: 1,
+        column: 1,
+        location: "src/lib.rs:3:5".to_string(),
+        message: "index out of bounds".to_string(),
+        cycles: None,
+    };
+    let output = format!("{record}\n").into_bytes();
+
+    let passing = test(ExpectedOutcome::Pass);
+    assert!(exit_result(&passing, true, Some(0), vec![]).is_ok());
+    assert_eq!(
+        exit_result(&passing, false, Some(PANIC_EXIT_CODE), output.clone())
+            .unwrap_err()
+            .kind(),
+        "panicked"
+    );
+    assert_eq!(
+        exit_result(&passing, false, Some(1), vec![])
+            .unwrap_err()
+            .kind(),
+        "exit_failure"
+    );
+
+    let panicking = test(ExpectedOutcome::PanicWithMessage(
+        "out of bounds".to_string(),
+    ));
+    assert!(exit_result(&panicking, false, Some(PANIC_EXIT_CODE), output.clone()).is_ok());
+    let wrong = test(ExpectedOutcome::PanicWithMessage("overflow".to_string()));
+    assert_eq!(
+        exit_result(&wrong, false, Some(PANIC_EXIT_CODE), output)
+            .unwrap_err()
+            .kind(),
+        "wrong_panic_message"
+    );
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_panic_record_round_trip() {
+    let record = PanicRecord {
+        test: "cases::case_1".to_string(),
+        file: "src/lib.rs".to_string(),
+        line: 12,
+        column: 4,
+        location: "src/lib.rs:14:9".to_string(),
+        message: "expected \"a\\b\"\n\tgot \u{1}".to_string(),
+        cycles: Some(1234),
+    };
+    let line = record.to_string();
+    assert!(!line.contains('\n'));
+    assert_eq!(PanicRecord::parse_line(&line), Some(record));
+}
+
+#[cfg(all(feature = "guest", not(valida)))]
+#[test]
+fn test_fixtures_in_test_request() {
+    let test = VmTest {
+        name: "tests::reads".to_string(),
+        source_file: "src/lib.rs".to_string(),
+        expect: ExpectedOutcome::Pass,
+        fixtures: vec![("data/a b.txt".to_string(), b"one\ntwo".to_vec())],
+    };
+    let mut request = Vec::new();
+    write_test_request(&mut request, &test, RunMode::Test).unwrap();
+    let mode_line = format!("{TEST_MODE} {FIXTURES} 1\n");
+    let start = request
+        .windows(mode_line.len())
+        .position(|w| w == mode_line.as_bytes())
+        .unwrap();
+
+    crate::io::testing::set_input(request[start + mode_line.len()..].to_vec());
+    fixtures::receive(1);
+    crate::io::testing::reset();
+    assert_eq!(fixtures::declared(), test.fixtures);
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_retryable() {
+    let exit = |code| {
+        Err::<(), _>(ValidaTestError::ExitFailure {
+            code,
+            output: String::new(),
+        })
+    };
+    assert!(retryable(&exit(Some(1)), 0, 1).is_some());
+    assert!(retryable(&exit(None), 0, 1).is_some());
+    assert!(retryable(&exit(Some(1)), 1, 1).is_none());
+    assert!(retryable(&exit(Some(PANIC_EXIT_CODE)), 0, 1).is_none());
+    let did_not_panic = Err::<(), _>(ValidaTestError::DidNotPanic {
+        output: String::new(),
+    });
+    assert!(retryable(&did_not_panic, 0, 1).is_none());
+    assert!(retryable(&Ok(()), 0, 1).is_none());
+}
@@ -0,0 +1,175 @@
+//! Per-test records of the runs in the VM, so a failure in CI can be reproduced locally.
+//!
+//! With [`ValidaTestConfig::artifact_dir`] set, each test run in the VM gets a directory named
+//! after the test, holding:
+//! - `input`: the exact bytes written to the VM's stdin, including answers to queries,
+//! - `stdout` and `stderr`: what the VM printed,
+//! - `output`: the output file `valida run` wrote,
+//! - `command`: a `valida run` command line that re-runs the test with the recorded input,
+//! - `run.json`: the binary, exit code, cycle count and result, with the failure if it failed.
+//!
+//! A retried test keeps the artifacts of its last attempt. Set [`VALIDA_TEST_REPLAY_ENV`] to a
+//! test's name to re-run its recorded run without building or running anything else.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use super::{ValidaTestConfig, ValidaTestError};
+
+/// Environment variable with the directory to write each VM test run's artifacts to.
+pub const VALIDA_TEST_ARTIFACTS_ENV: &str = "VALIDA_TEST_ARTIFACTS";
+
+/// Environment variable with the name of a test whose recorded VM run should be re-run, instead
+/// of running the tests.
+pub const VALIDA_TEST_REPLAY_ENV: &str = "VALIDA_TEST_REPLAY";
+
+/// What one test run in the VM was given and produced.
+pub(super) struct RunArtifacts<'a> {
+    pub binary: &'a Path,
+    pub input: &'a [u8],
+    pub stdout: &'a [u8],
+    pub stderr: &'a [u8],
+    /// The output file `valida run` was writing.
+    pub output_file: &'a Path,
+    /// The VM's exit code, if it exited rather than finishing the test as a worker or being
+    /// killed.
+    pub exit_code: Option<i32>,
+    pub result: &'a Result<Vec<u8>, ValidaTestError>,
+}
+
+/// The directory the artifacts of `test_name` are written to, if artifacts are enabled.
+pub(super) fn test_dir(test_name: &str) -> Option<PathBuf> {
+    let dir = ValidaTestConfig::get().artifact_dir.as_ref()?;
+    let name: String = test_name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    Some(dir.join(name))
+}
+
+/// Write the artifacts of a run of `test_name`, if artifacts are enabled.
+pub(super) fn write(test_name: &str, artifacts: &RunArtifacts) {
+    let Some(dir) = test_dir(test_name) else {
+        return;
+    };
+    if let Err(e) = write_to(&dir, test_name, artifacts) {
+        eprintln!(
+            "Failed to write the artifacts of test {test_name} to {}: {e}",
+            dir.display()
+        );
+    }
+}
+
+fn write_to(dir: &Path, test_name: &str, artifacts: &RunArtifacts) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("input"), artifacts.input)?;
+    fs::write(dir.join("stdout"), artifacts.stdout)?;
+    fs::write(dir.join("stderr"), artifacts.stderr)?;
+    fs::copy(artifacts.output_file, dir.join("output"))?;
+
+    let command = format!(
+        "{} run {} {} < {}",
+        ValidaTestConfig::get().valida_command.display(),
+        artifacts.binary.display(),
+        dir.join("output.replay").display(),
+        dir.join("input").display(),
+    );
+    fs::write(dir.join("command"), format!("{command}\n"))?;
+
+    let cycles = crate::host::parse_cycles(artifacts.stderr)
+        .or_else(|| crate::host::parse_cycles(artifacts.stdout));
+    let (kind, error) = match artifacts.result {
+        Ok(_) => ("ok", None),
+        Err(err) => (err.kind(), Some(err)),
+    };
+    let record = serde_json::json!({
+        "test": test_name,
+        "binary": artifacts.binary,
+        "command": command,
+        "exit_code": artifacts.exit_code,
+        "cycles": cycles,
+        "result": kind,
+        "error": error,
+    });
+    fs::write(dir.join("run.json"), format!("{record:#}\n"))
+}
+
+/// The command recorded to reproduce the last run of `test_name`, if there is one.
+pub(super) fn reproduce_command(test_name: &str) -> Option<String> {
+    let command = fs::read_to_string(test_dir(test_name)?.join("command")).ok()?;
+    Some(command.trim_end().to_string())
+}
+
+/// Re-run the recorded VM run of `test_name` with the binary and input it had, printing its
+/// output as it runs, and return the VM's exit code.
+pub(super) fn replay(test_name: &str) -> Result<i32, String> {
+    let dir = test_dir(test_name).ok_or_else(|| {
+        format!("Set {VALIDA_TEST_ARTIFACTS_ENV} to the directory the test runs were recorded in")
+    })?;
+    let no_run = |e| {
+        format!(
+            "No recorded run of test {test_name} in {}: {e}",
+            dir.display()
+        )
+    };
+    let run: serde_json::Value = fs::read(dir.join("run.json"))
+        .map_err(|e| no_run(e.to_string()))
+        .and_then(|run| serde_json::from_slice(&run).map_err(|e| no_run(e.to_string())))?;
+    let binary = run["binary"]
+        .as_str()
+        .ok_or_else(|| no_run("run.json has no binary".to_string()))?;
+    let input = fs::File::open(dir.join("input")).map_err(|e| no_run(e.to_string()))?;
+
+    let config = ValidaTestConfig::get();
+    println!(
+        "replaying test {test_name} on valida from {}",
+        dir.display()
+    );
+    let status = Command::new(&config.valida_command)
+        .arg("run")
+        .arg(binary)
+        .arg(dir.join("output.replay"))
+        .stdin(input)
+        .status()
+        .map_err(|e| format!("Failed to start {}: {e}", config.valida_command.display()))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[test]
+fn test_write_artifacts() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_file = tempfile::NamedTempFile::new().unwrap();
+    let result = Err(ValidaTestError::ExitFailure {
+        code: Some(3),
+        output: "boom\n".to_string(),
+    });
+    let artifacts = RunArtifacts {
+        binary: Path::new("target/valida-tests/guest"),
+        input: b"tests::it\nsrc/lib.rs\ntest\n",
+        stdout: b"boom\n",
+        stderr: b"Total cycles: 1234\n",
+        output_file: output_file.path(),
+        exit_code: Some(3),
+        result: &result,
+    };
+    write_to(dir.path(), "tests::it", &artifacts).unwrap();
+
+    assert_eq!(fs::read(dir.path().join("input")).unwrap(), artifacts.input);
+    let command = fs::read_to_string(dir.path().join("command")).unwrap();
+    assert!(command.contains(" run target/valida-tests/guest "));
+    assert!(command
+        .trim_end()
+        .ends_with(&format!("< {}", dir.path().join("input").display())));
+
+    let run: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.path().join("run.json")).unwrap()).unwrap();
+    assert_eq!(run["exit_code"], 3);
+    assert_eq!(run["cycles"], 1234);
+    assert_eq!(run["result"], "exit_failure");
+}
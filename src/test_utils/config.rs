@@ -0,0 +1,467 @@
+//! The settings of the host test runner, from the crate's manifest and the environment.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::{
+    parse_byte_size, ResourceLimits, Shard, VALIDA_TEST_ARTIFACTS_ENV, VALIDA_TEST_CARGO_ARGS_ENV,
+    VALIDA_TEST_DIFF_OUTPUT_ENV, VALIDA_TEST_ENV, VALIDA_TEST_JSON_ENV, VALIDA_TEST_REPLAY_ENV,
+    VALIDA_TEST_RETRIES_ENV, VALIDA_TEST_TIMEOUT_AS_PANIC_ENV,
+};
+
+/// Environment variable that sets the minimum time a test may take in the VM, in seconds.
+pub const VALIDA_TEST_MIN_TIMEOUT_ENV: &str = "VALIDA_TEST_MIN_TIMEOUT";
+
+/// Environment variable that sets how many times longer than on the host a test may take in the
+/// VM before it times out.
+pub const VALIDA_TEST_TIMEOUT_MULTIPLIER_ENV: &str = "VALIDA_TEST_TIMEOUT_MULTIPLIER";
+
+/// Environment variable that sets how many warm VM workers run tests in parallel; `0` starts a
+/// fresh `valida` process for each test.
+pub const VALIDA_TEST_WORKERS_ENV: &str = "VALIDA_TEST_WORKERS";
+
+/// Environment variable that sets how many ULPs apart the floats in a test's native and VM output
+/// may be before the outputs are reported as diverged.
+pub const VALIDA_TEST_FLOAT_ULPS_ENV: &str = "VALIDA_TEST_FLOAT_ULPS";
+
+/// Environment variable that makes the runner print the size of each Valida test binary it
+/// builds, with this many of the biggest symbols.
+pub const VALIDA_TEST_SIZE_REPORT_ENV: &str = "VALIDA_TEST_SIZE_REPORT";
+
+/// Environment variable that sets by how many percent a test's VM cycles or time may grow from
+/// its previous run before it is reported as a regression.
+pub const VALIDA_TEST_REGRESSION_THRESHOLD_ENV: &str = "VALIDA_TEST_REGRESSION_THRESHOLD";
+
+/// Environment variable naming a file a JUnit XML report of the run is written to.
+pub const VALIDA_TEST_JUNIT_ENV: &str = "VALIDA_TEST_JUNIT";
+
+/// Environment variable naming a file each test result and the summary are written to as JSON
+/// lines.
+pub const VALIDA_TEST_JSON_EVENTS_ENV: &str = "VALIDA_TEST_JSON_EVENTS";
+
+/// Environment variable that stops the run at the first test that fails.
+pub const VALIDA_TEST_FAIL_FAST_ENV: &str = "VALIDA_TEST_FAIL_FAST";
+
+/// Environment variable with the `valida` executable the tests run in.
+pub const VALIDA_COMMAND_ENV: &str = "VALIDA_COMMAND";
+
+/// How the host test runner runs tests.
+///
+/// The defaults can be set per crate in its `Cargo.toml`, and each setting can be overridden by
+/// its environment variable:
+/// ```toml
+/// [package.metadata.valida]
+/// test = true                           # VALIDA_TEST
+/// retries = 2                           # VALIDA_TEST_RETRIES
+/// fail-fast = true                      # VALIDA_TEST_FAIL_FAST, or pass `--fail-fast`
+/// json-report = "target/failures.jsonl" # VALIDA_TEST_JSON
+/// json-events = "target/tests.jsonl"    # VALIDA_TEST_JSON_EVENTS
+/// junit-report = "target/junit.xml"     # VALIDA_TEST_JUNIT
+/// artifact-dir = "target/valida-runs"   # VALIDA_TEST_ARTIFACTS
+/// memory-limit = "2G"                   # VALIDA_TEST_MEMORY_LIMIT
+/// time-limit = 300                      # VALIDA_TEST_TIME_LIMIT, in seconds
+/// min-timeout = 10                      # VALIDA_TEST_MIN_TIMEOUT, in seconds
+/// timeout-multiplier = 20               # VALIDA_TEST_TIMEOUT_MULTIPLIER
+/// timeout-as-panic = false              # VALIDA_TEST_TIMEOUT_AS_PANIC
+/// diff-output = false                   # VALIDA_TEST_DIFF_OUTPUT
+/// float-ulps = 4                        # VALIDA_TEST_FLOAT_ULPS
+/// workers = 4                           # VALIDA_TEST_WORKERS
+/// size-report = 10                      # VALIDA_TEST_SIZE_REPORT
+/// regression-threshold = 25             # VALIDA_TEST_REGRESSION_THRESHOLD, in percent
+/// cargo-args = ["--features", "slow"]   # VALIDA_TEST_CARGO_ARGS
+/// valida-command = "valida"             # VALIDA_COMMAND
+/// toolchain-dir = "/valida-toolchain"   # VALIDA_TOOLCHAIN_DIR
+/// ```
+/// Relative paths are relative to the crate's directory. The shard to run and the test to replay
+/// are only read from `VALIDA_TEST_SHARD` and `VALIDA_TEST_REPLAY`, since they differ between the
+/// runs of one suite.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidaTestConfig {
+    /// Run the tests in the VM as well as on the host.
+    pub run_on_valida: bool,
+    /// How many times a test whose VM run failed for environmental reasons, such as the VM
+    /// crashing or timing out, is retried.
+    pub retries: u32,
+    /// Stop at the first test that fails natively or in the VM.
+    pub fail_fast: bool,
+    /// The shard of the tests to run.
+    pub shard: Option<Shard>,
+    /// The test whose recorded VM run is re-run instead of running the tests, see
+    /// [`super::artifacts`].
+    pub replay: Option<String>,
+    /// The file VM test failures are appended to as JSON lines.
+    pub json_report: Option<PathBuf>,
+    /// The file every test result and the summary are written to as JSON lines, see
+    /// [`JsonReporter`](super::JsonReporter).
+    pub json_events: Option<PathBuf>,
+    /// The file a JUnit XML report is written to, see [`JunitReporter`](super::JunitReporter).
+    pub junit_report: Option<PathBuf>,
+    /// The directory each test's VM run is recorded in, see [`super::artifacts`].
+    pub artifact_dir: Option<PathBuf>,
+    pub limits: ResourceLimits,
+    /// The least time a test may take in the VM before it times out.
+    pub min_timeout: Duration,
+    /// How many times longer than on the host a test may take in the VM before it times out.
+    pub timeout_multiplier: u32,
+    /// A `should_panic` test that times out in the VM counts as having panicked.
+    pub timeout_as_panic: bool,
+    /// Diff what each test prints natively against what it prints in the VM.
+    pub diff_output: bool,
+    /// How many ULPs apart a float in a test's native output and the same float in its VM
+    /// output may be for [`diff_output`](Self::diff_output) to consider them equal.
+    pub float_ulps: u64,
+    /// How many long-lived VM processes tests are dispatched to, or `0` to start one per test.
+    pub workers: usize,
+    /// Print a size report of each test binary built for Valida, with this many of the biggest
+    /// symbols.
+    pub size_report: Option<usize>,
+    /// By how many percent a test's cycles, or its time if the VM has no cycle counter, may grow
+    /// from its previous run in the VM before it is reported, see [`super::timings`].
+    pub regression_threshold: f64,
+    /// The cargo arguments the VM tests are built with, instead of those of `cargo test`.
+    pub cargo_args: Option<Vec<String>>,
+    /// The `valida` executable the tests run in.
+    pub valida_command: PathBuf,
+    /// Where the Valida toolchain is installed, if not in the default location.
+    pub toolchain_dir: Option<PathBuf>,
+}
+
+impl Default for ValidaTestConfig {
+    fn default() -> Self {
+        Self {
+            run_on_valida: false,
+            retries: 0,
+            fail_fast: false,
+            shard: None,
+            replay: None,
+            json_report: None,
+            json_events: None,
+            junit_report: None,
+            artifact_dir: None,
+            limits: ResourceLimits::default(),
+            min_timeout: Duration::from_secs(10),
+            timeout_multiplier: 20,
+            timeout_as_panic: false,
+            diff_output: false,
+            float_ulps: crate::float::DEFAULT_MAX_ULPS,
+            workers: 0,
+            size_report: None,
+            regression_threshold: 25.0,
+            cargo_args: None,
+            valida_command: PathBuf::from(crate::host::DEFAULT_VALIDA_COMMAND),
+            toolchain_dir: None,
+        }
+    }
+}
+
+/// The `[package.metadata.valida]` section of a manifest.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct ManifestSection {
+    test: Option<bool>,
+    retries: Option<u32>,
+    fail_fast: Option<bool>,
+    json_report: Option<PathBuf>,
+    json_events: Option<PathBuf>,
+    junit_report: Option<PathBuf>,
+    artifact_dir: Option<PathBuf>,
+    memory_limit: Option<String>,
+    time_limit: Option<f64>,
+    min_timeout: Option<f64>,
+    timeout_multiplier: Option<u32>,
+    timeout_as_panic: Option<bool>,
+    diff_output: Option<bool>,
+    float_ulps: Option<u64>,
+    workers: Option<usize>,
+    size_report: Option<usize>,
+    regression_threshold: Option<f64>,
+    cargo_args: Option<Vec<String>>,
+    valida_command: Option<PathBuf>,
+    toolchain_dir: Option<PathBuf>,
+}
+
+/// The configuration [`ValidaTestConfig::get`] returns, once loaded or installed.
+static CONFIG: Mutex<Option<&'static ValidaTestConfig>> = Mutex::new(None);
+
+impl ValidaTestConfig {
+    /// The configuration of the test binary, loaded on first use unless one was given to
+    /// [`run_all`](super::run_all).
+    pub fn get() -> &'static Self {
+        CONFIG
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| Box::leak(Box::new(Self::from_env_and_file())))
+    }
+
+    /// Use `self` as the configuration of the test binary from now on.
+    ///
+    /// The previous configuration is leaked, since it may still be borrowed; it is small and
+    /// replaced at most once per run.
+    pub(super) fn install(self) {
+        *CONFIG.lock().unwrap() = Some(Box::leak(Box::new(self)));
+    }
+
+    /// Read the `[package.metadata.valida]` section of the manifest of the crate under test, then
+    /// apply the environment variables.
+    ///
+    /// A toolchain directory from the manifest is exported as `VALIDA_TOOLCHAIN_DIR` unless that
+    /// is already set, so the guest build helpers in [`crate::build`] use it.
+    ///
+    /// # Panics
+    /// If the manifest section or a variable is invalid.
+    pub fn from_env_and_file() -> Self {
+        let mut config = Self::default();
+        if let Some(dir) = env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from) {
+            let manifest = dir.join("Cargo.toml");
+            if let Ok(contents) = std::fs::read_to_string(&manifest) {
+                config
+                    .apply_manifest(&contents, &dir)
+                    .unwrap_or_else(|e| panic!("Invalid {}: {e}", manifest.display()));
+            }
+        }
+        config.apply_env();
+
+        if let Some(dir) = &config.toolchain_dir {
+            if env::var_os(crate::build::TOOLCHAIN_DIR_ENV).is_none() {
+                env::set_var(crate::build::TOOLCHAIN_DIR_ENV, dir);
+            }
+        }
+        config
+    }
+
+    /// Apply the `[package.metadata.valida]` section of `manifest`, a crate in `dir`.
+    pub fn apply_manifest(&mut self, manifest: &str, dir: &Path) -> Result<(), String> {
+        let manifest: toml::Value = toml::from_str(manifest).map_err(|e| e.to_string())?;
+        let Some(section) = manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("valida"))
+        else {
+            return Ok(());
+        };
+        let section = ManifestSection::deserialize(section.clone())
+            .map_err(|e| format!("[package.metadata.valida]: {e}"))?;
+
+        let seconds = |secs: f64| {
+            Duration::try_from_secs_f64(secs).map_err(|_| format!("invalid duration {secs}"))
+        };
+        if let Some(test) = section.test {
+            self.run_on_valida = test;
+        }
+        if let Some(retries) = section.retries {
+            self.retries = retries;
+        }
+        if let Some(fail_fast) = section.fail_fast {
+            self.fail_fast = fail_fast;
+        }
+        if let Some(path) = section.json_report {
+            self.json_report = Some(dir.join(path));
+        }
+        if let Some(path) = section.json_events {
+            self.json_events = Some(dir.join(path));
+        }
+        if let Some(path) = section.junit_report {
+            self.junit_report = Some(dir.join(path));
+        }
+        if let Some(path) = section.artifact_dir {
+            self.artifact_dir = Some(dir.join(path));
+        }
+        if let Some(limit) = section.memory_limit {
+            self.limits.memory =
+                Some(parse_byte_size(&limit).ok_or(format!("invalid memory limit {limit:?}"))?);
+        }
+        if let Some(secs) = section.time_limit {
+            self.limits.wall_clock = Some(seconds(secs)?);
+        }
+        if let Some(secs) = section.min_timeout {
+            self.min_timeout = seconds(secs)?;
+        }
+        if let Some(multiplier) = section.timeout_multiplier {
+            self.timeout_multiplier = multiplier;
+        }
+        if let Some(timeout_as_panic) = section.timeout_as_panic {
+            self.timeout_as_panic = timeout_as_panic;
+        }
+        if let Some(diff_output) = section.diff_output {
+            self.diff_output = diff_output;
+        }
+        if let Some(ulps) = section.float_ulps {
+            self.float_ulps = ulps;
+        }
+        if let Some(workers) = section.workers {
+            self.workers = workers;
+        }
+        if let Some(top) = section.size_report {
+            self.size_report = Some(top);
+        }
+        if let Some(percent) = section.regression_threshold {
+            self.regression_threshold = percent;
+        }
+        if let Some(args) = section.cargo_args {
+            self.cargo_args = Some(args);
+        }
+        if let Some(command) = section.valida_command {
+            // A bare command name is looked up in `$PATH`, not in the crate.
+            self.valida_command = if command.components().count() > 1 {
+                dir.join(command)
+            } else {
+                command
+            };
+        }
+        if let Some(toolchain_dir) = section.toolchain_dir {
+            self.toolchain_dir = Some(dir.join(toolchain_dir));
+        }
+        Ok(())
+    }
+
+    /// Override the settings whose environment variables are set.
+    ///
+    /// # Panics
+    /// If a variable is set to an invalid value.
+    pub fn apply_env(&mut self) {
+        if let Some(test) = env_bool(VALIDA_TEST_ENV) {
+            self.run_on_valida = test;
+        }
+        if let Ok(retries) = env::var(VALIDA_TEST_RETRIES_ENV) {
+            self.retries = retries.trim().parse().unwrap_or(0);
+        }
+        if let Some(fail_fast) = env_bool(VALIDA_TEST_FAIL_FAST_ENV) {
+            self.fail_fast = fail_fast;
+        }
+        self.shard = Shard::from_env();
+        self.replay = env::var(VALIDA_TEST_REPLAY_ENV).ok();
+        if let Some(path) = env::var_os(VALIDA_TEST_JSON_ENV) {
+            self.json_report = Some(PathBuf::from(path));
+        }
+        if let Some(path) = env::var_os(VALIDA_TEST_JSON_EVENTS_ENV) {
+            self.json_events = Some(PathBuf::from(path));
+        }
+        if let Some(path) = env::var_os(VALIDA_TEST_JUNIT_ENV) {
+            self.junit_report = Some(PathBuf::from(path));
+        }
+        if let Some(path) = env::var_os(VALIDA_TEST_ARTIFACTS_ENV) {
+            self.artifact_dir = Some(PathBuf::from(path));
+        }
+        let limits = ResourceLimits::from_env();
+        self.limits.memory = limits.memory.or(self.limits.memory);
+        self.limits.wall_clock = limits.wall_clock.or(self.limits.wall_clock);
+        if let Ok(value) = env::var(VALIDA_TEST_MIN_TIMEOUT_ENV) {
+            self.min_timeout = value
+                .trim()
+                .parse()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .unwrap_or_else(|| panic!("Invalid {VALIDA_TEST_MIN_TIMEOUT_ENV}: {value:?}"));
+        }
+        if let Ok(value) = env::var(VALIDA_TEST_TIMEOUT_MULTIPLIER_ENV) {
+            self.timeout_multiplier = value.trim().parse().unwrap_or_else(|_| {
+                panic!("Invalid {VALIDA_TEST_TIMEOUT_MULTIPLIER_ENV}: {value:?}")
+            });
+        }
+        if let Some(timeout_as_panic) = env_bool(VALIDA_TEST_TIMEOUT_AS_PANIC_ENV) {
+            self.timeout_as_panic = timeout_as_panic;
+        }
+        if let Some(diff_output) = env_bool(VALIDA_TEST_DIFF_OUTPUT_ENV) {
+            self.diff_output = diff_output;
+        }
+        if let Ok(value) = env::var(VALIDA_TEST_FLOAT_ULPS_ENV) {
+            self.float_ulps = value
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid {VALIDA_TEST_FLOAT_ULPS_ENV}: {value:?}"));
+        }
+        if let Ok(value) = env::var(VALIDA_TEST_WORKERS_ENV) {
+            self.workers = value
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid {VALIDA_TEST_WORKERS_ENV}: {value:?}"));
+        }
+        if let Ok(value) = env::var(VALIDA_TEST_SIZE_REPORT_ENV) {
+            self.size_report =
+                Some(value.trim().parse().unwrap_or_else(|_| {
+                    panic!("Invalid {VALIDA_TEST_SIZE_REPORT_ENV}: {value:?}")
+                }));
+        }
+        if let Ok(value) = env::var(VALIDA_TEST_REGRESSION_THRESHOLD_ENV) {
+            self.regression_threshold = value.trim().parse().unwrap_or_else(|_| {
+                panic!("Invalid {VALIDA_TEST_REGRESSION_THRESHOLD_ENV}: {value:?}")
+            });
+        }
+        if let Ok(args) = env::var(VALIDA_TEST_CARGO_ARGS_ENV) {
+            self.cargo_args = Some(args.split_whitespace().map(str::to_string).collect());
+        }
+        if let Some(command) = env::var_os(VALIDA_COMMAND_ENV) {
+            self.valida_command = PathBuf::from(command);
+        }
+        if let Some(dir) = env::var_os(crate::build::TOOLCHAIN_DIR_ENV) {
+            self.toolchain_dir = Some(PathBuf::from(dir));
+        }
+    }
+
+    /// How long a test that took `host_test_time` on the host may take in the VM.
+    pub fn vm_timeout(&self, host_test_time: Duration) -> Duration {
+        std::cmp::max(host_test_time * self.timeout_multiplier, self.min_timeout)
+    }
+}
+
+/// Whether the environment variable is set to a truthy value such as `1` or `true`, or `None` if
+/// it is not set.
+fn env_bool(name: &str) -> Option<bool> {
+    let value = env::var(name).ok()?.to_lowercase();
+    Some(matches!(value.as_str(), "1" | "true" | "yes" | "on"))
+}
+
+#[test]
+fn test_manifest_section() {
+    let manifest = r#"
+        [package]
+        name = "guest"
+
+        [package.metadata.valida]
+        test = true
+        retries = 3
+        json-report = "target/failures.jsonl"
+        memory-limit = "512M"
+        min-timeout = 2.5
+        workers = 4
+        float-ulps = 0
+        regression-threshold = 10.5
+        cargo-args = ["--features", "slow"]
+        valida-command = "bin/valida"
+    "#;
+    let mut config = ValidaTestConfig::default();
+    config
+        .apply_manifest(manifest, Path::new("/crate"))
+        .unwrap();
+
+    assert!(config.run_on_valida);
+    assert_eq!(config.retries, 3);
+    assert_eq!(
+        config.json_report.as_deref(),
+        Some(Path::new("/crate/target/failures.jsonl"))
+    );
+    assert_eq!(config.limits.memory, Some(512 << 20));
+    assert_eq!(config.workers, 4);
+    assert_eq!(config.float_ulps, 0);
+    assert_eq!(config.regression_threshold, 10.5);
+    assert_eq!(
+        config.vm_timeout(Duration::from_millis(10)),
+        Duration::from_millis(2500)
+    );
+    assert_eq!(
+        config.vm_timeout(Duration::from_secs(1)),
+        Duration::from_secs(20)
+    );
+    assert_eq!(config.cargo_args.unwrap(), ["--features", "slow"]);
+    assert_eq!(config.valida_command, Path::new("/crate/bin/valida"));
+
+    let mut config = ValidaTestConfig::default();
+    let unknown = "[package.metadata.valida]\nretry = 1\n";
+    assert!(config.apply_manifest(unknown, Path::new("/crate")).is_err());
+}
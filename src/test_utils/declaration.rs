@@ -0,0 +1,69 @@
+//! The tests that declare something about other tests rather than test anything, which the
+//! runner runs to collect what they declare.
+
+use std::sync::Mutex;
+
+use test::{TestDescAndFn, TestFn};
+
+/// A kind of declaration test: the tests a macro declares in a module, which push pairs naming
+/// tests relative to that module.
+pub(super) struct Declaration {
+    /// The name of the test the macro declares.
+    name: &'static str,
+    /// Where the declaration test pushes its pairs.
+    declared: &'static Mutex<Vec<(String, String)>>,
+}
+
+/// The tests [`valida_test_order!`](crate::valida_test_order) declares.
+pub(super) static ORDER: Declaration = Declaration {
+    name: "valida_test_order",
+    declared: &super::DECLARED_ORDER,
+};
+
+/// The tests [`valida_expect_output!`](crate::valida_expect_output) declares.
+pub(super) static OUTPUT: Declaration = Declaration {
+    name: "valida_expect_output",
+    declared: &super::DECLARED_OUTPUT,
+};
+
+impl Declaration {
+    /// Run the declaration tests of this kind among `tests`, returning the pairs each declared
+    /// with the path of its module, which ends in `::` unless it is the crate root.
+    pub(super) fn collect(&self, tests: &[&TestDescAndFn]) -> Vec<(String, (String, String))> {
+        let mut pairs = Vec::new();
+        for t in tests {
+            let name = t.desc.name.as_slice();
+            let (TestFn::StaticTestFn(declare), true) = (&t.testfn, self.matches(name)) else {
+                continue;
+            };
+            self.declared.lock().unwrap().clear();
+            let _ = declare();
+            let module = name.strip_suffix(self.name).unwrap_or_default();
+            pairs.extend(
+                std::mem::take(&mut *self.declared.lock().unwrap())
+                    .into_iter()
+                    .map(|pair| (module.to_string(), pair)),
+            );
+        }
+        pairs
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        name.rsplit("::").next() == Some(self.name)
+    }
+}
+
+/// Returns `true` for the declaration tests, which the runner runs to collect what they declare
+/// rather than as tests.
+pub(super) fn is_declaration(name: &str) -> bool {
+    [&ORDER, &OUTPUT].iter().any(|kind| kind.matches(name))
+}
+
+#[test]
+fn test_is_declaration() {
+    assert!(is_declaration("fs::valida_test_order"));
+    assert!(is_declaration("valida_test_order"));
+    assert!(is_declaration("tx::valida_expect_output"));
+    assert!(!is_declaration("fs::valida_test_order_check"));
+    assert!(!is_declaration("valida_test_order::read"));
+}
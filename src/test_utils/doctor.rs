@@ -0,0 +1,83 @@
+//! Diagnosing a missing or incomplete Valida installation.
+
+use std::{fmt, process::Command};
+
+use super::ValidaTestConfig;
+use crate::build::TOOLCHAIN_DIR_ENV;
+
+/// A part of the Valida setup that [`check_environment`] found missing, and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvironmentProblem {
+    /// What is missing.
+    pub missing: String,
+    /// How to install or point the runner at it.
+    pub fix: String,
+}
+
+impl fmt::Display for EnvironmentProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "missing {}\n    fix: {}", self.missing, self.fix)
+    }
+}
+
+/// Check that everything needed to build and run tests for Valida is installed: the `valida`
+/// rustup toolchain, the linker, libc and the other files of the Valida toolchain, and the
+/// `valida` command. Each problem found is printed with how to fix it, and returned.
+///
+/// The test runner calls this when building or running the tests for Valida fails.
+pub fn check_environment() -> Vec<EnvironmentProblem> {
+    let mut problems = Vec::new();
+    let install = "install valida-toolchain v0.7.0-alpha or newer";
+
+    let rustup_toolchain = Command::new("rustup")
+        .args(["run", "valida", "rustc", "--version"])
+        .output();
+    if !rustup_toolchain.is_ok_and(|output| output.status.success()) {
+        problems.push(EnvironmentProblem {
+            missing: "the `valida` rustup toolchain".to_string(),
+            fix: format!(
+                "{install}, then link its Rust toolchain with \
+                 `rustup toolchain link valida <toolchain dir>`"
+            ),
+        });
+    }
+
+    let toolchain = crate::build::toolchain_dir();
+    let triple = crate::target::target_triple();
+    if !toolchain.is_dir() {
+        problems.push(EnvironmentProblem {
+            missing: format!("the Valida toolchain in {}", toolchain.display()),
+            fix: format!("{install}, or set {TOOLCHAIN_DIR_ENV} to where it is installed"),
+        });
+    } else {
+        for (what, path) in crate::build::toolchain_files(&triple) {
+            if !path.is_file() {
+                problems.push(EnvironmentProblem {
+                    missing: format!("{what} for {triple} at {}", path.display()),
+                    fix: format!(
+                        "reinstall valida-toolchain in {}, or set {TOOLCHAIN_DIR_ENV} to a \
+                         complete installation",
+                        toolchain.display()
+                    ),
+                });
+            }
+        }
+    }
+
+    if let Err(e) = crate::host::check_valida(&ValidaTestConfig::get().valida_command) {
+        problems.push(EnvironmentProblem {
+            missing: "a usable `valida` command".to_string(),
+            fix: e.to_string(),
+        });
+    }
+
+    if problems.is_empty() {
+        eprintln!("The Valida environment looks complete.");
+    } else {
+        eprintln!("The Valida environment is incomplete:");
+        for problem in &problems {
+            eprintln!("  {problem}");
+        }
+    }
+    problems
+}
@@ -0,0 +1,63 @@
+//! The output tests are declared to print in the VM with
+//! [`valida_expect_output!`](crate::valida_expect_output).
+
+use std::collections::BTreeMap;
+
+use test::TestDescAndFn;
+
+use super::ValidaTestError;
+
+/// What each test must print in the VM.
+#[derive(Debug, Default)]
+pub(super) struct ExpectedOutput {
+    /// The substrings each test's output must contain, by test name.
+    patterns: BTreeMap<String, Vec<String>>,
+}
+
+impl ExpectedOutput {
+    /// Collect the output declared by the declaration tests among `tests`, by running them.
+    pub(super) fn declared(tests: &[&TestDescAndFn]) -> Self {
+        let mut expected = Self::default();
+        for (module, (test, pattern)) in super::declaration::OUTPUT.collect(tests) {
+            expected.add(format!("{module}{test}"), pattern);
+        }
+        expected
+    }
+
+    /// Declare that the test named `test` prints `pattern` in the VM.
+    fn add(&mut self, test: String, pattern: String) {
+        self.patterns.entry(test).or_default().push(pattern);
+    }
+
+    /// Check that `output`, what `test` printed in the VM, contains each pattern declared for it,
+    /// whether it passed or panicked as expected.
+    pub(super) fn check(&self, test: &str, output: &str) -> Result<(), ValidaTestError> {
+        let mut patterns = self.patterns.get(test).into_iter().flatten();
+        match patterns.find(|pattern| !output.contains(pattern.as_str())) {
+            Some(pattern) => Err(ValidaTestError::MissingOutput {
+                expected: pattern.clone(),
+                output: output.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_expected_output() {
+    let mut expected = ExpectedOutput::default();
+    expected.add(
+        "tx::rejects_overdraft".to_string(),
+        "balance: 10".to_string(),
+    );
+    expected.add("tx::rejects_overdraft".to_string(), "rejected".to_string());
+
+    let output = "balance: 10\nrejected\nvalida-panic: {}\n";
+    assert!(expected.check("tx::rejects_overdraft", output).is_ok());
+    assert!(expected.check("tx::other", "").is_ok());
+    let err = expected
+        .check("tx::rejects_overdraft", "balance: 0\n")
+        .unwrap_err();
+    assert_eq!(err.kind(), "missing_output");
+    assert!(err.to_string().contains("'balance: 10'"));
+}
@@ -0,0 +1,166 @@
+//! Data files a test reads, which are copied to a temporary directory natively and sent along
+//! with the test's request to the VM.
+//!
+//! ```rust,no_run
+//! # fn parse(json: &[u8]) -> Block { Block { height: json.len() as u64 } }
+//! # struct Block { height: u64 }
+//! #[test]
+//! fn parses_the_block() {
+//!     let fixtures = valida_rs::test_utils::fixtures(&["tests/data/block.json"]);
+//!     let block = parse(&fixtures.read("tests/data/block.json").unwrap());
+//!     assert_eq!(block.height, 1);
+//! }
+//! ```
+//! The files are declared when the test runs natively, so tests that only run in the VM cannot
+//! use fixtures.
+
+use std::sync::Mutex;
+
+#[cfg(valida)]
+use std::collections::BTreeMap;
+
+#[cfg(not(valida))]
+use std::path::{Component, Path, PathBuf};
+
+/// The files the current test declared natively, or the host sent for it in the VM, by the path
+/// they were declared with.
+static FILES: Mutex<Vec<(String, Vec<u8>)>> = Mutex::new(Vec::new());
+
+/// The data files of a test, from [`fixtures`].
+///
+/// Natively they are copies in a temporary directory, which is removed when this is dropped.
+#[derive(Debug)]
+pub struct Fixtures {
+    #[cfg(not(valida))]
+    dir: tempfile::TempDir,
+    #[cfg(valida)]
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Declare the files at `paths`, relative to the crate's directory, as fixtures of the running
+/// test, so the test can read them natively and in the VM.
+///
+/// # Panics
+/// Natively, if a file cannot be read or copied. In the VM, if the host did not send a file,
+/// e.g. because the test only runs in the VM.
+pub fn fixtures(paths: &[&str]) -> Fixtures {
+    #[cfg(not(valida))]
+    {
+        let dir = tempfile::tempdir().expect("failed to create the fixture directory");
+        let mut files = FILES.lock().unwrap();
+        for &path in paths {
+            let contents = std::fs::read(path)
+                .unwrap_or_else(|e| panic!("failed to read the fixture {path}: {e}"));
+            let copy = dir.path().join(relative(path));
+            if let Some(parent) = copy.parent() {
+                std::fs::create_dir_all(parent)
+                    .unwrap_or_else(|e| panic!("failed to copy the fixture {path}: {e}"));
+            }
+            std::fs::write(&copy, &contents)
+                .unwrap_or_else(|e| panic!("failed to copy the fixture {path}: {e}"));
+            files.retain(|(declared, _)| declared != path);
+            files.push((path.to_string(), contents));
+        }
+        Fixtures { dir }
+    }
+
+    #[cfg(valida)]
+    {
+        let sent: BTreeMap<String, Vec<u8>> = FILES.lock().unwrap().iter().cloned().collect();
+        let files = paths
+            .iter()
+            .map(|&path| match sent.get(path) {
+                Some(contents) => (path.to_string(), contents.clone()),
+                None => panic!("the fixture {path} was not sent to the VM"),
+            })
+            .collect();
+        Fixtures { files }
+    }
+}
+
+impl Fixtures {
+    /// The contents of the fixture declared as `path`.
+    pub fn read(&self, path: &str) -> std::io::Result<Vec<u8>> {
+        #[cfg(not(valida))]
+        return std::fs::read(self.path(path));
+
+        #[cfg(valida)]
+        self.files.get(path).cloned().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{path} is not a fixture of this test"),
+            )
+        })
+    }
+
+    /// The contents of the fixture declared as `path`, as text.
+    pub fn read_to_string(&self, path: &str) -> std::io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// The directory the fixtures are copied to, with the paths they were declared with.
+    #[cfg(not(valida))]
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Where the copy of the fixture declared as `path` is.
+    #[cfg(not(valida))]
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.dir.path().join(relative(path))
+    }
+}
+
+/// `path` without its root and parent components, so it stays inside the fixture directory.
+#[cfg(not(valida))]
+fn relative(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+/// Forget the fixtures of the previous test, before the next one runs natively.
+#[cfg(not(valida))]
+pub(super) fn clear() {
+    FILES.lock().unwrap().clear();
+}
+
+/// The fixtures the last test declared natively, to send to the VM.
+#[cfg(not(valida))]
+pub(super) fn declared() -> Vec<(String, Vec<u8>)> {
+    FILES.lock().unwrap().clone()
+}
+
+/// Read the `count` fixtures the host sent after the test's mode line, each as its encoded path
+/// on a line, its length on a line and its contents, replacing those of the previous test.
+#[cfg(feature = "guest")]
+pub(super) fn receive(count: usize) {
+    let mut files = FILES.lock().unwrap();
+    files.clear();
+    for _ in 0..count {
+        let path = crate::io::read_line::<String>().unwrap_or_default();
+        let contents = crate::io::read_line::<usize>().and_then(crate::io::read_n);
+        if let Ok(contents) = contents {
+            files.push((super::decode_protocol_field(&path), contents));
+        }
+    }
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_fixtures_are_copied() {
+    clear();
+    let fixtures = fixtures(&["Cargo.toml", "src/../README.md"]);
+    assert!(fixtures.dir().join("Cargo.toml").is_file());
+    assert_eq!(
+        fixtures.read("Cargo.toml").unwrap(),
+        std::fs::read("Cargo.toml").unwrap()
+    );
+    assert!(fixtures
+        .path("src/../README.md")
+        .starts_with(fixtures.dir()));
+    let declared: Vec<String> = declared().into_iter().map(|(path, _)| path).collect();
+    assert_eq!(declared, ["Cargo.toml", "src/../README.md"]);
+}
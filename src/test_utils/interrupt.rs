@@ -0,0 +1,51 @@
+//! Stopping a test run cleanly when the user presses Ctrl-C, or after the first failure with
+//! fail-fast.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// The exit status of a run stopped by Ctrl-C, as a shell reports a process killed by SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// How long the runner has to stop its VMs and print the summary before it is exited anyway,
+/// e.g. because a native test is still running.
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Handle Ctrl-C by asking the runner to stop, see [`requested`]. A second Ctrl-C, or the runner
+/// not stopping within the grace period, exits straight away.
+///
+/// Does nothing if the process already handles Ctrl-C.
+pub(super) fn install() {
+    let _ = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        eprintln!("\ninterrupted, stopping the running tests");
+        std::thread::spawn(|| {
+            std::thread::sleep(GRACE_PERIOD);
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        });
+    });
+}
+
+/// Stop the run as Ctrl-C does, but without the grace period, e.g. after the first failure.
+pub(super) fn stop() {
+    STOPPED.store(true, Ordering::SeqCst);
+}
+
+/// Returns `true` once the user pressed Ctrl-C or the run was [`stop`]ped. VM runs then stop,
+/// which kills their processes and removes their temporary files, and the runner prints the
+/// results so far.
+pub(super) fn requested() -> bool {
+    interrupted() || STOPPED.load(Ordering::SeqCst)
+}
+
+/// Returns `true` once the user pressed Ctrl-C.
+pub(super) fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
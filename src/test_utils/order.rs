@@ -0,0 +1,132 @@
+//! The order between tests declared with [`valida_test_order!`](crate::valida_test_order).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use test::TestDescAndFn;
+
+/// Which tests must run before which.
+#[derive(Debug, Default)]
+pub(super) struct TestOrder {
+    /// The tests each test must run after.
+    prerequisites: BTreeMap<String, Vec<String>>,
+    /// The name that decides the shard of each ordered test: the first name of the tests it is
+    /// ordered with, directly or not, so they all land in the same shard.
+    shard_keys: BTreeMap<String, String>,
+}
+
+impl TestOrder {
+    /// Collect the order declared by the declaration tests among `tests`, by running them.
+    pub(super) fn declared(tests: &[&TestDescAndFn]) -> Self {
+        let mut order = Self::default();
+        for (module, (first, second)) in super::declaration::ORDER.collect(tests) {
+            order.add(format!("{module}{first}"), format!("{module}{second}"));
+        }
+        order
+    }
+
+    /// Declare that the test named `first` runs before the one named `second`.
+    fn add(&mut self, first: String, second: String) {
+        let first_key = self.shard_key(&first).to_string();
+        let second_key = self.shard_key(&second).to_string();
+        let key = first_key.clone().min(second_key.clone());
+        for test in self.shard_keys.values_mut() {
+            if *test == first_key || *test == second_key {
+                test.clone_from(&key);
+            }
+        }
+        self.shard_keys.insert(first.clone(), key.clone());
+        self.shard_keys.insert(second.clone(), key);
+        self.prerequisites.entry(second).or_default().push(first);
+    }
+
+    /// The tests `test` must run after.
+    pub(super) fn prerequisites<'a>(&'a self, test: &str) -> impl Iterator<Item = &'a str> {
+        self.prerequisites
+            .get(test)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// The name [`Shard::contains`](super::Shard::contains) is given for `test`.
+    pub(super) fn shard_key<'a>(&'a self, test: &'a str) -> &'a str {
+        self.shard_keys.get(test).map_or(test, String::as_str)
+    }
+
+    /// Reorder `tests` so each runs after its prerequisites, keeping the order otherwise.
+    pub(super) fn sort(&self, tests: &mut Vec<&&TestDescAndFn>) {
+        let names: Vec<&str> = tests.iter().map(|t| t.desc.name.as_slice()).collect();
+        *tests = self.sorted(&names).into_iter().map(|i| tests[i]).collect();
+    }
+
+    /// The indices of `names` in an order that respects the declared one, taking the earliest
+    /// test whose prerequisites have run each time. Tests in a cycle keep their order at the end.
+    fn sorted(&self, names: &[&str]) -> Vec<usize> {
+        let index: HashMap<&str, usize> = names.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+        let mut waiting_for = vec![0; names.len()];
+        let mut unblocks = vec![Vec::new(); names.len()];
+        for (i, name) in names.iter().enumerate() {
+            for prerequisite in self.prerequisites(name) {
+                if let Some(&before) = index.get(prerequisite) {
+                    unblocks[before].push(i);
+                    waiting_for[i] += 1;
+                }
+            }
+        }
+
+        let mut ready: BTreeSet<usize> =
+            (0..names.len()).filter(|&i| waiting_for[i] == 0).collect();
+        let mut sorted = Vec::with_capacity(names.len());
+        while let Some(i) = ready.pop_first() {
+            sorted.push(i);
+            for &next in &unblocks[i] {
+                waiting_for[next] -= 1;
+                if waiting_for[next] == 0 {
+                    ready.insert(next);
+                }
+            }
+        }
+        if sorted.len() < names.len() {
+            let cycle: Vec<usize> = (0..names.len()).filter(|&i| waiting_for[i] > 0).collect();
+            let cycle_names: Vec<&str> = cycle.iter().map(|&i| names[i]).collect();
+            eprintln!(
+                "warning: the declared test order has a cycle between {}",
+                cycle_names.join(", ")
+            );
+            sorted.extend(cycle);
+        }
+        sorted
+    }
+}
+
+#[test]
+fn test_declared_order() {
+    let mut order = TestOrder::default();
+    order.add("fs::setup".to_string(), "fs::read".to_string());
+    order.add("fs::read".to_string(), "fs::cleanup".to_string());
+    order.add("net::b".to_string(), "net::a".to_string());
+
+    let names = [
+        "fs::cleanup",
+        "fs::read",
+        "net::a",
+        "other",
+        "fs::setup",
+        "net::b",
+    ];
+    let sorted: Vec<&str> = order.sorted(&names).into_iter().map(|i| names[i]).collect();
+    assert_eq!(
+        sorted,
+        [
+            "other",
+            "fs::setup",
+            "fs::read",
+            "fs::cleanup",
+            "net::b",
+            "net::a"
+        ]
+    );
+    assert_eq!(order.shard_key("fs::read"), "fs::cleanup");
+    assert_eq!(order.shard_key("fs::setup"), "fs::cleanup");
+    assert_eq!(order.shard_key("other"), "other");
+}
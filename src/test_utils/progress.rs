@@ -0,0 +1,120 @@
+//! The progress of the Valida test phase, for suites with many VM runs.
+
+use std::{
+    io::{IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+/// How wide the bar is, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// The completed VM runs out of the expected ones, with an estimate of the time left.
+///
+/// With the `progress` feature and stderr on a terminal, a bar is kept on the last line of the
+/// terminal, cleared while the runner prints and redrawn after each VM run. When stderr is not a
+/// terminal, a plain line is printed each time another tenth of the runs completes. Without the
+/// feature, nothing is printed.
+#[derive(Debug)]
+pub(super) struct Progress {
+    enabled: bool,
+    terminal: bool,
+    total: usize,
+    done: usize,
+    /// The last test sent to the VM.
+    current: Option<String>,
+    start: Instant,
+    drawn: bool,
+}
+
+impl Progress {
+    /// Track `total` VM runs.
+    pub(super) fn new(total: usize) -> Self {
+        Self {
+            enabled: cfg!(feature = "progress") && total > 0,
+            terminal: std::io::stderr().is_terminal(),
+            total,
+            done: 0,
+            current: None,
+            start: Instant::now(),
+            drawn: false,
+        }
+    }
+
+    /// Note that `test` was sent to the VM.
+    pub(super) fn running(&mut self, test: &str) {
+        self.current = Some(test.to_string());
+    }
+
+    /// Note that a VM run completed.
+    pub(super) fn finished(&mut self) {
+        self.done += 1;
+        let tenths = |done: usize| done * 10 / self.total.max(1);
+        if self.enabled && !self.terminal && tenths(self.done) > tenths(self.done - 1) {
+            eprintln!("valida progress: {}", self.status());
+        }
+    }
+
+    /// Note that a test expected to run in the VM will not, e.g. because it failed natively.
+    pub(super) fn skipped(&mut self) {
+        self.total = self.total.saturating_sub(1).max(self.done);
+    }
+
+    /// Remove the bar, before the runner prints.
+    pub(super) fn clear(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[2K");
+            self.drawn = false;
+        }
+    }
+
+    /// Draw the bar on the current line, which must be empty.
+    pub(super) fn draw(&mut self) {
+        if !self.enabled || !self.terminal || self.done >= self.total {
+            return;
+        }
+        let filled = BAR_WIDTH * self.done / self.total;
+        let current = self
+            .current
+            .as_deref()
+            .map(|test| format!(" {test}"))
+            .unwrap_or_default();
+        eprint!(
+            "[{}{}] {}{current}",
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            self.status()
+        );
+        let _ = std::io::stderr().flush();
+        self.drawn = true;
+    }
+
+    /// `done/total`, the elapsed time and the estimated time left.
+    fn status(&self) -> String {
+        let elapsed = self.start.elapsed();
+        let eta = match self.done {
+            0 => "?".to_string(),
+            done => format_duration(elapsed / done as u32 * self.total.saturating_sub(done) as u32),
+        };
+        format!(
+            "{}/{} in valida, {} elapsed, {eta} left",
+            self.done,
+            self.total,
+            format_duration(elapsed)
+        )
+    }
+}
+
+/// `duration` as minutes and seconds, e.g. `3:07`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[test]
+fn test_progress_status() {
+    let mut progress = Progress::new(4);
+    progress.finished();
+    progress.skipped();
+    assert!(progress.status().starts_with("1/3 in valida, 0:00 elapsed"));
+    assert_eq!(format_duration(Duration::from_secs(187)), "3:07");
+}
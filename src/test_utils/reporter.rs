@@ -0,0 +1,674 @@
+//! Where the host test runner reports what it runs, see [`TestReporter`].
+
+use std::{collections::BTreeMap, fmt, io::Write, time::Duration};
+
+use serde::{Serialize, Serializer};
+
+use super::Shard;
+
+/// Receives the results of the host test runner as it goes, e.g. to print them or to feed a
+/// dashboard.
+///
+/// The runner always reports to a [`ConsoleReporter`], and to a [`JsonReporter`] and a
+/// [`JunitReporter`] when their files are configured. To add another, set a runner that passes
+/// it to [`test_runner_with_reporter`](super::test_runner_with_reporter):
+/// ```rust
+/// #![feature(custom_test_frameworks, test)]
+/// #![test_runner(crate::runner)]
+/// # extern crate test;
+/// # use valida_rs::test_utils::{TestReporter, TestResult};
+/// # struct Dashboard;
+/// # impl Dashboard { fn connect() -> Self { Dashboard } }
+/// # impl TestReporter for Dashboard { fn test_finished(&mut self, _: &TestResult) {} }
+///
+/// fn runner(tests: &[&test::TestDescAndFn]) {
+///     valida_rs::test_utils::test_runner_with_reporter(tests, Dashboard::connect());
+/// }
+/// # fn main() {}
+/// ```
+/// Only the host calls the reporter; in the VM the same runner runs the requested test.
+pub trait TestReporter {
+    /// The run starts with `tests` tests, after filtering and sharding.
+    fn run_started(&mut self, tests: usize, filter: Option<&str>, shard: Option<Shard>) {
+        let _ = (tests, filter, shard);
+    }
+
+    /// `test` starts running in `phase`. Tests run in the VM in parallel are reported as they
+    /// finish, just before [`test_finished`](Self::test_finished).
+    fn test_started(&mut self, test: &str, phase: Phase) {
+        let _ = (test, phase);
+    }
+
+    /// A test finished, or was ignored or skipped, in one phase.
+    fn test_finished(&mut self, result: &TestResult);
+
+    /// The run ended, or could not start.
+    fn run_finished(&mut self, summary: &RunSummary) {
+        let _ = summary;
+    }
+}
+
+/// Where a test ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Native,
+    Valida,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Native => "native",
+            Phase::Valida => "valida",
+        })
+    }
+}
+
+/// How a test ended in one phase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TestStatus {
+    Passed,
+    /// The test passed in the VM on its `attempt`th retry.
+    Flaky {
+        attempt: u32,
+    },
+    /// The test failed, with the [`ValidaTestError`](super::ValidaTestError) kind in the VM.
+    Failed {
+        message: String,
+        kind: Option<&'static str>,
+    },
+    /// The test is ignored, with the reason given in `#[ignore = "..."]`.
+    Ignored {
+        reason: Option<&'static str>,
+    },
+    /// The test does not run in this phase, see [`TestEnvironment`](super::TestEnvironment).
+    Skipped {
+        reason: &'static str,
+    },
+    /// The test is a kind the runner cannot run.
+    Unsupported,
+    /// The run was interrupted while the test ran.
+    Interrupted,
+}
+
+/// The result of a test in one phase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    pub name: String,
+    pub phase: Phase,
+    pub status: TestStatus,
+    /// How long the test took, if it ran.
+    pub duration: Option<Duration>,
+    /// The cycles the test took in the VM, if it passed and the VM has a cycle counter.
+    pub cycles: Option<u64>,
+    /// What the test printed, if it was captured.
+    pub output: Option<String>,
+}
+
+/// How many tests passed, failed and were skipped in one phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PhaseCounts {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+/// The totals of a run, as in its last lines.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RunSummary {
+    /// The number of tests selected.
+    pub tests: usize,
+    /// The filter no test matched, if none did.
+    pub unmatched_filter: Option<String>,
+    #[serde(skip)]
+    pub shard: Option<Shard>,
+    pub interrupted: bool,
+    /// Why the run could not start, e.g. because the `valida` command is missing.
+    pub error: Option<String>,
+    /// The run stopped at the first failure, with fail-fast.
+    pub stopped_early: bool,
+    pub native: PhaseCounts,
+    pub valida: PhaseCounts,
+    /// The number of VM failures of each [`ValidaTestError`](super::ValidaTestError) kind.
+    pub valida_failure_kinds: BTreeMap<&'static str, usize>,
+    /// The tests that passed in the VM on a retry, included in `valida.passed`.
+    pub flaky: usize,
+    /// The tests whose VM output differed from their native output, if outputs were compared.
+    pub diverged: Option<usize>,
+    /// The tests that got slower in the VM than in their previous run.
+    pub regressed: usize,
+    pub ignored: usize,
+    /// The number of ignored tests with each reason given in `#[ignore = "..."]`.
+    pub ignore_reasons: BTreeMap<&'static str, usize>,
+    pub unsupported: usize,
+}
+
+impl RunSummary {
+    /// Returns `true` if the run started, no test failed and the run was not interrupted.
+    pub fn success(&self) -> bool {
+        self.native.failed == 0
+            && self.valida.failed == 0
+            && !self.interrupted
+            && self.error.is_none()
+    }
+}
+
+/// What happened to a test natively and in the VM, as [`run_all`](super::run_all) returns it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TestRunReport {
+    pub name: String,
+    pub native: TestStatus,
+    /// How the test ended in the VM, or `None` if it did not get there, e.g. because the VM
+    /// phase is disabled or the test failed natively.
+    pub valida: Option<TestStatus>,
+    pub durations: PhaseDurations,
+    /// The cycles the test took in the VM, if it passed and the VM has a cycle counter.
+    pub cycles: Option<u64>,
+    pub output: TestOutput,
+}
+
+/// How long a test took in each phase it ran in, serialized in seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PhaseDurations {
+    #[serde(serialize_with = "seconds")]
+    pub native: Option<Duration>,
+    #[serde(serialize_with = "seconds")]
+    pub valida: Option<Duration>,
+}
+
+/// What a test printed in each phase, when the runner captured it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TestOutput {
+    pub native: Option<String>,
+    pub valida: Option<String>,
+}
+
+/// The report of each test of a run, in the order they ran natively, and the run's totals.
+///
+/// It collects them as a [`TestReporter`], so it can also be passed to
+/// [`test_runner_with_reporter`](super::test_runner_with_reporter) by reference.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SuiteReport {
+    pub tests: Vec<TestRunReport>,
+    pub summary: RunSummary,
+}
+
+impl SuiteReport {
+    /// Returns `true` if the run started, no test failed and the run was not interrupted.
+    pub fn success(&self) -> bool {
+        self.summary.success()
+    }
+
+    /// The exit status of a test binary whose run this was: 0 on success,
+    /// [`INTERRUPTED_EXIT_CODE`](super::INTERRUPTED_EXIT_CODE) if it was interrupted and 1
+    /// otherwise.
+    #[cfg(not(valida))]
+    pub fn exit_code(&self) -> i32 {
+        match (self.success(), self.summary.interrupted) {
+            (true, _) => 0,
+            (false, true) => super::INTERRUPTED_EXIT_CODE,
+            (false, false) => 1,
+        }
+    }
+
+    /// The report of the test named `name`, if it was selected.
+    pub fn test(&self, name: &str) -> Option<&TestRunReport> {
+        self.tests.iter().find(|t| t.name == name)
+    }
+}
+
+impl TestReporter for SuiteReport {
+    fn test_finished(&mut self, result: &TestResult) {
+        match result.phase {
+            Phase::Native => self.tests.push(TestRunReport {
+                name: result.name.clone(),
+                native: result.status.clone(),
+                valida: None,
+                durations: PhaseDurations {
+                    native: result.duration,
+                    valida: None,
+                },
+                cycles: None,
+                output: TestOutput {
+                    native: result.output.clone(),
+                    valida: None,
+                },
+            }),
+            // The runner reports each test natively before it reports it in the VM.
+            Phase::Valida => {
+                if let Some(report) = self.tests.iter_mut().rfind(|t| t.name == result.name) {
+                    report.valida = Some(result.status.clone());
+                    report.durations.valida = result.duration;
+                    report.cycles = result.cycles;
+                    report.output.valida.clone_from(&result.output);
+                }
+            }
+        }
+    }
+
+    fn run_finished(&mut self, summary: &RunSummary) {
+        self.summary = summary.clone();
+    }
+}
+
+/// Serialize `duration` as seconds.
+fn seconds<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    duration.map(|d| d.as_secs_f64()).serialize(serializer)
+}
+
+/// Reports to stdout, with failure messages on stderr, as libtest does.
+#[derive(Debug, Default)]
+pub struct ConsoleReporter;
+
+impl TestReporter for ConsoleReporter {
+    fn run_started(&mut self, tests: usize, filter: Option<&str>, shard: Option<Shard>) {
+        if let Some(f) = filter {
+            println!("Running tests matching '{}'", f);
+        }
+        let shard_note = shard.map(|s| format!(" (shard {s})")).unwrap_or_default();
+        println!("running {tests} tests{shard_note}");
+    }
+
+    fn test_started(&mut self, test: &str, phase: Phase) {
+        print!("test {test} on {phase} ... ");
+        let _ = std::io::stdout().flush();
+    }
+
+    fn test_finished(&mut self, result: &TestResult) {
+        match &result.status {
+            TestStatus::Passed => println!("ok"),
+            TestStatus::Flaky { attempt } => println!("ok (flaky, passed on retry {attempt})"),
+            TestStatus::Failed { message, kind } => {
+                match kind {
+                    Some(kind) => println!("FAILED ({kind})"),
+                    None => println!("FAILED"),
+                }
+                match result.phase {
+                    Phase::Native => eprintln!(
+                        "\ntest {} on native failure message: {message}",
+                        result.name
+                    ),
+                    Phase::Valida => {
+                        eprintln!("\n\ntest {} failure message: {message}\n\n", result.name)
+                    }
+                }
+            }
+            TestStatus::Ignored { reason: None } => println!("ignored"),
+            TestStatus::Ignored {
+                reason: Some(reason),
+            } => println!("ignored, {reason}"),
+            TestStatus::Skipped { reason } => println!("skipped ({reason})"),
+            TestStatus::Unsupported => println!("unsupported"),
+            TestStatus::Interrupted => println!("interrupted"),
+        }
+    }
+
+    fn run_finished(&mut self, summary: &RunSummary) {
+        // The runner printed why the run could not start.
+        if summary.error.is_some() {
+            return;
+        }
+        if let Some(f) = &summary.unmatched_filter {
+            println!("\nno tests matched filter '{}'", f);
+            return;
+        }
+        let shard_note = summary
+            .shard
+            .map(|s| format!(" (shard {s})"))
+            .unwrap_or_default();
+        let failure_breakdown = if summary.valida_failure_kinds.is_empty() {
+            String::new()
+        } else {
+            let kinds: Vec<String> = summary
+                .valida_failure_kinds
+                .iter()
+                .map(|(kind, count)| format!("{count} {kind}"))
+                .collect();
+            format!(" ({})", kinds.join(", "))
+        };
+        let diverged_note = summary
+            .diverged
+            .map(|diverged| format!("; {diverged} diverged from native output"))
+            .unwrap_or_default();
+        let regressed_note = match summary.regressed {
+            0 => String::new(),
+            regressed => format!("; {regressed} slower than their previous run"),
+        };
+        let (native, valida) = (summary.native, summary.valida);
+        let ignore_reasons = ignore_reasons_note(summary);
+        println!(
+            "\ntest result: {}{shard_note}\n\
+            on native:      {} passed; {} failed; {} skipped\n\
+            on valida:      {} passed; {} failed{failure_breakdown}; {} flaky; {} skipped{diverged_note}{regressed_note}\n\
+            {} ignored{ignore_reasons};\n\
+            {} unsupported\n\n",
+            match (summary.interrupted, summary.success()) {
+                (true, _) => "interrupted",
+                (false, true) => "ok",
+                (false, false) if summary.stopped_early => "FAILED (stopped at the first failure)",
+                (false, false) => "FAILED",
+            },
+            native.passed,
+            native.failed,
+            native.skipped,
+            valida.passed,
+            valida.failed,
+            summary.flaky,
+            valida.skipped,
+            summary.ignored,
+            summary.unsupported,
+        );
+    }
+}
+
+/// The ignored tests grouped by reason, e.g. ` (2 'needs toolchain X', 1 'flaky')`, or nothing if
+/// no ignored test gave a reason.
+fn ignore_reasons_note(summary: &RunSummary) -> String {
+    if summary.ignore_reasons.is_empty() {
+        return String::new();
+    }
+    let mut groups: Vec<String> = summary
+        .ignore_reasons
+        .iter()
+        .map(|(reason, count)| format!("{count} '{reason}'"))
+        .collect();
+    let without_reason = summary.ignored - summary.ignore_reasons.values().sum::<usize>();
+    if without_reason > 0 {
+        groups.push(format!("{without_reason} without a reason"));
+    }
+    format!(" ({})", groups.join(", "))
+}
+
+/// Writes each result, then the summary, as a line of JSON.
+///
+/// Results are objects with `"type": "test"`, the test's `name` and `phase`, its status tagged by
+/// `status`, and its `duration` in seconds and `cycles` if known. The summary has
+/// `"type": "summary"` and the fields of [`RunSummary`].
+#[cfg(not(valida))]
+#[derive(Debug)]
+pub struct JsonReporter<W: Write> {
+    out: W,
+}
+
+#[cfg(not(valida))]
+impl<W: Write> JsonReporter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    fn write(&mut self, record: serde_json::Value) {
+        if let Err(e) = writeln!(self.out, "{record}").and_then(|()| self.out.flush()) {
+            eprintln!("Failed to write a JSON test event: {e}");
+        }
+    }
+}
+
+#[cfg(not(valida))]
+impl<W: Write> TestReporter for JsonReporter<W> {
+    fn test_finished(&mut self, result: &TestResult) {
+        let mut record = serde_json::json!({
+            "type": "test",
+            "name": result.name,
+            "phase": result.phase,
+            "duration": result.duration.map(|d| d.as_secs_f64()),
+            "cycles": result.cycles,
+        });
+        if let serde_json::Value::Object(status) = serde_json::json!(result.status) {
+            record.as_object_mut().unwrap().extend(status);
+        }
+        self.write(record);
+    }
+
+    fn run_finished(&mut self, summary: &RunSummary) {
+        let mut record = serde_json::json!({ "type": "summary" });
+        if let serde_json::Value::Object(fields) = serde_json::json!(summary) {
+            record.as_object_mut().unwrap().extend(fields);
+        }
+        self.write(record);
+    }
+}
+
+/// Writes a JUnit XML report when the run ends, with a test suite per phase, as CI services
+/// display them.
+#[derive(Debug)]
+pub struct JunitReporter<W: Write> {
+    out: W,
+    results: Vec<TestResult>,
+}
+
+impl<W: Write> JunitReporter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            results: Vec::new(),
+        }
+    }
+
+    /// The report of the results so far.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+        for phase in [Phase::Native, Phase::Valida] {
+            let results: Vec<&TestResult> =
+                self.results.iter().filter(|r| r.phase == phase).collect();
+            let count =
+                |f: fn(&TestStatus) -> bool| results.iter().filter(|r| f(&r.status)).count();
+            let time: Duration = results.iter().filter_map(|r| r.duration).sum();
+            xml += &format!(
+                "  <testsuite name=\"{phase}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+                results.len(),
+                count(|s| matches!(s, TestStatus::Failed { .. })),
+                count(|s| !matches!(
+                    s,
+                    TestStatus::Passed | TestStatus::Flaky { .. } | TestStatus::Failed { .. }
+                )),
+                time.as_secs_f64()
+            );
+            for result in results {
+                let (class, name) = result.name.rsplit_once("::").unwrap_or(("", &result.name));
+                xml += &format!(
+                    "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                    escape_xml(class),
+                    escape_xml(name),
+                    result.duration.unwrap_or_default().as_secs_f64()
+                );
+                xml += &match &result.status {
+                    TestStatus::Passed | TestStatus::Flaky { .. } => "/>\n".to_string(),
+                    TestStatus::Failed { message, kind } => format!(
+                        ">\n      <failure message=\"{}\" type=\"{}\">{}</failure>\n    </testcase>\n",
+                        escape_xml(message.lines().next().unwrap_or_default()),
+                        kind.unwrap_or("failure"),
+                        escape_xml(message)
+                    ),
+                    TestStatus::Skipped { reason } => {
+                        format!(">\n      <skipped message=\"{reason}\"/>\n    </testcase>\n")
+                    }
+                    TestStatus::Ignored {
+                        reason: Some(reason),
+                    } => format!(
+                        ">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                        escape_xml(reason)
+                    ),
+                    TestStatus::Ignored { reason: None }
+                    | TestStatus::Unsupported
+                    | TestStatus::Interrupted => {
+                        ">\n      <skipped/>\n    </testcase>\n".to_string()
+                    }
+                };
+            }
+            xml += "  </testsuite>\n";
+        }
+        xml + "</testsuites>\n"
+    }
+}
+
+impl<W: Write> TestReporter for JunitReporter<W> {
+    fn test_finished(&mut self, result: &TestResult) {
+        self.results.push(result.clone());
+    }
+
+    fn run_finished(&mut self, _summary: &RunSummary) {
+        let xml = self.to_xml();
+        if let Err(e) = self
+            .out
+            .write_all(xml.as_bytes())
+            .and_then(|()| self.out.flush())
+        {
+            eprintln!("Failed to write the JUnit report: {e}");
+        }
+    }
+}
+
+/// Reports to each reporter in turn.
+impl TestReporter for Vec<Box<dyn TestReporter + '_>> {
+    fn run_started(&mut self, tests: usize, filter: Option<&str>, shard: Option<Shard>) {
+        for reporter in self {
+            reporter.run_started(tests, filter, shard);
+        }
+    }
+
+    fn test_started(&mut self, test: &str, phase: Phase) {
+        for reporter in self {
+            reporter.test_started(test, phase);
+        }
+    }
+
+    fn test_finished(&mut self, result: &TestResult) {
+        for reporter in self {
+            reporter.test_finished(result);
+        }
+    }
+
+    fn run_finished(&mut self, summary: &RunSummary) {
+        for reporter in self {
+            reporter.run_finished(summary);
+        }
+    }
+}
+
+impl<R: TestReporter + ?Sized> TestReporter for &mut R {
+    fn run_started(&mut self, tests: usize, filter: Option<&str>, shard: Option<Shard>) {
+        (**self).run_started(tests, filter, shard);
+    }
+
+    fn test_started(&mut self, test: &str, phase: Phase) {
+        (**self).test_started(test, phase);
+    }
+
+    fn test_finished(&mut self, result: &TestResult) {
+        (**self).test_finished(result);
+    }
+
+    fn run_finished(&mut self, summary: &RunSummary) {
+        (**self).run_finished(summary);
+    }
+}
+
+/// `text` with the characters XML gives a meaning to escaped, and those XML 1.0 does not allow,
+/// e.g. the escape of an ANSI color code, replaced with U+FFFD.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {
+                escaped.push(char::REPLACEMENT_CHARACTER)
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn test_ignore_reasons_note() {
+    let mut summary = RunSummary {
+        ignored: 3,
+        ..Default::default()
+    };
+    assert_eq!(ignore_reasons_note(&summary), "");
+    summary.ignore_reasons.insert("needs toolchain X", 2);
+    assert_eq!(
+        ignore_reasons_note(&summary),
+        " (2 'needs toolchain X', 1 without a reason)"
+    );
+}
+
+#[test]
+fn test_junit_report() {
+    let mut reporter = JunitReporter::new(Vec::new());
+    reporter.test_finished(&TestResult {
+        name: "io::tests::roundtrip".to_string(),
+        phase: Phase::Native,
+        status: TestStatus::Passed,
+        duration: Some(Duration::from_millis(1500)),
+        cycles: None,
+        output: None,
+    });
+    reporter.test_finished(&TestResult {
+        name: "io::tests::roundtrip".to_string(),
+        phase: Phase::Valida,
+        status: TestStatus::Failed {
+            message: "left <> right".to_string(),
+            kind: Some("panicked"),
+        },
+        duration: None,
+        cycles: None,
+        output: None,
+    });
+    reporter.run_finished(&RunSummary::default());
+
+    let xml = String::from_utf8(reporter.out).unwrap();
+    assert!(xml.contains(
+        "<testsuite name=\"native\" tests=\"1\" failures=\"0\" skipped=\"0\" time=\"1.500\">"
+    ));
+    assert!(xml.contains("<testcase classname=\"io::tests\" name=\"roundtrip\" time=\"1.500\"/>"));
+    assert!(xml.contains(
+        "<failure message=\"left &lt;&gt; right\" type=\"panicked\">left &lt;&gt; right</failure>"
+    ));
+    assert_eq!(
+        escape_xml("\x1b[31mred\x1b[0m\tok"),
+        "\u{fffd}[31mred\u{fffd}[0m\tok"
+    );
+}
+
+#[cfg(not(valida))]
+#[test]
+fn test_suite_report() {
+    let result = |phase, status, output: &str| TestResult {
+        name: "io::tests::roundtrip".to_string(),
+        phase,
+        status,
+        duration: Some(Duration::from_millis(250)),
+        cycles: (phase == Phase::Valida).then_some(1234),
+        output: Some(output.to_string()),
+    };
+    let mut report = SuiteReport::default();
+    report.test_finished(&result(Phase::Native, TestStatus::Passed, "native"));
+    report.test_finished(&result(
+        Phase::Valida,
+        TestStatus::Flaky { attempt: 1 },
+        "vm",
+    ));
+    report.run_finished(&RunSummary {
+        tests: 1,
+        ..Default::default()
+    });
+
+    let test = report.test("io::tests::roundtrip").unwrap();
+    assert_eq!(test.valida, Some(TestStatus::Flaky { attempt: 1 }));
+    assert_eq!(test.output.valida.as_deref(), Some("vm"));
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["tests"][0]["durations"]["valida"], 0.25);
+    assert_eq!(json["tests"][0]["native"]["status"], "passed");
+    assert_eq!(json["summary"]["tests"], 1);
+    assert_eq!(report.exit_code(), 0);
+    report.summary.error = Some("valida not found".to_string());
+    assert_eq!(report.exit_code(), 1);
+}
@@ -0,0 +1,109 @@
+//! The durations and cycle counts of past test runs, kept to spot tests that got slower.
+
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+/// The file in the target directory the timings are kept in.
+pub(super) const TIMINGS_FILE: &str = "valida-test-times.json";
+
+/// Durations shorter than this vary too much between runs to compare.
+const MIN_COMPARED_DURATION: Duration = Duration::from_millis(100);
+
+/// How long a test took natively and in the VM the last time it passed in the VM.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(super) struct TestTiming {
+    /// Seconds natively.
+    pub native: Option<f64>,
+    /// Seconds in the VM.
+    pub valida: Option<f64>,
+    /// Cycles in the VM, if it has a cycle counter.
+    pub cycles: Option<u64>,
+}
+
+/// The timings of each test by name, read from and saved to [`TIMINGS_FILE`].
+#[derive(Debug, Default)]
+pub(super) struct Timings {
+    path: PathBuf,
+    tests: BTreeMap<String, TestTiming>,
+}
+
+impl Timings {
+    /// Read the timings of previous runs; a missing or unreadable file starts afresh.
+    pub(super) fn load() -> Self {
+        let path = super::cargo_target_dir().join(TIMINGS_FILE);
+        let tests = std::fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+        Self { path, tests }
+    }
+
+    /// Record `timing` for `test`, returning how it regressed from the previous run by more
+    /// than `threshold` percent, if it did.
+    pub(super) fn record(
+        &mut self,
+        test: &str,
+        timing: TestTiming,
+        threshold: f64,
+    ) -> Option<String> {
+        let regression = self
+            .tests
+            .get(test)
+            .and_then(|previous| regression(previous, &timing, threshold));
+        self.tests.insert(test.to_string(), timing);
+        regression
+    }
+
+    /// Save the timings, including those of tests that did not run this time.
+    pub(super) fn save(&self) {
+        let json = serde_json::to_vec_pretty(&self.tests).expect("timings can always be encoded");
+        if let Err(e) = std::fs::write(&self.path, json) {
+            eprintln!(
+                "Failed to save test timings to {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// How `current` is more than `threshold` percent slower than `previous` in the VM, in cycles if
+/// both have them and otherwise in time.
+fn regression(previous: &TestTiming, current: &TestTiming, threshold: f64) -> Option<String> {
+    let increase = |before: f64, after: f64| {
+        let percent = (after / before - 1.0) * 100.0;
+        (percent > threshold).then_some(percent)
+    };
+    if let (Some(before), Some(after)) = (previous.cycles, current.cycles) {
+        let percent = increase(before.max(1) as f64, after as f64)?;
+        return Some(format!("{before} -> {after} cycles (+{percent:.0}%)"));
+    }
+    let (before, after) = (previous.valida?, current.valida?);
+    if Duration::from_secs_f64(after) < MIN_COMPARED_DURATION {
+        return None;
+    }
+    let percent = increase(before.max(MIN_COMPARED_DURATION.as_secs_f64()), after)?;
+    Some(format!("{before:.2}s -> {after:.2}s (+{percent:.0}%)"))
+}
+
+#[test]
+fn test_regression() {
+    let timing = |valida, cycles| TestTiming {
+        native: Some(0.01),
+        valida: Some(valida),
+        cycles,
+    };
+    assert_eq!(
+        regression(&timing(1.0, Some(1000)), &timing(1.0, Some(1300)), 25.0).as_deref(),
+        Some("1000 -> 1300 cycles (+30%)")
+    );
+    assert_eq!(
+        regression(&timing(1.0, Some(1000)), &timing(9.0, Some(1100)), 25.0),
+        None
+    );
+    assert!(regression(&timing(1.0, None), &timing(1.5, None), 25.0).is_some());
+    assert_eq!(
+        regression(&timing(0.01, None), &timing(0.05, None), 25.0),
+        None
+    );
+}
@@ -0,0 +1,125 @@
+//! Threads that degrade to sequential code in the VM.
+//!
+//! The VM runs a single thread, so `std::thread::spawn` and rayon fail or misbehave there. The
+//! functions here run closures on real threads on the host and one after the other in the VM,
+//! so code shared between a guest and its host needs only one implementation.
+//! ```rust
+//! use valida_rs::thread::ParIter;
+//!
+//! let blocks = [b"genesis".to_vec(), b"block 1".to_vec()];
+//! let handle = valida_rs::thread::spawn(|| (0..1000u64).sum::<u64>());
+//! let lengths = blocks.par_map(|block| block.len());
+//! let setup = handle.join().unwrap();
+//! # assert_eq!(lengths, [7, 7]);
+//! # assert_eq!(setup, 499500);
+//! ```
+
+/// A handle to a closure started with [`spawn`].
+///
+/// In the VM the closure has already run by the time [`spawn`] returns.
+pub struct JoinHandle<T>(Inner<T>);
+
+enum Inner<T> {
+    #[cfg(valida)]
+    Done(T),
+    #[cfg(not(valida))]
+    Thread(std::thread::JoinHandle<T>),
+}
+
+/// Run `f` on a new thread on the host, or right away in the VM.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    #[cfg(valida)]
+    return JoinHandle(Inner::Done(f()));
+
+    #[cfg(not(valida))]
+    JoinHandle(Inner::Thread(std::thread::spawn(f)))
+}
+
+impl<T> JoinHandle<T> {
+    /// Wait for the closure to finish and return its result, or the payload it panicked with.
+    ///
+    /// A panic in the VM halts it instead, so there this always succeeds.
+    pub fn join(self) -> std::thread::Result<T> {
+        match self.0 {
+            #[cfg(valida)]
+            Inner::Done(value) => Ok(value),
+            #[cfg(not(valida))]
+            Inner::Thread(handle) => handle.join(),
+        }
+    }
+
+    /// Whether the closure has finished.
+    pub fn is_finished(&self) -> bool {
+        match &self.0 {
+            #[cfg(valida)]
+            Inner::Done(_) => true,
+            #[cfg(not(valida))]
+            Inner::Thread(handle) => handle.is_finished(),
+        }
+    }
+}
+
+/// Data-parallel operations on slices, split across the available cores on the host and run
+/// sequentially in the VM.
+pub trait ParIter<T: Sync> {
+    /// Map every element with `f`, keeping the order of the elements.
+    fn par_map<U, F>(&self, f: F) -> Vec<U>
+    where
+        U: Send,
+        F: Fn(&T) -> U + Sync;
+
+    /// Call `f` on every element, in no particular order on the host.
+    fn par_for_each<F>(&self, f: F)
+    where
+        F: Fn(&T) + Sync,
+    {
+        self.par_map(f);
+    }
+}
+
+impl<T: Sync> ParIter<T> for [T] {
+    fn par_map<U, F>(&self, f: F) -> Vec<U>
+    where
+        U: Send,
+        F: Fn(&T) -> U + Sync,
+    {
+        #[cfg(valida)]
+        return self.iter().map(f).collect();
+
+        #[cfg(not(valida))]
+        {
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            if threads == 1 || self.len() < 2 {
+                return self.iter().map(f).collect();
+            }
+            let f = &f;
+            std::thread::scope(|scope| {
+                let chunks: Vec<_> = self
+                    .chunks(self.len().div_ceil(threads))
+                    .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+                    .collect();
+                chunks
+                    .into_iter()
+                    .flat_map(|chunk| match chunk.join() {
+                        Ok(mapped) => mapped,
+                        Err(payload) => std::panic::resume_unwind(payload),
+                    })
+                    .collect()
+            })
+        }
+    }
+}
+
+#[test]
+fn test_par_map_keeps_order() {
+    let numbers: Vec<u64> = (0..1000).collect();
+    let squares = numbers.par_map(|n| n * n);
+    assert_eq!(squares, numbers.iter().map(|n| n * n).collect::<Vec<_>>());
+
+    let handle = spawn(|| 6 * 7);
+    assert_eq!(handle.join().unwrap(), 42);
+}
@@ -0,0 +1,250 @@
+//! Deterministic replacements for `std::time::{Instant, SystemTime}`.
+//!
+//! The VM has no clock, so `std::time` cannot tell a guest the time. Here the current time is
+//! supplied by the host instead: [`Runner::start_time`](crate::host::Runner::start_time) writes
+//! it to the input tape, [`read_start_time`] reads it back, and from then on the clock advances
+//! by [`CYCLE_DURATION`] per VM cycle. The same input then always yields the same timestamps, so
+//! time-dependent logic such as a token expiry check can be proven and replayed.
+//! ```rust
+//! use valida_rs::time::{self, SystemTime, UNIX_EPOCH};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # valida_rs::io::testing::set_input(b"1700000000000000000\n2000000000\n".to_vec());
+//! time::read_start_time()?;
+//! let expires_at: u64 = valida_rs::io::read_line()?;
+//! let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+//! assert!(now.as_secs() < expires_at);
+//! # valida_rs::io::testing::reset();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Without a cycle counter the VM's clock stands still. Off the VM the clock advances with the
+//! real monotonic clock, and before [`read_start_time`] or [`set_start_time`] it starts at the
+//! real system time; in the VM it starts at [`UNIX_EPOCH`].
+
+use std::{
+    error::Error,
+    fmt,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+/// How far the VM's clock advances per cycle.
+pub const CYCLE_DURATION: Duration = Duration::from_nanos(1);
+
+/// The start of Unix time, 1970-01-01 00:00:00 UTC.
+pub const UNIX_EPOCH: SystemTime = SystemTime(Duration::ZERO);
+
+/// A point on the guest's monotonic clock, like `std::time::Instant`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(Duration);
+
+/// A point in Unix time on the guest's clock, like `std::time::SystemTime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(Duration);
+
+/// The error of [`SystemTime::duration_since`] when the other time is later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemTimeError(Duration);
+
+impl SystemTimeError {
+    /// How much later the other time is.
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl fmt::Display for SystemTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "second time provided was later than self by {:?}",
+            self.0
+        )
+    }
+}
+
+impl Error for SystemTimeError {}
+
+struct Clock {
+    /// The Unix time at which the clock started.
+    origin: Duration,
+    #[cfg(valida)]
+    cycles: Option<u64>,
+    #[cfg(not(valida))]
+    instant: std::time::Instant,
+}
+
+impl Clock {
+    fn start() -> Self {
+        #[cfg(valida)]
+        return Self {
+            origin: Duration::ZERO,
+            cycles: crate::intrinsics::cycle_count(),
+        };
+
+        #[cfg(not(valida))]
+        Self {
+            origin: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default(),
+            instant: std::time::Instant::now(),
+        }
+    }
+
+    /// How far the clock has advanced since it started.
+    fn elapsed(&self) -> Duration {
+        #[cfg(valida)]
+        return match (crate::intrinsics::cycle_count(), self.cycles) {
+            (Some(now), Some(start)) => {
+                let nanos = CYCLE_DURATION.as_nanos() * u128::from(now.saturating_sub(start));
+                Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+            }
+            _ => Duration::ZERO,
+        };
+
+        #[cfg(not(valida))]
+        self.instant.elapsed()
+    }
+}
+
+static CLOCK: Mutex<Option<Clock>> = Mutex::new(None);
+
+/// Run `f` on the clock, starting it first if nothing has used it yet.
+fn with_clock<T>(f: impl FnOnce(&mut Clock) -> T) -> T {
+    let mut clock = CLOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    f(clock.get_or_insert_with(Clock::start))
+}
+
+/// Set the clock so that [`SystemTime::now`] is `now`. [`Instant`]s are unaffected.
+pub fn set_start_time(now: SystemTime) {
+    with_clock(|clock| clock.origin = now.0.saturating_sub(clock.elapsed()));
+}
+
+/// Read the current time the host wrote with `Runner::start_time` off the input tape, and set the
+/// clock to it.
+///
+/// The host writes the time right after the program selector, so call this before reading any
+/// other input.
+pub fn read_start_time() -> Result<SystemTime, Box<dyn Error>> {
+    let nanos: u64 = crate::io::read_line()?;
+    let now = UNIX_EPOCH + Duration::from_nanos(nanos);
+    set_start_time(now);
+    Ok(now)
+}
+
+impl Instant {
+    /// The current point on the clock.
+    pub fn now() -> Self {
+        Self(with_clock(|clock| clock.elapsed()))
+    }
+
+    /// The time from `earlier` to `self`, or zero if `earlier` is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// The time since `self`.
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration).map(Self)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_sub(duration).map(Self)
+    }
+}
+
+impl SystemTime {
+    /// The current Unix time on the clock.
+    pub fn now() -> Self {
+        Self(with_clock(|clock| clock.origin + clock.elapsed()))
+    }
+
+    /// The time from `earlier` to `self`, or an error holding the time back if `earlier` is
+    /// later.
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        self.0
+            .checked_sub(earlier.0)
+            .ok_or_else(|| SystemTimeError(earlier.0 - self.0))
+    }
+
+    /// The time since `self`, or an error if `self` is in the future.
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        Self::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration).map(Self)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_sub(duration).map(Self)
+    }
+}
+
+impl From<SystemTime> for std::time::SystemTime {
+    fn from(time: SystemTime) -> Self {
+        std::time::UNIX_EPOCH + time.0
+    }
+}
+
+macro_rules! impl_time_arithmetic {
+    ($($time:ident),*) => {$(
+        impl Add<Duration> for $time {
+            type Output = $time;
+
+            fn add(self, duration: Duration) -> $time {
+                self.checked_add(duration)
+                    .expect("overflow when adding duration to time")
+            }
+        }
+
+        impl AddAssign<Duration> for $time {
+            fn add_assign(&mut self, duration: Duration) {
+                *self = *self + duration;
+            }
+        }
+
+        impl Sub<Duration> for $time {
+            type Output = $time;
+
+            fn sub(self, duration: Duration) -> $time {
+                self.checked_sub(duration)
+                    .expect("overflow when subtracting duration from time")
+            }
+        }
+
+        impl SubAssign<Duration> for $time {
+            fn sub_assign(&mut self, duration: Duration) {
+                *self = *self - duration;
+            }
+        }
+    )*};
+}
+
+impl_time_arithmetic!(Instant, SystemTime);
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+#[test]
+fn test_clock_starts_at_the_time_set() {
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    set_start_time(start);
+    let elapsed = SystemTime::now().duration_since(start).unwrap();
+    assert!(elapsed < Duration::from_secs(60));
+    let earlier = Instant::now();
+    assert!(Instant::now() >= earlier);
+    assert!(UNIX_EPOCH.duration_since(start).is_err());
+}
@@ -0,0 +1,106 @@
+//! Breadcrumbs of the phases a guest went through, printed when it panics.
+//!
+//! Backtraces are not available in the VM. Instead, [`breadcrumb!`] records the name of a phase
+//! and where it was entered in a small ring buffer, and the panic hooks installed by
+//! `entrypoint!` and the test runner print the most recent ones, so a panic deep inside library
+//! code shows at least what the guest was doing.
+//! ```rust
+//! # let parse = |input: &[u8]| input.len();
+//! # let verify = |_: &usize| ();
+//! # let input = b"block";
+//! valida_rs::trace::breadcrumb!("parse block");
+//! let block = parse(input);
+//! valida_rs::trace::breadcrumb!("verify signatures");
+//! verify(&block);
+//! # assert_eq!(valida_rs::trace::recent().len(), 2);
+//! ```
+
+use std::{
+    fmt,
+    sync::{Mutex, PoisonError},
+};
+
+pub use crate::__trace_breadcrumb as breadcrumb;
+
+/// How many of the most recent breadcrumbs are kept.
+pub const CAPACITY: usize = 16;
+
+/// A phase recorded with [`breadcrumb!`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breadcrumb {
+    pub phase: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+}
+
+impl fmt::Display for Breadcrumb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.phase, self.file, self.line)
+    }
+}
+
+struct Trail {
+    crumbs: [Option<Breadcrumb>; CAPACITY],
+    /// Where the next breadcrumb goes, overwriting the oldest once the trail is full.
+    next: usize,
+}
+
+static TRAIL: Mutex<Trail> = Mutex::new(Trail {
+    crumbs: [None; CAPACITY],
+    next: 0,
+});
+
+fn trail() -> std::sync::MutexGuard<'static, Trail> {
+    // The trail is read by panic hooks, which must not panic on a poisoned lock.
+    TRAIL.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Record `crumb`, forgetting the oldest if [`CAPACITY`] are kept already.
+pub fn record(crumb: Breadcrumb) {
+    let mut trail = trail();
+    let next = trail.next;
+    trail.crumbs[next] = Some(crumb);
+    trail.next = (next + 1) % CAPACITY;
+}
+
+/// The breadcrumbs kept, oldest first.
+pub fn recent() -> Vec<Breadcrumb> {
+    let trail = trail();
+    let (newer, older) = trail.crumbs.split_at(trail.next);
+    older.iter().chain(newer).flatten().copied().collect()
+}
+
+/// Forget all breadcrumbs.
+pub fn clear() {
+    let mut trail = trail();
+    trail.crumbs = [None; CAPACITY];
+    trail.next = 0;
+}
+
+/// Print the breadcrumbs kept, oldest first, if there are any.
+pub fn print_recent() {
+    let crumbs = recent();
+    if crumbs.is_empty() {
+        return;
+    }
+    println!("recent breadcrumbs, most recent last:");
+    for crumb in crumbs {
+        println!("  {crumb}");
+    }
+}
+
+#[test]
+fn test_keeps_most_recent() {
+    clear();
+    for line in 0..CAPACITY as u32 + 3 {
+        record(Breadcrumb {
+            phase: "phase",
+            file: file!(),
+            line,
+        });
+    }
+    let lines: Vec<u32> = recent().iter().map(|crumb| crumb.line).collect();
+    assert_eq!(lines, (3..CAPACITY as u32 + 3).collect::<Vec<_>>());
+    clear();
+    assert!(recent().is_empty());
+}
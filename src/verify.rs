@@ -0,0 +1,150 @@
+//! Verifying Valida proofs inside a guest, to compose proofs (aggregation, rollups).
+//!
+//! In the VM this uses the recursion facility, which requires the `intrinsics` feature. On the
+//! host, so that composing guests can be tested natively, proofs are checked by running
+//! `valida verify` on the program registered for the program id with `register_program`, which
+//! requires the `host` feature.
+
+use std::fmt;
+
+#[cfg(all(valida, feature = "intrinsics"))]
+extern "C" {
+    fn valida_verify_proof(
+        proof: *const u8,
+        proof_len: usize,
+        program_id: *const u8,
+        journal: *const u8,
+        journal_len: usize,
+    ) -> u32;
+}
+
+/// Why a proof was not accepted.
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The proof does not attest to a run of the program that output the journal.
+    Rejected,
+    /// Proofs cannot be verified: the `intrinsics` feature is disabled in the VM, or the `host`
+    /// feature on the host.
+    Unsupported,
+    /// No program was registered for the program id, see [`register_program`].
+    #[cfg(all(not(valida), feature = "host"))]
+    UnknownProgram([u8; 32]),
+    /// Running `valida verify` failed.
+    #[cfg(all(not(valida), feature = "host"))]
+    Host(crate::host::ProveError),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Rejected => write!(f, "Proof rejected"),
+            VerifyError::Unsupported => write!(
+                f,
+                "Proof verification requires the `intrinsics` feature of valida-rs in the VM, or \
+                 the `host` feature on the host"
+            ),
+            #[cfg(all(not(valida), feature = "host"))]
+            VerifyError::UnknownProgram(id) => {
+                write!(f, "No program registered for program id ")?;
+                id.iter().try_for_each(|b| write!(f, "{b:02x}"))
+            }
+            #[cfg(all(not(valida), feature = "host"))]
+            VerifyError::Host(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verify that `proof_bytes` proves a run of the program `program_id` whose output was `journal`.
+pub fn verify_valida_proof(
+    proof_bytes: &[u8],
+    program_id: &[u8; 32],
+    journal: &[u8],
+) -> Result<(), VerifyError> {
+    #[cfg(all(valida, feature = "intrinsics"))]
+    {
+        let accepted = unsafe {
+            valida_verify_proof(
+                proof_bytes.as_ptr(),
+                proof_bytes.len(),
+                program_id.as_ptr(),
+                journal.as_ptr(),
+                journal.len(),
+            )
+        };
+        if accepted != 0 {
+            Ok(())
+        } else {
+            Err(VerifyError::Rejected)
+        }
+    }
+
+    #[cfg(any(
+        all(valida, not(feature = "intrinsics")),
+        all(not(valida), not(feature = "host"))
+    ))]
+    {
+        let _ = (proof_bytes, program_id, journal);
+        Err(VerifyError::Unsupported)
+    }
+
+    #[cfg(all(not(valida), feature = "host"))]
+    host::verify(proof_bytes, program_id, journal)
+}
+
+/// Register the guest binary at `elf` as the program `program_id`, for [`verify_valida_proof`]
+/// on the host.
+#[cfg(all(not(valida), feature = "host"))]
+pub fn register_program(program_id: [u8; 32], elf: impl Into<std::path::PathBuf>) {
+    host::PROGRAMS
+        .lock()
+        .unwrap()
+        .insert(program_id, elf.into());
+}
+
+#[cfg(all(not(valida), feature = "host"))]
+mod host {
+    use std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{LazyLock, Mutex},
+    };
+
+    use super::VerifyError;
+    use crate::host::{Proof, ProveError, Prover};
+
+    pub(super) static PROGRAMS: LazyLock<Mutex<HashMap<[u8; 32], PathBuf>>> =
+        LazyLock::new(Default::default);
+
+    pub(super) fn verify(
+        proof_bytes: &[u8],
+        program_id: &[u8; 32],
+        journal: &[u8],
+    ) -> Result<(), VerifyError> {
+        let elf = PROGRAMS
+            .lock()
+            .unwrap()
+            .get(program_id)
+            .cloned()
+            .ok_or(VerifyError::UnknownProgram(*program_id))?;
+        let proof = Proof {
+            proof: proof_bytes.to_vec(),
+            output: journal.to_vec(),
+        };
+        match Prover::new().verify(elf, &proof) {
+            Ok(()) => Ok(()),
+            Err(ProveError::VerificationFailed { .. }) => Err(VerifyError::Rejected),
+            Err(e) => Err(VerifyError::Host(e)),
+        }
+    }
+}
+
+#[cfg(all(not(valida), feature = "host"))]
+#[test]
+fn test_verify_unknown_program() {
+    assert!(matches!(
+        verify_valida_proof(b"proof", &[7; 32], b"journal"),
+        Err(VerifyError::UnknownProgram([7, ..]))
+    ));
+}
@@ -1,4 +1,4 @@
-#![feature(custom_test_frameworks, test)]
+#![feature(custom_test_frameworks)]
 #![test_runner(valida_rs::test_utils::test_runner)]
 
 #[test]
@@ -14,6 +14,18 @@ fn test_integration_fail() {
     assert_eq!(1 + 1, 3);
 }
 
+valida_rs::valida_expect_output!(
+    test_integration, "Running integration test...";
+    test_integration_fail, "Running integration test fail...";
+);
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn test_integration_fail_with_message() {
+    let v: Vec<u32> = Vec::new();
+    println!("{}", v[std::hint::black_box(1)]);
+}
+
 #[test]
 #[ignore]
 fn test_integration_ignore() {
@@ -24,8 +36,152 @@ fn test_integration_ignore() {
 #[test]
 #[ignore]
 fn test_panics_on_valida() {
-    #[allow(unexpected_cfgs)]
-    if cfg!(target_arch = "delendum") {
+    if valida_rs::target::is_valida() {
         panic!("This test will panic on valida, but not on the native host");
     }
 }
+
+valida_rs::valida_bench!(bench_integration_sum, |b| {
+    b.iter(|| (0..1000u64).sum::<u64>())
+});
+
+#[test]
+fn test_bench_summary_round_trip() {
+    let summary = valida_rs::bench::BenchSummary {
+        name: "bench_integration_sum".to_string(),
+        iterations: 10,
+        min: Some(118),
+        median: Some(120),
+        unit: Some(valida_rs::bench::BenchUnit::Cycles),
+    };
+    let line = summary.to_string();
+    assert_eq!(
+        valida_rs::bench::BenchSummary::parse_line(&line),
+        Some(summary)
+    );
+}
+
+valida_rs::valida_test_cases! {
+    fn test_integration_cases(a: u64, b: u64, sum: u64) {
+        assert_eq!(a + b, sum);
+    }
+    zero: (0, 0, 0),
+    small: (1, 2, 3),
+    #[should_panic]
+    wrong: (1, 1, 3),
+}
+
+#[test]
+fn test_mocked_tapes() {
+    use valida_rs::io;
+
+    io::testing::set_input(b"42\nrest".to_vec());
+    assert_eq!(io::read_line::<u32>().unwrap(), 42);
+    assert_eq!(io::read().unwrap(), b"rest");
+
+    io::write(&7u8).unwrap();
+    assert_eq!(io::testing::take_output(), b"1\n\x07");
+    io::testing::reset();
+}
+
+#[derive(Debug, PartialEq, valida_rs::schema::ValidaIo)]
+#[valida_io(version = 1)]
+struct Transfer {
+    from: String,
+    amount: u64,
+}
+
+#[derive(Debug, PartialEq, valida_rs::schema::ValidaIo)]
+struct Pair(u8, Vec<u8>);
+
+#[test]
+fn test_valida_io_round_trip() {
+    use valida_rs::{io, schema::ValidaIo};
+
+    let transfer = Transfer {
+        from: "alice".to_string(),
+        amount: 5,
+    };
+    let bytes = transfer.to_bytes().unwrap();
+    assert_eq!(bytes[0], 1);
+    assert_eq!(Transfer::from_bytes(&bytes).unwrap(), transfer);
+    assert!(Pair::from_bytes(&bytes).is_err());
+    assert_ne!(Transfer::SCHEMA_HASH, Pair::SCHEMA_HASH);
+
+    io::testing::set_input(Vec::new());
+    Pair(2, vec![3]).write_to_tape().unwrap();
+    let output = io::testing::take_output();
+    io::testing::set_input(output);
+    assert_eq!(Pair::read_from_tape().unwrap(), Pair(2, vec![3]));
+    io::testing::reset();
+}
+
+#[test]
+fn test_snapshot_of_committed_output() {
+    let value = valida_rs::valida_snapshot!("integration_output", {
+        valida_rs::io::write(&(1u32, 2u32)).unwrap();
+        3
+    });
+    assert_eq!(value, 3);
+}
+
+#[test]
+fn test_valida_dbg_returns_its_value() {
+    let text = String::from("moved");
+    assert_eq!(valida_rs::valida_dbg!(1 + 2), 3);
+    assert_eq!(valida_rs::valida_dbg!(text), "moved");
+    assert_eq!(valida_rs::valida_dbg!(1, "two",), (1, "two"));
+}
+
+valida_rs::cfg_valida! {
+    fn environment() -> &'static str {
+        "valida"
+    }
+}
+
+valida_rs::cfg_host! {
+    fn environment() -> &'static str {
+        "host"
+    }
+}
+
+#[test]
+fn test_cfg_macros_match_is_valida() {
+    assert_eq!(environment() == "valida", valida_rs::is_valida());
+}
+
+valida_rs::host_only! {
+    #[test]
+    fn test_spawns_a_thread() {
+        let handle = std::thread::spawn(|| 1 + 1);
+        assert_eq!(handle.join().unwrap(), 2);
+    }
+
+    static FIXTURE_WRITTEN: std::sync::atomic::AtomicBool =
+        std::sync::atomic::AtomicBool::new(false);
+
+    #[test]
+    fn test_order_reads_fixture() {
+        assert!(FIXTURE_WRITTEN.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_order_writes_fixture() {
+        FIXTURE_WRITTEN.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    valida_rs::valida_test_order!(test_order_writes_fixture before test_order_reads_fixture);
+}
+
+static INCLUDED_SOURCE: &[u8] = valida_rs::include_input!("valida_integration_test.rs");
+
+#[test]
+fn test_include_input_is_aligned() {
+    assert_eq!(
+        INCLUDED_SOURCE,
+        include_bytes!("valida_integration_test.rs").as_slice()
+    );
+    assert_eq!(INCLUDED_SOURCE.as_ptr() as usize % 16, 0);
+    let aligned = valida_rs::include_input!("valida_integration_test.rs", align = 64);
+    assert_eq!(aligned.as_ptr() as usize % 64, 0);
+}